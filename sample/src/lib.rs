@@ -1,17 +1,19 @@
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::time::Instant;
-use weechat::bar::{BarItem, LightBarItem};
+use weechat::bar::BarItem;
 use weechat::{
     weechat_plugin, ArgsWeechat, Buffer, CommandDescription, CommandHook,
-    Config, ConfigOption, ConfigSectionInfo, HasHData, NickArgs, StringOption,
-    Weechat, WeechatPlugin, WeechatResult,
+    Config, ConfigOption, ConfigSectionInfo, HasHData, KeyContext,
+    NickSettings, PluginError, StringOption, StringOptionSettings, Weechat,
+    WeechatPlugin, WeechatResult, Window,
 };
 
 struct SamplePlugin {
     weechat: Weechat,
     _rust_hook: CommandHook<String>,
-    _rust_config: Config<String>,
-    _item: BarItem<String>,
+    config: Config<String>,
+    _item: BarItem,
 }
 
 impl SamplePlugin {
@@ -34,17 +36,20 @@ impl SamplePlugin {
         }
     }
 
-    fn option_change_cb(_data: &mut String, option: &StringOption) {
-        let weechat = option.get_weechat();
-        weechat.print("Changing rust option");
+    fn option_change_cb(weechat: &Weechat, option: &StringOption) {
+        weechat.print(&format!("Changing rust option to {}", option.value()));
     }
 
     fn bar_cb(
-        _data: &String,
-        _item: &LightBarItem,
-        _buffer: &Buffer,
+        _weechat: &Weechat,
+        _window: Option<Window>,
+        buffer: Option<Buffer>,
+        _extra_info: &HashMap<String, String>,
     ) -> String {
-        "rust/sample".to_owned()
+        match buffer {
+            Some(buffer) => format!("rust/sample: {}", buffer.get_name()),
+            None => "rust/sample".to_owned(),
+        }
     }
 }
 
@@ -71,23 +76,20 @@ impl WeechatPlugin for SamplePlugin {
 
         let op_group = buffer.add_group("operators", "blue", true, None);
         let emma = buffer.add_nick(
-            NickArgs {
-                name: "Emma",
-                color: "magenta",
-                prefix: "&",
-                prefix_color: "green",
-                ..Default::default()
-            },
-            Some(&op_group),
+            NickSettings::new("Emma")
+                .color("magenta")
+                .prefix("&")
+                .prefix_color("green"),
+            op_group.as_ref(),
         );
 
-        weechat.print(&format!("Nick name getting test: {}", emma.get_name()));
+        if let Some(emma) = emma {
+            weechat
+                .print(&format!("Nick name getting test: {}", emma.get_name()));
+        }
 
         for nick_number in 0..n {
-            let nick = NickArgs {
-                name: &format!("nick_{}", nick_number),
-                ..Default::default()
-            };
+            let nick = NickSettings::new(format!("nick_{}", nick_number));
             let _ = buffer.add_nick(nick, None);
         }
 
@@ -103,14 +105,24 @@ impl WeechatPlugin for SamplePlugin {
             ..Default::default()
         };
 
-        let command = weechat.hook_command(
-            sample_command,
-            SamplePlugin::rust_command_cb,
-            Some("Hello rust command".to_owned()),
-        );
-
-        let mut config =
-            weechat.config_new("rust_sample", None, None::<String>);
+        let command = weechat
+            .hook_command(
+                sample_command,
+                SamplePlugin::rust_command_cb,
+                Some("Hello rust command".to_owned()),
+            )
+            .map_err(|e| {
+                PluginError::new(format!(
+                    "Can't create the rust command: {}",
+                    e
+                ))
+            })?;
+
+        let mut keys = HashMap::new();
+        keys.insert("meta-g".to_owned(), "/rustcommand".to_owned());
+        weechat.key_bind(KeyContext::Default, &keys);
+
+        let config = weechat.config_new("rust_sample", None, None::<String>);
 
         let section_info: ConfigSectionInfo<String> = ConfigSectionInfo {
             name: "sample_section",
@@ -119,23 +131,30 @@ impl WeechatPlugin for SamplePlugin {
 
         let section = config.new_section(section_info);
 
-        section.new_string_option(
-            "test_option",
-            "",
-            "",
-            "",
-            false,
-            Some(SamplePlugin::option_change_cb),
-            None::<String>,
-        );
+        section
+            .add_string_option(
+                StringOptionSettings::new("test_option")
+                    .on_change(SamplePlugin::option_change_cb),
+            )
+            .unwrap();
+
+        if let Err(e) = config.read() {
+            if e.is_fatal() {
+                weechat
+                    .print_error(&format!("Error reading the config: {}", e));
+            }
+        }
 
-        let item =
-            weechat.new_bar_item("buffer_plugin", SamplePlugin::bar_cb, None);
+        let item = weechat
+            .new_bar_item("buffer_plugin", SamplePlugin::bar_cb)
+            .map_err(|e| {
+                PluginError::new(format!("Can't create the bar item: {}", e))
+            })?;
 
         Ok(SamplePlugin {
             weechat,
             _rust_hook: command,
-            _rust_config: config,
+            config,
             _item: item,
         })
     }
@@ -143,6 +162,13 @@ impl WeechatPlugin for SamplePlugin {
 
 impl Drop for SamplePlugin {
     fn drop(&mut self) {
+        self.weechat.key_unbind(KeyContext::Default, "meta-g");
+
+        if let Err(e) = self.config.write() {
+            self.weechat
+                .print_error(&format!("Error writing the config: {}", e));
+        }
+
         self.weechat.print("Bye rust!");
     }
 }
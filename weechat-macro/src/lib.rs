@@ -10,13 +10,49 @@ use syn::{parse_macro_input, Error, LitStr};
 
 use quote::quote;
 
+/// The licenses that are accepted for the `license` field, as SPDX
+/// identifiers.
+const LICENSES: &[&str] = &[
+    "0BSD",
+    "AGPL-3.0",
+    "AGPL-3.0-only",
+    "AGPL-3.0-or-later",
+    "Apache-2.0",
+    "Artistic-2.0",
+    "BSD-2-Clause",
+    "BSD-3-Clause",
+    "BSL-1.0",
+    "CC0-1.0",
+    "EPL-2.0",
+    "GPL-2.0",
+    "GPL-2.0-only",
+    "GPL-2.0-or-later",
+    "GPL-3.0",
+    "GPL-3.0-only",
+    "GPL-3.0-or-later",
+    "ISC",
+    "LGPL-2.1",
+    "LGPL-2.1-only",
+    "LGPL-2.1-or-later",
+    "LGPL-3.0",
+    "LGPL-3.0-only",
+    "LGPL-3.0-or-later",
+    "MIT",
+    "MPL-2.0",
+    "Unlicense",
+    "WTFPL",
+    "Zlib",
+];
+
 struct WeechatPluginInfo {
     plugin: syn::Ident,
+    plugin_name: String,
     name: (usize, Literal),
     author: (usize, Literal),
     description: (usize, Literal),
     version: (usize, Literal),
     license: (usize, Literal),
+    priority: Option<syn::LitInt>,
 }
 
 enum WeechatVariable {
@@ -25,23 +61,92 @@ enum WeechatVariable {
     Description(syn::LitStr),
     Version(syn::LitStr),
     License(syn::LitStr),
+    Priority(syn::LitInt),
+}
+
+fn string_to_pair(string: &str) -> (usize, Literal) {
+    let mut bytes = string.as_bytes().to_vec();
+    bytes.push(0);
+    let len = bytes.len();
+    (len, Literal::byte_string(&bytes))
+}
+
+/// Check that a version looks like `1.2.3`, optionally followed by a
+/// pre-release or build suffix, e.g. `0.1.0-dev`.
+fn is_valid_version(version: &str) -> bool {
+    let numbers = version.split(&['-', '+'][..]).next().unwrap_or("");
+    let parts: Vec<&str> = numbers.split('.').collect();
+
+    (1..=3).contains(&parts.len())
+        && parts
+            .iter()
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Check that a license is a known SPDX identifier, expressions that combine
+/// licenses using `OR` or `AND` are accepted as well.
+fn is_valid_license(license: &str) -> bool {
+    license
+        .split(" OR ")
+        .flat_map(|l| l.split(" AND "))
+        .map(|l| l.trim().trim_start_matches('(').trim_end_matches(')'))
+        .all(|l| LICENSES.contains(&l))
 }
 
 impl WeechatVariable {
-    fn litstr_to_pair(string: &LitStr) -> (usize, Literal) {
-        let mut bytes = string.value().into_bytes();
-        bytes.push(0);
-        let len = bytes.len();
-        (len, Literal::byte_string(&bytes))
+    fn key(&self) -> &'static str {
+        match self {
+            WeechatVariable::Name(_) => "name",
+            WeechatVariable::Author(_) => "author",
+            WeechatVariable::Description(_) => "description",
+            WeechatVariable::Version(_) => "version",
+            WeechatVariable::License(_) => "license",
+            WeechatVariable::Priority(_) => "priority",
+        }
     }
 
-    fn as_pair(&self) -> (usize, Literal) {
+    fn string(&self) -> Option<&LitStr> {
         match self {
-            WeechatVariable::Name(string) => WeechatVariable::litstr_to_pair(string),
-            WeechatVariable::Author(string) => WeechatVariable::litstr_to_pair(string),
-            WeechatVariable::Description(string) => WeechatVariable::litstr_to_pair(string),
-            WeechatVariable::Version(string) => WeechatVariable::litstr_to_pair(string),
-            WeechatVariable::License(string) => WeechatVariable::litstr_to_pair(string),
+            WeechatVariable::Name(string)
+            | WeechatVariable::Author(string)
+            | WeechatVariable::Description(string)
+            | WeechatVariable::Version(string)
+            | WeechatVariable::License(string) => Some(string),
+            WeechatVariable::Priority(_) => None,
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        if let Some(string) = self.string() {
+            if string.value().contains('\0') {
+                return Err(Error::new(
+                    string.span(),
+                    format!("the plugin {} can't contain a NUL byte", self.key()),
+                ));
+            }
+        }
+
+        match self {
+            WeechatVariable::Name(name) if name.value().is_empty() => {
+                Err(Error::new(name.span(), "the plugin name can't be empty"))
+            }
+            WeechatVariable::Version(version) if !is_valid_version(&version.value()) => {
+                Err(Error::new(
+                    version.span(),
+                    "the plugin version should be in the form `major.minor.patch`, e.g. \"0.1.0\"",
+                ))
+            }
+            WeechatVariable::License(license) if !is_valid_license(&license.value()) => {
+                Err(Error::new(
+                    license.span(),
+                    format!(
+                        "the plugin license should be a SPDX license identifier, one of: {}",
+                        LICENSES.join(", ")
+                    ),
+                ))
+            }
+            WeechatVariable::Priority(priority) => priority.base10_parse::<i32>().map(|_| ()),
+            _ => Ok(()),
         }
     }
 }
@@ -50,16 +155,25 @@ impl Parse for WeechatVariable {
     fn parse(input: ParseStream) -> Result<Self> {
         let key: Ident = input.parse()?;
         input.parse::<syn::Token![:]>()?;
-        let value = input.parse()?;
-
-        match key.to_string().to_lowercase().as_ref() {
-            "name" => Ok(WeechatVariable::Name(value)),
-            "author" => Ok(WeechatVariable::Author(value)),
-            "description" => Ok(WeechatVariable::Description(value)),
-            "version" => Ok(WeechatVariable::Version(value)),
-            "license" => Ok(WeechatVariable::License(value)),
-            _ => Err(Error::new(key.span(), "expected one of bla")),
-        }
+
+        let variable = match key.to_string().to_lowercase().as_ref() {
+            "name" => WeechatVariable::Name(input.parse()?),
+            "author" => WeechatVariable::Author(input.parse()?),
+            "description" => WeechatVariable::Description(input.parse()?),
+            "version" => WeechatVariable::Version(input.parse()?),
+            "license" => WeechatVariable::License(input.parse()?),
+            "priority" => WeechatVariable::Priority(input.parse()?),
+            _ => {
+                return Err(Error::new(
+                    key.span(),
+                    "expected one of name, author, description, version, license or priority",
+                ))
+            }
+        };
+
+        variable.validate()?;
+
+        Ok(variable)
     }
 }
 
@@ -72,24 +186,72 @@ impl Parse for WeechatPluginInfo {
             input.parse_terminated(WeechatVariable::parse)?;
         let mut variables = HashMap::new();
 
-        for arg in args.pairs() {
-            let variable = arg.value();
-            match variable {
-                WeechatVariable::Name(_) => variables.insert("name", *variable),
-                WeechatVariable::Author(_) => variables.insert("author", *variable),
-                WeechatVariable::Description(_) => variables.insert("description", *variable),
-                WeechatVariable::Version(_) => variables.insert("version", *variable),
-                WeechatVariable::License(_) => variables.insert("license", *variable),
-            };
+        for variable in args.into_iter() {
+            let key = variable.key();
+
+            if variables.contains_key(key) {
+                let span = match &variable {
+                    WeechatVariable::Priority(priority) => priority.span(),
+                    other => other.string().unwrap().span(),
+                };
+
+                return Err(Error::new(
+                    span,
+                    format!("the plugin {} is set more than once", key),
+                ));
+            }
+
+            variables.insert(key, variable);
         }
 
+        let span = input.span();
+        let plugin_name = variables
+            .get("name")
+            .and_then(WeechatVariable::string)
+            .map(LitStr::value)
+            .unwrap_or_default();
+        let mut required = |key: &str| match variables
+            .remove(key)
+            .as_ref()
+            .and_then(WeechatVariable::string)
+        {
+            Some(string) => Ok(string_to_pair(&string.value())),
+            None => Err(Error::new(span, format!("the plugin {} is missing", key))),
+        };
+
+        let name = required("name")?;
+        let author = required("author")?;
+        let description = required("description")?;
+        let license = required("license")?;
+
+        // Fall back to the version of the crate that uses the macro.
+        let version = match required("version") {
+            Ok(version) => version,
+            Err(_) => {
+                let version = std::env::var("CARGO_PKG_VERSION").map_err(|_| {
+                    Error::new(
+                        span,
+                        "the plugin version is missing and CARGO_PKG_VERSION isn't set",
+                    )
+                })?;
+                string_to_pair(&version)
+            }
+        };
+
+        let priority = match variables.remove("priority") {
+            Some(WeechatVariable::Priority(priority)) => Some(priority),
+            _ => None,
+        };
+
         Ok(WeechatPluginInfo {
             plugin,
-            name: variables.remove("name").unwrap().as_pair(),
-            author: variables.remove("author").unwrap().as_pair(),
-            description: variables.remove("description").unwrap().as_pair(),
-            version: variables.remove("version").unwrap().as_pair(),
-            license: variables.remove("license").unwrap().as_pair(),
+            plugin_name,
+            name,
+            author,
+            description,
+            version,
+            license,
+            priority,
         })
     }
 }
@@ -99,6 +261,17 @@ impl Parse for WeechatPluginInfo {
 /// This configures the Weechat init and end method as well as additonal plugin
 /// metadata.
 ///
+/// The fields can be given in any order. The `name`, `author`,
+/// `description` and `license` fields are required, the `license` needs to
+/// be a SPDX license identifier. The `version` defaults to the version of
+/// the crate. The optional `priority` decides the order in which plugins
+/// are loaded, plugins with a higher priority are loaded first, the
+/// default priority of Weechat is 1000.
+///
+/// The macro adds the `with()` and `with_mut()` functions to the plugin
+/// struct, they give hook callbacks access to the plugin while it's loaded.
+/// The plugin can only be accessed on the main thread.
+///
 /// # Example
 /// ```ignore
 /// weechat_plugin!(
@@ -107,18 +280,21 @@ impl Parse for WeechatPluginInfo {
 ///     author: "poljar",
 ///     description: "",
 ///     version: "0.1.0",
-///     license: "MIT"
+///     license: "MIT",
+///     priority: 2000
 /// );
 /// ```
 #[proc_macro]
 pub fn weechat_plugin(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let WeechatPluginInfo {
         plugin,
+        plugin_name,
         name,
         author,
         description,
         version,
         license,
+        priority,
     } = parse_macro_input!(input as WeechatPluginInfo);
 
     let (name_len, name) = name;
@@ -127,6 +303,13 @@ pub fn weechat_plugin(input: proc_macro::TokenStream) -> proc_macro::TokenStream
     let (license_len, license) = license;
     let (version_len, version) = version;
 
+    let priority = priority.map(|priority| {
+        quote! {
+            #[no_mangle]
+            pub static weechat_plugin_priority: ::libc::c_int = #priority;
+        }
+    });
+
     let result = quote! {
         #[no_mangle]
         pub static weechat_plugin_api_version: [u8; weechat_sys::WEECHAT_PLUGIN_API_VERSION_LENGTH] = *weechat_sys::WEECHAT_PLUGIN_API_VERSION;
@@ -146,7 +329,12 @@ pub fn weechat_plugin(input: proc_macro::TokenStream) -> proc_macro::TokenStream
         #[no_mangle]
         pub static weechat_plugin_license: [u8; #license_len] = *#license;
 
-        static mut __PLUGIN: Option<#plugin> = None;
+        #priority
+
+        thread_local! {
+            static __PLUGIN: ::std::cell::RefCell<Option<#plugin>> =
+                ::std::cell::RefCell::new(None);
+        }
 
         #[no_mangle]
         /// This function is called when plugin is loaded by WeeChat.
@@ -163,14 +351,33 @@ pub fn weechat_plugin(input: proc_macro::TokenStream) -> proc_macro::TokenStream
                 Weechat::from_ptr(plugin)
             };
             let args = ArgsWeechat::new(argc, argv);
-            match <#plugin as ::weechat::WeechatPlugin>::init(weechat, args) {
-                Ok(p) => {
-                    unsafe {
-                        __PLUGIN = Some(p);
-                    }
+            Weechat::init_main_thread();
+            // Start the executor before the plugin, so closures can be queued
+            // from threads that the plugin spawns.
+            if let Err(e) = ::weechat::executor::start(&weechat) {
+                weechat.print_error(&format!("{}: {}", #plugin_name, e));
+                return weechat_sys::WEECHAT_RC_ERROR;
+            }
+            // A panic must not unwind into Weechat, a plugin that panics
+            // while it's initialized fails to load.
+            let result = ::weechat::catch_panic(plugin, || {
+                <#plugin as ::weechat::WeechatPlugin>::init(weechat, args)
+            });
+            match result {
+                Some(Ok(p)) => {
+                    __PLUGIN.with(|plugin| *plugin.borrow_mut() = Some(p));
                     return weechat_sys::WEECHAT_RC_OK;
                 }
-                Err(_e) => {
+                Some(Err(e)) => {
+                    let weechat = unsafe {
+                        Weechat::from_ptr(plugin)
+                    };
+                    weechat.print_error(&format!("{}: {}", #plugin_name, e));
+                    ::weechat::executor::shutdown();
+                    return weechat_sys::WEECHAT_RC_ERROR;
+                }
+                None => {
+                    ::weechat::executor::shutdown();
                     return weechat_sys::WEECHAT_RC_ERROR;
                 }
             }
@@ -182,22 +389,208 @@ pub fn weechat_plugin(input: proc_macro::TokenStream) -> proc_macro::TokenStream
         /// # Safety
         /// This function needs to be an extern C function and it can't be
         /// mangled, otherwise Weechat will not find the symbol.
-        pub unsafe extern "C" fn weechat_plugin_end(_plugin: *mut weechat_sys::t_weechat_plugin) -> ::libc::c_int {
-            unsafe {
-                __PLUGIN = None;
+        pub unsafe extern "C" fn weechat_plugin_end(weechat_ptr: *mut weechat_sys::t_weechat_plugin) -> ::libc::c_int {
+            // Take the plugin out first so it can't be accessed anymore
+            // while it's being dropped.
+            let plugin = __PLUGIN.with(|plugin| plugin.borrow_mut().take());
+            let dropped = ::weechat::catch_panic(weechat_ptr, || drop(plugin));
+            // Drop the tasks that are still running, they might have been
+            // spawned while the plugin was dropped.
+            ::weechat::executor::shutdown();
+            match dropped {
+                Some(()) => weechat_sys::WEECHAT_RC_OK,
+                None => weechat_sys::WEECHAT_RC_ERROR,
             }
-            weechat_sys::WEECHAT_RC_OK
         }
 
-        pub(crate) fn plugin() -> &'static mut #plugin {
-            unsafe {
-                match &mut __PLUGIN {
-                    Some(p) => p,
-                    None => panic!("Weechat plugin isn't initialized"),
-                }
+        #[allow(dead_code)]
+        impl #plugin {
+            /// Run a function with a reference to the plugin.
+            ///
+            /// Calls can be nested, as long as the plugin isn't borrowed
+            /// mutably at the same time.
+            ///
+            /// # Panics
+            ///
+            /// Panics if the plugin isn't initialized, e.g. while its `init`
+            /// or `drop` method runs, if this is called outside of the main
+            /// thread, or if the plugin is borrowed mutably.
+            pub fn with<R>(f: impl FnOnce(&#plugin) -> R) -> R {
+                __PLUGIN.with(|plugin| {
+                    let plugin = plugin
+                        .try_borrow()
+                        .expect("The Weechat plugin is already borrowed mutably");
+
+                    match &*plugin {
+                        Some(p) => f(p),
+                        None => panic!("Weechat plugin isn't initialized or accessed outside of the main thread"),
+                    }
+                })
+            }
+
+            /// Run a function with a mutable reference to the plugin.
+            ///
+            /// # Panics
+            ///
+            /// Panics if the plugin isn't initialized, e.g. while its `init`
+            /// or `drop` method runs, if this is called outside of the main
+            /// thread, or if the plugin is already borrowed, e.g. because
+            /// this is called from within `with()` or `with_mut()`.
+            pub fn with_mut<R>(f: impl FnOnce(&mut #plugin) -> R) -> R {
+                __PLUGIN.with(|plugin| {
+                    let mut plugin = plugin
+                        .try_borrow_mut()
+                        .expect("The Weechat plugin is already borrowed");
+
+                    match &mut *plugin {
+                        Some(p) => f(p),
+                        None => panic!("Weechat plugin isn't initialized or accessed outside of the main thread"),
+                    }
+                })
             }
         }
     };
 
     result.into()
-}
\ No newline at end of file
+}
+/// Get the name of the hdata variable for a struct field, the field name can
+/// be overridden using `#[hdata(rename = "name")]`.
+fn hdata_field_name(field: &syn::Field) -> Result<String> {
+    let mut name = field.ident.as_ref().unwrap().to_string();
+
+    for attr in field.attrs.iter().filter(|a| a.path.is_ident("hdata")) {
+        let meta = attr.parse_meta()?;
+
+        let list = match meta {
+            syn::Meta::List(list) => list,
+            _ => {
+                return Err(Error::new_spanned(
+                    attr,
+                    "expected #[hdata(rename = \"...\")]",
+                ))
+            }
+        };
+
+        for nested in list.nested.iter() {
+            match nested {
+                syn::NestedMeta::Meta(syn::Meta::NameValue(value))
+                    if value.path.is_ident("rename") =>
+                {
+                    match &value.lit {
+                        syn::Lit::Str(string) => name = string.value(),
+                        lit => return Err(Error::new_spanned(lit, "expected a string literal")),
+                    }
+                }
+                other => {
+                    return Err(Error::new_spanned(
+                        other,
+                        "unknown hdata attribute, expected rename",
+                    ))
+                }
+            }
+        }
+    }
+
+    Ok(name)
+}
+
+/// Get the inner type of an `Option<T>` field type.
+fn option_inner_type(ty: &syn::Type) -> Option<&syn::Type> {
+    let path = match ty {
+        syn::Type::Path(path) if path.qself.is_none() => &path.path,
+        _ => return None,
+    };
+
+    let segment = path.segments.last()?;
+
+    if segment.ident != "Option" {
+        return None;
+    }
+
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) if args.args.len() == 1 => {
+            match args.args.first()? {
+                syn::GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn expand_from_hdata(input: syn::DeriveInput) -> Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+
+    let fields = match &input.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Named(fields),
+            ..
+        }) => &fields.named,
+        _ => {
+            return Err(Error::new_spanned(
+                &input,
+                "FromHData can only be derived for structs with named fields",
+            ))
+        }
+    };
+
+    let mut initializers = Vec::new();
+
+    for field in fields {
+        let ident = field.ident.as_ref().unwrap();
+        let var_name = hdata_field_name(field)?;
+
+        let initializer = match option_inner_type(&field.ty) {
+            Some(ty) => quote! { #ident: hdata.get_var::<#ty>(#var_name) },
+            None => {
+                let ty = &field.ty;
+                quote! { #ident: hdata.get_var::<#ty>(#var_name)? }
+            }
+        };
+
+        initializers.push(initializer);
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::weechat::hdata::FromHData for #name #ty_generics #where_clause {
+            fn from_hdata(hdata: &::weechat::hdata::HData) -> Option<Self> {
+                Some(#name {
+                    #(#initializers,)*
+                })
+            }
+        }
+    })
+}
+
+/// Derive the `FromHData` trait for a struct, this allows reading all the
+/// fields of the struct out of a hdata object at once.
+///
+/// The fields are read using the `HDataType` trait, the name of the hdata
+/// variable is the name of the field unless it's renamed using the
+/// `#[hdata(rename = "name")]` attribute. Creating the struct fails if a
+/// variable is missing, unless the field is an `Option`.
+///
+/// # Example
+/// ```ignore
+/// #[derive(FromHData)]
+/// struct Line {
+///     #[hdata(rename = "date")]
+///     time: DateTime<Utc>,
+///     prefix: Option<String>,
+///     message: String,
+///     highlight: char,
+/// }
+///
+/// let line = Line::from_hdata(&hdata);
+/// ```
+#[proc_macro_derive(FromHData, attributes(hdata))]
+pub fn derive_from_hdata(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as syn::DeriveInput);
+
+    match expand_from_hdata(input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
@@ -0,0 +1,86 @@
+//! Measure the overhead of printing through the bindings.
+//!
+//! The plugin struct is filled with a `printf_date_tags` stub that only
+//! reads the message, so the numbers show the cost of the conversions the
+//! bindings do for every printed line. Run it with `cargo bench`.
+
+use std::ffi::CStr;
+use std::hint::black_box;
+use std::os::raw::c_char;
+use std::time::{Duration, Instant};
+
+use weechat::Weechat;
+use weechat_sys::{t_gui_buffer, t_weechat_plugin, time_t};
+
+const LINES: u32 = 200_000;
+
+type PrintfStub = unsafe extern "C" fn(
+    *mut t_gui_buffer,
+    time_t,
+    *const c_char,
+    *const c_char,
+    *const c_char,
+);
+type PrintfDateTags = unsafe extern "C" fn(
+    *mut t_gui_buffer,
+    time_t,
+    *const c_char,
+    *const c_char,
+    ...
+);
+
+// The bindings always print using a "%s" format string, the message is the
+// only variadic argument.
+unsafe extern "C" fn printf_date_tags(
+    _buffer: *mut t_gui_buffer,
+    _date: time_t,
+    _tags: *const c_char,
+    _format: *const c_char,
+    message: *const c_char,
+) {
+    black_box(CStr::from_ptr(message).to_bytes().len());
+}
+
+fn bench(name: &str, weechat: &Weechat, line: &str) {
+    let start = Instant::now();
+
+    for _ in 0..LINES {
+        weechat.print(black_box(line));
+    }
+
+    let elapsed = start.elapsed();
+    let per_line = elapsed / LINES;
+
+    println!(
+        "{:<24} {:>8.2?} total, {:>6} ns per line",
+        name,
+        elapsed,
+        per_line.as_nanos()
+    );
+}
+
+fn main() {
+    let mut plugin: Box<t_weechat_plugin> =
+        Box::new(unsafe { std::mem::zeroed() });
+    plugin.printf_date_tags = unsafe {
+        std::mem::transmute::<PrintfStub, Option<PrintfDateTags>>(
+            printf_date_tags,
+        )
+    };
+
+    Weechat::init_main_thread();
+    let weechat = Weechat::from_ptr(&mut *plugin);
+
+    // Warm up the allocator.
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_millis(200) {
+        weechat.print("warm up");
+    }
+
+    let backlog_line = "<poljar> Did anybody try out the new release yet? \
+                        It seems to be a lot faster when joining rooms.";
+
+    bench("short line", &weechat, "hello");
+    bench("backlog line", &weechat, backlog_line);
+    bench("line with a null byte", &weechat, "hello\0world");
+}
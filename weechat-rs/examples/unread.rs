@@ -0,0 +1,108 @@
+//! A plugin that shows the number of unread lines of a buffer in a bar.
+//!
+//! Every window gets its own bar, the bar item receives the buffer that is
+//! displayed in the window and shows the unread lines of that buffer. The
+//! item is rebuilt whenever the hotlist changes.
+//!
+//! Build the plugin with `cargo build --example unread` and load the library
+//! from `target/debug/examples/` using `/plugin load`.
+
+use std::collections::HashMap;
+
+use weechat::hdata::HDataPointer;
+use weechat::{
+    weechat_plugin, ArgsWeechat, Bar, BarItem, BarItems, BarSettings, BarType,
+    Buffer, HasHData, PluginError, SignalHook, SignalHookValue, Weechat,
+    WeechatPlugin, WeechatResult, Window,
+};
+
+struct Unread {
+    _item: BarItem,
+    _bar: Bar,
+    _hotlist_hook: SignalHook<(), ()>,
+}
+
+impl Unread {
+    fn item_cb(
+        _weechat: &Weechat,
+        _window: Option<Window>,
+        buffer: Option<Buffer>,
+        _extra_info: &HashMap<String, String>,
+    ) -> String {
+        // The hotlist counts the unread lines of a buffer per priority.
+        let unread: i32 = buffer
+            .and_then(|buffer| buffer.get_hdata("buffer"))
+            .and_then(|hdata| hdata.get_var::<HDataPointer>("hotlist"))
+            .and_then(|hotlist| hotlist.get_hdata("hotlist"))
+            .map(|hdata| {
+                (0..4)
+                    .filter_map(|i| hdata.get_var_at::<i32>("count", i))
+                    .sum()
+            })
+            .unwrap_or(0);
+
+        if unread == 0 {
+            String::new()
+        } else {
+            format!("unread: {}", unread)
+        }
+    }
+
+    fn hotlist_cb(_data: &(), weechat: &Weechat, _value: SignalHookValue) {
+        weechat.update_bar_item("unread");
+    }
+}
+
+impl WeechatPlugin for Unread {
+    fn init(weechat: Weechat, _args: ArgsWeechat) -> WeechatResult<Self> {
+        let item =
+            weechat
+                .new_bar_item("unread", Unread::item_cb)
+                .map_err(|e| {
+                    PluginError::new(format!(
+                        "Can't create the unread item: {}",
+                        e
+                    ))
+                })?;
+
+        let items = BarItems::new()
+            .delimited("buffer_name")
+            .glued(item.name())
+            .spacer()
+            .item("buffer_nicklist_count");
+
+        let bar = weechat
+            .new_bar(
+                BarSettings::new("unread")
+                    .bar_type(BarType::Window)
+                    .items(items.as_slice()),
+            )
+            .map_err(|e| {
+                PluginError::new(format!("Can't create the unread bar: {}", e))
+            })?;
+
+        let hotlist_hook = weechat
+            .hook_signal("hotlist_changed", None, Unread::hotlist_cb, None)
+            .map_err(|e| {
+                PluginError::new(format!(
+                    "Can't hook the hotlist signal: {}",
+                    e
+                ))
+            })?;
+
+        Ok(Unread {
+            _item: item,
+            _bar: bar,
+            _hotlist_hook: hotlist_hook,
+        })
+    }
+}
+
+weechat_plugin!(
+    Unread,
+    name: "unread",
+    author: "poljar",
+    description: "Show the unread lines of the buffers in a bar",
+    version: "0.1.0",
+    license: "MIT"
+);
@@ -1,78 +1,775 @@
 use core::ptr;
 use libc::c_char;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
 use std::os::raw::c_void;
 use weechat_sys::{
-    t_gui_bar_item, t_gui_buffer, t_gui_window, t_hashtable, t_weechat_plugin,
+    t_gui_bar, t_gui_bar_item, t_gui_buffer, t_gui_window, t_hashtable,
+    t_weechat_plugin,
 };
 
-use crate::{Buffer, LossyCString, Weechat};
+use crate::hashtable::hashtable_to_map;
+use crate::{Buffer, LossyCString, Weechat, Window};
 
-struct BarItemCbData<T> {
-    callback: fn(&T, &LightBarItem, &Buffer) -> String,
-    callback_data: T,
+/// The type of a bar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarType {
+    /// The bar is displayed once on the screen, outside of the windows.
+    Root,
+    /// The bar is displayed in every window.
+    Window,
+}
+
+impl BarType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            BarType::Root => "root",
+            BarType::Window => "window",
+        }
+    }
+}
+
+/// The position of a bar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarPosition {
+    /// The bar is displayed at the top.
+    Top,
+    /// The bar is displayed at the bottom.
+    Bottom,
+    /// The bar is displayed on the left.
+    Left,
+    /// The bar is displayed on the right.
+    Right,
+}
+
+impl BarPosition {
+    fn as_str(&self) -> &'static str {
+        match self {
+            BarPosition::Top => "top",
+            BarPosition::Bottom => "bottom",
+            BarPosition::Left => "left",
+            BarPosition::Right => "right",
+        }
+    }
+}
+
+/// How the items of a bar are laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarFilling {
+    /// The items are displayed on a single line.
+    Horizontal,
+    /// Every item is displayed on its own line.
+    Vertical,
+    /// The items are displayed in columns, the columns are filled line by
+    /// line.
+    ColumnsHorizontal,
+    /// The items are displayed in columns, the columns are filled column by
+    /// column.
+    ColumnsVertical,
+}
+
+impl BarFilling {
+    fn as_str(&self) -> &'static str {
+        match self {
+            BarFilling::Horizontal => "horizontal",
+            BarFilling::Vertical => "vertical",
+            BarFilling::ColumnsHorizontal => "columns_horizontal",
+            BarFilling::ColumnsVertical => "columns_vertical",
+        }
+    }
+}
+
+/// Error that is returned if a bar couldn't be created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BarError {
+    /// A bar with the given name already exists.
+    AlreadyExists(String),
+    /// Weechat refused to create the bar, e.g. because a color or the
+    /// condition was invalid.
+    CreationFailed(String),
+}
+
+impl std::fmt::Display for BarError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BarError::AlreadyExists(name) => {
+                write!(f, "a bar with the name {:?} already exists", name)
+            }
+            BarError::CreationFailed(name) => {
+                write!(f, "the bar {:?} couldn't be created", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BarError {}
+
+/// Error that is returned if a bar item couldn't be created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BarItemError {
+    /// Weechat refused to create the bar item, e.g. because the plugin
+    /// already has an item with the same name.
+    CreationFailed(String),
+}
+
+impl std::fmt::Display for BarItemError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BarItemError::CreationFailed(name) => {
+                write!(f, "the bar item {:?} couldn't be created", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BarItemError {}
+
+/// Settings for a new bar.
+///
+/// # Example
+///
+/// ```ignore
+/// let settings = BarSettings::new("channels")
+///     .bar_type(BarType::Root)
+///     .position(BarPosition::Left)
+///     .filling_left_right(BarFilling::Vertical)
+///     .size(20)
+///     .items(&["channels"]);
+///
+/// let bar = weechat.new_bar(settings)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct BarSettings {
+    name: String,
+    hidden: bool,
+    priority: i32,
+    bar_type: BarType,
+    condition: String,
+    position: BarPosition,
+    filling_top_bottom: BarFilling,
+    filling_left_right: BarFilling,
+    size: u32,
+    size_max: u32,
+    color_fg: String,
+    color_delim: String,
+    color_bg: String,
+    color_bg_inactive: String,
+    separator: bool,
+    items: Vec<String>,
+}
+
+impl BarSettings {
+    /// Create new settings for a bar with the given name.
+    ///
+    /// The bar defaults to a visible window bar at the top that uses the
+    /// default colors and has no items.
+    pub fn new<N: Into<String>>(name: N) -> Self {
+        BarSettings {
+            name: name.into(),
+            hidden: false,
+            priority: 0,
+            bar_type: BarType::Window,
+            condition: String::new(),
+            position: BarPosition::Top,
+            filling_top_bottom: BarFilling::Horizontal,
+            filling_left_right: BarFilling::Vertical,
+            size: 0,
+            size_max: 0,
+            color_fg: "default".to_owned(),
+            color_delim: "default".to_owned(),
+            color_bg: "default".to_owned(),
+            color_bg_inactive: "default".to_owned(),
+            separator: false,
+            items: Vec::new(),
+        }
+    }
+
+    /// Should the bar be hidden when it is created.
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    /// Set the priority of the bar, bars with a higher priority are
+    /// displayed closer to the edge of the screen.
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Set the type of the bar.
+    pub fn bar_type(mut self, bar_type: BarType) -> Self {
+        self.bar_type = bar_type;
+        self
+    }
+
+    /// Set the condition that decides if the bar is displayed, e.g.
+    /// `"active"` or an evaluated expression like `"${buffer.number} == 1"`.
+    pub fn condition<C: Into<String>>(mut self, condition: C) -> Self {
+        self.condition = condition.into();
+        self
+    }
+
+    /// Set the position of the bar.
+    pub fn position(mut self, position: BarPosition) -> Self {
+        self.position = position;
+        self
+    }
+
+    /// Set the filling of the bar if it's at the top or the bottom.
+    pub fn filling_top_bottom(mut self, filling: BarFilling) -> Self {
+        self.filling_top_bottom = filling;
+        self
+    }
+
+    /// Set the filling of the bar if it's on the left or the right.
+    pub fn filling_left_right(mut self, filling: BarFilling) -> Self {
+        self.filling_left_right = filling;
+        self
+    }
+
+    /// Set the size of the bar, in lines for bars at the top or the bottom
+    /// and in characters for bars on the left or the right. A size of `0`
+    /// fits the bar to its content.
+    pub fn size(mut self, size: u32) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Set the maximum size of the bar if it fits itself to its content, `0`
+    /// means there is no maximum.
+    pub fn size_max(mut self, size_max: u32) -> Self {
+        self.size_max = size_max;
+        self
+    }
+
+    /// Set the color of the text of the bar.
+    pub fn color_fg<C: Into<String>>(mut self, color: C) -> Self {
+        self.color_fg = color.into();
+        self
+    }
+
+    /// Set the color of the delimiters of the bar.
+    pub fn color_delim<C: Into<String>>(mut self, color: C) -> Self {
+        self.color_delim = color.into();
+        self
+    }
+
+    /// Set the background color of the bar.
+    pub fn color_bg<C: Into<String>>(mut self, color: C) -> Self {
+        self.color_bg = color.into();
+        self
+    }
+
+    /// Set the background color of the bar if it's displayed in a window
+    /// that isn't the current one.
+    pub fn color_bg_inactive<C: Into<String>>(mut self, color: C) -> Self {
+        self.color_bg_inactive = color.into();
+        self
+    }
+
+    /// Should a separator line be displayed between the bar and the rest of
+    /// the screen.
+    pub fn separator(mut self, separator: bool) -> Self {
+        self.separator = separator;
+        self
+    }
+
+    /// Set the items of the bar.
+    ///
+    /// Every entry is displayed as its own item, multiple item names can be
+    /// glued together in a single entry using `+`, e.g. `"buffer_name+lag"`.
+    pub fn items<I: AsRef<str>>(mut self, items: &[I]) -> Self {
+        self.items = items.iter().map(|i| i.as_ref().to_owned()).collect();
+        self
+    }
+}
+
+/// A builder for the items of a bar.
+///
+/// Items that are added using [`BarItems::item`] are separated by a space
+/// when the bar is displayed, items that are added using
+/// [`BarItems::glued`] are displayed right after the previous item.
+///
+/// Whether the bar is displayed at all is decided by the condition of the
+/// bar, see [`BarSettings::condition`].
+///
+/// # Example
+///
+/// ```ignore
+/// // Builds the items "[buffer_name]+unread", "spacer" and "lag".
+/// let items = BarItems::new()
+///     .delimited("buffer_name")
+///     .glued("unread")
+///     .spacer()
+///     .item("lag");
+///
+/// let settings = BarSettings::new("status").items(items.as_slice());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BarItems {
+    items: Vec<String>,
+}
+
+impl BarItems {
+    /// Create an empty list of bar items.
+    pub fn new() -> Self {
+        BarItems::default()
+    }
+
+    /// Add an item, it's separated from the previous item by a space.
+    pub fn item<N: AsRef<str>>(mut self, name: N) -> Self {
+        self.items.push(name.as_ref().to_owned());
+        self
+    }
+
+    /// Add an item that is displayed right after the previous item, without
+    /// a space between them.
+    ///
+    /// The item is added as a normal item if it's the first one.
+    pub fn glued<N: AsRef<str>>(mut self, name: N) -> Self {
+        match self.items.last_mut() {
+            Some(last) => {
+                last.push('+');
+                last.push_str(name.as_ref());
+            }
+            None => self.items.push(name.as_ref().to_owned()),
+        }
+
+        self
+    }
+
+    /// Add an item that is surrounded by `[` and `]`, the brackets are
+    /// displayed using the delimiter color of the bar.
+    pub fn delimited<N: AsRef<str>>(self, name: N) -> Self {
+        self.item(format!("[{}]", name.as_ref()))
+    }
+
+    /// Add an item that is always displayed for the given buffer, no matter
+    /// which buffer is displayed in the window.
+    #[track_caller]
+    pub fn for_buffer<N: AsRef<str>>(self, buffer: &Buffer, name: N) -> Self {
+        self.item(buffer_item_name(buffer, name.as_ref()))
+    }
+
+    /// Add a spacer, the spacers of a bar share the space that isn't used by
+    /// other items.
+    ///
+    /// Spacers are supported since Weechat 3.1 and only in bars on the top
+    /// or the bottom with a horizontal filling.
+    pub fn spacer(self) -> Self {
+        self.item("spacer")
+    }
+
+    /// Get the items, the result can be passed to [`BarSettings::items`] or
+    /// [`Bar::set_items`].
+    pub fn as_slice(&self) -> &[String] {
+        &self.items
+    }
+}
+
+impl fmt::Display for BarItems {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.items.join(","))
+    }
+}
+
+#[track_caller]
+fn buffer_item_name(buffer: &Buffer, name: &str) -> String {
+    format!("@{}:{}", buffer.get_full_name(), name)
+}
+
+/// The properties of a bar that can be changed using [`Bar::set`].
+const BAR_PROPERTIES: &[&str] = &[
+    "name",
+    "hidden",
+    "priority",
+    "conditions",
+    "position",
+    "filling_top_bottom",
+    "filling_left_right",
+    "size",
+    "size_max",
+    "color_fg",
+    "color_delim",
+    "color_bg",
+    "color_bg_inactive",
+    "separator",
+    "items",
+];
+
+/// A handle to a bar.
+///
+/// Bars that were created using [`Weechat::new_bar`] are automatically
+/// removed when the object is dropped, bars that were found using
+/// [`Weechat::bar_search`] are left alone.
+pub struct Bar {
+    name: RefCell<String>,
+    ptr: *mut t_gui_bar,
     weechat_ptr: *mut t_weechat_plugin,
+    owned: bool,
+}
+
+impl Drop for Bar {
+    fn drop(&mut self) {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let bar_remove = weechat.get().bar_remove.unwrap();
+
+        // The user might have deleted the bar already using `/bar del`.
+        if self.owned && self.is_valid() {
+            unsafe { bar_remove(self.ptr) };
+        }
+    }
+}
+
+impl Bar {
+    /// Check if the bar still exists.
+    #[track_caller]
+    fn is_valid(&self) -> bool {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+
+        weechat.hdata_contains(
+            cstr!("bar"),
+            cstr!("gui_bars"),
+            self.ptr as *mut c_void,
+        )
+    }
+
+    /// Get the name of the bar.
+    pub fn name(&self) -> String {
+        self.name.borrow().clone()
+    }
+
+    /// Redraw the bar.
+    #[track_caller]
+    pub fn update(&self) {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let bar_update = weechat.get().bar_update.unwrap();
+
+        let name = LossyCString::new(&*self.name.borrow());
+
+        unsafe { bar_update(name.as_ptr()) }
+    }
+
+    /// Set a property of the bar.
+    ///
+    /// Returns `true` if the property was set, `false` if the property is
+    /// unknown, the value is invalid or the bar doesn't exist anymore.
+    ///
+    /// * `property` - The name of the property, e.g. `"hidden"` or `"items"`.
+    ///     The properties match the settings of [`BarSettings`], the
+    ///     condition is called `"conditions"`.
+    /// * `value` - The new value of the property.
+    #[track_caller]
+    pub fn set(&self, property: &str, value: &str) -> bool {
+        if !BAR_PROPERTIES.contains(&property) || !self.is_valid() {
+            return false;
+        }
+
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let bar_set = weechat.get().bar_set.unwrap();
+
+        let c_property = LossyCString::new(property);
+        let c_value = LossyCString::new(value);
+
+        let ret =
+            unsafe { bar_set(self.ptr, c_property.as_ptr(), c_value.as_ptr()) };
+
+        if ret != 1 {
+            return false;
+        }
+
+        if property == "name" {
+            *self.name.borrow_mut() = value.to_owned();
+        }
+
+        true
+    }
+
+    /// Hide the bar.
+    pub fn hide(&self) -> bool {
+        self.set("hidden", "on")
+    }
+
+    /// Show the bar if it's hidden.
+    pub fn show(&self) -> bool {
+        self.set("hidden", "off")
+    }
+
+    /// Hide the bar if it's displayed, show it otherwise.
+    pub fn toggle(&self) -> bool {
+        self.set("hidden", "toggle")
+    }
+
+    /// Replace the items of the bar.
+    ///
+    /// * `items` - The new items, see [`BarSettings::items`].
+    pub fn set_items<I: AsRef<str>>(&self, items: &[I]) -> bool {
+        let items: Vec<&str> = items.iter().map(|i| i.as_ref()).collect();
+        self.set("items", &items.join(","))
+    }
+
+    /// Remove the bar.
+    ///
+    /// This removes the bar even if it wasn't created by the plugin.
+    pub fn remove(mut self) {
+        self.owned = true;
+    }
 }
 
-/// A handle to a bar item. The bar item is automatically removed when the object is
-/// dropped.
-pub struct BarItem<T> {
-    item: LightBarItem,
-    _data: Box<BarItemCbData<T>>,
+type BarItemCallback = Box<
+    dyn FnMut(
+        &Weechat,
+        Option<Window>,
+        Option<Buffer>,
+        &HashMap<String, String>,
+    ) -> String,
+>;
+
+struct BarItemCbData {
+    callback: BarItemCallback,
+    weechat_ptr: *mut t_weechat_plugin,
 }
 
-/// A handle to a bar item that is passed to callbacks.
-pub struct LightBarItem {
+/// A handle to a bar item. The bar item is automatically removed when the
+/// object is dropped.
+pub struct BarItem {
+    name: String,
     ptr: *mut t_gui_bar_item,
     weechat_ptr: *mut t_weechat_plugin,
+    _data: Box<BarItemCbData>,
 }
 
-impl<T> Drop for BarItem<T> {
+impl Drop for BarItem {
     fn drop(&mut self) {
-        let weechat = Weechat::from_ptr(self.item.weechat_ptr);
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
         let bar_item_remove = weechat.get().bar_item_remove.unwrap();
-        unsafe { bar_item_remove(self.item.ptr) };
+        unsafe { bar_item_remove(self.ptr) };
+    }
+}
+
+impl BarItem {
+    /// Get the name of the bar item.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Update the bar item, its callback will be called to build the new
+    /// content of the bar item.
+    #[track_caller]
+    pub fn update(&self) {
+        Weechat::from_ptr(self.weechat_ptr).update_bar_item(&self.name);
+    }
+
+    /// Get a reference to the bar item that is always displayed for the
+    /// given buffer, no matter which buffer is displayed in the window.
+    ///
+    /// The reference can be used as one of the items of a bar, the callback
+    /// of the bar item then receives the given buffer.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let settings = BarSettings::new("status")
+    ///     .items(&[item.for_buffer(&core_buffer)]);
+    /// ```
+    #[track_caller]
+    pub fn for_buffer(&self, buffer: &Buffer) -> String {
+        buffer_item_name(buffer, &self.name)
     }
 }
 
 impl Weechat {
+    /// Search for a bar by its name.
+    ///
+    /// Returns `None` if no bar with the given name exists. The bar isn't
+    /// removed when the returned object is dropped.
+    ///
+    /// * `name` - The name of the bar, e.g. `"nicklist"`.
+    #[track_caller]
+    pub fn bar_search(&self, name: &str) -> Option<Bar> {
+        let bar_search = self.get().bar_search.unwrap();
+
+        let c_name = LossyCString::new(name);
+
+        let ptr = unsafe { bar_search(c_name.as_ptr()) };
+
+        if ptr.is_null() {
+            None
+        } else {
+            Some(Bar {
+                name: RefCell::new(name.to_owned()),
+                ptr,
+                weechat_ptr: self.ptr,
+                owned: false,
+            })
+        }
+    }
+
+    /// Create a new bar.
+    ///
+    /// Returns an error if a bar with the same name already exists or if
+    /// Weechat couldn't create the bar.
+    ///
+    /// * `settings` - Settings that decide how the bar looks like.
+    #[track_caller]
+    pub fn new_bar(&self, settings: BarSettings) -> Result<Bar, BarError> {
+        if self.bar_search(&settings.name).is_some() {
+            return Err(BarError::AlreadyExists(settings.name));
+        }
+
+        let bar_new = self.get().bar_new.unwrap();
+
+        let bool_str = |value: bool| if value { "on" } else { "off" };
+
+        let name = LossyCString::new(&settings.name);
+        let hidden = LossyCString::new(bool_str(settings.hidden));
+        let priority = LossyCString::from_string(settings.priority.to_string());
+        let bar_type = LossyCString::new(settings.bar_type.as_str());
+        let condition = LossyCString::new(&settings.condition);
+        let position = LossyCString::new(settings.position.as_str());
+        let filling_top_bottom =
+            LossyCString::new(settings.filling_top_bottom.as_str());
+        let filling_left_right =
+            LossyCString::new(settings.filling_left_right.as_str());
+        let size = LossyCString::from_string(settings.size.to_string());
+        let size_max = LossyCString::from_string(settings.size_max.to_string());
+        let color_fg = LossyCString::new(&settings.color_fg);
+        let color_delim = LossyCString::new(&settings.color_delim);
+        let color_bg = LossyCString::new(&settings.color_bg);
+        let color_bg_inactive = LossyCString::new(&settings.color_bg_inactive);
+        let separator = LossyCString::new(bool_str(settings.separator));
+        let items = LossyCString::new(settings.items.join(","));
+
+        let ptr = unsafe {
+            bar_new(
+                name.as_ptr(),
+                hidden.as_ptr(),
+                priority.as_ptr(),
+                bar_type.as_ptr(),
+                condition.as_ptr(),
+                position.as_ptr(),
+                filling_top_bottom.as_ptr(),
+                filling_left_right.as_ptr(),
+                size.as_ptr(),
+                size_max.as_ptr(),
+                color_fg.as_ptr(),
+                color_delim.as_ptr(),
+                color_bg.as_ptr(),
+                color_bg_inactive.as_ptr(),
+                separator.as_ptr(),
+                items.as_ptr(),
+            )
+        };
+
+        if ptr.is_null() {
+            Err(BarError::CreationFailed(settings.name))
+        } else {
+            Ok(Bar {
+                name: RefCell::new(settings.name),
+                ptr,
+                weechat_ptr: self.ptr,
+                owned: true,
+            })
+        }
+    }
+
     /// Create a new bar item that can be added by a user.
-    // TODO: Provide window object, the callback should accept a Window object wrapping a t_gui_window
-    pub fn new_bar_item<T>(
+    ///
+    /// Returns an error if Weechat refused to create the item, e.g. because
+    /// the plugin already has an item with the same name.
+    ///
+    /// * `name` - The name of the bar item.
+    /// * `callback` - A function that will be called when the content of the
+    ///     bar item needs to be built, it receives the window and the buffer
+    ///     the bar item is displayed for as well as extra info that Weechat
+    ///     passes to some items, and returns the content. The buffer is the
+    ///     one that is displayed in the window unless the item was added for
+    ///     a specific buffer using [`BarItem::for_buffer`]. Items of root
+    ///     bars receive neither a window nor a buffer, see
+    ///     [`Weechat::bar_item_target`] to fall back to the current window.
+    ///
+    /// The callback is called separately for every window that displays a
+    /// bar containing the item, so the content can differ per window and
+    /// buffer without keeping track of the buffers in the plugin. Updating
+    /// the bar item using [`BarItem::update`] or [`Weechat::update_bar_item`]
+    /// rebuilds the content for all windows and bars at once, there is no way
+    /// to refresh the item for a single buffer.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let item = weechat.new_bar_item("buffer_lines", |_, _, buffer, _| {
+    ///     let buffer = match buffer {
+    ///         Some(buffer) => buffer,
+    ///         None => return String::new(),
+    ///     };
+    ///
+    ///     let lines = buffer
+    ///         .get_hdata("buffer")
+    ///         .and_then(|hdata| hdata.get_var::<HDataPointer>("own_lines"))
+    ///         .and_then(|lines| lines.get_hdata("lines"))
+    ///         .and_then(|hdata| hdata.get_var::<i32>("lines_count"))
+    ///         .unwrap_or(0);
+    ///
+    ///     format!("{} lines", lines)
+    /// })?;
+    /// ```
+    #[track_caller]
+    pub fn new_bar_item(
         &self,
         name: &str,
-        callback: fn(data: &T, item: &LightBarItem, buffer: &Buffer) -> String,
-        callback_data: Option<T>,
-    ) -> BarItem<T>
-    where
-        T: Default,
-    {
-        unsafe extern "C" fn c_item_cb<T>(
+        callback: impl FnMut(
+                &Weechat,
+                Option<Window>,
+                Option<Buffer>,
+                &HashMap<String, String>,
+            ) -> String
+            + 'static,
+    ) -> Result<BarItem, BarItemError> {
+        unsafe extern "C" fn c_item_cb(
             pointer: *const c_void,
             _data: *mut c_void,
-            bar_item: *mut t_gui_bar_item,
-            _window: *mut t_gui_window,
+            _bar_item: *mut t_gui_bar_item,
+            window: *mut t_gui_window,
             buffer: *mut t_gui_buffer,
-            _extra_info: *mut t_hashtable,
+            extra_info: *mut t_hashtable,
         ) -> *mut c_char {
-            let data: &mut BarItemCbData<T> =
-                { &mut *(pointer as *mut BarItemCbData<T>) };
-            let callback = data.callback;
-            let callback_data = &data.callback_data;
-            let buffer = Buffer::from_ptr(data.weechat_ptr, buffer);
-
-            let item = LightBarItem {
-                ptr: bar_item,
-                weechat_ptr: data.weechat_ptr,
+            let data: &mut BarItemCbData =
+                { &mut *(pointer as *mut BarItemCbData) };
+            let weechat = Weechat::from_ptr(data.weechat_ptr);
+
+            let extra_info = if extra_info.is_null() {
+                HashMap::new()
+            } else {
+                hashtable_to_map(&weechat, extra_info)
+            };
+
+            let window = if window.is_null() {
+                None
+            } else {
+                Some(Window::from_ptr(data.weechat_ptr, window))
             };
 
-            let ret = callback(&callback_data, &item, &buffer);
+            let buffer = if buffer.is_null() {
+                None
+            } else {
+                Some(Buffer::from_ptr(data.weechat_ptr, buffer))
+            };
+
+            let callback = &mut data.callback;
+
+            let ret = crate::catch_panic(data.weechat_ptr, || {
+                callback(&weechat, window, buffer, &extra_info)
+            })
+            .unwrap_or_default();
             // weechat wants malloc'ed string
             libc::strdup(LossyCString::new(ret).as_ptr())
         }
 
-        let data = Box::new(BarItemCbData::<T> {
-            callback,
-            callback_data: callback_data.unwrap_or_default(),
+        let data = Box::new(BarItemCbData {
+            callback: Box::new(callback),
             weechat_ptr: self.ptr,
         });
 
@@ -85,7 +782,7 @@ impl Weechat {
             bar_item_new(
                 self.ptr,
                 bar_item_name.as_ptr(),
-                Some(c_item_cb::<T>),
+                Some(c_item_cb),
                 data_ref as *const _ as *const c_void,
                 ptr::null_mut(),
             )
@@ -93,16 +790,59 @@ impl Weechat {
 
         let hook_data = unsafe { Box::from_raw(data_ref) };
 
-        BarItem {
-            item: LightBarItem {
-                ptr: hook_ptr,
-                weechat_ptr: self.ptr,
-            },
-            _data: hook_data,
+        if hook_ptr.is_null() {
+            return Err(BarItemError::CreationFailed(name.to_owned()));
         }
+
+        Ok(BarItem {
+            name: name.to_owned(),
+            ptr: hook_ptr,
+            weechat_ptr: self.ptr,
+            _data: hook_data,
+        })
+    }
+
+    /// Get the window and the buffer a bar item should be built for.
+    ///
+    /// Items of root bars aren't displayed in a window, their callback
+    /// receives neither a window nor a buffer. This falls back to the
+    /// current window and the buffer that is displayed in it, a window or a
+    /// buffer that was passed to the callback is returned as is.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let item = weechat.new_bar_item(
+    ///     "buffer_short_name",
+    ///     |weechat, window, buffer, _| {
+    ///         match weechat.bar_item_target(window, buffer) {
+    ///             (_, Some(buffer)) => buffer.get_short_name().into_owned(),
+    ///             (_, None) => String::new(),
+    ///         }
+    ///     },
+    /// )?;
+    /// ```
+    #[track_caller]
+    pub fn bar_item_target(
+        &self,
+        window: Option<Window>,
+        buffer: Option<Buffer>,
+    ) -> (Option<Window>, Option<Buffer>) {
+        let window = window.or_else(|| self.current_window());
+
+        let buffer = match buffer {
+            Some(buffer) => Some(buffer),
+            None => window.as_ref().map(|window| window.buffer()),
+        };
+
+        (window, buffer)
     }
 
-    /// Triggers a bar update to update by calling its callback
+    /// Update a bar item, the callback of the bar item will be called to
+    /// build the new content of the bar item.
+    ///
+    /// Nothing happens if no bar item with the given name exists.
+    #[track_caller]
     pub fn update_bar_item(&self, name: &str) {
         let bar_item_update = self.get().bar_item_update.unwrap();
 
@@ -111,3 +851,154 @@ impl Weechat {
         unsafe { bar_item_update(name.as_ptr()) }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::mock::MockWeechat;
+
+    #[test]
+    fn glued_item_on_an_empty_list_is_a_normal_item() {
+        let items = BarItems::new().glued("lag").glued("unread");
+        assert_eq!(items.as_slice(), ["lag+unread"]);
+    }
+
+    #[test]
+    fn delimited_items_are_surrounded_by_brackets() {
+        let items = BarItems::new().delimited("buffer_name").item("lag");
+        assert_eq!(items.as_slice(), ["[buffer_name]", "lag"]);
+    }
+
+    #[test]
+    fn items_for_a_buffer_use_the_full_name() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let buffer =
+            weechat.buffer_new::<(), ()>("room", None, None, None, None);
+
+        let items = BarItems::new().for_buffer(&buffer, "unread").glued("lag");
+
+        assert_eq!(items.as_slice(), ["@mock.room:unread+lag"]);
+    }
+
+    #[test]
+    fn items_are_displayed_separated_by_commas() {
+        assert_eq!(BarItems::new().to_string(), "");
+
+        let items = BarItems::new()
+            .delimited("buffer_name")
+            .glued("unread")
+            .spacer()
+            .item("lag");
+
+        assert_eq!(items.to_string(), "[buffer_name]+unread,spacer,lag");
+    }
+
+    type Targets = Rc<RefCell<Vec<(bool, Option<String>)>>>;
+
+    fn new_item(weechat: &Weechat, targets: &Targets) -> BarItem {
+        let targets = targets.clone();
+
+        weechat
+            .new_bar_item("mock_item", move |_, window, buffer, _| {
+                let name = buffer.as_ref().map(|b| b.get_name().into_owned());
+                targets.borrow_mut().push((window.is_some(), name));
+                "content".to_owned()
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn root_bar_items_get_neither_a_window_nor_a_buffer() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let targets = Targets::default();
+
+        let _item = new_item(&weechat, &targets);
+        weechat.update_bar_item("mock_item");
+
+        assert_eq!(*targets.borrow(), [(false, None)]);
+        assert_eq!(mock.bar_item_content("mock_item").unwrap(), "content");
+    }
+
+    #[test]
+    fn bar_items_get_the_buffer_they_are_displayed_for() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let targets = Targets::default();
+        let buffer =
+            weechat.buffer_new::<(), ()>("room", None, None, None, None);
+
+        let _item = new_item(&weechat, &targets);
+        mock.build_bar_item("mock_item", buffer.ptr);
+
+        assert_eq!(*targets.borrow(), [(false, Some("room".to_owned()))]);
+    }
+
+    #[test]
+    fn bar_item_target_keeps_the_given_buffer() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let buffer =
+            weechat.buffer_new::<(), ()>("room", None, None, None, None);
+
+        // The mock doesn't have a current window to fall back to.
+        let (window, target) = weechat.bar_item_target(None, None);
+        assert!(window.is_none());
+        assert!(target.is_none());
+
+        let (_, target) = weechat.bar_item_target(None, Some(buffer));
+        assert_eq!(target.unwrap().get_name(), "room");
+    }
+
+    #[test]
+    fn bar_items_with_a_taken_name_are_refused() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let targets = Targets::default();
+
+        let _item = new_item(&weechat, &targets);
+        let item =
+            weechat.new_bar_item("mock_item", |_, _, _, _| String::new());
+
+        assert_eq!(
+            item.err(),
+            Some(BarItemError::CreationFailed("mock_item".to_owned()))
+        );
+
+        // The item that was created first is kept.
+        weechat.update_bar_item("mock_item");
+        assert_eq!(mock.bar_item_content("mock_item").unwrap(), "content");
+    }
+
+    #[test]
+    fn updating_a_bar_item_rebuilds_it() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let builds = Rc::new(RefCell::new(0));
+
+        let counter = builds.clone();
+        let item = weechat
+            .new_bar_item("counter", move |_, _, _, _| {
+                *counter.borrow_mut() += 1;
+                counter.borrow().to_string()
+            })
+            .unwrap();
+
+        item.update();
+        item.update();
+
+        assert_eq!(*builds.borrow(), 2);
+        assert_eq!(mock.bar_item_content("counter").unwrap(), "2");
+
+        // Updating a bar item that doesn't exist does nothing.
+        weechat.update_bar_item("missing");
+        drop(item);
+        weechat.update_bar_item("counter");
+
+        assert_eq!(*builds.borrow(), 2);
+    }
+}
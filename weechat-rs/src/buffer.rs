@@ -1,20 +1,30 @@
 //! Weechat Buffer module containing Buffer and Nick types.
-use crate::{LossyCString, Weechat};
+use crate::{callback_return_code, LossyCString, Prefix, ReturnCode, Weechat};
 use libc::{c_char, c_int};
 use std::borrow::Cow;
+use std::cell::Cell;
 use std::ffi::CStr;
 use std::os::raw::c_void;
 use std::ptr;
+use std::rc::Rc;
 use weechat_sys::{
-    t_gui_buffer, t_gui_nick, t_gui_nick_group, t_weechat_plugin, WEECHAT_RC_OK,
+    t_gui_buffer, t_gui_nick, t_gui_nick_group, t_weechat_plugin,
 };
 
 /// A high level Buffer type encapsulating weechats C buffer pointer.
 /// The buffer won't be closed if the object is destroyed.
+///
+/// Buffers created with `Weechat::buffer_new()` notice when they get closed,
+/// e.g. by the user with `/buffer close`. Printing to a closed buffer or
+/// changing it does nothing, getters return empty values. Buffers that are
+/// obtained in other ways, e.g. with `Weechat::buffer_search()`, should not
+/// be kept around, `is_valid()` can be used to check them.
 #[derive(Eq)]
 pub struct Buffer {
     pub(crate) weechat: *mut t_weechat_plugin,
     pub(crate) ptr: *mut t_gui_buffer,
+    /// Set for buffers created by the plugin, cleared by the close callback.
+    pub(crate) valid: Option<Rc<Cell<bool>>>,
 }
 
 impl PartialEq for Buffer {
@@ -29,6 +39,7 @@ pub(crate) struct BufferPointers<A, B> {
     pub(crate) input_data: A,
     pub(crate) close_cb: Option<fn(&B, Buffer)>,
     pub(crate) close_cb_data: B,
+    pub(crate) valid: Rc<Cell<bool>>,
 }
 
 impl Weechat {
@@ -40,6 +51,7 @@ impl Weechat {
     ///     window); if the name starts with (?i), the search is case
     ///     insensitive.
     /// Returns a Buffer if one is found, otherwise None.
+    #[track_caller]
     pub fn buffer_search(
         &self,
         plugin_name: &str,
@@ -61,6 +73,7 @@ impl Weechat {
     }
 
     /// Get the currently open buffer
+    #[track_caller]
     pub fn current(&self) -> Option<Buffer> {
         let buffer_search = self.get().buffer_search.unwrap();
 
@@ -73,6 +86,29 @@ impl Weechat {
         }
     }
 
+    /// Get all the open buffers.
+    /// * `name_mask` - Only return buffers whose full name matches the mask,
+    ///     e.g. `"irc.*"`. The mask may start or end with a `*` wildcard.
+    #[track_caller]
+    pub fn buffers(&self, name_mask: Option<&str>) -> Vec<Buffer> {
+        let mut infolist =
+            match self.infolist_get_with("buffer", None, name_mask) {
+                Some(infolist) => infolist,
+                None => return Vec::new(),
+            };
+
+        let mut buffers = Vec::new();
+
+        while let Some(item) = infolist.next_item() {
+            if let Some(ptr) = item.pointer("pointer") {
+                buffers
+                    .push(Buffer::from_ptr(self.ptr, ptr as *mut t_gui_buffer));
+            }
+        }
+
+        buffers
+    }
+
     /// Create a new Weechat buffer
     /// * `name` - Name of the new buffer
     /// * `input_cb` - Callback that will be called when something is entered
@@ -82,6 +118,7 @@ impl Weechat {
     /// * `close_cb` - Callback that will be called when the buffer is closed.
     /// * `close_cb_data` - Reference to some data that will be passed to the
     ///     close callback.
+    #[track_caller]
     pub fn buffer_new<A: Default, B: Default>(
         &self,
         name: &str,
@@ -101,14 +138,21 @@ impl Weechat {
             let pointers: &mut BufferPointers<A, B> =
                 { &mut *(pointer as *mut BufferPointers<A, B>) };
 
-            let buffer = Buffer::from_ptr(pointers.weechat, buffer);
+            let buffer = Buffer {
+                weechat: pointers.weechat,
+                ptr: buffer,
+                valid: Some(pointers.valid.clone()),
+            };
             let data = &mut pointers.input_data;
 
-            if let Some(callback) = pointers.input_cb {
-                callback(data, buffer, input_data)
+            match pointers.input_cb {
+                Some(callback) => {
+                    callback_return_code(pointers.weechat, |_| {
+                        callback(data, buffer, input_data)
+                    })
+                }
+                None => ReturnCode::Ok.into_raw(),
             }
-
-            WEECHAT_RC_OK
         }
 
         unsafe extern "C" fn c_close_cb<A, B>(
@@ -119,15 +163,31 @@ impl Weechat {
             // We use from_raw() here so that the box get's freed at the end
             // of this scope.
             let pointers = Box::from_raw(pointer as *mut BufferPointers<A, B>);
-            let buffer = Buffer::from_ptr(pointers.weechat, buffer);
+            let buffer = Buffer {
+                weechat: pointers.weechat,
+                ptr: buffer,
+                valid: Some(pointers.valid.clone()),
+            };
             let data = &pointers.close_cb_data;
 
-            if let Some(callback) = pointers.close_cb {
-                callback(data, buffer)
-            }
-            WEECHAT_RC_OK
+            let ret = match pointers.close_cb {
+                Some(callback) => {
+                    callback_return_code(pointers.weechat, |_| {
+                        callback(data, buffer)
+                    })
+                }
+                None => ReturnCode::Ok.into_raw(),
+            };
+
+            // Mark all the handles of the buffer as dead, the pointer will
+            // be freed once we return.
+            pointers.valid.set(false);
+
+            ret
         }
 
+        let valid = Rc::new(Cell::new(true));
+
         // We create a box and use leak to stop rust from freeing our data,
         // we are giving weechat ownership over the data and will free it in
         // the buffer close callback.
@@ -137,6 +197,7 @@ impl Weechat {
             input_data: input_data.unwrap_or_default(),
             close_cb,
             close_cb_data: close_cb_data.unwrap_or_default(),
+            valid: valid.clone(),
         });
         let buffer_pointers_ref: &BufferPointers<A, B> =
             Box::leak(buffer_pointers);
@@ -166,6 +227,7 @@ impl Weechat {
         Buffer {
             weechat: self.ptr,
             ptr: buf_ptr,
+            valid: Some(valid),
         }
     }
 }
@@ -178,6 +240,21 @@ pub(crate) type WeechatInputCbT = unsafe extern "C" fn(
 ) -> c_int;
 
 /// Nick creation arguments
+///
+/// Replaced by the `NickSettings` builder, `NickArgs` can still be passed to
+/// `Buffer::add_nick()` since it converts into `NickSettings`. To migrate
+/// replace
+///
+/// ```ignore
+/// NickArgs { name: "Emma", color: "magenta", ..Default::default() }
+/// ```
+///
+/// with
+///
+/// ```ignore
+/// NickSettings::new("Emma").color("magenta")
+/// ```
+#[deprecated(note = "Use NickSettings instead.")]
 pub struct NickArgs<'a> {
     /// Name of the new nick.
     pub name: &'a str,
@@ -191,27 +268,108 @@ pub struct NickArgs<'a> {
     pub visible: bool,
 }
 
+/// Settings for a new nick, used with `Buffer::add_nick()`.
+///
+/// # Example
+///
+/// ```ignore
+/// let settings = NickSettings::new("Emma")
+///     .color("magenta")
+///     .prefix("&")
+///     .prefix_color("green");
+///
+/// let nick = buffer.add_nick(settings, Some(&group));
+/// ```
+#[derive(Debug, Clone)]
+pub struct NickSettings {
+    name: String,
+    color: String,
+    prefix: String,
+    prefix_color: String,
+    visible: bool,
+}
+
+impl NickSettings {
+    /// Create new settings for a nick with the given name.
+    ///
+    /// The nick defaults to a visible nick with the default color and no
+    /// prefix.
+    pub fn new<N: Into<String>>(name: N) -> Self {
+        NickSettings {
+            name: name.into(),
+            color: String::new(),
+            prefix: String::new(),
+            prefix_color: String::new(),
+            visible: true,
+        }
+    }
+
+    /// Set the color of the nick.
+    pub fn color<C: Into<String>>(mut self, color: C) -> Self {
+        self.color = color.into();
+        self
+    }
+
+    /// Set the prefix that will be shown before the name, e.g. `"@"`.
+    pub fn prefix<P: Into<String>>(mut self, prefix: P) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Set the color of the prefix.
+    pub fn prefix_color<C: Into<String>>(mut self, color: C) -> Self {
+        self.prefix_color = color.into();
+        self
+    }
+
+    /// Should the nick be visible in the nicklist.
+    pub fn visible(mut self, visible: bool) -> Self {
+        self.visible = visible;
+        self
+    }
+}
+
+#[allow(deprecated)]
+impl From<NickArgs<'_>> for NickSettings {
+    #[track_caller]
+    fn from(args: NickArgs<'_>) -> Self {
+        NickSettings::new(args.name)
+            .color(args.color)
+            .prefix(args.prefix)
+            .prefix_color(args.prefix_color)
+            .visible(args.visible)
+    }
+}
+
 /// Weechat Nick type
+///
+/// A nick notices if its buffer was closed, if the buffer was created by the
+/// plugin. Getters of such a nick return empty values and removing it does
+/// nothing.
 pub struct Nick {
-    ptr: *mut t_gui_nick,
+    pub(crate) ptr: *mut t_gui_nick,
     buf_ptr: *mut t_gui_buffer,
-    weechat_ptr: *mut t_weechat_plugin,
+    pub(crate) weechat_ptr: *mut t_weechat_plugin,
+    buffer_valid: Option<Rc<Cell<bool>>>,
 }
 
 impl Nick {
-    /// Create a high level Nick object from C nick and buffer pointers.
-    pub(crate) fn from_ptr(
-        ptr: *mut t_gui_nick,
-        buf_ptr: *mut t_gui_buffer,
-        weechat_ptr: *mut t_weechat_plugin,
-    ) -> Nick {
+    /// Create a high level Nick object from a C nick pointer and the buffer
+    /// the nick belongs to.
+    pub(crate) fn from_ptr(ptr: *mut t_gui_nick, buffer: &Buffer) -> Nick {
         Nick {
             ptr,
-            buf_ptr,
-            weechat_ptr,
+            buf_ptr: buffer.ptr,
+            weechat_ptr: buffer.weechat,
+            buffer_valid: buffer.valid.clone(),
         }
     }
 
+    /// Check if the buffer of the nick is known to be closed.
+    pub(crate) fn buffer_closed(&self) -> bool {
+        matches!(&self.buffer_valid, Some(valid) if !valid.get())
+    }
+
     /// Get a Weechat object out of the nick.
     fn get_weechat(&self) -> Weechat {
         Weechat::from_ptr(self.weechat_ptr)
@@ -221,7 +379,12 @@ impl Nick {
     /// * `property` - The name of the property to get the value for, this can
     ///     be one of name, color, prefix or prefix_color. If a unknown
     ///     property is requested an empty string is returned.
+    #[track_caller]
     pub fn get_string(&self, property: &str) -> Option<Cow<str>> {
+        if self.buffer_closed() {
+            return None;
+        }
+
         let weechat = self.get_weechat();
         let get_string = weechat.get().nicklist_nick_get_string.unwrap();
         let c_property = LossyCString::new(property);
@@ -237,12 +400,101 @@ impl Nick {
     }
 
     /// Get the name property of the nick.
+    #[track_caller]
     pub fn get_name(&self) -> Cow<str> {
-        self.get_string("name").unwrap()
+        self.get_string("name").unwrap_or_default()
+    }
+
+    /// Get the color of the nick.
+    #[track_caller]
+    pub fn color(&self) -> Option<Cow<'_, str>> {
+        self.get_string("color")
+    }
+
+    /// Get the prefix of the nick, e.g. `"@"` for operators.
+    #[track_caller]
+    pub fn prefix(&self) -> Option<Cow<'_, str>> {
+        self.get_string("prefix")
+    }
+
+    /// Get the color of the prefix of the nick.
+    #[track_caller]
+    pub fn prefix_color(&self) -> Option<Cow<'_, str>> {
+        self.get_string("prefix_color")
+    }
+
+    /// Check if the nick is still part of the nicklist of its buffer.
+    ///
+    /// The nick becomes invalid if it, or the group it belongs to, was
+    /// removed, or if its buffer was closed. This walks the whole nicklist,
+    /// in debug builds removing a nick that isn't valid anymore panics.
+    #[track_caller]
+    pub fn is_valid(&self) -> bool {
+        if self.buffer_closed() {
+            return false;
+        }
+
+        let weechat = self.get_weechat();
+        let nicklist_get_next_item =
+            weechat.get().nicklist_get_next_item.unwrap();
+
+        let mut group: *mut t_gui_nick_group = ptr::null_mut();
+        let mut nick: *mut t_gui_nick = ptr::null_mut();
+
+        loop {
+            unsafe {
+                nicklist_get_next_item(self.buf_ptr, &mut group, &mut nick)
+            };
+
+            if group.is_null() && nick.is_null() {
+                return false;
+            }
+
+            if nick == self.ptr {
+                return true;
+            }
+        }
+    }
+
+    #[track_caller]
+    fn debug_check_valid(&self) {
+        debug_assert!(
+            self.is_valid(),
+            "The nick was removed from the nicklist"
+        );
     }
 
     /// Removes the nick from it's nicklist
-    pub fn remove(&self) {
+    ///
+    /// The nick is consumed, so it can't be used after it was removed:
+    ///
+    /// ```compile_fail
+    /// fn use_after_remove(nick: weechat::Nick) {
+    ///     nick.remove();
+    ///     nick.get_name();
+    /// }
+    /// ```
+    #[track_caller]
+    pub fn remove(self) {
+        self.remove_from_nicklist()
+    }
+
+    /// Removes the nick from it's nicklist, without consuming the nick.
+    #[deprecated(note = "Use remove() instead, which consumes the nick.")]
+    #[track_caller]
+    pub fn remove_by_ref(&self) {
+        self.remove_from_nicklist()
+    }
+
+    #[track_caller]
+    fn remove_from_nicklist(&self) {
+        // The nicklist was freed together with the buffer.
+        if self.buffer_closed() {
+            return;
+        }
+
+        self.debug_check_valid();
+
         let weechat = self.get_weechat();
 
         let nicklist_remove_nick = weechat.get().nicklist_remove_nick.unwrap();
@@ -256,9 +508,34 @@ impl Nick {
 /// Weechat nicklist Group type.
 pub struct NickGroup {
     pub(crate) ptr: *mut t_gui_nick_group,
-    _buf_ptr: *mut t_gui_buffer,
+    buf_ptr: *mut t_gui_buffer,
+    weechat_ptr: *mut t_weechat_plugin,
+    buffer_valid: Option<Rc<Cell<bool>>>,
+}
+
+impl NickGroup {
+    /// Removes the group from its nicklist, the nicks and groups inside of
+    /// the group are removed as well.
+    ///
+    /// Nick objects of the removed nicks can't be used anymore.
+    #[track_caller]
+    pub fn remove(self) {
+        if matches!(&self.buffer_valid, Some(valid) if !valid.get()) {
+            return;
+        }
+
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+
+        let nicklist_remove_group =
+            weechat.get().nicklist_remove_group.unwrap();
+
+        unsafe {
+            nicklist_remove_group(self.buf_ptr, self.ptr);
+        }
+    }
 }
 
+#[allow(deprecated)]
 impl<'a> Default for NickArgs<'a> {
     fn default() -> NickArgs<'a> {
         NickArgs {
@@ -301,18 +578,63 @@ impl Buffer {
         Buffer {
             weechat: weechat_ptr,
             ptr: buffer_ptr,
+            valid: None,
+        }
+    }
+
+    /// Check if the buffer is still open.
+    ///
+    /// Buffers created by the plugin know if they were closed, for other
+    /// buffers the pointer is checked against the list of open buffers.
+    #[track_caller]
+    pub fn is_valid(&self) -> bool {
+        match &self.valid {
+            Some(valid) => valid.get(),
+            None => self.pointer_is_valid(),
         }
     }
 
+    /// Check if the buffer is known to be closed, this only catches buffers
+    /// that were created by the plugin.
+    pub(crate) fn is_closed(&self) -> bool {
+        matches!(&self.valid, Some(valid) if !valid.get())
+    }
+
+    #[track_caller]
+    fn pointer_is_valid(&self) -> bool {
+        let weechat = Weechat::from_ptr(self.weechat);
+
+        weechat.hdata_contains(
+            cstr!("buffer"),
+            cstr!("gui_buffers"),
+            self.ptr as *mut c_void,
+        )
+    }
+
     /// Create a sealed buffer.
     ///
     /// This prevents access to the buffer but allows it to be passed
-    /// between threads until it is unsealed.
+    /// between threads until it is unsealed. Unsealing fails if the buffer
+    /// was closed in the meantime.
+    #[track_caller]
     pub fn seal(&self) -> crate::Sealed<Self> {
-        crate::Sealed(Buffer {
-            weechat: self.weechat,
-            ptr: self.ptr,
-        })
+        #[track_caller]
+        fn check(buffer: &Buffer, _: &Weechat, full_name: &str) -> bool {
+            // The pointer might have been reused for a new buffer.
+            buffer.pointer_is_valid() && buffer.get_full_name() == full_name
+        }
+
+        // The validity flag can't be shared with other threads, the sealed
+        // buffer is checked using its pointer instead.
+        crate::Sealed::new(
+            Buffer {
+                weechat: self.weechat,
+                ptr: self.ptr,
+                valid: None,
+            },
+            self.get_full_name().into_owned(),
+            check,
+        )
     }
 
     /// Get the Weechat plugin object from a Buffer object.
@@ -321,11 +643,16 @@ impl Buffer {
     }
 
     /// Display a message on the buffer.
+    #[track_caller]
     pub fn print(&self, message: &str) {
+        if self.is_closed() {
+            return;
+        }
+
         let weechat = Weechat::from_ptr(self.weechat);
         let printf_date_tags = weechat.get().printf_date_tags.unwrap();
 
-        let fmt_str = LossyCString::new("%s");
+        let fmt_str = cstr!("%s");
         let c_message = LossyCString::new(message);
 
         unsafe {
@@ -339,12 +666,25 @@ impl Buffer {
         }
     }
 
+    /// Display an error message on the buffer, the message is prefixed with
+    /// the error prefix.
+    #[track_caller]
+    pub fn print_error(&self, message: &str) {
+        let weechat = Weechat::from_ptr(self.weechat);
+        self.print(&format!("{}{}", weechat.prefix(Prefix::Error), message));
+    }
+
     /// Display a message on the buffer with attached date and tags
+    #[track_caller]
     pub fn print_tags_dated(&self, date: i64, tags: &str, message: &str) {
+        if self.is_closed() {
+            return;
+        }
+
         let weechat = Weechat::from_ptr(self.weechat);
         let printf_date_tags = weechat.get().printf_date_tags.unwrap();
 
-        let fmt_str = LossyCString::new("%s");
+        let fmt_str = cstr!("%s");
         let tags = LossyCString::new(tags);
         let message = LossyCString::new(message);
 
@@ -360,7 +700,12 @@ impl Buffer {
     }
 
     /// Search for a nicklist group by name
+    #[track_caller]
     pub fn search_nicklist_group(&self, name: &str) -> Option<NickGroup> {
+        if self.is_closed() {
+            return None;
+        }
+
         let weechat = Weechat::from_ptr(self.weechat);
 
         let nicklist_search_group =
@@ -375,20 +720,22 @@ impl Buffer {
             if group.is_null() {
                 None
             } else {
-                Some(NickGroup {
-                    ptr: group,
-                    _buf_ptr: self.ptr,
-                })
+                Some(self.nick_group(group))
             }
         }
     }
 
     /// Search for a nick in a group
+    #[track_caller]
     pub fn search_nick(
         &self,
         nick: &str,
         group: Option<&NickGroup>,
     ) -> Option<Nick> {
+        if self.is_closed() {
+            return None;
+        }
+
         let weechat = Weechat::from_ptr(self.weechat);
 
         let nicklist_search_nick = weechat.get().nicklist_search_nick.unwrap();
@@ -402,7 +749,7 @@ impl Buffer {
             if nick.is_null() {
                 None
             } else {
-                Some(Nick::from_ptr(nick, self.ptr, self.weechat))
+                Some(Nick::from_ptr(nick, self))
             }
         }
     }
@@ -411,18 +758,31 @@ impl Buffer {
     /// created nick.
     /// The nick won't be removed from the nicklist if the returned nick is
     /// dropped.
-    /// * `nick` - Nick arguments struct for the nick that should be added.
+    /// * `nick` - Settings for the nick that should be added.
     /// * `group` - Nicklist group that the nick should be added to. If no
     ///     group is provided the nick is added to the root group.
-    pub fn add_nick(&self, nick: NickArgs, group: Option<&NickGroup>) -> Nick {
+    ///
+    /// Returns `None` if the nick couldn't be added, e.g. because the
+    /// buffer was closed or it already contains a nick with the same name.
+    #[track_caller]
+    pub fn add_nick(
+        &self,
+        nick: impl Into<NickSettings>,
+        group: Option<&NickGroup>,
+    ) -> Option<Nick> {
+        if self.is_closed() {
+            return None;
+        }
+
         let weechat = Weechat::from_ptr(self.weechat);
+        let nick = nick.into();
 
         // TODO this conversions can fail if any of those strings contain a
         // null byte.
-        let c_nick = LossyCString::new(nick.name);
-        let color = LossyCString::new(nick.color);
-        let prefix = LossyCString::new(nick.prefix);
-        let prefix_color = LossyCString::new(nick.prefix_color);
+        let c_nick = LossyCString::new(&nick.name);
+        let color = LossyCString::new(&nick.color);
+        let prefix = LossyCString::new(&nick.prefix);
+        let prefix_color = LossyCString::new(&nick.prefix_color);
         let add_nick = weechat.get().nicklist_add_nick.unwrap();
 
         let group_ptr = match group {
@@ -442,7 +802,11 @@ impl Buffer {
             )
         };
 
-        Nick::from_ptr(nick_ptr, self.ptr, self.weechat)
+        if nick_ptr.is_null() {
+            None
+        } else {
+            Some(Nick::from_ptr(nick_ptr, self))
+        }
     }
 
     /// Create and add a new nicklist group to the buffers nicklist.
@@ -451,15 +815,22 @@ impl Buffer {
     /// * `visible` - Should the group be visible in the nicklist.
     /// * `parent_group` - Parent group that the group should be added to.
     ///     If no group is provided the group is added to the root group.
-    /// Returns the new nicklist group. The group is not removed if the object
-    /// is dropped.
+    ///
+    /// Returns the new nicklist group, or `None` if the group couldn't be
+    /// added, e.g. because the buffer was closed. The group is not removed
+    /// if the object is dropped.
+    #[track_caller]
     pub fn add_group(
         &self,
         name: &str,
         color: &str,
         visible: bool,
         parent_group: Option<&NickGroup>,
-    ) -> NickGroup {
+    ) -> Option<NickGroup> {
+        if self.is_closed() {
+            return None;
+        }
+
         let weechat = Weechat::from_ptr(self.weechat);
         let add_group = weechat.get().nicklist_add_group.unwrap();
 
@@ -481,27 +852,44 @@ impl Buffer {
             )
         };
 
+        if group_ptr.is_null() {
+            None
+        } else {
+            Some(self.nick_group(group_ptr))
+        }
+    }
+
+    fn nick_group(&self, ptr: *mut t_gui_nick_group) -> NickGroup {
         NickGroup {
-            ptr: group_ptr,
-            _buf_ptr: self.ptr,
+            ptr,
+            buf_ptr: self.ptr,
+            weechat_ptr: self.weechat,
+            buffer_valid: self.valid.clone(),
         }
     }
 
-    fn set(&self, property: &str, value: &str) {
+    fn set(&self, property: &CStr, value: &str) {
+        if self.is_closed() {
+            return;
+        }
+
         let weechat = Weechat::from_ptr(self.weechat);
 
         let buffer_set = weechat.get().buffer_set.unwrap();
-        let option = LossyCString::new(property);
         let value = LossyCString::new(value);
 
-        unsafe { buffer_set(self.ptr, option.as_ptr(), value.as_ptr()) };
+        unsafe { buffer_set(self.ptr, property.as_ptr(), value.as_ptr()) };
     }
 
-    fn get_string(&self, property: &str) -> Option<Cow<str>> {
+    #[track_caller]
+    fn get_string(&self, property: &CStr) -> Option<Cow<str>> {
+        if self.is_closed() {
+            return None;
+        }
+
         let weechat = Weechat::from_ptr(self.weechat);
 
         let buffer_get = weechat.get().buffer_get_string.unwrap();
-        let property = LossyCString::new(property);
 
         unsafe {
             let value = buffer_get(self.ptr, property.as_ptr());
@@ -514,104 +902,121 @@ impl Buffer {
     }
 
     /// Get the value of a buffer localvar
+    #[track_caller]
     pub fn get_localvar(&self, property: &str) -> Option<Cow<str>> {
-        self.get_string(&format!("localvar_{}", property))
+        self.get_string(&LossyCString::from_string(format!(
+            "localvar_{}",
+            property
+        )))
     }
 
     /// Set the value of a buffer localvar
+    #[track_caller]
     pub fn set_localvar(&self, property: &str, value: &str) {
-        self.set(&format!("localvar_set_{}", property), value)
+        self.set(
+            &LossyCString::from_string(format!("localvar_set_{}", property)),
+            value,
+        )
     }
 
     /// Get the full name of the buffer.
+    #[track_caller]
     pub fn get_full_name(&self) -> Cow<str> {
-        self.get_string("full_name").unwrap()
+        self.get_string(cstr!("full_name")).unwrap_or_default()
     }
 
     /// Set the full name of the buffer
     pub fn set_full_name(&self, name: &str) {
-        self.set("full_name", name);
+        self.set(cstr!("full_name"), name);
     }
 
     /// Get the name of the buffer.
+    #[track_caller]
     pub fn get_name(&self) -> Cow<str> {
-        self.get_string("name").unwrap()
+        self.get_string(cstr!("name")).unwrap_or_default()
     }
 
     /// Set the name of the buffer.
     pub fn set_name(&self, name: &str) {
-        self.set("name", name);
+        self.set(cstr!("name"), name);
     }
 
     /// Get the short_name of the buffer.
+    #[track_caller]
     pub fn get_short_name(&self) -> Cow<str> {
-        self.get_string("short_name").unwrap()
+        self.get_string(cstr!("short_name")).unwrap_or_default()
     }
 
     /// Set the short_name of the buffer.
     pub fn set_short_name(&self, name: &str) {
-        self.set("short_name", name);
+        self.set(cstr!("short_name"), name);
     }
 
     /// Get the plugin name of the plugin that owns this buffer.
+    #[track_caller]
     pub fn plugin_name(&self) -> Cow<str> {
-        self.get_string("plugin").unwrap()
+        self.get_string(cstr!("plugin")).unwrap_or_default()
     }
 
     /// Hide time for all lines in the buffer.
     pub fn disable_time_for_each_line(&self) {
-        self.set("time_for_each_line", "0");
+        self.set(cstr!("time_for_each_line"), "0");
     }
 
     /// Disable the nicklist for this buffer.
     pub fn disable_nicklist(&self) {
-        self.set("nicklist", "0")
+        self.set(cstr!("nicklist"), "0")
     }
 
     /// Enable the nicklist for this buffer.
     pub fn enable_nicklist(&self) {
-        self.set("nicklist", "1")
+        self.set(cstr!("nicklist"), "1")
     }
 
     /// Set the title of the buffer.
     /// * `title` - The new title that will be set.
     pub fn set_title(&self, title: &str) {
-        self.set("title", title);
+        self.set(cstr!("title"), title);
     }
 
     /// Disable logging for this buffer.
     pub fn disable_log(&self) {
-        self.set("localvar_set_no_log", "1");
+        self.set(cstr!("localvar_set_no_log"), "1");
     }
 
-    /// Mark previous messages as read, setting the unread marker after the current last line of the
-    /// buffer.
+    /// Mark previous messages as read, setting the unread marker after the
+    /// current last line of the buffer.
     pub fn mark_read(&self) {
-        self.set("unread", "");
+        self.set(cstr!("unread"), "");
     }
 
     /// Remove buffer from the hotlist.
     pub fn clear_hotlist(&self) {
-        self.set("hotlist", "-1");
+        self.set(cstr!("hotlist"), "-1");
     }
 
     /// Enable hotlist
     pub fn enable_hotlist(&self) {
-        self.set("hotlist", "+");
+        self.set(cstr!("hotlist"), "+");
     }
 
     /// Disable hotlist
     pub fn disable_hotlist(&self) {
-        self.set("hotlist", "-");
+        self.set(cstr!("hotlist"), "-");
     }
 
     /// Add buffer to the hotlist.
     pub fn set_hotlist(&self, priority: HotlistPriority) {
-        self.set("hotlist", priority.to_c_rep());
+        self.set(cstr!("hotlist"), priority.to_c_rep());
     }
 
     /// Clear buffer contents
+    #[track_caller]
     pub fn clear(&self) {
+        if self.is_closed() {
+            return;
+        }
+
         let weechat = Weechat::from_ptr(self.weechat);
 
         let buffer_clear = weechat.get().buffer_clear.unwrap();
@@ -619,12 +1024,133 @@ impl Buffer {
     }
 
     /// Get the contents of the input
+    #[track_caller]
     pub fn input(&self) -> Cow<str> {
-        self.get_string("input").unwrap()
+        self.get_string(cstr!("input")).unwrap_or_default()
     }
 
     /// Switch to the buffer
     pub fn switch_to(&self) {
-        self.set("display", "1");
+        self.set(cstr!("display"), "1");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::mock::MockWeechat;
+
+    thread_local! {
+        static CLOSED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn close_cb(_: &(), buffer: Buffer) {
+        assert_eq!(buffer.get_name(), "room");
+        CLOSED.with(|closed| closed.set(closed.get() + 1));
+    }
+
+    #[test]
+    fn buffer_closed_behind_the_plugins_back() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+
+        let buffer = weechat.buffer_new::<(), ()>(
+            "room",
+            None,
+            None,
+            Some(close_cb),
+            None,
+        );
+        let group = buffer.add_group("ops", "blue", true, None).unwrap();
+        let nick = buffer
+            .add_nick(NickSettings::new("Emma"), Some(&group))
+            .unwrap();
+
+        buffer.print("before");
+        assert!(buffer.is_valid());
+        assert!(nick.is_valid());
+        assert_eq!(buffer.get_name(), "room");
+
+        // The user runs `/buffer close` on the buffer.
+        mock.close_buffer(buffer.ptr);
+        assert_eq!(CLOSED.with(Cell::get), 1);
+
+        assert!(!buffer.is_valid());
+        buffer.print("after");
+        buffer.set_title("title");
+        assert_eq!(buffer.get_name(), "");
+        assert!(buffer.search_nick("Emma", None).is_none());
+        assert!(buffer.add_nick(NickSettings::new("Ada"), None).is_none());
+        assert!(buffer.add_group("voice", "green", true, None).is_none());
+
+        assert!(!nick.is_valid());
+        assert_eq!(nick.get_name(), "");
+        assert!(nick.color().is_none());
+        nick.remove();
+        group.remove();
+
+        assert_eq!(mock.messages(), ["before"]);
+        // None of the calls above passed the freed buffer to Weechat.
+        assert_eq!(mock.invalid_accesses(), 0);
+    }
+
+    #[test]
+    fn removed_nicks_are_gone() {
+        let mock = MockWeechat::new();
+        let buffer = mock
+            .weechat()
+            .buffer_new::<(), ()>("room", None, None, None, None);
+
+        let group = buffer.add_group("ops", "blue", true, None).unwrap();
+        let emma = buffer.add_nick(NickSettings::new("Emma"), None).unwrap();
+        let ada = buffer
+            .add_nick(NickSettings::new("Ada"), Some(&group))
+            .unwrap();
+
+        emma.remove();
+        assert!(buffer.search_nick("Emma", None).is_none());
+        assert!(ada.is_valid());
+
+        // Removing the group removes the nicks inside of it.
+        group.remove();
+        assert!(!ada.is_valid());
+        assert!(buffer.search_nick("Ada", None).is_none());
+        assert_eq!(mock.invalid_accesses(), 0);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "The nick was removed from the nicklist")]
+    fn removing_a_removed_nick_panics_in_debug_builds() {
+        let mock = MockWeechat::new();
+        let buffer = mock
+            .weechat()
+            .buffer_new::<(), ()>("room", None, None, None, None);
+
+        let group = buffer.add_group("ops", "blue", true, None).unwrap();
+        let nick = buffer
+            .add_nick(NickSettings::new("Emma"), Some(&group))
+            .unwrap();
+
+        group.remove();
+        nick.remove();
+    }
+
+    #[test]
+    fn adding_a_nick_twice_fails() {
+        let mock = MockWeechat::new();
+        let buffer = mock
+            .weechat()
+            .buffer_new::<(), ()>("room", None, None, None, None);
+
+        let nick = buffer
+            .add_nick(NickSettings::new("Emma").color("magenta"), None)
+            .unwrap();
+
+        assert_eq!(nick.get_name(), "Emma");
+        assert_eq!(nick.color().as_deref(), Some("magenta"));
+        assert!(buffer.add_nick(NickSettings::new("Emma"), None).is_none());
     }
 }
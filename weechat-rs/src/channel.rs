@@ -0,0 +1,620 @@
+//! Main thread channel module.
+//! Allows threads to send a stream of items to a handler that runs on the
+//! Weechat main thread.
+//!
+//! Every channel has its own pipe that is watched by an fd hook, the handler
+//! runs from the Weechat main loop for every item in the order the items
+//! were sent. The hook is removed once all the senders are dropped and all
+//! the items were handled, or when the plugin gets unloaded.
+
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, ThreadId};
+
+use libc::c_void;
+use weechat_sys::t_weechat_plugin;
+
+use crate::hooks::{FdHook, FdHookAction, FdHookMode, FdReadiness, HookError};
+use crate::{catch_panic, Weechat};
+
+/// The fd hook of a channel, the hook stays in the registry after it was
+/// unhooked until the next channel is created or the plugin gets unloaded.
+trait ChannelHook {
+    fn is_hooked(&self) -> bool;
+}
+
+impl<T> ChannelHook for FdHook<(), Receiver<T>> {
+    fn is_hooked(&self) -> bool {
+        FdHook::is_hooked(self)
+    }
+}
+
+thread_local! {
+    static CHANNELS: RefCell<HashMap<usize, Box<dyn ChannelHook>>> =
+        RefCell::new(HashMap::new());
+    static NEXT_ID: Cell<usize> = const { Cell::new(0) };
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// How a channel behaves if items are sent faster than they are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelCapacity {
+    /// The channel can hold any number of items.
+    Unbounded,
+    /// The channel holds up to the given number of items, sending blocks
+    /// while the channel is full.
+    ///
+    /// Sending on the main thread never blocks since the items can only be
+    /// handled once the main thread is idle, the item is queued anyway.
+    Block(usize),
+    /// The channel holds up to the given number of items, sending fails
+    /// with `SendError::Full` while the channel is full.
+    DropNewest(usize),
+}
+
+/// Error that is returned if an item couldn't be sent, the item is given
+/// back.
+#[derive(PartialEq, Eq)]
+pub enum SendError<T> {
+    /// The channel is full.
+    Full(T),
+    /// The channel was closed because the plugin got unloaded.
+    Closed(T),
+}
+
+impl<T> SendError<T> {
+    /// Get back the item that couldn't be sent.
+    pub fn into_inner(self) -> T {
+        match self {
+            SendError::Full(item) | SendError::Closed(item) => item,
+        }
+    }
+}
+
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::Full(_) => write!(f, "Full(..)"),
+            SendError::Closed(_) => write!(f, "Closed(..)"),
+        }
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::Full(_) => write!(f, "the channel is full"),
+            SendError::Closed(_) => write!(f, "the channel is closed"),
+        }
+    }
+}
+
+impl<T> std::error::Error for SendError<T> {}
+
+/// Errors that can happen while a channel is created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChannelError {
+    /// The pipe of the channel couldn't be created, contains the OS error
+    /// code.
+    Pipe(i32),
+    /// The pipe of the channel couldn't be hooked.
+    Hook(HookError),
+}
+
+impl fmt::Display for ChannelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChannelError::Pipe(errno) => write!(
+                f,
+                "can't create the pipe of the channel: {}",
+                std::io::Error::from_raw_os_error(*errno)
+            ),
+            ChannelError::Hook(e) => {
+                write!(f, "can't hook the pipe of the channel: {}", e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ChannelError {}
+
+/// The state that is shared between the senders and the receiving side.
+struct Channel<T> {
+    queue: Mutex<VecDeque<T>>,
+    not_full: Condvar,
+    capacity: ChannelCapacity,
+    senders: AtomicUsize,
+    closed: AtomicBool,
+    main_thread: ThreadId,
+    write_fd: RawFd,
+}
+
+impl<T> Channel<T> {
+    fn wakeup(&self) {
+        // The pipe is non-blocking, if it's full a wakeup is already pending
+        // so the result can be ignored.
+        let byte = 1u8;
+        unsafe {
+            libc::write(self.write_fd, &byte as *const u8 as *const c_void, 1)
+        };
+    }
+
+    fn close(&self) -> VecDeque<T> {
+        let items = {
+            let mut queue = lock(&self.queue);
+            self.closed.store(true, Ordering::SeqCst);
+            std::mem::take(&mut *queue)
+        };
+
+        self.not_full.notify_all();
+
+        items
+    }
+}
+
+impl<T> Drop for Channel<T> {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.write_fd) };
+    }
+}
+
+/// The sending side of a main thread channel.
+///
+/// The sender can be cloned and sent to other threads. The channel is
+/// removed once all the senders are dropped and the remaining items were
+/// handled.
+pub struct MainSender<T> {
+    channel: Arc<Channel<T>>,
+}
+
+impl<T> Clone for MainSender<T> {
+    fn clone(&self) -> Self {
+        self.channel.senders.fetch_add(1, Ordering::SeqCst);
+
+        MainSender {
+            channel: self.channel.clone(),
+        }
+    }
+}
+
+impl<T> Drop for MainSender<T> {
+    fn drop(&mut self) {
+        // Wake up the receiving side so it notices that the last sender is
+        // gone.
+        if self.channel.senders.fetch_sub(1, Ordering::SeqCst) == 1
+            && !self.channel.closed.load(Ordering::SeqCst)
+        {
+            self.channel.wakeup();
+        }
+    }
+}
+
+impl<T> MainSender<T> {
+    /// Send an item to the handler of the channel.
+    ///
+    /// Returns an error containing the item if the channel is full and
+    /// items are dropped when the channel is full, or if the channel was
+    /// closed because the plugin got unloaded.
+    pub fn send(&self, item: T) -> Result<(), SendError<T>> {
+        let channel = &self.channel;
+
+        {
+            let mut queue = lock(&channel.queue);
+
+            loop {
+                if channel.closed.load(Ordering::SeqCst) {
+                    return Err(SendError::Closed(item));
+                }
+
+                match channel.capacity {
+                    ChannelCapacity::Block(capacity)
+                        if queue.len() >= capacity
+                            && thread::current().id()
+                                != channel.main_thread =>
+                    {
+                        queue = match channel.not_full.wait(queue) {
+                            Ok(guard) => guard,
+                            Err(poisoned) => poisoned.into_inner(),
+                        };
+                    }
+                    ChannelCapacity::DropNewest(capacity)
+                        if queue.len() >= capacity =>
+                    {
+                        return Err(SendError::Full(item));
+                    }
+                    _ => break,
+                }
+            }
+
+            queue.push_back(item);
+        }
+
+        channel.wakeup();
+        Ok(())
+    }
+}
+
+type Handler<T> = Box<dyn FnMut(&Weechat, T)>;
+
+/// The receiving side of a main thread channel, it owns the read end of the
+/// pipe and the handler.
+struct Receiver<T> {
+    read_fd: RawFd,
+    channel: Arc<Channel<T>>,
+    handler: Option<Handler<T>>,
+    weechat_ptr: *mut t_weechat_plugin,
+}
+
+impl<T> Receiver<T> {
+    /// Drop the handler and the items that weren't handled yet, wake up
+    /// blocked senders and close the read end of the pipe.
+    fn close(&mut self) {
+        drop(self.channel.close());
+        self.handler = None;
+
+        if self.read_fd >= 0 {
+            unsafe { libc::close(self.read_fd) };
+            self.read_fd = -1;
+        }
+    }
+}
+
+impl<T> AsRawFd for Receiver<T> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.read_fd
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+fn receive_cb<T: 'static>(
+    _data: &(),
+    weechat: &Weechat,
+    receiver: &mut Receiver<T>,
+    _: FdReadiness,
+) -> FdHookAction {
+    let mut buffer = [0u8; 64];
+
+    loop {
+        let read = unsafe {
+            libc::read(
+                receiver.read_fd,
+                buffer.as_mut_ptr() as *mut c_void,
+                buffer.len(),
+            )
+        };
+
+        if read <= 0 {
+            break;
+        }
+    }
+
+    let items = std::mem::take(&mut *lock(&receiver.channel.queue));
+    receiver.channel.not_full.notify_all();
+
+    if let Some(handler) = receiver.handler.as_mut() {
+        for item in items {
+            catch_panic(receiver.weechat_ptr, || handler(weechat, item));
+        }
+    }
+
+    let disconnected = receiver.channel.senders.load(Ordering::SeqCst) == 0
+        && lock(&receiver.channel.queue).is_empty();
+
+    if disconnected {
+        // Weechat allows a hook to be removed while its callback runs. The
+        // handler and the pipe are released right away, the unhooked hook
+        // object is dropped when the next channel is created or the plugin
+        // gets unloaded.
+        receiver.close();
+        FdHookAction::Unhook
+    } else {
+        FdHookAction::Continue
+    }
+}
+
+/// Remove all the channels, the items that are still queued are dropped.
+///
+/// This is called when the plugin gets unloaded.
+pub(crate) fn shutdown() {
+    let channels = CHANNELS.with(|channels| channels.take());
+    drop(channels);
+}
+
+impl Weechat {
+    /// Create a channel that sends items from any thread to a handler on the
+    /// main thread.
+    ///
+    /// The handler runs from the Weechat main loop for every item, in the
+    /// order the items were sent. Panics inside the handler are caught and
+    /// printed on the core buffer. The channel is closed when the plugin
+    /// gets unloaded, items that weren't handled yet are dropped.
+    ///
+    /// Returns an error if the pipe of the channel can't be created or
+    /// hooked.
+    ///
+    /// * `capacity` - How many items the channel can hold and what happens
+    ///     if it's full.
+    /// * `handler` - The function that will be called for every item.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let sender = weechat.main_channel(
+    ///     ChannelCapacity::Block(100),
+    ///     move |_, event: Event| buffer.print(&event.to_string()),
+    /// )?;
+    ///
+    /// std::thread::spawn(move || {
+    ///     while let Some(event) = socket.next_event() {
+    ///         if sender.send(event).is_err() {
+    ///             break;
+    ///         }
+    ///     }
+    /// });
+    /// ```
+    #[track_caller]
+    pub fn main_channel<T: Send + 'static>(
+        &self,
+        capacity: ChannelCapacity,
+        handler: impl FnMut(&Weechat, T) + 'static,
+    ) -> Result<MainSender<T>, ChannelError> {
+        let mut fds = [0; 2];
+
+        let ret = unsafe { libc::pipe(fds.as_mut_ptr()) };
+
+        if ret != 0 {
+            let errno = std::io::Error::last_os_error().raw_os_error();
+            return Err(ChannelError::Pipe(errno.unwrap_or(0)));
+        }
+
+        for fd in fds.iter() {
+            unsafe {
+                let flags = libc::fcntl(*fd, libc::F_GETFL);
+                libc::fcntl(*fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+            }
+        }
+
+        let channel = Arc::new(Channel {
+            queue: Mutex::new(VecDeque::new()),
+            not_full: Condvar::new(),
+            capacity,
+            senders: AtomicUsize::new(1),
+            closed: AtomicBool::new(false),
+            main_thread: thread::current().id(),
+            write_fd: fds[1],
+        });
+
+        let id = NEXT_ID.with(|id| {
+            let next = id.get();
+            id.set(next.wrapping_add(1));
+            next
+        });
+
+        let receiver = Receiver {
+            read_fd: fds[0],
+            channel: channel.clone(),
+            handler: Some(Box::new(handler)),
+            weechat_ptr: self.ptr,
+        };
+
+        // If the hook fails the receiver is dropped, closing both ends of the
+        // pipe.
+        let hook = self
+            .hook_fd_with_weechat(
+                receiver,
+                FdHookMode::Read,
+                receive_cb::<T>,
+                None,
+            )
+            .map_err(ChannelError::Hook)?;
+
+        CHANNELS.with(|channels| {
+            let mut channels = channels.borrow_mut();
+            channels.retain(|_, hook| hook.is_hooked());
+            channels.insert(id, Box::new(hook));
+        });
+
+        Ok(MainSender { channel })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::mock::MockWeechat;
+
+    fn collect<T: Send + 'static>(
+        mock: &MockWeechat,
+        capacity: ChannelCapacity,
+    ) -> (MainSender<T>, Rc<RefCell<Vec<T>>>) {
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let handler_received = received.clone();
+
+        let sender = mock
+            .weechat()
+            .main_channel(capacity, move |_, item| {
+                handler_received.borrow_mut().push(item)
+            })
+            .unwrap();
+
+        (sender, received)
+    }
+
+    #[test]
+    fn items_are_handled_in_order() {
+        let mock = MockWeechat::new();
+        let (sender, received) = collect(&mock, ChannelCapacity::Unbounded);
+
+        let threads: Vec<_> = (0..2)
+            .map(|t| {
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        sender.send((t, i)).unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        mock.run_until_idle();
+
+        let received = received.borrow();
+        assert_eq!(received.len(), 200);
+
+        for t in 0..2 {
+            let items: Vec<_> = received
+                .iter()
+                .filter(|(thread, _)| *thread == t)
+                .map(|(_, i)| *i)
+                .collect();
+            assert_eq!(items, (0..100).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn full_channel_drops_newest() {
+        let mock = MockWeechat::new();
+        let (sender, received) = collect(&mock, ChannelCapacity::DropNewest(2));
+
+        assert_eq!(sender.send(1), Ok(()));
+        assert_eq!(sender.send(2), Ok(()));
+        assert_eq!(sender.send(3), Err(SendError::Full(3)));
+
+        mock.run_until_idle();
+        assert_eq!(*received.borrow(), [1, 2]);
+
+        assert_eq!(sender.send(4), Ok(()));
+        mock.run_until_idle();
+        assert_eq!(*received.borrow(), [1, 2, 4]);
+    }
+
+    #[test]
+    fn full_channel_blocks_other_threads() {
+        let mock = MockWeechat::new();
+        let (sender, received) = collect(&mock, ChannelCapacity::Block(2));
+
+        // The main thread can't block, the item is queued anyway.
+        for i in 0..3 {
+            assert_eq!(sender.send(i), Ok(()));
+        }
+
+        let thread_sender = sender.clone();
+        let thread = thread::spawn(move || {
+            for i in 3..10 {
+                thread_sender.send(i).unwrap();
+            }
+        });
+
+        // The thread can only send two items at a time, wait until it's
+        // blocked and check that the channel didn't grow.
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(lock(&sender.channel.queue).len(), 3);
+
+        while received.borrow().len() < 10 {
+            if mock.run_fd_hooks() == 0 {
+                thread::sleep(Duration::from_millis(1));
+            }
+
+            assert!(lock(&sender.channel.queue).len() <= 3);
+        }
+
+        thread.join().unwrap();
+        assert_eq!(*received.borrow(), (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn channel_is_removed_after_the_last_sender() {
+        let mock = MockWeechat::new();
+        let (sender, received) = collect(&mock, ChannelCapacity::Unbounded);
+        let hooks = mock.hooked_fds().len();
+
+        let second = sender.clone();
+        sender.send(1).unwrap();
+        drop(sender);
+
+        mock.run_until_idle();
+        assert_eq!(mock.hooked_fds().len(), hooks);
+
+        second.send(2).unwrap();
+        drop(second);
+
+        mock.run_until_idle();
+
+        // The remaining item is handled before the channel is removed.
+        assert_eq!(*received.borrow(), [1, 2]);
+        assert_eq!(mock.hooked_fds().len(), hooks - 1);
+        assert_eq!(Rc::strong_count(&received), 1);
+    }
+
+    #[test]
+    fn channel_is_removed_by_its_own_callback() {
+        let mock = MockWeechat::new();
+        let (sender, received) = collect(&mock, ChannelCapacity::Unbounded);
+        let hooks = mock.hooked_fds().len();
+
+        sender.send(1).unwrap();
+        drop(sender);
+
+        // Only the hook of the channel runs, nothing is left for the
+        // executor to clean up.
+        assert_eq!(mock.run_fd_hooks(), 1);
+        assert_eq!(*received.borrow(), [1]);
+        assert_eq!(mock.hooked_fds().len(), hooks - 1);
+        assert_eq!(Rc::strong_count(&received), 1);
+
+        // The unhooked channel is dropped once the next one is created.
+        let (_sender, _) = collect::<()>(&mock, ChannelCapacity::Unbounded);
+        assert_eq!(CHANNELS.with(|channels| channels.borrow().len()), 1);
+    }
+
+    #[test]
+    fn shutdown_drops_queued_items() {
+        let mock = MockWeechat::new();
+        let (sender, received) = collect(&mock, ChannelCapacity::Unbounded);
+
+        let item = Arc::new(());
+        sender.send(item.clone()).unwrap();
+
+        let fds = mock.hooked_fds();
+        let read_fd = *fds.last().unwrap();
+        let write_fd = sender.channel.write_fd;
+
+        crate::executor::shutdown();
+
+        // The handler and the item are dropped and the pipe is closed.
+        assert_eq!(Rc::strong_count(&received), 1);
+        assert_eq!(Arc::strong_count(&item), 1);
+        assert_eq!(unsafe { libc::fcntl(read_fd, libc::F_GETFD) }, -1);
+        assert!(matches!(
+            sender.send(item.clone()),
+            Err(SendError::Closed(_))
+        ));
+
+        drop(sender);
+        assert_eq!(unsafe { libc::fcntl(write_fd, libc::F_GETFD) }, -1);
+    }
+}
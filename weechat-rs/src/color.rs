@@ -0,0 +1,462 @@
+//! Weechat colors.
+//! Allows parsing color option values and converting them into color codes
+//! that can be used when printing, as well as building colored text.
+
+use std::borrow::Cow;
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::Weechat;
+
+/// The basic colors of Weechat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BasicColor {
+    /// Black
+    Black,
+    /// Dark gray
+    DarkGray,
+    /// Red
+    Red,
+    /// Light red
+    LightRed,
+    /// Green
+    Green,
+    /// Light green
+    LightGreen,
+    /// Brown
+    Brown,
+    /// Yellow
+    Yellow,
+    /// Blue
+    Blue,
+    /// Light blue
+    LightBlue,
+    /// Magenta
+    Magenta,
+    /// Light magenta
+    LightMagenta,
+    /// Cyan
+    Cyan,
+    /// Light cyan
+    LightCyan,
+    /// Gray
+    Gray,
+    /// White
+    White,
+}
+
+impl BasicColor {
+    const ALL: [BasicColor; 16] = [
+        BasicColor::Black,
+        BasicColor::DarkGray,
+        BasicColor::Red,
+        BasicColor::LightRed,
+        BasicColor::Green,
+        BasicColor::LightGreen,
+        BasicColor::Brown,
+        BasicColor::Yellow,
+        BasicColor::Blue,
+        BasicColor::LightBlue,
+        BasicColor::Magenta,
+        BasicColor::LightMagenta,
+        BasicColor::Cyan,
+        BasicColor::LightCyan,
+        BasicColor::Gray,
+        BasicColor::White,
+    ];
+
+    /// Get the Weechat name of the color.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BasicColor::Black => "black",
+            BasicColor::DarkGray => "darkgray",
+            BasicColor::Red => "red",
+            BasicColor::LightRed => "lightred",
+            BasicColor::Green => "green",
+            BasicColor::LightGreen => "lightgreen",
+            BasicColor::Brown => "brown",
+            BasicColor::Yellow => "yellow",
+            BasicColor::Blue => "blue",
+            BasicColor::LightBlue => "lightblue",
+            BasicColor::Magenta => "magenta",
+            BasicColor::LightMagenta => "lightmagenta",
+            BasicColor::Cyan => "cyan",
+            BasicColor::LightCyan => "lightcyan",
+            BasicColor::Gray => "gray",
+            BasicColor::White => "white",
+        }
+    }
+}
+
+/// Attributes that can be applied to a color.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ColorAttributes {
+    /// Bold text, `*` in Weechat color names.
+    pub bold: bool,
+    /// Reverse video, `!` in Weechat color names.
+    pub reverse: bool,
+    /// Italic text, `/` in Weechat color names.
+    pub italic: bool,
+    /// Underlined text, `_` in Weechat color names.
+    pub underline: bool,
+    /// Keep the attributes of the previous color, `|` in Weechat color names.
+    pub keep: bool,
+}
+
+impl ColorAttributes {
+    fn is_empty(&self) -> bool {
+        *self == ColorAttributes::default()
+    }
+}
+
+impl fmt::Display for ColorAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let attributes = [
+            (self.bold, '*'),
+            (self.reverse, '!'),
+            (self.italic, '/'),
+            (self.underline, '_'),
+            (self.keep, '|'),
+        ];
+
+        for (set, c) in attributes.iter() {
+            if *set {
+                write!(f, "{}", c)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A Weechat color, e.g. the value of a color option.
+///
+/// Colors can be parsed from the Weechat color names, parsing never fails,
+/// unknown names are parsed as a `Color::Custom` color. Converting a color to
+/// a string gives back the Weechat color name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Color {
+    /// The default color of the terminal.
+    Default,
+    /// One of the basic Weechat colors.
+    Basic(BasicColor),
+    /// A color of the extended 256 color palette.
+    Extended(u8),
+    /// A color that Weechat knows by name, e.g. a color alias.
+    Custom(String),
+    /// A color with attributes, e.g. `*red` for bold red text.
+    Styled(ColorAttributes, Box<Color>),
+}
+
+impl FromStr for Color {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut attributes = ColorAttributes::default();
+        let mut name = s;
+
+        loop {
+            let mut chars = name.chars();
+
+            match chars.next() {
+                Some('*') => attributes.bold = true,
+                Some('!') => attributes.reverse = true,
+                Some('/') => attributes.italic = true,
+                Some('_') => attributes.underline = true,
+                Some('|') => attributes.keep = true,
+                _ => break,
+            }
+
+            name = chars.as_str();
+        }
+
+        let color = if name == "default" {
+            Color::Default
+        } else if let Some(color) =
+            BasicColor::ALL.iter().find(|c| c.as_str() == name)
+        {
+            Color::Basic(*color)
+        } else if let Ok(number) = name.parse::<u8>() {
+            Color::Extended(number)
+        } else {
+            Color::Custom(name.to_owned())
+        };
+
+        if attributes.is_empty() {
+            Ok(color)
+        } else {
+            Ok(Color::Styled(attributes, Box::new(color)))
+        }
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Color::Default => write!(f, "default"),
+            Color::Basic(color) => write!(f, "{}", color.as_str()),
+            Color::Extended(number) => write!(f, "{}", number),
+            Color::Custom(name) => write!(f, "{}", name),
+            Color::Styled(attributes, color) => {
+                write!(f, "{}{}", attributes, color)
+            }
+        }
+    }
+}
+
+impl Weechat {
+    /// Get the color code for a color.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let color = option.color();
+    /// buffer.print(&format!("{}warning", weechat.color_for(&color)));
+    /// ```
+    #[track_caller]
+    pub fn color_for(&self, color: &Color) -> Cow<'_, str> {
+        self.color(&color.to_string())
+    }
+
+    /// Get the terminal color that is the closest to a RGB color.
+    ///
+    /// Returns the number of the terminal color, 0 if the color couldn't be
+    /// converted.
+    ///
+    /// * `rgb` - The RGB color, e.g. `0xff8700`.
+    /// * `limit` - The number of terminal colors that can be used, e.g. 256.
+    #[track_caller]
+    pub fn color_rgb_to_term(&self, rgb: u32, limit: i32) -> i32 {
+        self.info_get("color_rgb2term", &format!("{},{}", rgb, limit))
+            .and_then(|color| color.parse().ok())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Color(String),
+    Text(String),
+}
+
+#[derive(Debug, Clone, Default)]
+struct Style {
+    fg: Option<String>,
+    bg: Option<String>,
+    attributes: ColorAttributes,
+}
+
+impl Style {
+    /// The color names that need to be applied after a reset to get back to
+    /// this style.
+    fn color_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        let attributes = [
+            (self.attributes.bold, "bold"),
+            (self.attributes.reverse, "reverse"),
+            (self.attributes.italic, "italic"),
+            (self.attributes.underline, "underline"),
+        ];
+
+        for (set, name) in attributes.iter() {
+            if *set {
+                names.push(name.to_string());
+            }
+        }
+
+        match (&self.fg, &self.bg) {
+            (Some(fg), Some(bg)) => names.push(format!("|{},{}", fg, bg)),
+            (Some(fg), None) => names.push(format!("|{}", fg)),
+            (None, Some(bg)) => names.push(format!(",{}", bg)),
+            (None, None) => (),
+        }
+
+        names
+    }
+}
+
+/// A builder for colored text.
+///
+/// The builder keeps track of the current style, which allows nesting styled
+/// text and restoring the outer style afterwards. Color names are only
+/// converted into color codes once the text gets built.
+///
+/// # Example
+///
+/// ```ignore
+/// let text = Styled::new()
+///     .fg("red")
+///     .bold()
+///     .text("alert")
+///     .reset()
+///     .text(": the server is down")
+///     .build(&weechat);
+///
+/// buffer.print(&text);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Styled {
+    segments: Vec<Segment>,
+    style: Style,
+}
+
+impl Styled {
+    /// Create a new empty styled text.
+    pub fn new() -> Self {
+        Styled::default()
+    }
+
+    fn push_color(mut self, name: String) -> Self {
+        self.segments.push(Segment::Color(name));
+        self
+    }
+
+    fn attribute(mut self, name: &str) -> Self {
+        match name {
+            "bold" => self.style.attributes.bold = true,
+            "reverse" => self.style.attributes.reverse = true,
+            "italic" => self.style.attributes.italic = true,
+            "underline" => self.style.attributes.underline = true,
+            _ => unreachable!(),
+        }
+
+        self.push_color(name.to_owned())
+    }
+
+    /// Set the foreground color, the current attributes are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `color` - The name of the color, e.g. `"red"` or `"214"`.
+    pub fn fg(mut self, color: &str) -> Self {
+        self.style.fg = Some(color.to_owned());
+        self.push_color(format!("|{}", color))
+    }
+
+    /// Set the background color.
+    ///
+    /// # Arguments
+    ///
+    /// * `color` - The name of the color, e.g. `"blue"` or `"17"`.
+    pub fn bg(mut self, color: &str) -> Self {
+        self.style.bg = Some(color.to_owned());
+        self.push_color(format!(",{}", color))
+    }
+
+    /// Set the colors using the Weechat color syntax.
+    ///
+    /// The foreground color may be prefixed with attributes (`*` for bold,
+    /// `!` for reverse, `/` for italic, `_` for underline and `|` to keep the
+    /// current attributes) and followed by a background color, separated by
+    /// a `,` or a `:`, e.g. `"*yellow:red"`.
+    ///
+    /// Without the `|` attribute the current attributes are replaced.
+    pub fn color(mut self, spec: &str) -> Self {
+        let (fg, bg) = match spec.find(&[',', ':'][..]) {
+            Some(pos) => (&spec[..pos], Some(&spec[pos + 1..])),
+            None => (spec, None),
+        };
+
+        let (attributes, fg) = match fg.parse::<Color>() {
+            Ok(Color::Styled(attributes, color)) => (attributes, *color),
+            Ok(color) => (ColorAttributes::default(), color),
+            Err(e) => match e {},
+        };
+
+        if attributes.keep {
+            let current = &mut self.style.attributes;
+            current.bold |= attributes.bold;
+            current.reverse |= attributes.reverse;
+            current.italic |= attributes.italic;
+            current.underline |= attributes.underline;
+        } else {
+            self.style.attributes = attributes;
+        }
+
+        let fg = fg.to_string();
+
+        let name = match bg {
+            Some(bg) => format!("{}{},{}", attributes, fg, bg),
+            None => format!("{}{}", attributes, fg),
+        };
+
+        if !fg.is_empty() {
+            self.style.fg = Some(fg);
+        }
+
+        if let Some(bg) = bg {
+            self.style.bg = Some(bg.to_owned());
+        }
+
+        self.push_color(name)
+    }
+
+    /// Make the following text bold.
+    pub fn bold(self) -> Self {
+        self.attribute("bold")
+    }
+
+    /// Reverse the colors of the following text.
+    pub fn reverse(self) -> Self {
+        self.attribute("reverse")
+    }
+
+    /// Make the following text italic.
+    pub fn italic(self) -> Self {
+        self.attribute("italic")
+    }
+
+    /// Underline the following text.
+    pub fn underline(self) -> Self {
+        self.attribute("underline")
+    }
+
+    /// Add text using the current style.
+    pub fn text(mut self, text: &str) -> Self {
+        self.segments.push(Segment::Text(text.to_owned()));
+        self
+    }
+
+    /// Reset the colors and the attributes.
+    pub fn reset(mut self) -> Self {
+        self.style = Style::default();
+        self.push_color("reset".to_owned())
+    }
+
+    /// Reset the colors but keep the attributes.
+    pub fn reset_color(mut self) -> Self {
+        self.style.fg = None;
+        self.style.bg = None;
+        self.push_color("resetcolor".to_owned())
+    }
+
+    /// Add another styled text, the current style is restored after it.
+    ///
+    /// The nested text starts out with the current style.
+    pub fn nested(mut self, styled: Styled) -> Self {
+        self.segments.extend(styled.segments);
+        self.segments.push(Segment::Color("reset".to_owned()));
+
+        for name in self.style.color_names() {
+            self.segments.push(Segment::Color(name));
+        }
+
+        self
+    }
+
+    /// Build the text, converting the color names into color codes.
+    #[track_caller]
+    pub fn build(&self, weechat: &Weechat) -> String {
+        let mut string = String::new();
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Color(name) => string.push_str(&weechat.color(name)),
+                Segment::Text(text) => string.push_str(text),
+            }
+        }
+
+        string
+    }
+}
@@ -1,18 +1,23 @@
 use libc::{c_char, c_int};
 use std::borrow::Cow;
 use std::ffi::CStr;
+use std::marker::PhantomData;
 use std::os::raw::c_void;
 use std::ptr;
 
-use weechat_sys::{t_gui_buffer, t_gui_completion, t_weechat_plugin};
+use weechat_sys::{t_gui_buffer, t_gui_completion, t_hdata, t_weechat_plugin};
 
-use crate::hooks::Hook;
-use crate::{Buffer, LossyCString, ReturnCode, Weechat};
+use crate::hooks::{Hook, HookError};
+use crate::{Buffer, LossyCString, ReturnCode, UnsupportedVersion, Weechat};
 
 /// A handle to a completion item.
-pub struct Completion {
+///
+/// The handle is only valid while the completion callback runs.
+pub struct Completion<'a> {
     weechat_ptr: *mut t_weechat_plugin,
     ptr: *mut t_gui_completion,
+    buffer_ptr: *mut t_gui_buffer,
+    phantom: PhantomData<&'a t_gui_completion>,
 }
 
 /// The positions an entry can be added to a completion list.
@@ -36,39 +41,99 @@ impl CompletionPosition {
     }
 }
 
-impl Completion {
+impl<'a> Completion<'a> {
     pub(crate) fn from_raw(
         weechat: *mut t_weechat_plugin,
         completion: *mut t_gui_completion,
-    ) -> Completion {
+        buffer: *mut t_gui_buffer,
+    ) -> Completion<'a> {
         Completion {
             weechat_ptr: weechat,
             ptr: completion,
+            buffer_ptr: buffer,
+            phantom: PhantomData,
         }
     }
 
+    #[track_caller]
+    fn get_string(&self, property: &str) -> Cow<'_, str> {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let completion_get_string =
+            weechat.get().completion_get_string.unwrap();
+
+        let property = LossyCString::new(property);
+
+        unsafe {
+            let value = completion_get_string(self.ptr, property.as_ptr());
+
+            if value.is_null() {
+                Cow::from("")
+            } else {
+                CStr::from_ptr(value).to_string_lossy()
+            }
+        }
+    }
+
+    /// Get the word that is being completed, e.g. `"al"` if the user typed
+    /// `/query al` and pressed tab.
+    #[track_caller]
+    pub fn base_word(&self) -> Cow<'_, str> {
+        self.get_string("base_word")
+    }
+
+    /// Get the command that is being completed without the command
+    /// character, e.g. `"query"`. The string is empty if the input isn't a
+    /// command.
+    #[track_caller]
+    pub fn base_command(&self) -> Cow<'_, str> {
+        self.get_string("base_command")
+    }
+
+    /// Get the arguments of the command that is being completed, e.g.
+    /// `"al"` for the input `/query al`.
+    #[track_caller]
+    pub fn arguments(&self) -> Cow<'_, str> {
+        self.get_string("args")
+    }
+
+    /// Get the buffer the completion is happening in.
+    pub fn buffer(&self) -> Buffer {
+        Buffer::from_ptr(self.weechat_ptr, self.buffer_ptr)
+    }
+
     /// Add a word for completion, keeping the list sorted.
+    #[track_caller]
     pub fn add(&self, word: &str) {
         self.add_with_options(word, false, CompletionPosition::Sorted)
     }
 
-    /// Add a word for completion in a specific position specific if the word is a nick name
+    /// Add a word for completion at a specific position.
+    ///
+    /// Empty words are ignored, duplicate words are removed by Weechat.
+    ///
+    /// * `word` - The word that should be added.
+    /// * `is_nick` - Is the word a nick, nicks get the nick completion
+    ///     suffix appended and their case is ignored while completing.
+    /// * `position` - Where the word should be inserted into the list.
+    #[track_caller]
     pub fn add_with_options(
         &self,
         word: &str,
         is_nick: bool,
         position: CompletionPosition,
     ) {
-        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        if word.is_empty() {
+            return;
+        }
 
-        let hook_completion_list_add =
-            weechat.get().hook_completion_list_add.unwrap();
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let completion_list_add = weechat.get().completion_list_add.unwrap();
 
         let word = LossyCString::new(word);
         let method = LossyCString::new(position.value());
 
         unsafe {
-            hook_completion_list_add(
+            completion_list_add(
                 self.ptr,
                 word.as_ptr(),
                 is_nick as i32,
@@ -76,6 +141,197 @@ impl Completion {
             );
         }
     }
+
+    /// Add multiple words for completion at a specific position, the words
+    /// aren't treated as nicks.
+    ///
+    /// Empty words are ignored, duplicate words are removed by Weechat.
+    ///
+    /// * `words` - The words that should be added.
+    /// * `position` - Where the words should be inserted into the list.
+    #[track_caller]
+    pub fn extend<I, S>(&self, words: I, position: CompletionPosition)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let completion_list_add = weechat.get().completion_list_add.unwrap();
+
+        let method = LossyCString::new(position.value());
+
+        for word in words {
+            let word = word.as_ref();
+
+            if word.is_empty() {
+                continue;
+            }
+
+            let word = LossyCString::new(word);
+
+            unsafe {
+                completion_list_add(
+                    self.ptr,
+                    word.as_ptr(),
+                    0,
+                    method.as_ptr(),
+                );
+            }
+        }
+    }
+}
+
+/// The direction in which a standalone completion cycles through the
+/// candidates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionDirection {
+    /// Complete to the next candidate.
+    Next,
+    /// Complete to the previous candidate.
+    Previous,
+}
+
+/// A completion that can be used to complete arbitrary text, independent of
+/// the input of a buffer.
+///
+/// The completion is freed when the object is dropped.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut completion = weechat
+///     .completion(&buffer)?
+///     .expect("Can't create the completion");
+///
+/// if let Some(word) =
+///     completion.search("/help filt", 10, CompletionDirection::Next)
+/// {
+///     // Prints "filter".
+///     weechat.print(&word);
+/// }
+/// ```
+pub struct StandaloneCompletion {
+    weechat_ptr: *mut t_weechat_plugin,
+    ptr: *mut t_gui_completion,
+}
+
+impl Drop for StandaloneCompletion {
+    fn drop(&mut self) {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let completion_free = weechat.get().completion_free.unwrap();
+
+        unsafe { completion_free(self.ptr) };
+    }
+}
+
+impl StandaloneCompletion {
+    #[track_caller]
+    fn hdata(&self) -> *mut t_hdata {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let hdata_get = weechat.get().hdata_get.unwrap();
+
+        let name = cstr!("completion");
+
+        unsafe { hdata_get(self.weechat_ptr, name.as_ptr()) }
+    }
+
+    /// Search for a completion of the text.
+    ///
+    /// Returns the word the text is completed to, or `None` if no candidate
+    /// was found. Calling this again with the same text cycles through the
+    /// candidates.
+    ///
+    /// * `text` - The text that should be completed, e.g. `"/help filt"`.
+    /// * `position` - The position of the cursor in the text, in characters.
+    /// * `direction` - Cycle to the next or to the previous candidate.
+    #[track_caller]
+    pub fn search(
+        &mut self,
+        text: &str,
+        position: usize,
+        direction: CompletionDirection,
+    ) -> Option<String> {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let completion_search = weechat.get().completion_search.unwrap();
+        let hdata_string = weechat.get().hdata_string.unwrap();
+
+        let text = LossyCString::new(text);
+        let direction = match direction {
+            CompletionDirection::Next => 1,
+            CompletionDirection::Previous => -1,
+        };
+
+        unsafe {
+            let found = completion_search(
+                self.ptr,
+                text.as_ptr(),
+                position as c_int,
+                direction,
+            );
+
+            if found != 1 {
+                return None;
+            }
+
+            let hdata = self.hdata();
+
+            if hdata.is_null() {
+                return None;
+            }
+
+            let name = cstr!("word_found");
+            let word =
+                hdata_string(hdata, self.ptr as *mut c_void, name.as_ptr());
+
+            if word.is_null() {
+                None
+            } else {
+                Some(CStr::from_ptr(word).to_string_lossy().into_owned())
+            }
+        }
+    }
+
+    /// Get the word that was replaced by the last search.
+    #[track_caller]
+    pub fn base_word(&self) -> Cow<'_, str> {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let completion_get_string =
+            weechat.get().completion_get_string.unwrap();
+
+        let property = cstr!("base_word");
+
+        unsafe {
+            let value = completion_get_string(self.ptr, property.as_ptr());
+
+            if value.is_null() {
+                Cow::from("")
+            } else {
+                CStr::from_ptr(value).to_string_lossy()
+            }
+        }
+    }
+
+    /// Get the position in the text, in characters, at which the replaced
+    /// word starts.
+    #[track_caller]
+    pub fn position_replace(&self) -> usize {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let hdata_integer = weechat.get().hdata_integer.unwrap();
+
+        let hdata = self.hdata();
+
+        if hdata.is_null() {
+            return 0;
+        }
+
+        let name = cstr!("position_replace");
+
+        let position = unsafe {
+            hdata_integer(hdata, self.ptr as *mut c_void, name.as_ptr())
+        };
+
+        position.max(0) as usize
+    }
 }
 
 /// Hook for a completion item, the hook is removed when the object is dropped.
@@ -91,6 +347,38 @@ struct CompletionHookData<T> {
 }
 
 impl Weechat {
+    /// Create a standalone completion for a buffer.
+    ///
+    /// Returns an error if the running version of Weechat is older than
+    /// 2.9.0, or `Ok(None)` if the completion couldn't be created.
+    ///
+    /// * `buffer` - The buffer the completion will happen in, its nicklist
+    ///     and local variables are used for the completion.
+    #[track_caller]
+    pub fn completion(
+        &self,
+        buffer: &Buffer,
+    ) -> Result<Option<StandaloneCompletion>, UnsupportedVersion> {
+        self.require_version(2, 9, 0)?;
+
+        if buffer.is_closed() {
+            return Ok(None);
+        }
+
+        let completion_new = self.get().completion_new.unwrap();
+
+        let ptr = unsafe { completion_new(self.ptr, buffer.ptr) };
+
+        if ptr.is_null() {
+            Ok(None)
+        } else {
+            Ok(Some(StandaloneCompletion {
+                weechat_ptr: self.ptr,
+                ptr,
+            }))
+        }
+    }
+
     /// Hook a completion.
     ///
     /// * `completion_item` - The name of the completion item
@@ -99,6 +387,9 @@ impl Weechat {
     ///     populate the words for the completion
     /// * `callback_data` - Data that will be passed to the callback every time
     ///     the callback runs. This data will be freed when the hook is unhooked.
+    ///
+    /// Returns an error if Weechat refused to create the hook.
+    #[track_caller]
     pub fn hook_completion<T>(
         &self,
         completion_item: &str,
@@ -110,7 +401,7 @@ impl Weechat {
             completion: Completion,
         ) -> ReturnCode,
         callback_data: Option<T>,
-    ) -> CompletionHook<T>
+    ) -> Result<CompletionHook<T>, HookError>
     where
         T: Default,
     {
@@ -125,17 +416,22 @@ impl Weechat {
                 { &mut *(pointer as *mut CompletionHookData<T>) };
             let callback = hook_data.callback;
             let callback_data = &hook_data.callback_data;
+            let buffer_ptr = buffer;
             let buffer = Buffer::from_ptr(hook_data.weechat_ptr, buffer);
 
             let completion_item =
                 CStr::from_ptr(completion_item).to_string_lossy();
 
-            callback(
-                callback_data,
-                buffer,
-                completion_item,
-                Completion::from_raw(hook_data.weechat_ptr, completion),
-            ) as i32
+            let weechat_ptr = hook_data.weechat_ptr;
+
+            crate::callback_return_code(weechat_ptr, |_| {
+                callback(
+                    callback_data,
+                    buffer,
+                    completion_item,
+                    Completion::from_raw(weechat_ptr, completion, buffer_ptr),
+                )
+            })
         }
 
         let data = Box::new(CompletionHookData {
@@ -161,14 +457,507 @@ impl Weechat {
             )
         };
         let hook_data = unsafe { Box::from_raw(data_ref) };
+
+        if hook_ptr.is_null() {
+            return Err(HookError::CreationFailed);
+        }
+
         let hook = Hook {
             ptr: hook_ptr,
             weechat_ptr: self.ptr,
         };
 
-        CompletionHook::<T> {
+        Ok(CompletionHook::<T> {
             _hook: hook,
             _hook_data: hook_data,
+        })
+    }
+}
+
+/// A completion item that can be used in a command completion template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompletionItem {
+    /// Names of all buffers.
+    BuffersNames,
+    /// Numbers of all buffers.
+    BuffersNumbers,
+    /// Names of all buffers, including the plugin name.
+    BuffersPluginsNames,
+    /// Nicks of the current buffers nicklist.
+    Nicks,
+    /// Commands (WeeChat and plugins).
+    Commands,
+    /// Color names.
+    Colors,
+    /// Configuration files.
+    ConfigFiles,
+    /// Configuration options.
+    ConfigOptions,
+    /// Filenames.
+    Filename,
+    /// Names of filters.
+    FiltersNames,
+    /// Names of infos hooked.
+    Infos,
+    /// Names of infolists hooked.
+    Infolists,
+    /// Names of plugins.
+    PluginsNames,
+    /// Names of bars.
+    BarsNames,
+    /// Numbers of windows.
+    WindowsNumbers,
+    /// A custom completion item, usually one hooked by a plugin with
+    /// [`hook_completion`](Weechat::hook_completion).
+    Custom(String),
+}
+
+impl CompletionItem {
+    /// Create a custom completion item with the given name.
+    pub fn custom<T: Into<String>>(name: T) -> CompletionItem {
+        CompletionItem::Custom(name.into())
+    }
+
+    fn name(&self) -> &str {
+        use CompletionItem::*;
+        match self {
+            BuffersNames => "buffers_names",
+            BuffersNumbers => "buffers_numbers",
+            BuffersPluginsNames => "buffers_plugins_names",
+            Nicks => "nicks",
+            Commands => "commands",
+            Colors => "colors",
+            ConfigFiles => "config_files",
+            ConfigOptions => "config_options",
+            Filename => "filename",
+            FiltersNames => "filters_names",
+            Infos => "infos",
+            Infolists => "infolists",
+            PluginsNames => "plugins_names",
+            BarsNames => "bars_names",
+            WindowsNumbers => "windows_numbers",
+            Custom(name) => name,
+        }
+    }
+}
+
+/// Errors that can happen while building a completion template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompletionTemplateError {
+    /// A word was empty.
+    EmptyWord,
+    /// A word contained whitespace or one of the template special characters
+    /// `|` and `%`.
+    InvalidWord(String),
+    /// The name of a completion item contained invalid characters, only
+    /// alphanumeric characters, `_`, `-` and `.` are allowed.
+    InvalidItemName(String),
+    /// An alternative consisting of multiple arguments was used as a single
+    /// argument, WeeChat has no way to express this.
+    NestedAlternatives,
+    /// The stop or repeat modifier wasn't used as the last argument, or was
+    /// combined with other alternatives for the same argument.
+    MisplacedModifier,
+}
+
+impl std::fmt::Display for CompletionTemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use CompletionTemplateError::*;
+        match self {
+            EmptyWord => write!(f, "empty word in completion template"),
+            InvalidWord(w) => {
+                write!(f, "invalid word in completion template: {:?}", w)
+            }
+            InvalidItemName(n) => {
+                write!(f, "invalid completion item name: {:?}", n)
+            }
+            NestedAlternatives => write!(
+                f,
+                "alternatives spanning multiple arguments can't be used \
+                 as a single argument"
+            ),
+            MisplacedModifier => write!(
+                f,
+                "the stop and repeat modifiers must be used alone as the \
+                 last argument"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CompletionTemplateError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TemplateToken {
+    Word(String),
+    Item(CompletionItem),
+    Stop,
+    Repeat,
+}
+
+impl TemplateToken {
+    fn is_modifier(&self) -> bool {
+        matches!(self, TemplateToken::Stop | TemplateToken::Repeat)
+    }
+}
+
+/// A single positional argument, the tokens are alternatives for it.
+type TemplateArgument = Vec<TemplateToken>;
+
+/// A builder for command completion templates.
+///
+/// The resulting string can be used as the `completion` field of a
+/// [`CommandDescription`](crate::CommandDescription).
+///
+/// Arguments are added with [`then`](CompletionTemplate::then), while
+/// [`or`](CompletionTemplate::or) starts a new, alternative template.
+///
+/// # Example
+/// ```ignore
+/// let template = CompletionTemplate::word("connect")
+///     .then(CompletionItem::custom("myplugin_servers"))
+///     .or(CompletionTemplate::word("disconnect"))
+///     .build()?;
+///
+/// assert_eq!(template, "connect %(myplugin_servers) || disconnect");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletionTemplate {
+    alternatives: Vec<Vec<TemplateArgument>>,
+    error: Option<CompletionTemplateError>,
+}
+
+impl CompletionTemplate {
+    fn from_token(token: TemplateToken) -> CompletionTemplate {
+        CompletionTemplate {
+            alternatives: vec![vec![vec![token]]],
+            error: None,
         }
     }
+
+    /// Create a template completing a single fixed word.
+    pub fn word<T: Into<String>>(word: T) -> CompletionTemplate {
+        let word = word.into();
+
+        let error = if word.is_empty() {
+            Some(CompletionTemplateError::EmptyWord)
+        } else if word
+            .chars()
+            .any(|c| c.is_whitespace() || c == '|' || c == '%')
+        {
+            Some(CompletionTemplateError::InvalidWord(word.clone()))
+        } else {
+            None
+        };
+
+        let mut template =
+            CompletionTemplate::from_token(TemplateToken::Word(word));
+        template.error = error;
+        template
+    }
+
+    /// Create a template completing one of the given words.
+    pub fn one_of<I, T>(words: I) -> CompletionTemplate
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        let mut words = words.into_iter().map(CompletionTemplate::word);
+
+        let first = match words.next() {
+            Some(w) => w,
+            None => {
+                let mut template = CompletionTemplate::word("");
+                template.error = Some(CompletionTemplateError::EmptyWord);
+                return template;
+            }
+        };
+
+        words
+            .fold(first, |template, word| template.or(word))
+            .merged()
+    }
+
+    /// Create a template using a completion item.
+    #[track_caller]
+    pub fn item(item: CompletionItem) -> CompletionTemplate {
+        let valid = !item.name().is_empty()
+            && item
+                .name()
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_-.".contains(c));
+
+        let error = if valid {
+            None
+        } else {
+            Some(CompletionTemplateError::InvalidItemName(
+                item.name().to_owned(),
+            ))
+        };
+
+        let mut template =
+            CompletionTemplate::from_token(TemplateToken::Item(item));
+        template.error = error;
+        template
+    }
+
+    /// Create a template using a custom completion item.
+    ///
+    /// This is a shorthand for
+    /// `CompletionTemplate::item(CompletionItem::custom(name))`.
+    pub fn custom<T: Into<String>>(name: T) -> CompletionTemplate {
+        CompletionTemplate::item(CompletionItem::custom(name))
+    }
+
+    /// Stop the completion for all the following arguments (`%-`).
+    pub fn stop() -> CompletionTemplate {
+        CompletionTemplate::from_token(TemplateToken::Stop)
+    }
+
+    /// Repeat the previous completion for all the following arguments
+    /// (`%*`).
+    pub fn repeat() -> CompletionTemplate {
+        CompletionTemplate::from_token(TemplateToken::Repeat)
+    }
+
+    /// Merge alternatives that consist of a single argument each into one
+    /// argument, e.g. `on || off` becomes `on|off`.
+    fn merged(mut self) -> CompletionTemplate {
+        if self.alternatives.len() == 1 {
+            return self;
+        }
+
+        if self.alternatives.iter().any(|a| a.len() != 1) {
+            self.error
+                .get_or_insert(CompletionTemplateError::NestedAlternatives);
+            return self;
+        }
+
+        let argument: TemplateArgument = self
+            .alternatives
+            .drain(..)
+            .flat_map(|mut a| a.remove(0))
+            .collect();
+
+        self.alternatives = vec![vec![argument]];
+        self
+    }
+
+    /// Add the given template as the next argument of the current
+    /// alternative.
+    ///
+    /// If the given template has multiple alternatives, each of them needs to
+    /// consist of a single argument, they are then merged into one argument,
+    /// otherwise building the template will fail.
+    pub fn then<T: Into<CompletionTemplate>>(
+        mut self,
+        next: T,
+    ) -> CompletionTemplate {
+        let next = next.into().merged();
+
+        if let Some(e) = next.error {
+            self.error.get_or_insert(e);
+            return self;
+        }
+
+        let current = self
+            .alternatives
+            .last_mut()
+            .expect("Completion template without alternatives");
+
+        if current
+            .last()
+            .into_iter()
+            .flatten()
+            .any(|t| t.is_modifier())
+        {
+            self.error
+                .get_or_insert(CompletionTemplateError::MisplacedModifier);
+            return self;
+        }
+
+        current.extend(next.alternatives.into_iter().flatten());
+        self
+    }
+
+    /// Add the given template as an alternative to this template (`||`).
+    pub fn or<T: Into<CompletionTemplate>>(
+        mut self,
+        other: T,
+    ) -> CompletionTemplate {
+        let other = other.into();
+
+        if let Some(e) = other.error {
+            self.error.get_or_insert(e);
+            return self;
+        }
+
+        self.alternatives.extend(other.alternatives);
+        self
+    }
+
+    /// Build the completion template string.
+    #[track_caller]
+    pub fn build(&self) -> Result<String, CompletionTemplateError> {
+        if let Some(e) = &self.error {
+            return Err(e.clone());
+        }
+
+        let modifier_misplaced = self.alternatives.iter().any(|arguments| {
+            arguments.iter().enumerate().any(|(i, argument)| {
+                argument.iter().any(|t| t.is_modifier())
+                    && (argument.len() != 1 || i != arguments.len() - 1)
+            })
+        });
+
+        if modifier_misplaced {
+            return Err(CompletionTemplateError::MisplacedModifier);
+        }
+
+        let alternatives: Vec<String> = self
+            .alternatives
+            .iter()
+            .map(|arguments| {
+                arguments
+                    .iter()
+                    .map(|argument| {
+                        argument
+                            .iter()
+                            .map(|token| match token {
+                                TemplateToken::Word(w) => w.clone(),
+                                TemplateToken::Item(i) => {
+                                    format!("%({})", i.name())
+                                }
+                                TemplateToken::Stop => "%-".to_owned(),
+                                TemplateToken::Repeat => "%*".to_owned(),
+                            })
+                            .collect::<Vec<String>>()
+                            .join("|")
+                    })
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            })
+            .collect();
+
+        Ok(alternatives.join(" || "))
+    }
+}
+
+impl From<CompletionItem> for CompletionTemplate {
+    fn from(item: CompletionItem) -> Self {
+        CompletionTemplate::item(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arguments_and_alternatives_are_joined() {
+        let template = CompletionTemplate::word("connect")
+            .then(CompletionItem::custom("myplugin_servers"))
+            .or(CompletionTemplate::word("disconnect"))
+            .build();
+
+        assert_eq!(
+            template.as_deref(),
+            Ok("connect %(myplugin_servers) || disconnect")
+        );
+    }
+
+    #[test]
+    fn single_argument_alternatives_are_merged() {
+        let template = CompletionTemplate::word("set")
+            .then(CompletionTemplate::one_of(["on", "off"]))
+            .then(CompletionTemplate::word("now").or(CompletionItem::Nicks))
+            .then(CompletionTemplate::repeat())
+            .build();
+
+        assert_eq!(template.as_deref(), Ok("set on|off now|%(nicks) %*"));
+        assert_eq!(
+            CompletionTemplate::item(CompletionItem::BuffersNames)
+                .then(CompletionTemplate::stop())
+                .build()
+                .as_deref(),
+            Ok("%(buffers_names) %-")
+        );
+    }
+
+    #[test]
+    fn invalid_words_and_items_are_errors() {
+        use CompletionTemplateError::*;
+
+        assert_eq!(CompletionTemplate::word("").build(), Err(EmptyWord));
+        assert_eq!(
+            CompletionTemplate::one_of(Vec::<String>::new()).build(),
+            Err(EmptyWord)
+        );
+        assert_eq!(
+            CompletionTemplate::word("a b").build(),
+            Err(InvalidWord("a b".to_owned()))
+        );
+        assert_eq!(
+            CompletionTemplate::word("join")
+                .then(CompletionTemplate::word("50%"))
+                .build(),
+            Err(InvalidWord("50%".to_owned()))
+        );
+        assert_eq!(
+            CompletionTemplate::custom("my servers").build(),
+            Err(InvalidItemName("my servers".to_owned()))
+        );
+        // The first error is kept.
+        assert_eq!(
+            CompletionTemplate::word("a|b")
+                .or(CompletionTemplate::word(""))
+                .build(),
+            Err(InvalidWord("a|b".to_owned()))
+        );
+    }
+
+    #[test]
+    fn nested_alternatives_and_misplaced_modifiers_are_errors() {
+        use CompletionTemplateError::*;
+
+        let nested = CompletionTemplate::word("add")
+            .then(CompletionItem::Nicks)
+            .or(CompletionTemplate::word("list"));
+
+        assert_eq!(
+            CompletionTemplate::word("user").then(nested).build(),
+            Err(NestedAlternatives)
+        );
+        assert_eq!(
+            CompletionTemplate::word("kick")
+                .then(CompletionTemplate::stop())
+                .then(CompletionItem::Nicks)
+                .build(),
+            Err(MisplacedModifier)
+        );
+        assert_eq!(
+            CompletionTemplate::word("kick")
+                .then(CompletionTemplate::stop().or(CompletionItem::Nicks))
+                .build(),
+            Err(MisplacedModifier)
+        );
+        assert_eq!(CompletionTemplate::repeat().build().as_deref(), Ok("%*"));
+    }
+
+    #[test]
+    fn errors_are_displayed() {
+        use CompletionTemplateError::*;
+
+        assert_eq!(EmptyWord.to_string(), "empty word in completion template");
+        assert_eq!(
+            InvalidWord("a b".to_owned()).to_string(),
+            "invalid word in completion template: \"a b\""
+        );
+        assert_eq!(
+            InvalidItemName("x y".to_owned()).to_string(),
+            "invalid completion item name: \"x y\""
+        );
+        assert!(NestedAlternatives.to_string().contains("single argument"));
+        assert!(MisplacedModifier.to_string().contains("last argument"));
+    }
 }
@@ -1,20 +1,32 @@
 //! Weechat Configuration module
 
 use libc::{c_char, c_int};
-use std::collections::HashMap;
+use std::cell::RefCell;
 use std::ffi::CStr;
+use std::marker::PhantomData;
 use std::os::raw::c_void;
 use std::ptr;
+use std::rc::Rc;
 
 use crate::config_options::{
-    BooleanOption, ColorOption, ConfigOption, IntegerOption, OptionDescription,
-    OptionPointers, OptionType, StringOption,
+    notify_change_listeners, AnyOption, BooleanOption, CheckValueCallback,
+    ColorOption, ConfigOption, ConfigOptionRef, EnumOption, IntegerOption,
+    OptionCallback, OptionDescription, OptionPointers, OptionType,
+    StringOption,
+};
+use crate::option_settings::{
+    BooleanOptionSettings, ColorOptionSettings, EnumOptionSettings,
+    IntegerOptionSettings, OptionSettingsError, StringOptionSettings,
+};
+use crate::{
+    callback_return_code, catch_panic, LossyCString, OptionChanged, ReturnCode,
+    Weechat,
 };
-use crate::{LossyCString, Weechat};
-use std::borrow::Cow;
 use weechat_sys::{
     t_config_file, t_config_option, t_config_section, t_weechat_plugin,
-    WEECHAT_RC_OK,
+    WEECHAT_CONFIG_OPTION_SET_ERROR, WEECHAT_CONFIG_READ_FILE_NOT_FOUND,
+    WEECHAT_CONFIG_READ_OK, WEECHAT_CONFIG_WRITE_ERROR,
+    WEECHAT_CONFIG_WRITE_MEMORY_ERROR, WEECHAT_CONFIG_WRITE_OK,
 };
 
 /// Weechat configuration file
@@ -22,21 +34,88 @@ pub struct Config<T> {
     ptr: *mut t_config_file,
     weechat_ptr: *mut t_weechat_plugin,
     _config_data: Box<ConfigPointers<T>>,
-    sections: HashMap<String, ConfigSection>,
+    // The sections are boxed so they keep their address while new sections
+    // are added, the config hands out references to them.
+    #[allow(clippy::vec_box)]
+    sections: RefCell<Vec<Box<ConfigSection>>>,
 }
 
 struct ConfigPointers<T> {
     reload_cb: Option<fn(&mut T)>,
     reload_data: T,
+    weechat_ptr: *mut t_weechat_plugin,
 }
 
 /// Weechat Configuration section
+///
+/// Sections are owned by their `Config` and options are borrowed from their
+/// section, so neither of them can outlive the config that created them.
 pub struct ConfigSection {
     ptr: *mut t_config_section,
     config_ptr: *mut t_config_file,
     weechat_ptr: *mut t_weechat_plugin,
+    // The section data is only set for sections that are owned by a
+    // `Config`, borrowed sections handed out to callbacks don't free the
+    // section.
+    _section_data: Option<Box<SectionPointers>>,
+    option_data: Rc<RefCell<Vec<OptionData>>>,
+}
+
+struct SectionPointers {
+    read_cb: Option<SectionReadCallback>,
+    write_cb: Option<SectionWriteCallback>,
+    write_default_cb: Option<SectionWriteCallback>,
+    weechat_ptr: *mut t_weechat_plugin,
+    option_data: Rc<RefCell<Vec<OptionData>>>,
 }
 
+/// Type erased callback data of a config option, the data is freed when the
+/// section that owns the option is freed.
+struct OptionData {
+    ptr: *mut c_void,
+    free: unsafe fn(*mut c_void),
+}
+
+impl Drop for OptionData {
+    fn drop(&mut self) {
+        unsafe { (self.free)(self.ptr) }
+    }
+}
+
+unsafe fn free_option_data<P>(ptr: *mut c_void) {
+    drop(Box::from_raw(ptr as *mut P));
+}
+
+/// A borrowed Weechat configuration file, it is passed to the read and write
+/// callbacks of a config section.
+pub struct ConfigFile<'a> {
+    ptr: *mut t_config_file,
+    weechat: &'a Weechat,
+}
+
+/// A function called when an option of a section is read from the disk.
+///
+/// The arguments are the config file, the section, the name of the option and
+/// the value of the option. The name and the value are `None` when the
+/// callback is called for the `[section]` header.
+pub type SectionReadCallback = Box<
+    dyn FnMut(
+        &Weechat,
+        &ConfigFile,
+        &mut ConfigSection,
+        Option<&str>,
+        Option<&str>,
+    ) -> OptionChanged,
+>;
+
+/// A function called when a section is written to the disk.
+///
+/// The arguments are the config file and the name of the section. The
+/// callback is responsible for writing the `[section]` header using
+/// `ConfigFile::write_line()`.
+pub type SectionWriteCallback =
+    Box<dyn FnMut(&Weechat, &ConfigFile, &str) -> Result<(), ConfigWriteError>>;
+
 /// Represents the options when creating a new config section.
 #[derive(Default)]
 pub struct ConfigSectionInfo<'a, T> {
@@ -48,20 +127,15 @@ pub struct ConfigSectionInfo<'a, T> {
     /// Can the user delete options?
     pub user_can_delete_option: bool,
 
-    /// A function called when an option from the section is read from the disk
-    pub read_callback: Option<fn(&T)>,
-    /// Data passed to the `read_callback`
-    pub read_callback_data: Option<T>,
+    /// A function called when an option from the section is read from the
+    /// disk, it can be used to create options on the fly.
+    pub read_callback: Option<SectionReadCallback>,
 
     /// A function called when the section is written to the disk
-    pub write_callback: Option<fn(&T)>,
-    /// Data passed to the `write_callback`
-    pub write_callback_data: Option<T>,
+    pub write_callback: Option<SectionWriteCallback>,
 
     /// A function called when default values for the section must be written to the disk
-    pub write_default_callback: Option<fn(&T)>,
-    /// Data passed to the `write_default_callback`
-    pub write_default_callback_data: Option<T>,
+    pub write_default_callback: Option<SectionWriteCallback>,
 
     /// A function called when a new option is created in the section
     pub create_option_callback: Option<fn(&T)>,
@@ -74,13 +148,75 @@ pub struct ConfigSectionInfo<'a, T> {
     pub delete_option_callback_data: Option<T>,
 }
 
+/// Error that can happen while reading a config file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigReadError {
+    /// The config file was not found on the disk. This isn't a fatal error,
+    /// the options keep their default values and the file will be created
+    /// once the config is written.
+    FileNotFound,
+    /// Weechat ran out of memory while reading the config file.
+    MemoryError,
+}
+
+impl ConfigReadError {
+    /// Is the error fatal, e.g. the config is unusable.
+    pub fn is_fatal(&self) -> bool {
+        match self {
+            ConfigReadError::FileNotFound => false,
+            ConfigReadError::MemoryError => true,
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigReadError::FileNotFound => {
+                write!(f, "the config file was not found")
+            }
+            ConfigReadError::MemoryError => {
+                write!(f, "not enough memory to read the config file")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigReadError {}
+
+/// Error that can happen while writing a config file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigWriteError {
+    /// The config file couldn't be written to the disk.
+    WriteError,
+    /// Weechat ran out of memory while writing the config file.
+    MemoryError,
+}
+
+impl std::fmt::Display for ConfigWriteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigWriteError::WriteError => {
+                write!(f, "the config file couldn't be written")
+            }
+            ConfigWriteError::MemoryError => {
+                write!(f, "not enough memory to write the config file")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigWriteError {}
+
 impl<T> Drop for Config<T> {
     fn drop(&mut self) {
         let weechat = Weechat::from_ptr(self.weechat_ptr);
         let config_free = weechat.get().config_free.unwrap();
 
-        // Drop the sections first.
-        self.sections.clear();
+        // Drop the sections first, this frees the options and the callback
+        // data of every section exactly once. Freed sections are removed
+        // from the config so `config_free()` won't free them again.
+        self.sections.get_mut().clear();
 
         unsafe {
             // Now drop the config.
@@ -91,11 +227,18 @@ impl<T> Drop for Config<T> {
 
 impl Drop for ConfigSection {
     fn drop(&mut self) {
+        if self._section_data.is_none() {
+            return;
+        }
+
         let weechat = Weechat::from_ptr(self.weechat_ptr);
 
         let options_free = weechat.get().config_section_free_options.unwrap();
         let section_free = weechat.get().config_section_free.unwrap();
 
+        // The option and section callback data is only freed after this,
+        // when the fields are dropped, the delete callbacks of the options
+        // can still run while the options are freed.
         unsafe {
             options_free(self.ptr);
             section_free(self.ptr);
@@ -105,30 +248,174 @@ impl Drop for ConfigSection {
 
 impl<T> Config<T> {
     /// Create a new section in the configuration file.
+    ///
+    /// The section lives as long as the config, so options of several
+    /// sections can be kept around at the same time.
+    #[track_caller]
     pub fn new_section<S: Default>(
-        &mut self,
+        &self,
         section_info: ConfigSectionInfo<S>,
     ) -> &ConfigSection {
         let weechat = Weechat::from_ptr(self.weechat_ptr);
 
         let new_section = weechat.get().config_new_section.unwrap();
 
+        unsafe extern "C" fn c_read_cb(
+            pointer: *const c_void,
+            _data: *mut c_void,
+            config: *mut t_config_file,
+            section: *mut t_config_section,
+            option_name: *const c_char,
+            value: *const c_char,
+        ) -> c_int {
+            let pointers: &mut SectionPointers =
+                { &mut *(pointer as *mut SectionPointers) };
+            let weechat_ptr = pointers.weechat_ptr;
+
+            let option_name = if option_name.is_null() {
+                None
+            } else {
+                Some(CStr::from_ptr(option_name).to_string_lossy())
+            };
+            let value = if value.is_null() {
+                None
+            } else {
+                Some(CStr::from_ptr(value).to_string_lossy())
+            };
+
+            let option_data = pointers.option_data.clone();
+            let callback = match pointers.read_cb.as_mut() {
+                Some(callback) => callback,
+                None => return WEECHAT_CONFIG_OPTION_SET_ERROR,
+            };
+
+            catch_panic(weechat_ptr, || {
+                let weechat = Weechat::from_ptr(weechat_ptr);
+                let config = ConfigFile {
+                    ptr: config,
+                    weechat: &weechat,
+                };
+                // The section is owned by the `Config`, the borrowed one
+                // doesn't free it.
+                let mut section = ConfigSection {
+                    ptr: section,
+                    config_ptr: config.ptr,
+                    weechat_ptr,
+                    _section_data: None,
+                    option_data,
+                };
+
+                callback(
+                    &weechat,
+                    &config,
+                    &mut section,
+                    option_name.as_deref(),
+                    value.as_deref(),
+                ) as i32
+            })
+            .unwrap_or(WEECHAT_CONFIG_OPTION_SET_ERROR)
+        }
+
+        unsafe extern "C" fn c_write_cb(
+            pointer: *const c_void,
+            _data: *mut c_void,
+            config: *mut t_config_file,
+            section_name: *const c_char,
+        ) -> c_int {
+            let pointers: &mut SectionPointers =
+                { &mut *(pointer as *mut SectionPointers) };
+            let callback = pointers.write_cb.as_mut();
+            call_write_cb(pointers.weechat_ptr, callback, config, section_name)
+        }
+
+        unsafe extern "C" fn c_write_default_cb(
+            pointer: *const c_void,
+            _data: *mut c_void,
+            config: *mut t_config_file,
+            section_name: *const c_char,
+        ) -> c_int {
+            let pointers: &mut SectionPointers =
+                { &mut *(pointer as *mut SectionPointers) };
+            let callback = pointers.write_default_cb.as_mut();
+            call_write_cb(pointers.weechat_ptr, callback, config, section_name)
+        }
+
+        unsafe fn call_write_cb(
+            weechat_ptr: *mut t_weechat_plugin,
+            callback: Option<&mut SectionWriteCallback>,
+            config: *mut t_config_file,
+            section_name: *const c_char,
+        ) -> c_int {
+            let section_name = CStr::from_ptr(section_name).to_string_lossy();
+
+            let callback = match callback {
+                Some(callback) => callback,
+                None => return WEECHAT_CONFIG_WRITE_ERROR,
+            };
+
+            catch_panic(weechat_ptr, || {
+                let weechat = Weechat::from_ptr(weechat_ptr);
+                let config = ConfigFile {
+                    ptr: config,
+                    weechat: &weechat,
+                };
+
+                match callback(&weechat, &config, &section_name) {
+                    Ok(()) => WEECHAT_CONFIG_WRITE_OK,
+                    Err(ConfigWriteError::WriteError) => {
+                        WEECHAT_CONFIG_WRITE_ERROR
+                    }
+                    Err(ConfigWriteError::MemoryError) => {
+                        WEECHAT_CONFIG_WRITE_MEMORY_ERROR
+                    }
+                }
+            })
+            .unwrap_or(WEECHAT_CONFIG_WRITE_ERROR)
+        }
+
         let name = LossyCString::new(section_info.name);
 
+        let c_read_cb: Option<WeechatSectionReadCbT> =
+            match section_info.read_callback {
+                Some(_) => Some(c_read_cb),
+                None => None,
+            };
+        let c_write_cb: Option<WeechatSectionWriteCbT> =
+            match section_info.write_callback {
+                Some(_) => Some(c_write_cb),
+                None => None,
+            };
+        let c_write_default_cb: Option<WeechatSectionWriteCbT> =
+            match section_info.write_default_callback {
+                Some(_) => Some(c_write_default_cb),
+                None => None,
+            };
+
+        let option_data = Rc::new(RefCell::new(Vec::new()));
+
+        let section_data = Box::new(SectionPointers {
+            read_cb: section_info.read_callback,
+            write_cb: section_info.write_callback,
+            write_default_cb: section_info.write_default_callback,
+            weechat_ptr: weechat.ptr,
+            option_data: option_data.clone(),
+        });
+        let section_data_ptr = &*section_data as *const _ as *const c_void;
+
         let ptr = unsafe {
             new_section(
                 self.ptr,
                 name.as_ptr(),
                 section_info.user_can_add_options as i32,
                 section_info.user_can_delete_option as i32,
-                None,
+                c_read_cb,
+                section_data_ptr,
                 ptr::null_mut(),
+                c_write_cb,
+                section_data_ptr,
                 ptr::null_mut(),
-                None,
-                ptr::null_mut(),
-                ptr::null_mut(),
-                None,
-                ptr::null_mut(),
+                c_write_default_cb,
+                section_data_ptr,
                 ptr::null_mut(),
                 None,
                 ptr::null_mut(),
@@ -138,38 +425,180 @@ impl<T> Config<T> {
                 ptr::null_mut(),
             )
         };
-        let section = ConfigSection {
+        let section = Box::new(ConfigSection {
             ptr,
             config_ptr: self.ptr,
             weechat_ptr: weechat.ptr,
-        };
-        self.sections.insert(section_info.name.to_string(), section);
-        &self.sections[section_info.name]
+            _section_data: Some(section_data),
+            option_data,
+        });
+        let section_ptr: *const ConfigSection = &*section;
+        self.sections.borrow_mut().push(section);
+
+        // The section is boxed and boxes are only removed from the
+        // list when the config is dropped, so the section outlives the
+        // returned reference.
+        unsafe { &*section_ptr }
     }
 
-    /// Load configuration data from the disk
-    pub fn read(&self) {
+    /// Search for a section of this config by its name.
+    #[track_caller]
+    pub fn search_section(&self, name: &str) -> Option<&ConfigSection> {
         let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let search_section = weechat.get().config_search_section.unwrap();
 
-        let config_read = weechat.get().config_read.unwrap();
+        let name = LossyCString::new(name);
+
+        let ptr = unsafe { search_section(self.ptr, name.as_ptr()) };
+
+        if ptr.is_null() {
+            return None;
+        }
+
+        let sections = self.sections.borrow();
+        let section = sections.iter().find(|section| section.ptr == ptr)?;
+        let section_ptr: *const ConfigSection = &**section;
+
+        // See `new_section()`, the sections live as long as the
+        // config.
+        Some(unsafe { &*section_ptr })
+    }
+
+    /// Search for an option of this config using its full name, e.g.
+    /// `"my_plugin.server.port"`.
+    ///
+    /// Returns `None` if the option doesn't exist or if it belongs to another
+    /// config file.
+    #[track_caller]
+    pub fn search_with_string(&self, name: &str) -> Option<AnyOption<'_>> {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let search_with_string =
+            weechat.get().config_search_with_string.unwrap();
+
+        let name = LossyCString::new(name);
+
+        let mut config_ptr = ptr::null_mut();
+        let mut section_ptr = ptr::null_mut();
+        let mut option_ptr = ptr::null_mut();
 
         unsafe {
-            config_read(self.ptr);
+            search_with_string(
+                name.as_ptr(),
+                &mut config_ptr,
+                &mut section_ptr,
+                &mut option_ptr,
+                ptr::null_mut(),
+            );
+        }
+
+        if config_ptr != self.ptr || option_ptr.is_null() {
+            return None;
+        }
+
+        AnyOption::from_ptrs(option_ptr, self.weechat_ptr)
+    }
+
+    /// Load configuration data from the disk.
+    ///
+    /// This should be called after all the sections and options of the
+    /// config have been created.
+    #[track_caller]
+    pub fn read(&self) -> Result<(), ConfigReadError> {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+
+        let config_read = weechat.get().config_read.unwrap();
+
+        let ret = unsafe { config_read(self.ptr) };
+
+        match ret {
+            WEECHAT_CONFIG_READ_OK => Ok(()),
+            WEECHAT_CONFIG_READ_FILE_NOT_FOUND => {
+                Err(ConfigReadError::FileNotFound)
+            }
+            _ => Err(ConfigReadError::MemoryError),
         }
     }
 
-    /// Save this config file to the disk
-    pub fn write(&self) {
+    /// Save this config file to the disk.
+    #[track_caller]
+    pub fn write(&self) -> Result<(), ConfigWriteError> {
         let weechat = Weechat::from_ptr(self.weechat_ptr);
 
         let config_write = weechat.get().config_write.unwrap();
 
-        unsafe {
-            config_write(self.ptr);
+        let ret = unsafe { config_write(self.ptr) };
+
+        match ret {
+            WEECHAT_CONFIG_WRITE_OK => Ok(()),
+            WEECHAT_CONFIG_WRITE_MEMORY_ERROR => {
+                Err(ConfigWriteError::MemoryError)
+            }
+            _ => Err(ConfigWriteError::WriteError),
         }
     }
 }
 
+impl<'a> ConfigFile<'a> {
+    /// Write a line to the config file.
+    ///
+    /// Returns true if the line was written, false otherwise.
+    ///
+    /// * `name` - The name of the option, or the name of the section if no
+    ///     value is given.
+    /// * `value` - The value of the option, if `None` a `[name]` section
+    ///     header is written.
+    #[track_caller]
+    pub fn write_line(&self, name: &str, value: Option<&str>) -> bool {
+        let write_line = self.weechat.get().config_write_line.unwrap();
+
+        let name = LossyCString::new(name);
+
+        let ret = unsafe {
+            match value {
+                Some(value) => {
+                    let format = cstr!("%s");
+                    let value = LossyCString::new(value);
+                    write_line(
+                        self.ptr,
+                        name.as_ptr(),
+                        format.as_ptr(),
+                        value.as_ptr(),
+                    )
+                }
+                None => write_line(self.ptr, name.as_ptr(), ptr::null()),
+            }
+        };
+
+        ret == 1
+    }
+
+    /// Write an option and its value to the config file.
+    ///
+    /// Returns true if the option was written, false otherwise.
+    #[track_caller]
+    pub fn write_option<'b, O: ConfigOption<'b>>(&self, option: &O) -> bool {
+        let write_option = self.weechat.get().config_write_option.unwrap();
+
+        unsafe { write_option(self.ptr, option.get_ptr()) == 1 }
+    }
+}
+
+type WeechatSectionReadCbT = unsafe extern "C" fn(
+    pointer: *const c_void,
+    _data: *mut c_void,
+    config_pointer: *mut t_config_file,
+    section_pointer: *mut t_config_section,
+    option_name: *const c_char,
+    value: *const c_char,
+) -> c_int;
+
+type WeechatSectionWriteCbT = unsafe extern "C" fn(
+    pointer: *const c_void,
+    _data: *mut c_void,
+    config_pointer: *mut t_config_file,
+    section_name: *const c_char,
+) -> c_int;
+
 type WeechatOptChangeCbT = unsafe extern "C" fn(
     pointer: *const c_void,
     _data: *mut c_void,
@@ -184,84 +613,373 @@ type WeechatOptCheckCbT = unsafe extern "C" fn(
 ) -> c_int;
 
 impl ConfigSection {
-    /// Create a new string Weechat configuration option.
-    pub fn new_string_option<D>(
+    /// Search for an option of this section by its name.
+    #[track_caller]
+    pub fn search_option(&self, name: &str) -> Option<AnyOption<'_>> {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let search_option = weechat.get().config_search_option.unwrap();
+
+        let name = LossyCString::new(name);
+
+        let ptr =
+            unsafe { search_option(self.config_ptr, self.ptr, name.as_ptr()) };
+
+        if ptr.is_null() {
+            None
+        } else {
+            AnyOption::from_ptrs(ptr, self.weechat_ptr)
+        }
+    }
+
+    /// Get all the options of this section.
+    ///
+    /// The options are fetched from Weechat itself, so this includes options
+    /// that were created in a section read callback.
+    #[track_caller]
+    pub fn options(&self) -> impl Iterator<Item = AnyOption<'_>> {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let hdata_get = weechat.get().hdata_get.unwrap();
+        let hdata_pointer = weechat.get().hdata_pointer.unwrap();
+
+        let section_name = cstr!("config_section");
+        let option_name = cstr!("config_option");
+        let options = cstr!("options");
+        let next_option = cstr!("next_option");
+
+        let mut found = Vec::new();
+
+        unsafe {
+            let section_hdata =
+                hdata_get(self.weechat_ptr, section_name.as_ptr());
+            let option_hdata =
+                hdata_get(self.weechat_ptr, option_name.as_ptr());
+
+            if section_hdata.is_null() || option_hdata.is_null() {
+                return found.into_iter();
+            }
+
+            let mut ptr = hdata_pointer(
+                section_hdata,
+                self.ptr as *mut _,
+                options.as_ptr(),
+            ) as *mut t_config_option;
+
+            while !ptr.is_null() {
+                if let Some(option) =
+                    AnyOption::from_ptrs(ptr, self.weechat_ptr)
+                {
+                    found.push(option);
+                }
+
+                ptr = hdata_pointer(
+                    option_hdata,
+                    ptr as *mut _,
+                    next_option.as_ptr(),
+                ) as *mut t_config_option;
+            }
+        }
+
+        found.into_iter()
+    }
+
+    /// Create a new string option using the given settings.
+    ///
+    /// Returns an error if the settings are invalid or if the option can't
+    /// be created.
+    #[track_caller]
+    pub fn add_string_option(
         &self,
-        name: &str,
-        description: &str,
-        default_value: &str,
-        value: &str,
-        null_allowed: bool,
-        change_cb: Option<fn(&mut D, &StringOption)>,
-        change_cb_data: Option<D>,
-    ) -> StringOption
-    where
-        D: Default,
-    {
+        mut settings: StringOptionSettings,
+    ) -> Result<StringOption<'_>, OptionSettingsError> {
+        settings.validate()?;
+
+        let description = settings.full_description();
+        let check_cb = settings.take_check_cb();
+        let value = settings.value.as_ref().unwrap_or(&settings.default_value);
+
         let ptr = self.new_option(
             OptionDescription {
-                name,
-                description,
+                name: &settings.name,
+                description: &description,
                 option_type: OptionType::String,
+                default_value: &settings.default_value,
+                value,
+                null_allowed: settings.null_allowed,
+                ..Default::default()
+            },
+            check_cb,
+            settings.change_cb,
+            settings.delete_cb,
+        )?;
+
+        Ok(StringOption {
+            ptr,
+            weechat_ptr: self.weechat_ptr,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Create a new boolean option using the given settings.
+    ///
+    /// Returns an error if the settings are invalid or if the option can't
+    /// be created.
+    #[track_caller]
+    pub fn add_boolean_option(
+        &self,
+        settings: BooleanOptionSettings,
+    ) -> Result<BooleanOption<'_>, OptionSettingsError> {
+        settings.validate()?;
+
+        let to_str = |value| if value { "on" } else { "off" };
+        let default_value = to_str(settings.default_value);
+        let value = to_str(settings.value.unwrap_or(settings.default_value));
+
+        let ptr = self.new_option(
+            OptionDescription {
+                name: &settings.name,
+                description: &settings.description,
+                option_type: OptionType::Boolean,
                 default_value,
                 value,
-                null_allowed,
+                null_allowed: settings.null_allowed,
                 ..Default::default()
             },
             None,
-            None::<String>,
-            change_cb,
-            change_cb_data,
+            settings.change_cb,
+            settings.delete_cb,
+        )?;
+
+        Ok(BooleanOption {
+            ptr,
+            weechat_ptr: self.weechat_ptr,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Create a new integer option using the given settings.
+    ///
+    /// Returns an error if the settings are invalid, e.g. if the default
+    /// value is outside of the range of the option, or if the option can't
+    /// be created.
+    #[track_caller]
+    pub fn add_integer_option(
+        &self,
+        settings: IntegerOptionSettings,
+    ) -> Result<IntegerOption<'_>, OptionSettingsError> {
+        settings.validate()?;
+
+        let (min, max) = settings.range();
+        let string_values = settings.string_values.join("|");
+        let default_value = settings.format_value(settings.default_value);
+        let value = settings
+            .format_value(settings.value.unwrap_or(settings.default_value));
+
+        let ptr = self.new_option(
+            OptionDescription {
+                name: &settings.name,
+                description: &settings.description,
+                option_type: OptionType::Integer,
+                string_values: &string_values,
+                min,
+                max,
+                default_value: &default_value,
+                value: &value,
+                null_allowed: settings.null_allowed,
+            },
+            settings.check_cb,
+            settings.change_cb,
+            settings.delete_cb,
+        )?;
+
+        Ok(IntegerOption {
+            ptr,
+            weechat_ptr: self.weechat_ptr,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Create a new color option using the given settings.
+    ///
+    /// Returns an error if the settings are invalid or if the option can't
+    /// be created.
+    #[track_caller]
+    pub fn add_color_option(
+        &self,
+        settings: ColorOptionSettings,
+    ) -> Result<ColorOption<'_>, OptionSettingsError> {
+        settings.validate()?;
+
+        let value = settings.value.as_ref().unwrap_or(&settings.default_value);
+
+        let ptr = self.new_option(
+            OptionDescription {
+                name: &settings.name,
+                description: &settings.description,
+                option_type: OptionType::Color,
+                default_value: &settings.default_value,
+                value,
+                null_allowed: settings.null_allowed,
+                ..Default::default()
+            },
+            settings.check_cb,
+            settings.change_cb,
+            settings.delete_cb,
+        )?;
+
+        Ok(ColorOption {
+            ptr,
+            weechat_ptr: self.weechat_ptr,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Create a new enum option using the given settings.
+    ///
+    /// Returns an error if the settings are invalid, e.g. if the default
+    /// value isn't one of the values of the option, or if the option can't
+    /// be created.
+    #[track_caller]
+    pub fn add_enum_option(
+        &self,
+        settings: EnumOptionSettings,
+    ) -> Result<EnumOption<'_>, OptionSettingsError> {
+        settings.validate()?;
+
+        let string_values = settings.values.join("|");
+        let value = settings.value.as_ref().unwrap_or(&settings.default_value);
+
+        let ptr = self.new_option(
+            OptionDescription {
+                name: &settings.name,
+                description: &settings.description,
+                option_type: OptionType::Integer,
+                string_values: &string_values,
+                default_value: &settings.default_value,
+                value,
+                null_allowed: settings.null_allowed,
+                ..Default::default()
+            },
             None,
-            None::<String>,
-        );
+            settings.change_cb,
+            settings.delete_cb,
+        )?;
+
+        Ok(EnumOption {
+            ptr,
+            weechat_ptr: self.weechat_ptr,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Create a new string Weechat configuration option.
+    ///
+    /// The `check_cb` can be used to reject invalid values before they are
+    /// set.
+    ///
+    /// The `change_cb` is called after the value of the option changed and
+    /// the `delete_cb` is called when the option is deleted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the option already exists or if Weechat fails to create it.
+    #[allow(clippy::too_many_arguments)]
+    #[deprecated(note = "Use add_string_option() instead.")]
+    #[track_caller]
+    pub fn new_string_option(
+        &self,
+        name: &str,
+        description: &str,
+        default_value: &str,
+        value: &str,
+        null_allowed: bool,
+        check_cb: Option<CheckValueCallback<StringOption<'static>>>,
+        change_cb: Option<OptionCallback<StringOption<'static>>>,
+        delete_cb: Option<OptionCallback<StringOption<'static>>>,
+    ) -> StringOption<'_> {
+        let ptr = self
+            .new_option(
+                OptionDescription {
+                    name,
+                    description,
+                    option_type: OptionType::String,
+                    default_value,
+                    value,
+                    null_allowed,
+                    ..Default::default()
+                },
+                check_cb,
+                change_cb,
+                delete_cb,
+            )
+            .expect("Can't create the option");
         StringOption {
             ptr,
             weechat_ptr: self.weechat_ptr,
+            _phantom: PhantomData,
         }
     }
 
     /// Create a new boolean Weechat configuration option.
-    pub fn new_boolean_option<D>(
+    ///
+    /// The `change_cb` is called after the value of the option changed and
+    /// the `delete_cb` is called when the option is deleted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the option already exists or if Weechat fails to create it.
+    #[allow(clippy::too_many_arguments)]
+    #[deprecated(note = "Use add_boolean_option() instead.")]
+    #[track_caller]
+    pub fn new_boolean_option(
         &self,
         name: &str,
         description: &str,
         default_value: bool,
         value: bool,
         null_allowed: bool,
-        change_cb: Option<fn(&mut D, &BooleanOption)>,
-        change_cb_data: Option<D>,
-    ) -> BooleanOption
-    where
-        D: Default,
-    {
+        change_cb: Option<OptionCallback<BooleanOption<'static>>>,
+        delete_cb: Option<OptionCallback<BooleanOption<'static>>>,
+    ) -> BooleanOption<'_> {
         let value = if value { "on" } else { "off" };
         let default_value = if default_value { "on" } else { "off" };
-        let ptr = self.new_option(
-            OptionDescription {
-                name,
-                description,
-                option_type: OptionType::Boolean,
-                default_value,
-                value,
-                null_allowed,
-                ..Default::default()
-            },
-            None,
-            None::<String>,
-            change_cb,
-            change_cb_data,
-            None,
-            None::<String>,
-        );
+        let ptr = self
+            .new_option(
+                OptionDescription {
+                    name,
+                    description,
+                    option_type: OptionType::Boolean,
+                    default_value,
+                    value,
+                    null_allowed,
+                    ..Default::default()
+                },
+                None,
+                change_cb,
+                delete_cb,
+            )
+            .expect("Can't create the option");
         BooleanOption {
             ptr,
             weechat_ptr: self.weechat_ptr,
+            _phantom: PhantomData,
         }
     }
 
     /// Create a new integer Weechat configuration option.
-    pub fn new_integer_option<D>(
+    ///
+    /// The `check_cb` can be used to reject invalid values before they are
+    /// set.
+    ///
+    /// The `change_cb` is called after the value of the option changed and
+    /// the `delete_cb` is called when the option is deleted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the option already exists or if Weechat fails to create it.
+    #[allow(clippy::too_many_arguments)]
+    #[deprecated(note = "Use add_integer_option() instead.")]
+    #[track_caller]
+    pub fn new_integer_option(
         &self,
         name: &str,
         description: &str,
@@ -271,91 +989,152 @@ impl ConfigSection {
         default_value: &str,
         value: &str,
         null_allowed: bool,
-        change_cb: Option<fn(&mut D, &IntegerOption)>,
-        change_cb_data: Option<D>,
-    ) -> IntegerOption
-    where
-        D: Default,
-    {
-        let ptr = self.new_option(
-            OptionDescription {
-                name,
-                option_type: OptionType::Integer,
-                description,
-                string_values,
-                min,
-                max,
-                default_value,
-                value,
-                null_allowed,
-            },
-            None,
-            None::<String>,
-            change_cb,
-            change_cb_data,
-            None,
-            None::<String>,
-        );
+        check_cb: Option<CheckValueCallback<IntegerOption<'static>>>,
+        change_cb: Option<OptionCallback<IntegerOption<'static>>>,
+        delete_cb: Option<OptionCallback<IntegerOption<'static>>>,
+    ) -> IntegerOption<'_> {
+        let ptr = self
+            .new_option(
+                OptionDescription {
+                    name,
+                    option_type: OptionType::Integer,
+                    description,
+                    string_values,
+                    min,
+                    max,
+                    default_value,
+                    value,
+                    null_allowed,
+                },
+                check_cb,
+                change_cb,
+                delete_cb,
+            )
+            .expect("Can't create the option");
         IntegerOption {
             ptr,
             weechat_ptr: self.weechat_ptr,
+            _phantom: PhantomData,
         }
     }
 
     /// Create a new color Weechat configuration option.
-    pub fn new_color_option<D>(
+    ///
+    /// The `check_cb` can be used to reject invalid values before they are
+    /// set.
+    ///
+    /// The `change_cb` is called after the value of the option changed and
+    /// the `delete_cb` is called when the option is deleted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the option already exists or if Weechat fails to create it.
+    #[allow(clippy::too_many_arguments)]
+    #[deprecated(note = "Use add_color_option() instead.")]
+    #[track_caller]
+    pub fn new_color_option(
         &self,
         name: &str,
         description: &str,
         default_value: &str,
         value: &str,
         null_allowed: bool,
-        change_cb: Option<fn(&mut D, &ColorOption)>,
-        change_cb_data: Option<D>,
-    ) -> ColorOption
-    where
-        D: Default,
-    {
-        let ptr = self.new_option(
-            OptionDescription {
-                name,
-                description,
-                option_type: OptionType::Color,
-                default_value,
-                value,
-                null_allowed,
-                ..Default::default()
-            },
-            None,
-            None::<String>,
-            change_cb,
-            change_cb_data,
-            None,
-            None::<String>,
-        );
+        check_cb: Option<CheckValueCallback<ColorOption<'static>>>,
+        change_cb: Option<OptionCallback<ColorOption<'static>>>,
+        delete_cb: Option<OptionCallback<ColorOption<'static>>>,
+    ) -> ColorOption<'_> {
+        let ptr = self
+            .new_option(
+                OptionDescription {
+                    name,
+                    description,
+                    option_type: OptionType::Color,
+                    default_value,
+                    value,
+                    null_allowed,
+                    ..Default::default()
+                },
+                check_cb,
+                change_cb,
+                delete_cb,
+            )
+            .expect("Can't create the option");
         ColorOption {
             ptr,
             weechat_ptr: self.weechat_ptr,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Create a new enum Weechat configuration option.
+    ///
+    /// An enum option is an integer option that can only take one of the
+    /// given string values, the values are shown when the option is completed
+    /// using `/set`.
+    ///
+    /// * `values` - The allowed values of the option, the values must not
+    ///     contain a `|`.
+    /// * `default_value` - The default value, must be one of `values`.
+    /// * `value` - The current value, must be one of `values`.
+    ///
+    /// The `change_cb` is called after the value of the option changed and
+    /// the `delete_cb` is called when the option is deleted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the option already exists or if Weechat fails to create it.
+    #[allow(clippy::too_many_arguments)]
+    #[deprecated(note = "Use add_enum_option() instead.")]
+    #[track_caller]
+    pub fn new_enum_option(
+        &self,
+        name: &str,
+        description: &str,
+        values: &[&str],
+        default_value: &str,
+        value: &str,
+        null_allowed: bool,
+        change_cb: Option<OptionCallback<EnumOption<'static>>>,
+        delete_cb: Option<OptionCallback<EnumOption<'static>>>,
+    ) -> EnumOption<'_> {
+        let string_values = values.join("|");
+
+        let ptr = self
+            .new_option(
+                OptionDescription {
+                    name,
+                    description,
+                    option_type: OptionType::Integer,
+                    string_values: &string_values,
+                    default_value,
+                    value,
+                    null_allowed,
+                    ..Default::default()
+                },
+                None,
+                change_cb,
+                delete_cb,
+            )
+            .expect("Can't create the option");
+        EnumOption {
+            ptr,
+            weechat_ptr: self.weechat_ptr,
+            _phantom: PhantomData,
         }
     }
 
-    fn new_option<'a, T, A, B, C>(
+    #[track_caller]
+    fn new_option<T>(
         &self,
         option_description: OptionDescription,
-        check_cb: Option<fn(&mut A, &T, Cow<str>)>,
-        check_cb_data: Option<A>,
-        change_cb: Option<fn(&mut B, &T)>,
-        change_cb_data: Option<B>,
-        delete_cb: Option<fn(&mut C, &T)>,
-        delete_cb_data: Option<C>,
-    ) -> *mut t_config_option
+        check_cb: Option<CheckValueCallback<T>>,
+        change_cb: Option<OptionCallback<T>>,
+        delete_cb: Option<OptionCallback<T>>,
+    ) -> Result<*mut t_config_option, OptionSettingsError>
     where
         T: ConfigOption<'static>,
-        A: Default,
-        B: Default,
-        C: Default,
     {
-        unsafe extern "C" fn c_check_cb<T, A, B, C>(
+        unsafe extern "C" fn c_check_cb<T>(
             pointer: *const c_void,
             _data: *mut c_void,
             option_pointer: *mut t_config_option,
@@ -365,56 +1144,80 @@ impl ConfigSection {
             T: ConfigOption<'static>,
         {
             let value = CStr::from_ptr(value).to_string_lossy();
-            let pointers: &mut OptionPointers<T, A, B, C> =
-                { &mut *(pointer as *mut OptionPointers<T, A, B, C>) };
-
-            let option = T::from_ptrs(option_pointer, pointers.weechat_ptr);
+            let pointers: &OptionPointers<T> =
+                { &*(pointer as *const OptionPointers<T>) };
 
-            let data = &mut pointers.check_cb_data;
+            let weechat_ptr = pointers.weechat_ptr;
+            let option = T::from_ptrs(option_pointer, weechat_ptr);
 
-            if let Some(callback) = pointers.check_cb {
-                callback(data, &option, value)
+            let mut callback = match pointers.check_cb.try_borrow_mut() {
+                Ok(callback) => callback,
+                Err(_) => return 1,
             };
 
-            WEECHAT_RC_OK
+            if let Some(callback) = callback.as_mut() {
+                // A panicking check rejects the value, the option keeps its
+                // previous value.
+                catch_panic(weechat_ptr, || {
+                    let weechat = Weechat::from_ptr(weechat_ptr);
+                    callback(&weechat, &option, value)
+                })
+                .unwrap_or(false) as c_int
+            } else {
+                1
+            }
         }
 
-        unsafe extern "C" fn c_change_cb<T, A, B, C>(
+        unsafe extern "C" fn c_change_cb<T>(
             pointer: *const c_void,
             _data: *mut c_void,
             option_pointer: *mut t_config_option,
         ) where
             T: ConfigOption<'static>,
         {
-            let pointers: &mut OptionPointers<T, A, B, C> =
-                { &mut *(pointer as *mut OptionPointers<T, A, B, C>) };
-
-            let option = T::from_ptrs(option_pointer, pointers.weechat_ptr);
-
-            let data = &mut pointers.change_cb_data;
-
-            if let Some(callback) = pointers.change_cb {
-                callback(data, &option)
-            };
+            let pointers: &OptionPointers<T> =
+                { &*(pointer as *const OptionPointers<T>) };
+            call_option_cb(&pointers.change_cb, pointers, option_pointer);
+            notify_change_listeners(option_pointer, false);
         }
 
-        unsafe extern "C" fn c_delete_cb<T, A, B, C>(
+        unsafe extern "C" fn c_delete_cb<T>(
             pointer: *const c_void,
             _data: *mut c_void,
             option_pointer: *mut t_config_option,
         ) where
             T: ConfigOption<'static>,
         {
-            let pointers: &mut OptionPointers<T, A, B, C> =
-                { &mut *(pointer as *mut OptionPointers<T, A, B, C>) };
-
-            let option = T::from_ptrs(option_pointer, pointers.weechat_ptr);
-
-            let data = &mut pointers.delete_cb_data;
+            let pointers: &OptionPointers<T> =
+                { &*(pointer as *const OptionPointers<T>) };
+            call_option_cb(&pointers.delete_cb, pointers, option_pointer);
+            notify_change_listeners(option_pointer, true);
+        }
 
-            if let Some(callback) = pointers.delete_cb {
-                callback(data, &option)
+        #[track_caller]
+        fn call_option_cb<T>(
+            callback: &RefCell<Option<OptionCallback<T>>>,
+            pointers: &OptionPointers<T>,
+            option_pointer: *mut t_config_option,
+        ) where
+            T: ConfigOption<'static>,
+        {
+            let weechat_ptr = pointers.weechat_ptr;
+            let option = T::from_ptrs(option_pointer, weechat_ptr);
+
+            // If the callback is already running, e.g. it changed the value
+            // of its own option, the nested call is skipped.
+            let mut callback = match callback.try_borrow_mut() {
+                Ok(callback) => callback,
+                Err(_) => return,
             };
+
+            if let Some(callback) = callback.as_mut() {
+                catch_panic(weechat_ptr, || {
+                    let weechat = Weechat::from_ptr(weechat_ptr);
+                    callback(&weechat, &option)
+                });
+            }
         }
 
         let weechat = Weechat::from_ptr(self.weechat_ptr);
@@ -427,37 +1230,31 @@ impl ConfigSection {
         let default_value = LossyCString::new(option_description.default_value);
         let value = LossyCString::new(option_description.value);
 
-        let option_pointers = Box::new(OptionPointers::<T, A, B, C> {
-            weechat_ptr: self.weechat_ptr,
-            check_cb: check_cb,
-            check_cb_data: check_cb_data.unwrap_or_default(),
-            change_cb: change_cb,
-            change_cb_data: change_cb_data.unwrap_or_default(),
-            delete_cb: delete_cb,
-            delete_cb_data: delete_cb_data.unwrap_or_default(),
-        });
-
-        // TODO this leaks curently.
-        let option_pointers_ref: &OptionPointers<T, A, B, C> =
-            Box::leak(option_pointers);
-
         let c_check_cb: Option<WeechatOptCheckCbT> = match check_cb {
-            Some(_) => Some(c_check_cb::<T, A, B, C>),
+            Some(_) => Some(c_check_cb::<T>),
             None => None,
         };
 
-        let c_change_cb: Option<WeechatOptChangeCbT> = match change_cb {
-            Some(_) => Some(c_change_cb::<T, A, B, C>),
-            None => None,
-        };
+        // The change and delete callbacks are always set, they also notify
+        // the change streams of the option.
+        let c_change_cb: Option<WeechatOptChangeCbT> = Some(c_change_cb::<T>);
+        let c_delete_cb: Option<WeechatOptChangeCbT> = Some(c_delete_cb::<T>);
 
-        let c_delete_cb: Option<WeechatOptChangeCbT> = match delete_cb {
-            Some(_) => Some(c_delete_cb::<T, A, B, C>),
-            None => None,
-        };
+        if self.search_option(option_description.name).is_some() {
+            return Err(OptionSettingsError::AlreadyExists(
+                option_description.name.to_owned(),
+            ));
+        }
+
+        let option_pointers = Box::into_raw(Box::new(OptionPointers::<T> {
+            weechat_ptr: self.weechat_ptr,
+            check_cb: RefCell::new(check_cb),
+            change_cb: RefCell::new(change_cb),
+            delete_cb: RefCell::new(delete_cb),
+        }));
 
         let config_new_option = weechat.get().config_new_option.unwrap();
-        unsafe {
+        let ptr = unsafe {
             config_new_option(
                 self.config_ptr,
                 self.ptr,
@@ -471,16 +1268,34 @@ impl ConfigSection {
                 value.as_ptr(),
                 option_description.null_allowed as i32,
                 c_check_cb,
-                option_pointers_ref as *const _ as *const c_void,
+                option_pointers as *const c_void,
                 ptr::null_mut(),
                 c_change_cb,
-                option_pointers_ref as *const _ as *const c_void,
+                option_pointers as *const c_void,
                 ptr::null_mut(),
                 c_delete_cb,
-                option_pointers_ref as *const _ as *const c_void,
+                option_pointers as *const c_void,
                 ptr::null_mut(),
             )
+        };
+
+        if ptr.is_null() {
+            // Weechat didn't keep a reference to the callbacks, so they can be
+            // freed right away.
+            unsafe { drop(Box::from_raw(option_pointers)) };
+            return Err(OptionSettingsError::CreationFailed(
+                option_description.name.to_owned(),
+            ));
         }
+
+        // The option data is freed together with the section, Weechat frees
+        // the options of a section before the section itself.
+        self.option_data.borrow_mut().push(OptionData {
+            ptr: option_pointers as *mut c_void,
+            free: free_option_data::<OptionPointers<T>>,
+        });
+
+        Ok(ptr)
     }
 }
 
@@ -492,6 +1307,25 @@ type WeechatReloadT = unsafe extern "C" fn(
 
 /// Configuration file part of the weechat API.
 impl Weechat {
+    /// Search for a config option by its full name, e.g.
+    /// `"weechat.look.nick_prefix"`.
+    ///
+    /// The option can belong to Weechat or to any other plugin. Returns
+    /// `None` if no option with the given name exists.
+    #[track_caller]
+    pub fn config_get(&self, name: &str) -> Option<ConfigOptionRef<'_>> {
+        let config_get = self.get().config_get.unwrap();
+        let name = LossyCString::new(name);
+
+        let ptr = unsafe { config_get(name.as_ptr()) };
+
+        if ptr.is_null() {
+            None
+        } else {
+            Some(ConfigOptionRef { ptr, weechat: self })
+        }
+    }
+
     /// Create a new Weechat configuration file, returns a `Config` object.
     /// The configuration file is freed when the `Config` object is dropped.
     /// * `name` - Name of the new configuration file
@@ -500,6 +1334,7 @@ impl Weechat {
     /// * `reload_data` - Data that will be taken over by weechat and passed
     /// to the reload callback, this data will be freed when the `Config`
     /// object returned by this method is dropped.
+    #[track_caller]
     pub fn config_new<T: Default>(
         &self,
         name: &str,
@@ -516,11 +1351,14 @@ impl Weechat {
 
             let data = &mut pointers.reload_data;
 
-            if let Some(callback) = pointers.reload_cb {
-                callback(data)
+            match pointers.reload_cb {
+                Some(callback) => {
+                    callback_return_code(pointers.weechat_ptr, |_| {
+                        callback(data)
+                    })
+                }
+                None => ReturnCode::Ok.into_raw(),
             }
-
-            WEECHAT_RC_OK
         }
 
         let c_name = LossyCString::new(name);
@@ -528,6 +1366,7 @@ impl Weechat {
         let config_pointers = Box::new(ConfigPointers::<T> {
             reload_cb: reload_callback,
             reload_data: reload_data.unwrap_or_default(),
+            weechat_ptr: self.ptr,
         });
         let config_pointers_ref = Box::leak(config_pointers);
 
@@ -552,7 +1391,106 @@ impl Weechat {
             ptr: config_ptr,
             weechat_ptr: self.ptr,
             _config_data: config_data,
-            sections: HashMap::new(),
+            sections: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::mock::MockWeechat;
+
+    #[test]
+    fn options_of_several_sections_can_be_kept() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let deleted = Rc::new(RefCell::new(0));
+
+        {
+            let config = weechat.config_new("mock", None, None::<()>);
+
+            let look = config.new_section(ConfigSectionInfo::<()> {
+                name: "look",
+                ..Default::default()
+            });
+            let network = config.new_section(ConfigSectionInfo::<()> {
+                name: "network",
+                ..Default::default()
+            });
+
+            let record = changes.clone();
+            let on_delete = deleted.clone();
+            let nick_color = look
+                .add_string_option(
+                    StringOptionSettings::new("nick_color")
+                        .default_value("blue")
+                        .on_change(move |_, option| {
+                            record
+                                .borrow_mut()
+                                .push(option.value().into_owned())
+                        })
+                        .on_delete(move |_, _| *on_delete.borrow_mut() += 1),
+                )
+                .unwrap();
+            let on_delete = deleted.clone();
+            let show_topic = look
+                .add_boolean_option(
+                    BooleanOptionSettings::new("show_topic")
+                        .default_value(true)
+                        .on_delete(move |_, _| *on_delete.borrow_mut() += 1),
+                )
+                .unwrap();
+
+            let record = changes.clone();
+            let on_delete = deleted.clone();
+            let port = network
+                .add_integer_option(
+                    IntegerOptionSettings::new("port")
+                        .max(65535)
+                        .default_value(6667)
+                        .on_change(move |_, option| {
+                            record.borrow_mut().push(option.value().to_string())
+                        })
+                        .on_delete(move |_, _| *on_delete.borrow_mut() += 1),
+                )
+                .unwrap();
+            let on_delete = deleted.clone();
+            let timeout = network
+                .add_integer_option(
+                    IntegerOptionSettings::new("timeout")
+                        .max(600)
+                        .default_value(60)
+                        .on_delete(move |_, _| *on_delete.borrow_mut() += 1),
+                )
+                .unwrap();
+
+            assert_eq!(nick_color.set("red".into()), OptionChanged::Changed);
+            assert_eq!(port.set("6697"), OptionChanged::Changed);
+            assert_eq!(show_topic.set(false), OptionChanged::Changed);
+            assert_eq!(timeout.set("601"), OptionChanged::Error);
+
+            assert_eq!(nick_color.value(), "red");
+            assert!(!show_topic.value());
+            assert_eq!(port.value(), 6697);
+            assert_eq!(timeout.value(), 60);
+
+            assert_eq!(
+                config.search_section("network").map(|s| s.ptr),
+                Some(network.ptr)
+            );
+            assert_eq!(*changes.borrow(), ["red", "6697"]);
+            assert_eq!(*deleted.borrow(), 0);
         }
+
+        assert_eq!(*deleted.borrow(), 4);
+        assert_eq!(Rc::strong_count(&changes), 1);
+        assert_eq!(Rc::strong_count(&deleted), 1);
+        assert_eq!(mock.config_objects(), 0);
+        assert_eq!(mock.invalid_accesses(), 0);
     }
 }
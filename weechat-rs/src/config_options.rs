@@ -1,8 +1,18 @@
 //! A module providing a typed api for Weechat configuration files
 
-use crate::{LossyCString, Weechat};
+use crate::{Color, ConfigSection, LossyCString, Weechat};
+use futures_core::Stream;
+use libc::{c_char, c_int};
 use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
 use std::ffi::CStr;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::task::{Context, Poll, Waker};
 use weechat_sys::{t_config_option, t_weechat_plugin};
 
 #[derive(Default)]
@@ -18,14 +28,23 @@ pub(crate) struct OptionDescription<'a> {
     pub null_allowed: bool,
 }
 
-pub(crate) enum OptionType {
+/// The type of a config option as Weechat stores it.
+///
+/// Options that take one of a set of string values are integer options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionType {
+    /// A boolean option.
     Boolean,
+    /// An integer option.
     Integer,
+    /// A string option.
     String,
+    /// A color option.
     Color,
 }
 
 impl OptionType {
+    /// Get the name Weechat uses for the type, e.g. `"boolean"`.
     pub fn as_str(&self) -> &'static str {
         match self {
             OptionType::Boolean => "boolean",
@@ -34,6 +53,22 @@ impl OptionType {
             OptionType::Color => "color",
         }
     }
+
+    fn from_name(name: &str) -> Option<OptionType> {
+        match name {
+            "boolean" => Some(OptionType::Boolean),
+            "integer" | "enum" => Some(OptionType::Integer),
+            "string" => Some(OptionType::String),
+            "color" => Some(OptionType::Color),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for OptionType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 impl Default for OptionType {
@@ -42,15 +77,102 @@ impl Default for OptionType {
     }
 }
 
-/// A trait that defines common behavior for the different data types of config options.
-pub trait ConfigOption<'a> {
-    type R;
+#[track_caller]
+fn option_string_property<'a>(
+    option: &'a (impl BaseConfigOption + ?Sized),
+    property: &CStr,
+) -> Option<Cow<'a, str>> {
+    let weechat = option.get_weechat();
+    let option_get_string = weechat.get().config_option_get_string.unwrap();
+
+    unsafe {
+        let value = option_get_string(option.get_ptr(), property.as_ptr());
+        if value.is_null() {
+            None
+        } else {
+            Some(CStr::from_ptr(value).to_string_lossy())
+        }
+    }
+}
 
+/// A trait for the metadata that all config options share, independent of
+/// the type of their value.
+///
+/// The trait can be used as a trait object, e.g. to list options of
+/// different types.
+///
+/// # Example
+///
+/// ```ignore
+/// fn describe(option: &dyn BaseConfigOption) -> String {
+///     format!(
+///         "{}.{}.{} ({}) = {}",
+///         option.config_name(),
+///         option.section_name(),
+///         option.name(),
+///         option.option_type(),
+///         option.value_string(),
+///     )
+/// }
+/// ```
+pub trait BaseConfigOption {
     /// Returns the weechat object that this config option was created with.
     fn get_weechat(&self) -> Weechat;
     /// Returns the raw pointer to the config option.
     fn get_ptr(&self) -> *mut t_config_option;
 
+    /// Get the value of the option formatted as a string, the way Weechat
+    /// displays it.
+    fn value_string(&self) -> Cow<'_, str>;
+
+    /// Get the default value of the option formatted as a string.
+    fn default_value_string(&self) -> Cow<'_, str>;
+
+    /// Get the name of the option.
+    #[track_caller]
+    fn name(&self) -> Cow<'_, str> {
+        option_string_property(self, cstr!("name")).unwrap_or_default()
+    }
+
+    /// Get the description of the option.
+    #[track_caller]
+    fn description(&self) -> Cow<'_, str> {
+        option_string_property(self, cstr!("description")).unwrap_or_default()
+    }
+
+    /// Get the name of the section the option belongs to.
+    #[track_caller]
+    fn section_name(&self) -> Cow<'_, str> {
+        option_string_property(self, cstr!("section_name")).unwrap_or_default()
+    }
+
+    /// Get the name of the config file the option belongs to.
+    #[track_caller]
+    fn config_name(&self) -> Cow<'_, str> {
+        option_string_property(self, cstr!("config_name")).unwrap_or_default()
+    }
+
+    /// Get the type of the option.
+    #[track_caller]
+    fn option_type(&self) -> OptionType {
+        option_string_property(self, cstr!("type"))
+            .and_then(|name| OptionType::from_name(&name))
+            // Every option has one of the known types, Weechat refuses to
+            // create options of other types.
+            .unwrap_or(OptionType::String)
+    }
+
+    /// Does the option have its default value.
+    #[track_caller]
+    fn is_default(&self) -> bool {
+        self.value_string() == self.default_value_string()
+    }
+}
+
+/// A trait that defines common behavior for the different data types of config options.
+pub trait ConfigOption<'a>: BaseConfigOption {
+    type R;
+
     /// Constructs a ConfigOption from its raw pointer and a weechat pointer.
     fn from_ptrs(
         ptr: *mut t_config_option,
@@ -60,10 +182,66 @@ pub trait ConfigOption<'a> {
     /// Get the value of the option.
     fn value(&'a self) -> Self::R;
 
-    /// Set the value of the option
-    fn set(&'a self, value: Self::R) -> crate::OptionChanged;
+    /// Set the value of the option, the change callback of the option runs.
+    #[track_caller]
+    fn set(&'a self, value: Self::R) -> crate::OptionChanged {
+        self.set_with_callback(value, true)
+    }
+
+    /// Set the value of the option.
+    ///
+    /// * `value` - The new value of the option.
+    /// * `run_callback` - Should the change callback of the option run, it
+    ///     can be disabled while a batch of options is changed.
+    fn set_with_callback(
+        &'a self,
+        value: Self::R,
+        run_callback: bool,
+    ) -> crate::OptionChanged;
+
+    /// Get the default value of the option.
+    fn default_value(&'a self) -> Self::R;
+
+    /// Is the value of the option null.
+    ///
+    /// Options can only be null if they were created with `null_allowed`
+    /// set, the value getter returns the empty or zero value of the option
+    /// type for null options.
+    #[track_caller]
+    fn is_null(&self) -> bool {
+        let weechat = self.get_weechat();
+        let option_is_null = weechat.get().config_option_is_null.unwrap();
+
+        unsafe { option_is_null(self.get_ptr()) != 0 }
+    }
+
+    /// Get the value of the option, `None` if the option is null.
+    #[track_caller]
+    fn value_opt(&'a self) -> Option<Self::R> {
+        if self.is_null() {
+            None
+        } else {
+            Some(self.value())
+        }
+    }
+
+    /// Set the value of the option to null.
+    ///
+    /// This only succeeds if the option was created with `null_allowed`
+    /// set.
+    #[track_caller]
+    fn set_null(&self, run_callback: bool) -> crate::OptionChanged {
+        let weechat = self.get_weechat();
+        let option_set_null = weechat.get().config_option_set_null.unwrap();
+
+        let ret =
+            unsafe { option_set_null(self.get_ptr(), run_callback as i32) };
+
+        crate::OptionChanged::from_int(ret)
+    }
 
     /// Resets the option to its default value.
+    #[track_caller]
     fn reset(&self, run_callback: bool) -> crate::OptionChanged {
         let weechat = self.get_weechat();
         let option_reset = weechat.get().config_option_reset.unwrap();
@@ -72,88 +250,144 @@ pub trait ConfigOption<'a> {
 
         crate::OptionChanged::from_int(ret)
     }
+
+    /// Unsets the option, depending on the section the option is either
+    /// reset to its default value or removed.
+    #[track_caller]
+    fn unset(&self) -> crate::OptionUnset {
+        let weechat = self.get_weechat();
+        let option_unset = weechat.get().config_option_unset.unwrap();
+
+        let ret = unsafe { option_unset(self.get_ptr()) };
+
+        crate::OptionUnset::from_int(ret)
+    }
 }
 
-pub(crate) struct OptionPointers<T, A, B, C> {
+/// A function that checks if a new value is valid for an option.
+///
+/// The function is called with the option and the proposed value before the
+/// value is set, it should return `true` if the value is valid. If `false` is
+/// returned the option keeps its previous value.
+pub type CheckValueCallback<T> = Box<dyn FnMut(&Weechat, &T, Cow<str>) -> bool>;
+
+/// A function that is called when an option is changed or deleted.
+///
+/// The function receives a handle to the option, the new value can be read
+/// using the getters of the option. If the function changes the value of its
+/// own option, the nested change doesn't run the function again.
+pub type OptionCallback<T> = Box<dyn FnMut(&Weechat, &T)>;
+
+pub(crate) struct OptionPointers<T> {
     pub(crate) weechat_ptr: *mut t_weechat_plugin,
-    pub(crate) check_cb: Option<fn(&mut A, &T, Cow<str>)>,
-    pub(crate) check_cb_data: A,
-    pub(crate) change_cb: Option<fn(&mut B, &T)>,
-    pub(crate) change_cb_data: B,
-    pub(crate) delete_cb: Option<fn(&mut C, &T)>,
-    pub(crate) delete_cb_data: C,
+    pub(crate) check_cb: RefCell<Option<CheckValueCallback<T>>>,
+    pub(crate) change_cb: RefCell<Option<OptionCallback<T>>>,
+    pub(crate) delete_cb: RefCell<Option<OptionCallback<T>>>,
 }
 
 /// A config option with a string value.
-pub struct StringOption {
+pub struct StringOption<'a> {
     pub(crate) ptr: *mut t_config_option,
     pub(crate) weechat_ptr: *mut t_weechat_plugin,
+    pub(crate) _phantom: PhantomData<&'a ConfigSection>,
 }
 
 /// A config option with a boolean value.
-pub struct BooleanOption {
+pub struct BooleanOption<'a> {
     pub(crate) ptr: *mut t_config_option,
     pub(crate) weechat_ptr: *mut t_weechat_plugin,
+    pub(crate) _phantom: PhantomData<&'a ConfigSection>,
 }
 
 /// A config option with a integer value.
-pub struct IntegerOption {
+pub struct IntegerOption<'a> {
     pub(crate) ptr: *mut t_config_option,
     pub(crate) weechat_ptr: *mut t_weechat_plugin,
+    pub(crate) _phantom: PhantomData<&'a ConfigSection>,
 }
 
 /// A config option with a color value.
-pub struct ColorOption {
+pub struct ColorOption<'a> {
+    pub(crate) ptr: *mut t_config_option,
+    pub(crate) weechat_ptr: *mut t_weechat_plugin,
+    pub(crate) _phantom: PhantomData<&'a ConfigSection>,
+}
+
+/// A config option that takes one value out of a fixed set of string values.
+pub struct EnumOption<'a> {
     pub(crate) ptr: *mut t_config_option,
     pub(crate) weechat_ptr: *mut t_weechat_plugin,
+    pub(crate) _phantom: PhantomData<&'a ConfigSection>,
 }
 
-impl<'a> ConfigOption<'a> for StringOption {
+impl<'a> ConfigOption<'a> for StringOption<'a> {
     type R = Cow<'a, str>;
 
-    fn get_weechat(&self) -> Weechat {
-        Weechat::from_ptr(self.weechat_ptr)
-    }
-    fn get_ptr(&self) -> *mut t_config_option {
-        self.ptr
-    }
     fn from_ptrs(
         ptr: *mut t_config_option,
         weechat_ptr: *mut t_weechat_plugin,
-    ) -> StringOption {
-        StringOption { ptr, weechat_ptr }
+    ) -> Self {
+        StringOption {
+            ptr,
+            weechat_ptr,
+            _phantom: PhantomData,
+        }
     }
 
+    #[track_caller]
     fn value(&self) -> Self::R {
         let weechat = self.get_weechat();
         let config_string = weechat.get().config_string.unwrap();
         unsafe {
             let string = config_string(self.get_ptr());
-            CStr::from_ptr(string).to_string_lossy()
+            if string.is_null() {
+                Cow::Borrowed("")
+            } else {
+                CStr::from_ptr(string).to_string_lossy()
+            }
         }
     }
 
-    fn set(&'a self, value: Self::R) -> crate::OptionChanged {
-        set_str_option(self, value.as_ref())
+    #[track_caller]
+    fn default_value(&'a self) -> Self::R {
+        let weechat = self.get_weechat();
+        let config_string_default =
+            weechat.get().config_string_default.unwrap();
+        unsafe {
+            let string = config_string_default(self.get_ptr());
+            if string.is_null() {
+                Cow::Borrowed("")
+            } else {
+                CStr::from_ptr(string).to_string_lossy()
+            }
+        }
+    }
+
+    #[track_caller]
+    fn set_with_callback(
+        &'a self,
+        value: Self::R,
+        run_callback: bool,
+    ) -> crate::OptionChanged {
+        set_str_option(self, value.as_ref(), run_callback)
     }
 }
 
-impl<'a> ConfigOption<'a> for BooleanOption {
+impl<'a> ConfigOption<'a> for BooleanOption<'a> {
     type R = bool;
 
-    fn get_weechat(&self) -> Weechat {
-        Weechat::from_ptr(self.weechat_ptr)
-    }
-    fn get_ptr(&self) -> *mut t_config_option {
-        self.ptr
-    }
     fn from_ptrs(
         ptr: *mut t_config_option,
         weechat_ptr: *mut t_weechat_plugin,
-    ) -> BooleanOption {
-        BooleanOption { ptr, weechat_ptr }
+    ) -> Self {
+        BooleanOption {
+            ptr,
+            weechat_ptr,
+            _phantom: PhantomData,
+        }
     }
 
+    #[track_caller]
     fn value(&self) -> Self::R {
         let weechat = self.get_weechat();
         let config_boolean = weechat.get().config_boolean.unwrap();
@@ -161,104 +395,1149 @@ impl<'a> ConfigOption<'a> for BooleanOption {
         ret != 0
     }
 
-    fn set(&'a self, value: Self::R) -> crate::OptionChanged {
-        set_str_option(self, if value { "true" } else { "false" })
+    #[track_caller]
+    fn default_value(&self) -> Self::R {
+        let weechat = self.get_weechat();
+        let config_boolean_default =
+            weechat.get().config_boolean_default.unwrap();
+        let ret = unsafe { config_boolean_default(self.get_ptr()) };
+        ret != 0
+    }
+
+    #[track_caller]
+    fn set_with_callback(
+        &'a self,
+        value: Self::R,
+        run_callback: bool,
+    ) -> crate::OptionChanged {
+        let value = if value { "true" } else { "false" };
+        set_str_option(self, value, run_callback)
     }
 }
 
-impl<'a> ConfigOption<'a> for IntegerOption {
+impl<'a> ConfigOption<'a> for IntegerOption<'a> {
     type R = i32;
 
-    fn get_weechat(&self) -> Weechat {
-        Weechat::from_ptr(self.weechat_ptr)
-    }
-    fn get_ptr(&self) -> *mut t_config_option {
-        self.ptr
-    }
     fn from_ptrs(
         ptr: *mut t_config_option,
         weechat_ptr: *mut t_weechat_plugin,
-    ) -> IntegerOption {
-        IntegerOption { ptr, weechat_ptr }
+    ) -> Self {
+        IntegerOption {
+            ptr,
+            weechat_ptr,
+            _phantom: PhantomData,
+        }
     }
 
+    #[track_caller]
     fn value(&self) -> Self::R {
         let weechat = self.get_weechat();
         let config_integer = weechat.get().config_integer.unwrap();
         unsafe { config_integer(self.get_ptr()) }
     }
 
-    fn set(&'a self, value: Self::R) -> crate::OptionChanged {
-        set_str_option(self, &value.to_string())
+    #[track_caller]
+    fn default_value(&self) -> Self::R {
+        let weechat = self.get_weechat();
+        let config_integer_default =
+            weechat.get().config_integer_default.unwrap();
+        unsafe { config_integer_default(self.get_ptr()) }
+    }
+
+    /// Set the value of the option.
+    ///
+    /// For options with string values the value is the index of the string
+    /// value, an index that is out of range results in an error.
+    #[track_caller]
+    fn set_with_callback(
+        &'a self,
+        value: Self::R,
+        run_callback: bool,
+    ) -> crate::OptionChanged {
+        match self.string_values() {
+            Some(values) => {
+                match usize::try_from(value).ok().and_then(|i| values.get(i)) {
+                    Some(value) => set_str_option(self, value, run_callback),
+                    None => crate::OptionChanged::Error,
+                }
+            }
+            None => set_str_option(self, &value.to_string(), run_callback),
+        }
+    }
+}
+
+impl<'a> ConfigOption<'a> for ColorOption<'a> {
+    type R = Cow<'a, str>;
+
+    fn from_ptrs(
+        ptr: *mut t_config_option,
+        weechat_ptr: *mut t_weechat_plugin,
+    ) -> Self {
+        ColorOption {
+            ptr,
+            weechat_ptr,
+            _phantom: PhantomData,
+        }
+    }
+
+    #[track_caller]
+    fn value(&'a self) -> Self::R {
+        let weechat = self.get_weechat();
+        let config_color = weechat.get().config_color.unwrap();
+        unsafe {
+            let string = config_color(self.get_ptr());
+            if string.is_null() {
+                Cow::Borrowed("")
+            } else {
+                CStr::from_ptr(string).to_string_lossy()
+            }
+        }
+    }
+
+    #[track_caller]
+    fn default_value(&'a self) -> Self::R {
+        let weechat = self.get_weechat();
+        let config_color_default = weechat.get().config_color_default.unwrap();
+        unsafe {
+            let string = config_color_default(self.get_ptr());
+            if string.is_null() {
+                Cow::Borrowed("")
+            } else {
+                CStr::from_ptr(string).to_string_lossy()
+            }
+        }
+    }
+
+    #[track_caller]
+    fn set_with_callback(
+        &'a self,
+        value: Self::R,
+        run_callback: bool,
+    ) -> crate::OptionChanged {
+        set_str_option(self, value.as_ref(), run_callback)
     }
 }
 
-impl<'a> ConfigOption<'a> for ColorOption {
+impl<'a> ConfigOption<'a> for EnumOption<'a> {
     type R = Cow<'a, str>;
 
+    fn from_ptrs(
+        ptr: *mut t_config_option,
+        weechat_ptr: *mut t_weechat_plugin,
+    ) -> Self {
+        EnumOption {
+            ptr,
+            weechat_ptr,
+            _phantom: PhantomData,
+        }
+    }
+
+    #[track_caller]
+    fn value(&'a self) -> Self::R {
+        let weechat = self.get_weechat();
+        let config_string = weechat.get().config_string.unwrap();
+        unsafe {
+            let string = config_string(self.get_ptr());
+            if string.is_null() {
+                Cow::Borrowed("")
+            } else {
+                CStr::from_ptr(string).to_string_lossy()
+            }
+        }
+    }
+
+    #[track_caller]
+    fn default_value(&'a self) -> Self::R {
+        let weechat = self.get_weechat();
+        let config_string_default =
+            weechat.get().config_string_default.unwrap();
+        unsafe {
+            let string = config_string_default(self.get_ptr());
+            if string.is_null() {
+                Cow::Borrowed("")
+            } else {
+                CStr::from_ptr(string).to_string_lossy()
+            }
+        }
+    }
+
+    #[track_caller]
+    fn set_with_callback(
+        &'a self,
+        value: Self::R,
+        run_callback: bool,
+    ) -> crate::OptionChanged {
+        set_str_option(self, value.as_ref(), run_callback)
+    }
+}
+
+unsafe fn string_or_empty<'a>(string: *const c_char) -> Cow<'a, str> {
+    if string.is_null() {
+        Cow::Borrowed("")
+    } else {
+        CStr::from_ptr(string).to_string_lossy()
+    }
+}
+
+fn boolean_string(value: c_int) -> Cow<'static, str> {
+    Cow::Borrowed(if value != 0 { "on" } else { "off" })
+}
+
+impl BaseConfigOption for StringOption<'_> {
     fn get_weechat(&self) -> Weechat {
         Weechat::from_ptr(self.weechat_ptr)
     }
+
     fn get_ptr(&self) -> *mut t_config_option {
         self.ptr
     }
-    fn from_ptrs(
+
+    #[track_caller]
+    fn value_string(&self) -> Cow<'_, str> {
+        let config_string = self.get_weechat().get().config_string.unwrap();
+        unsafe { string_or_empty(config_string(self.ptr)) }
+    }
+
+    #[track_caller]
+    fn default_value_string(&self) -> Cow<'_, str> {
+        let config_string_default =
+            self.get_weechat().get().config_string_default.unwrap();
+        unsafe { string_or_empty(config_string_default(self.ptr)) }
+    }
+}
+
+impl BaseConfigOption for BooleanOption<'_> {
+    fn get_weechat(&self) -> Weechat {
+        Weechat::from_ptr(self.weechat_ptr)
+    }
+
+    fn get_ptr(&self) -> *mut t_config_option {
+        self.ptr
+    }
+
+    #[track_caller]
+    fn value_string(&self) -> Cow<'_, str> {
+        let config_boolean = self.get_weechat().get().config_boolean.unwrap();
+        boolean_string(unsafe { config_boolean(self.ptr) })
+    }
+
+    #[track_caller]
+    fn default_value_string(&self) -> Cow<'_, str> {
+        let config_boolean_default =
+            self.get_weechat().get().config_boolean_default.unwrap();
+        boolean_string(unsafe { config_boolean_default(self.ptr) })
+    }
+}
+
+impl BaseConfigOption for IntegerOption<'_> {
+    fn get_weechat(&self) -> Weechat {
+        Weechat::from_ptr(self.weechat_ptr)
+    }
+
+    fn get_ptr(&self) -> *mut t_config_option {
+        self.ptr
+    }
+
+    /// For options with string values this is the selected string value,
+    /// otherwise it's the number formatted as a string.
+    #[track_caller]
+    fn value_string(&self) -> Cow<'_, str> {
+        let weechat = self.get_weechat();
+        let config_string = weechat.get().config_string.unwrap();
+        let config_integer = weechat.get().config_integer.unwrap();
+
+        unsafe {
+            let string = config_string(self.ptr);
+            if string.is_null() {
+                Cow::Owned(config_integer(self.ptr).to_string())
+            } else {
+                CStr::from_ptr(string).to_string_lossy()
+            }
+        }
+    }
+
+    #[track_caller]
+    fn default_value_string(&self) -> Cow<'_, str> {
+        let weechat = self.get_weechat();
+        let config_string_default =
+            weechat.get().config_string_default.unwrap();
+        let config_integer_default =
+            weechat.get().config_integer_default.unwrap();
+
+        unsafe {
+            let string = config_string_default(self.ptr);
+            if string.is_null() {
+                Cow::Owned(config_integer_default(self.ptr).to_string())
+            } else {
+                CStr::from_ptr(string).to_string_lossy()
+            }
+        }
+    }
+}
+
+impl BaseConfigOption for ColorOption<'_> {
+    fn get_weechat(&self) -> Weechat {
+        Weechat::from_ptr(self.weechat_ptr)
+    }
+
+    fn get_ptr(&self) -> *mut t_config_option {
+        self.ptr
+    }
+
+    #[track_caller]
+    fn value_string(&self) -> Cow<'_, str> {
+        let config_color = self.get_weechat().get().config_color.unwrap();
+        unsafe { string_or_empty(config_color(self.ptr)) }
+    }
+
+    #[track_caller]
+    fn default_value_string(&self) -> Cow<'_, str> {
+        let config_color_default =
+            self.get_weechat().get().config_color_default.unwrap();
+        unsafe { string_or_empty(config_color_default(self.ptr)) }
+    }
+}
+
+impl BaseConfigOption for EnumOption<'_> {
+    fn get_weechat(&self) -> Weechat {
+        Weechat::from_ptr(self.weechat_ptr)
+    }
+
+    fn get_ptr(&self) -> *mut t_config_option {
+        self.ptr
+    }
+
+    #[track_caller]
+    fn value_string(&self) -> Cow<'_, str> {
+        let config_string = self.get_weechat().get().config_string.unwrap();
+        unsafe { string_or_empty(config_string(self.ptr)) }
+    }
+
+    #[track_caller]
+    fn default_value_string(&self) -> Cow<'_, str> {
+        let config_string_default =
+            self.get_weechat().get().config_string_default.unwrap();
+        unsafe { string_or_empty(config_string_default(self.ptr)) }
+    }
+}
+
+/// Error returned if the value of an option can't be parsed.
+#[derive(Debug)]
+pub struct InvalidOptionValue<E> {
+    /// The value of the option that couldn't be parsed.
+    pub value: String,
+    /// The error that was returned while parsing the value.
+    pub error: E,
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for InvalidOptionValue<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid option value {:?}: {}", self.value, self.error)
+    }
+}
+
+impl<E: std::fmt::Debug + std::fmt::Display> std::error::Error
+    for InvalidOptionValue<E>
+{
+}
+
+/// A config option of any type, returned when searching for options.
+pub enum AnyOption<'a> {
+    /// A string option.
+    String(StringOption<'a>),
+    /// A boolean option.
+    Boolean(BooleanOption<'a>),
+    /// An integer option.
+    Integer(IntegerOption<'a>),
+    /// A color option.
+    Color(ColorOption<'a>),
+    /// An integer option that takes one of a set of string values.
+    Enum(EnumOption<'a>),
+}
+
+impl<'a> AnyOption<'a> {
+    /// Create an option of the correct type from a raw option pointer.
+    #[track_caller]
+    pub(crate) fn from_ptrs(
         ptr: *mut t_config_option,
         weechat_ptr: *mut t_weechat_plugin,
-    ) -> ColorOption {
-        ColorOption { ptr, weechat_ptr }
+    ) -> Option<AnyOption<'a>> {
+        let weechat = Weechat::from_ptr(weechat_ptr);
+        let get_string = weechat.get().config_option_get_string.unwrap();
+        let get_pointer = weechat.get().config_option_get_pointer.unwrap();
+
+        let type_property = cstr!("type");
+        let values_property = cstr!("string_values");
+
+        let option_type = unsafe {
+            let option_type = get_string(ptr, type_property.as_ptr());
+            if option_type.is_null() {
+                return None;
+            }
+            CStr::from_ptr(option_type).to_string_lossy()
+        };
+
+        let option = match option_type.as_ref() {
+            "string" => {
+                AnyOption::String(StringOption::from_ptrs(ptr, weechat_ptr))
+            }
+            "boolean" => {
+                AnyOption::Boolean(BooleanOption::from_ptrs(ptr, weechat_ptr))
+            }
+            "integer" => {
+                let string_values =
+                    unsafe { get_pointer(ptr, values_property.as_ptr()) };
+
+                if string_values.is_null() {
+                    AnyOption::Integer(IntegerOption::from_ptrs(
+                        ptr,
+                        weechat_ptr,
+                    ))
+                } else {
+                    AnyOption::Enum(EnumOption::from_ptrs(ptr, weechat_ptr))
+                }
+            }
+            "color" => {
+                AnyOption::Color(ColorOption::from_ptrs(ptr, weechat_ptr))
+            }
+            _ => return None,
+        };
+
+        Some(option)
     }
+}
+
+/// A borrowed handle to a config option that wasn't created by the plugin,
+/// e.g. an option of Weechat itself or of another plugin.
+///
+/// The getters return `None` if the option doesn't have the requested type.
+pub struct ConfigOptionRef<'a> {
+    pub(crate) ptr: *mut t_config_option,
+    pub(crate) weechat: &'a Weechat,
+}
+
+impl<'a> ConfigOptionRef<'a> {
+    #[track_caller]
+    fn get_string_property(&self, property: &str) -> Option<Cow<'_, str>> {
+        let option_get_string =
+            self.weechat.get().config_option_get_string.unwrap();
+        let property = LossyCString::new(property);
 
-    fn value(&'a self) -> Self::R {
-        let weechat = self.get_weechat();
-        let config_color = weechat.get().config_color.unwrap();
         unsafe {
-            let string = config_color(self.get_ptr());
-            CStr::from_ptr(string).to_string_lossy()
+            let value = option_get_string(self.ptr, property.as_ptr());
+            if value.is_null() {
+                None
+            } else {
+                Some(CStr::from_ptr(value).to_string_lossy())
+            }
         }
     }
 
-    fn set(&'a self, value: Self::R) -> crate::OptionChanged {
-        set_str_option(self, value.as_ref())
+    #[track_caller]
+    fn has_type(&self, option_type: OptionType) -> bool {
+        self.option_type().as_deref() == Some(option_type.as_str())
+    }
+
+    /// Get the name of the option.
+    #[track_caller]
+    pub fn name(&self) -> Option<Cow<'_, str>> {
+        self.get_string_property("name")
+    }
+
+    /// Get the type of the option, e.g. `"boolean"`, `"integer"`, `"string"`
+    /// or `"color"`.
+    #[track_caller]
+    pub fn option_type(&self) -> Option<Cow<'_, str>> {
+        self.get_string_property("type")
+    }
+
+    /// Is the value of the option null.
+    #[track_caller]
+    pub fn is_null(&self) -> bool {
+        let option_is_null = self.weechat.get().config_option_is_null.unwrap();
+        unsafe { option_is_null(self.ptr) != 0 }
+    }
+
+    /// Get the value of a string option.
+    #[track_caller]
+    pub fn string(&self) -> Option<Cow<'_, str>> {
+        if !self.has_type(OptionType::String) {
+            return None;
+        }
+
+        let config_string = self.weechat.get().config_string.unwrap();
+        unsafe {
+            let string = config_string(self.ptr);
+            if string.is_null() {
+                None
+            } else {
+                Some(CStr::from_ptr(string).to_string_lossy())
+            }
+        }
+    }
+
+    /// Get the value of an integer option.
+    #[track_caller]
+    pub fn integer(&self) -> Option<i32> {
+        if !self.has_type(OptionType::Integer) {
+            return None;
+        }
+
+        let config_integer = self.weechat.get().config_integer.unwrap();
+        Some(unsafe { config_integer(self.ptr) })
+    }
+
+    /// Get the value of a boolean option.
+    #[track_caller]
+    pub fn boolean(&self) -> Option<bool> {
+        if !self.has_type(OptionType::Boolean) {
+            return None;
+        }
+
+        let config_boolean = self.weechat.get().config_boolean.unwrap();
+        Some(unsafe { config_boolean(self.ptr) != 0 })
+    }
+
+    /// Get the value of a color option.
+    #[track_caller]
+    pub fn color(&self) -> Option<Cow<'_, str>> {
+        if !self.has_type(OptionType::Color) {
+            return None;
+        }
+
+        let config_color = self.weechat.get().config_color.unwrap();
+        unsafe {
+            let color = config_color(self.ptr);
+            if color.is_null() {
+                None
+            } else {
+                Some(CStr::from_ptr(color).to_string_lossy())
+            }
+        }
+    }
+
+    /// Set the value of the option.
+    ///
+    /// The option isn't owned by the plugin, changing it affects Weechat or
+    /// the plugin that owns the option, prefer to only read such options.
+    ///
+    /// * `value` - The new value of the option, it is parsed according to the
+    ///     type of the option.
+    /// * `run_callback` - Should the change callback of the option run.
+    #[track_caller]
+    pub fn set_foreign_value(
+        &self,
+        value: &str,
+        run_callback: bool,
+    ) -> crate::OptionChanged {
+        let config_option_set = self.weechat.get().config_option_set.unwrap();
+        let value = LossyCString::new(value);
+
+        let ret = unsafe {
+            config_option_set(self.ptr, value.as_ptr(), run_callback as i32)
+        };
+        crate::OptionChanged::from_int(ret)
     }
 }
 
-impl PartialEq<bool> for BooleanOption {
+impl PartialEq<bool> for BooleanOption<'_> {
+    #[track_caller]
     fn eq(&self, other: &bool) -> bool {
         self.value() == *other
     }
 }
 
-impl StringOption {
+impl BooleanOption<'_> {
+    /// Get a stream of the values the option is changed to, see
+    /// `OptionChanges`.
+    pub fn changes(&self) -> OptionChanges<bool> {
+        OptionChanges::new(self.ptr, self.weechat_ptr, |ptr, weechat_ptr| {
+            BooleanOption::from_ptrs(ptr, weechat_ptr).value()
+        })
+    }
+}
+
+impl StringOption<'_> {
     /// Set the value of the option
+    #[track_caller]
     pub fn set(&self, value: &str) -> crate::OptionChanged {
-        set_str_option(self, value)
+        set_str_option(self, value, true)
+    }
+
+    /// Set the value of the option, optionally without running the change
+    /// callback of the option.
+    #[track_caller]
+    pub fn set_with_callback(
+        &self,
+        value: &str,
+        run_callback: bool,
+    ) -> crate::OptionChanged {
+        set_str_option(self, value, run_callback)
+    }
+
+    /// Parse the value of the option into a user defined type.
+    ///
+    /// Returns an error containing the stored value if it can't be parsed,
+    /// e.g. if the config file was edited by hand.
+    #[track_caller]
+    pub fn value_as<T: FromStr>(
+        &self,
+    ) -> Result<T, InvalidOptionValue<T::Err>> {
+        let value = self.value();
+
+        value.parse().map_err(|error| InvalidOptionValue {
+            value: value.into_owned(),
+            error,
+        })
+    }
+
+    /// Get the value of the option with secured data references, e.g.
+    /// `${sec.data.token}`, expanded.
+    ///
+    /// The returned string may contain secrets, it should never be printed or
+    /// logged.
+    #[track_caller]
+    pub fn secure_value(&self) -> Option<String> {
+        self.get_weechat().expand_secure_data(&self.value())
+    }
+
+    /// Get a stream of the values the option is changed to, see
+    /// `OptionChanges`.
+    pub fn changes(&self) -> OptionChanges<String> {
+        OptionChanges::new(self.ptr, self.weechat_ptr, |ptr, weechat_ptr| {
+            StringOption::from_ptrs(ptr, weechat_ptr)
+                .value()
+                .into_owned()
+        })
     }
 }
 
-impl ColorOption {
+impl ColorOption<'_> {
     /// Set the value of the option
+    #[track_caller]
+    pub fn set(&self, value: &str) -> crate::OptionChanged {
+        set_str_option(self, value, true)
+    }
+
+    /// Set the value of the option, optionally without running the change
+    /// callback of the option.
+    #[track_caller]
+    pub fn set_with_callback(
+        &self,
+        value: &str,
+        run_callback: bool,
+    ) -> crate::OptionChanged {
+        set_str_option(self, value, run_callback)
+    }
+
+    /// Get the value of the option as a parsed color.
+    #[track_caller]
+    pub fn color(&self) -> Color {
+        match self.value().parse() {
+            Ok(color) => color,
+            Err(e) => match e {},
+        }
+    }
+
+    /// Get a stream of the values the option is changed to, see
+    /// `OptionChanges`.
+    pub fn changes(&self) -> OptionChanges<String> {
+        OptionChanges::new(self.ptr, self.weechat_ptr, |ptr, weechat_ptr| {
+            ColorOption::from_ptrs(ptr, weechat_ptr)
+                .value()
+                .into_owned()
+        })
+    }
+}
+
+impl EnumOption<'_> {
+    /// Set the value of the option, the value must be one of the allowed
+    /// values of the option.
+    #[track_caller]
     pub fn set(&self, value: &str) -> crate::OptionChanged {
-        set_str_option(self, value)
+        set_str_option(self, value, true)
+    }
+
+    /// Set the value of the option, optionally without running the change
+    /// callback of the option.
+    #[track_caller]
+    pub fn set_with_callback(
+        &self,
+        value: &str,
+        run_callback: bool,
+    ) -> crate::OptionChanged {
+        set_str_option(self, value, run_callback)
+    }
+
+    /// Get the index of the selected value in the list of allowed values.
+    #[track_caller]
+    pub fn index(&self) -> usize {
+        let weechat = self.get_weechat();
+        let config_integer = weechat.get().config_integer.unwrap();
+        unsafe { config_integer(self.get_ptr()) as usize }
+    }
+
+    /// Convert the selected value into a user defined type.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// enum Mode {
+    ///     Off,
+    ///     Local,
+    ///     Remote,
+    /// }
+    ///
+    /// impl FromStr for Mode {
+    ///     type Err = ();
+    ///
+    ///     fn from_str(s: &str) -> Result<Self, Self::Err> {
+    ///         match s {
+    ///             "off" => Ok(Mode::Off),
+    ///             "local" => Ok(Mode::Local),
+    ///             "remote" => Ok(Mode::Remote),
+    ///             _ => Err(()),
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// let mode: Mode = option.parse().unwrap();
+    /// ```
+    #[track_caller]
+    pub fn parse<E: FromStr>(&self) -> Result<E, E::Err> {
+        self.value().parse()
+    }
+
+    /// Get a stream of the values the option is changed to, see
+    /// `OptionChanges`.
+    pub fn changes(&self) -> OptionChanges<String> {
+        OptionChanges::new(self.ptr, self.weechat_ptr, |ptr, weechat_ptr| {
+            EnumOption::from_ptrs(ptr, weechat_ptr).value().into_owned()
+        })
     }
 }
 
-impl IntegerOption {
+impl IntegerOption<'_> {
     /// Set the value of the option, only valid for options with `string_values`
+    #[track_caller]
     pub fn set(&self, value: &str) -> crate::OptionChanged {
-        set_str_option(self, value)
+        set_str_option(self, value, true)
+    }
+
+    /// Set the value of the option, optionally without running the change
+    /// callback of the option.
+    #[track_caller]
+    pub fn set_with_callback(
+        &self,
+        value: &str,
+        run_callback: bool,
+    ) -> crate::OptionChanged {
+        set_str_option(self, value, run_callback)
+    }
+
+    /// Get the string values of the option, `None` if the option doesn't
+    /// have string values.
+    #[track_caller]
+    pub fn string_values(&self) -> Option<Vec<String>> {
+        let weechat = self.get_weechat();
+        let get_pointer = weechat.get().config_option_get_pointer.unwrap();
+
+        let property = cstr!("string_values");
+
+        unsafe {
+            let values = get_pointer(self.get_ptr(), property.as_ptr())
+                as *const *const c_char;
+
+            if values.is_null() {
+                return None;
+            }
+
+            let mut string_values = Vec::new();
+            let mut i = 0;
+
+            while !(*values.offset(i)).is_null() {
+                let value = CStr::from_ptr(*values.offset(i));
+                string_values.push(value.to_string_lossy().into_owned());
+                i += 1;
+            }
+
+            Some(string_values)
+        }
+    }
+
+    /// Get a stream of the values the option is changed to, see
+    /// `OptionChanges`.
+    pub fn changes(&self) -> OptionChanges<i32> {
+        OptionChanges::new(self.ptr, self.weechat_ptr, |ptr, weechat_ptr| {
+            IntegerOption::from_ptrs(ptr, weechat_ptr).value()
+        })
     }
 }
 
+#[track_caller]
 fn set_str_option<'a>(
     option: &impl ConfigOption<'a>,
     value: &str,
+    run_callback: bool,
 ) -> crate::OptionChanged {
     let weechat = option.get_weechat();
     let config_option_set = weechat.get().config_option_set.unwrap();
     unsafe {
         let string = LossyCString::new(value);
-        let ret = config_option_set(option.get_ptr(), string.as_ptr(), 1);
+        let ret = config_option_set(
+            option.get_ptr(),
+            string.as_ptr(),
+            run_callback as i32,
+        );
         crate::OptionChanged::from_int(ret)
     }
 }
+
+/// What happened to an option that has change streams.
+#[derive(Clone, Copy)]
+enum OptionEvent {
+    Changed,
+    Deleted,
+}
+
+type ChangeListener = Rc<dyn Fn(OptionEvent)>;
+
+// The change streams of an option, with the id of each stream.
+type ChangeListeners = Vec<(usize, ChangeListener)>;
+
+thread_local! {
+    // The change streams of every option, by the pointer of the option.
+    static CHANGE_LISTENERS: RefCell<HashMap<usize, ChangeListeners>> =
+        RefCell::new(HashMap::new());
+    static NEXT_LISTENER_ID: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Let the change streams of an option know that the option changed or was
+/// deleted, this is called from the callbacks of the options this crate
+/// creates.
+pub(crate) fn notify_change_listeners(
+    option: *mut t_config_option,
+    deleted: bool,
+) {
+    // The listeners are cloned out of the registry, a woken up task might
+    // drop its stream while the others are notified.
+    let listeners: Vec<ChangeListener> = CHANGE_LISTENERS.with(|listeners| {
+        let mut listeners = listeners.borrow_mut();
+
+        let found = if deleted {
+            listeners.remove(&(option as usize)).unwrap_or_default()
+        } else {
+            listeners
+                .get(&(option as usize))
+                .cloned()
+                .unwrap_or_default()
+        };
+
+        found.into_iter().map(|(_, listener)| listener).collect()
+    });
+
+    let event = if deleted {
+        OptionEvent::Deleted
+    } else {
+        OptionEvent::Changed
+    };
+
+    for listener in listeners {
+        listener(event);
+    }
+}
+
+struct ChangeQueue<T> {
+    values: VecDeque<T>,
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+/// A stream of the values an option is changed to.
+///
+/// The stream is created with the `changes()` method of an option, it
+/// yields the value of the option after each change and ends once the
+/// option is deleted. Changes are queued until the stream is polled, no
+/// change is lost.
+///
+/// The stream can only be polled on the main thread, it's meant to be
+/// consumed by a task spawned with `Weechat::spawn()`. Dropping the stream
+/// stops the notifications, other streams of the same option keep on getting
+/// every change.
+///
+/// Only options created by this plugin report their changes, a stream of an
+/// option that belongs to Weechat or to another plugin never yields a value.
+///
+/// # Example
+///
+/// ```ignore
+/// use futures::StreamExt;
+///
+/// let mut changes = option.changes();
+///
+/// weechat.spawn(async move {
+///     while let Some(value) = changes.next().await {
+///         Weechat::print(&format!("The option is now {}", value));
+///     }
+/// });
+/// ```
+pub struct OptionChanges<T> {
+    option: *mut t_config_option,
+    id: usize,
+    queue: Rc<RefCell<ChangeQueue<T>>>,
+}
+
+impl<T: 'static> OptionChanges<T> {
+    fn new(
+        option: *mut t_config_option,
+        weechat_ptr: *mut t_weechat_plugin,
+        value: fn(*mut t_config_option, *mut t_weechat_plugin) -> T,
+    ) -> Self {
+        let queue = Rc::new(RefCell::new(ChangeQueue {
+            values: VecDeque::new(),
+            waker: None,
+            closed: false,
+        }));
+
+        let weak_queue = Rc::downgrade(&queue);
+        let listener: ChangeListener = Rc::new(move |event| {
+            let queue = match weak_queue.upgrade() {
+                Some(queue) => queue,
+                None => return,
+            };
+            let mut queue = queue.borrow_mut();
+
+            match event {
+                OptionEvent::Changed => {
+                    queue.values.push_back(value(option, weechat_ptr))
+                }
+                OptionEvent::Deleted => queue.closed = true,
+            }
+
+            if let Some(waker) = queue.waker.take() {
+                waker.wake();
+            }
+        });
+
+        let id = NEXT_LISTENER_ID.with(|next_id| {
+            let id = next_id.get();
+            next_id.set(id.wrapping_add(1));
+            id
+        });
+
+        CHANGE_LISTENERS.with(|listeners| {
+            listeners
+                .borrow_mut()
+                .entry(option as usize)
+                .or_default()
+                .push((id, listener))
+        });
+
+        OptionChanges { option, id, queue }
+    }
+}
+
+impl<T> Stream for OptionChanges<T> {
+    type Item = T;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<T>> {
+        let mut queue = self.queue.borrow_mut();
+
+        if let Some(value) = queue.values.pop_front() {
+            Poll::Ready(Some(value))
+        } else if queue.closed {
+            Poll::Ready(None)
+        } else {
+            queue.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl<T> Drop for OptionChanges<T> {
+    fn drop(&mut self) {
+        // The registry might already be gone if the stream is dropped while
+        // the thread exits.
+        let _ = CHANGE_LISTENERS.try_with(|listeners| {
+            let mut listeners = listeners.borrow_mut();
+
+            if let Some(option_listeners) =
+                listeners.get_mut(&(self.option as usize))
+            {
+                option_listeners.retain(|(id, _)| *id != self.id);
+
+                if option_listeners.is_empty() {
+                    listeners.remove(&(self.option as usize));
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigSectionInfo;
+    use crate::mock::MockWeechat;
+    use crate::{
+        BooleanOptionSettings, ColorOptionSettings, EnumOptionSettings,
+        IntegerOptionSettings, StringOptionSettings,
+    };
+
+    fn describe(option: &dyn BaseConfigOption) -> String {
+        format!(
+            "{}.{}.{} ({}) = {}",
+            option.config_name(),
+            option.section_name(),
+            option.name(),
+            option.option_type(),
+            option.value_string(),
+        )
+    }
+
+    #[test]
+    fn options_can_be_described_through_the_base_trait() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let config = weechat.config_new("mock", None, None::<()>);
+        let section = config.new_section(ConfigSectionInfo::<()> {
+            name: "look",
+            ..Default::default()
+        });
+
+        let string = section
+            .add_string_option(
+                StringOptionSettings::new("prefix")
+                    .description("The prefix of messages")
+                    .default_value("=!="),
+            )
+            .unwrap();
+        let boolean = section
+            .add_boolean_option(
+                BooleanOptionSettings::new("show_topic").default_value(true),
+            )
+            .unwrap();
+        let integer = section
+            .add_integer_option(
+                IntegerOptionSettings::new("lines")
+                    .max(100)
+                    .default_value(10)
+                    .value(20),
+            )
+            .unwrap();
+        let color = section
+            .add_color_option(
+                ColorOptionSettings::new("nick_color").default_value("blue"),
+            )
+            .unwrap();
+        let enumeration = section
+            .add_enum_option(
+                EnumOptionSettings::new("align")
+                    .values(&["left", "right"])
+                    .default_value("left")
+                    .value("right"),
+            )
+            .unwrap();
+
+        let options: [&dyn BaseConfigOption; 5] =
+            [&string, &boolean, &integer, &color, &enumeration];
+        let described: Vec<String> =
+            options.iter().map(|option| describe(*option)).collect();
+
+        assert_eq!(
+            described,
+            [
+                "mock.look.prefix (string) = =!=",
+                "mock.look.show_topic (boolean) = on",
+                "mock.look.lines (integer) = 20",
+                "mock.look.nick_color (color) = blue",
+                "mock.look.align (integer) = right",
+            ]
+        );
+        assert_eq!(string.description(), "The prefix of messages");
+        assert_eq!(integer.default_value_string(), "10");
+        assert_eq!(
+            options.iter().map(|o| o.is_default()).collect::<Vec<_>>(),
+            [true, true, false, true, false]
+        );
+    }
+
+    fn change_listeners() -> usize {
+        CHANGE_LISTENERS.with(|l| l.borrow().values().map(Vec::len).sum())
+    }
+
+    async fn next<T>(changes: &mut OptionChanges<T>) -> Option<T> {
+        std::future::poll_fn(|cx| Pin::new(&mut *changes).poll_next(cx)).await
+    }
+
+    #[test]
+    fn change_streams_get_every_change() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let first = Rc::new(RefCell::new(Vec::new()));
+        let second = Rc::new(RefCell::new(Vec::new()));
+
+        let config = weechat.config_new("mock", None, None::<()>);
+        let section = config.new_section(ConfigSectionInfo::<()> {
+            name: "look",
+            ..Default::default()
+        });
+        let lines = section
+            .add_integer_option(
+                IntegerOptionSettings::new("lines")
+                    .max(100)
+                    .default_value(10),
+            )
+            .unwrap();
+
+        let mut changes = lines.changes();
+        let seen = first.clone();
+        let first_task = weechat.spawn(async move {
+            while let Some(value) = next(&mut changes).await {
+                seen.borrow_mut().push(value);
+            }
+        });
+
+        let mut changes = lines.changes();
+        let seen = second.clone();
+        let second_task = weechat.spawn(async move {
+            // Stop after two changes, this drops the stream.
+            for _ in 0..2 {
+                if let Some(value) = next(&mut changes).await {
+                    seen.borrow_mut().push(value);
+                }
+            }
+        });
+
+        mock.run_until_idle();
+        assert_eq!(change_listeners(), 2);
+
+        // Both changes are queued before the tasks run.
+        lines.set("20");
+        lines.set("30");
+        mock.run_until_idle();
+
+        assert_eq!(*first.borrow(), [20, 30]);
+        assert_eq!(*second.borrow(), [20, 30]);
+        assert!(second_task.is_finished());
+        assert_eq!(change_listeners(), 1);
+
+        lines.set("40");
+        // Changes without running the callbacks aren't reported.
+        lines.set_with_callback("50", false);
+        mock.run_until_idle();
+
+        assert_eq!(*first.borrow(), [20, 30, 40]);
+        assert_eq!(*second.borrow(), [20, 30]);
+
+        // Deleting the option ends the stream.
+        drop(config);
+        mock.run_until_idle();
+
+        assert!(first_task.is_finished());
+        assert_eq!(change_listeners(), 0);
+    }
+}
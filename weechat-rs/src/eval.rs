@@ -0,0 +1,191 @@
+//! Expression evaluation module.
+//! Allows plugins to evaluate Weechat `${...}` expressions and to expand
+//! paths using their own variables.
+
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::os::raw::c_void;
+
+use weechat_sys::t_hashtable;
+
+use crate::hdata::HDataPointer;
+use crate::{Buffer, Hashtable, HashtableItemType, LossyCString, Weechat};
+
+/// An object that can be referenced in an evaluated expression, e.g. as
+/// `${buffer.full_name}`.
+pub enum EvalPointer<'a> {
+    /// A buffer.
+    Buffer(&'a Buffer),
+    /// An object that was found using hdata.
+    HData(&'a HDataPointer),
+}
+
+impl<'a> EvalPointer<'a> {
+    fn as_ptr(&self) -> *mut c_void {
+        match self {
+            EvalPointer::Buffer(buffer) => buffer.ptr as *mut c_void,
+            EvalPointer::HData(pointer) => pointer.ptr,
+        }
+    }
+}
+
+impl<'a> From<&'a Buffer> for EvalPointer<'a> {
+    fn from(buffer: &'a Buffer) -> Self {
+        EvalPointer::Buffer(buffer)
+    }
+}
+
+impl<'a> From<&'a HDataPointer> for EvalPointer<'a> {
+    fn from(pointer: &'a HDataPointer) -> Self {
+        EvalPointer::HData(pointer)
+    }
+}
+
+type EvalFunction = unsafe extern "C" fn(
+    *const libc::c_char,
+    *mut t_hashtable,
+    *mut t_hashtable,
+    *mut t_hashtable,
+) -> *mut libc::c_char;
+
+impl Weechat {
+    #[track_caller]
+    fn string_hashtable(
+        &self,
+        map: &HashMap<String, String>,
+    ) -> Option<Hashtable> {
+        let hashtable = self.new_hashtable(
+            32,
+            HashtableItemType::String,
+            HashtableItemType::String,
+        )?;
+
+        for (key, value) in map {
+            hashtable.set(key, value);
+        }
+
+        Some(hashtable)
+    }
+
+    #[track_caller]
+    fn eval_with(
+        &self,
+        eval: EvalFunction,
+        string: &str,
+        pointers: &HashMap<String, EvalPointer>,
+        extra_vars: &HashMap<String, String>,
+        options: &HashMap<String, String>,
+    ) -> Option<String> {
+        let pointer_table = self.new_hashtable(
+            32,
+            HashtableItemType::String,
+            HashtableItemType::Pointer,
+        )?;
+
+        for (key, pointer) in pointers {
+            unsafe { pointer_table.set_pointer(key, pointer.as_ptr()).ok()? };
+        }
+
+        let extra_vars = self.string_hashtable(extra_vars)?;
+        let options = self.string_hashtable(options)?;
+
+        let string = LossyCString::new(string);
+
+        unsafe {
+            let result = eval(
+                string.as_ptr(),
+                pointer_table.ptr,
+                extra_vars.ptr,
+                options.ptr,
+            );
+
+            if result.is_null() {
+                None
+            } else {
+                let evaluated = CStr::from_ptr(result).to_string_lossy().into();
+                libc::free(result as *mut c_void);
+                Some(evaluated)
+            }
+        }
+    }
+
+    /// Evaluate an expression.
+    ///
+    /// Returns the evaluated expression, `None` if the expression couldn't be
+    /// evaluated.
+    ///
+    /// * `expression` - The expression to evaluate, e.g. `"${buffer.name}"`.
+    /// * `pointers` - Objects that can be referenced in the expression, the
+    ///     `"window"` and `"buffer"` pointers default to the current window
+    ///     and buffer.
+    /// * `extra_vars` - Extra variables that will be expanded in the
+    ///     expression.
+    /// * `options` - Options for the evaluation, e.g. `"type"` set to
+    ///     `"condition"` to evaluate a condition. See the weechat C API
+    ///     documentation for valid values.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut pointers = HashMap::new();
+    /// pointers.insert("buffer".to_owned(), EvalPointer::from(&buffer));
+    ///
+    /// let name = weechat.eval_expression(
+    ///     "${buffer.full_name}",
+    ///     &pointers,
+    ///     &HashMap::new(),
+    ///     &HashMap::new(),
+    /// );
+    /// ```
+    #[track_caller]
+    pub fn eval_expression(
+        &self,
+        expression: &str,
+        pointers: &HashMap<String, EvalPointer>,
+        extra_vars: &HashMap<String, String>,
+        options: &HashMap<String, String>,
+    ) -> Option<String> {
+        let string_eval_expression = self.get().string_eval_expression.unwrap();
+
+        self.eval_with(
+            string_eval_expression,
+            expression,
+            pointers,
+            extra_vars,
+            options,
+        )
+    }
+
+    /// Evaluate a path.
+    ///
+    /// A leading `%h` is replaced by the Weechat data directory, a leading
+    /// `~` by the home directory of the user, and the path is then evaluated
+    /// like an expression, e.g. `${weechat_data_dir}` is expanded.
+    ///
+    /// Returns the evaluated path, `None` if the path couldn't be evaluated.
+    ///
+    /// * `path` - The path to evaluate, e.g. `"%h/logs"`.
+    /// * `pointers` - Objects that can be referenced in the path.
+    /// * `extra_vars` - Extra variables that will be expanded in the path.
+    /// * `options` - Options for the evaluation, e.g. `"directory"` set to
+    ///     `"cache"` to replace `%h` with the cache directory. See the weechat
+    ///     C API documentation for valid values.
+    #[track_caller]
+    pub fn eval_path_home(
+        &self,
+        path: &str,
+        pointers: &HashMap<String, EvalPointer>,
+        extra_vars: &HashMap<String, String>,
+        options: &HashMap<String, String>,
+    ) -> Option<String> {
+        let string_eval_path_home = self.get().string_eval_path_home.unwrap();
+
+        self.eval_with(
+            string_eval_path_home,
+            path,
+            pointers,
+            extra_vars,
+            options,
+        )
+    }
+}
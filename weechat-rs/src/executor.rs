@@ -0,0 +1,678 @@
+//! Async executor module.
+//! Allows plugins to run futures and closures on the Weechat main thread.
+//!
+//! The executor is driven by the Weechat main loop, tasks and closures run on
+//! the main thread so they can safely use Weechat objects. Tasks can be woken
+//! up and closures can be queued from any thread, both write to a pipe that
+//! is watched by an fd hook.
+
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, ThreadId};
+
+use libc::c_void;
+use weechat_sys::t_weechat_plugin;
+
+use crate::hooks::{FdHook, FdHookAction, FdHookMode, FdReadiness, HookError};
+use crate::{catch_panic, panic_message, Weechat};
+
+thread_local! {
+    static EXECUTOR: RefCell<Option<Executor>> = const { RefCell::new(None) };
+}
+
+/// The shared state of the running executor, used to queue closures from
+/// other threads.
+static SHARED: Mutex<Option<Arc<Shared>>> = Mutex::new(None);
+
+type TaskId = usize;
+type Job = Box<dyn FnOnce(&Weechat) + Send>;
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// Errors that can happen while the executor is started.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StartError {
+    /// The wakeup pipe couldn't be created, contains the OS error code.
+    Pipe(i32),
+    /// The wakeup pipe couldn't be hooked.
+    Hook(HookError),
+}
+
+impl std::fmt::Display for StartError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StartError::Pipe(errno) => write!(
+                f,
+                "can't create the wakeup pipe of the executor: {}",
+                std::io::Error::from_raw_os_error(*errno)
+            ),
+            StartError::Hook(e) => {
+                write!(f, "can't hook the wakeup pipe of the executor: {}", e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StartError {}
+
+/// The state that is shared with the wakers and other threads.
+struct Shared {
+    ready: Mutex<VecDeque<TaskId>>,
+    jobs: Mutex<VecDeque<Job>>,
+    write_fd: RawFd,
+    closed: AtomicBool,
+    main_thread: ThreadId,
+    weechat_ptr: *mut t_weechat_plugin,
+}
+
+// The Weechat pointer is only ever dereferenced on the main thread.
+unsafe impl Send for Shared {}
+unsafe impl Sync for Shared {}
+
+impl Shared {
+    fn wakeup(&self) {
+        // The pipe is non-blocking, if it's full a wakeup is already pending
+        // so the result can be ignored.
+        let byte = 1u8;
+        unsafe {
+            libc::write(self.write_fd, &byte as *const u8 as *const c_void, 1)
+        };
+    }
+
+    fn schedule(&self, id: TaskId) {
+        if self.closed.load(Ordering::SeqCst) {
+            return;
+        }
+
+        lock(&self.ready).push_back(id);
+        self.wakeup();
+    }
+
+    fn take_ready(&self) -> VecDeque<TaskId> {
+        std::mem::take(&mut *lock(&self.ready))
+    }
+
+    /// Queue a closure, the closure is given back if the executor was shut
+    /// down.
+    fn queue(&self, job: Job) -> Result<(), Job> {
+        {
+            let mut jobs = lock(&self.jobs);
+
+            // The flag is checked while holding the lock, the queue is
+            // drained a last time after the flag was set.
+            if self.closed.load(Ordering::SeqCst) {
+                return Err(job);
+            }
+
+            jobs.push_back(job);
+        }
+
+        self.wakeup();
+        Ok(())
+    }
+
+    /// Run all the queued closures.
+    fn run_jobs(&self) {
+        let jobs = std::mem::take(&mut *lock(&self.jobs));
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+
+        for job in jobs {
+            catch_panic(self.weechat_ptr, || job(&weechat));
+        }
+    }
+
+    fn close(&self) {
+        let _jobs = lock(&self.jobs);
+        self.closed.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.write_fd) };
+    }
+}
+
+struct TaskWaker {
+    id: TaskId,
+    shared: Arc<Shared>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.shared.schedule(self.id);
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.shared.schedule(self.id);
+    }
+}
+
+struct Task {
+    id: TaskId,
+    future: RefCell<Option<Pin<Box<dyn Future<Output = ()>>>>>,
+    finished: Cell<bool>,
+    join_waker: RefCell<Option<Waker>>,
+}
+
+impl Task {
+    fn finish(&self) {
+        self.finished.set(true);
+
+        if let Some(waker) = self.join_waker.borrow_mut().take() {
+            waker.wake();
+        }
+    }
+}
+
+/// The read end of the wakeup pipe.
+struct WakeupPipe {
+    read_fd: RawFd,
+}
+
+impl AsRawFd for WakeupPipe {
+    fn as_raw_fd(&self) -> RawFd {
+        self.read_fd
+    }
+}
+
+impl Drop for WakeupPipe {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.read_fd) };
+    }
+}
+
+struct Executor {
+    weechat_ptr: *mut t_weechat_plugin,
+    tasks: RefCell<HashMap<TaskId, Rc<Task>>>,
+    next_id: Cell<TaskId>,
+    shared: Arc<Shared>,
+    _hook: FdHook<(), WakeupPipe>,
+}
+
+impl Executor {
+    #[track_caller]
+    fn new(weechat: &Weechat) -> Result<Executor, StartError> {
+        let mut fds = [0; 2];
+
+        let ret = unsafe { libc::pipe(fds.as_mut_ptr()) };
+
+        if ret != 0 {
+            let errno = std::io::Error::last_os_error().raw_os_error();
+            return Err(StartError::Pipe(errno.unwrap_or(0)));
+        }
+
+        for fd in fds.iter() {
+            unsafe {
+                let flags = libc::fcntl(*fd, libc::F_GETFL);
+                libc::fcntl(*fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+            }
+        }
+
+        let pipe = WakeupPipe { read_fd: fds[0] };
+        let shared = Arc::new(Shared {
+            ready: Mutex::new(VecDeque::new()),
+            jobs: Mutex::new(VecDeque::new()),
+            write_fd: fds[1],
+            closed: AtomicBool::new(false),
+            main_thread: thread::current().id(),
+            weechat_ptr: weechat.ptr,
+        });
+
+        fn wakeup_cb(
+            _data: &(),
+            _weechat: &Weechat,
+            pipe: &mut WakeupPipe,
+            _: FdReadiness,
+        ) -> FdHookAction {
+            let mut buffer = [0u8; 64];
+
+            loop {
+                let read = unsafe {
+                    libc::read(
+                        pipe.read_fd,
+                        buffer.as_mut_ptr() as *mut c_void,
+                        buffer.len(),
+                    )
+                };
+
+                if read <= 0 {
+                    break;
+                }
+            }
+
+            run_jobs();
+            run_ready();
+
+            FdHookAction::Continue
+        }
+
+        // Both ends of the pipe are closed if the hook fails, the read end
+        // is dropped by the hook and the write end by the shared state.
+        let hook = weechat
+            .hook_fd_with_weechat(pipe, FdHookMode::Read, wakeup_cb, None)
+            .map_err(StartError::Hook)?;
+
+        *lock(&SHARED) = Some(shared.clone());
+
+        Ok(Executor {
+            weechat_ptr: weechat.ptr,
+            tasks: RefCell::new(HashMap::new()),
+            next_id: Cell::new(0),
+            shared,
+            _hook: hook,
+        })
+    }
+
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()>>>) -> Rc<Task> {
+        let id = self.next_id.get();
+        self.next_id.set(id.wrapping_add(1));
+
+        let task = Rc::new(Task {
+            id,
+            future: RefCell::new(Some(future)),
+            finished: Cell::new(false),
+            join_waker: RefCell::new(None),
+        });
+
+        self.tasks.borrow_mut().insert(id, task.clone());
+        self.shared.schedule(id);
+
+        task
+    }
+}
+
+impl Drop for Executor {
+    fn drop(&mut self) {
+        self.shared.close();
+    }
+}
+
+/// Run all the closures that were queued using `on_main`.
+fn run_jobs() {
+    let shared = EXECUTOR
+        .with(|executor| executor.borrow().as_ref().map(|e| e.shared.clone()));
+
+    if let Some(shared) = shared {
+        shared.run_jobs();
+    }
+}
+
+/// Poll all the tasks that were woken up.
+fn run_ready() {
+    let ready = EXECUTOR.with(|executor| {
+        executor.borrow().as_ref().map(|e| e.shared.take_ready())
+    });
+
+    for id in ready.into_iter().flatten() {
+        // Don't keep the executor borrowed while the task is polled, the task
+        // might spawn other tasks.
+        let task = EXECUTOR.with(|executor| {
+            executor.borrow().as_ref().and_then(|e| {
+                let task = e.tasks.borrow().get(&id).cloned()?;
+                Some((task, e.shared.clone(), e.weechat_ptr))
+            })
+        });
+
+        let (task, shared, weechat_ptr) = match task {
+            Some(task) => task,
+            None => continue,
+        };
+
+        // The future is taken out while it's polled, if the task was woken up
+        // multiple times it's only polled once.
+        let mut future = match task.future.borrow_mut().take() {
+            Some(future) => future,
+            None => continue,
+        };
+
+        let waker = Waker::from(Arc::new(TaskWaker { id, shared }));
+        let mut context = Context::from_waker(&waker);
+
+        let poll =
+            catch_panic(weechat_ptr, || future.as_mut().poll(&mut context));
+
+        match poll {
+            Some(Poll::Pending) => {
+                // The task might have been cancelled while it was polled.
+                if !task.finished.get() {
+                    *task.future.borrow_mut() = Some(future);
+                }
+            }
+            Some(Poll::Ready(())) | None => remove_task(&task),
+        }
+    }
+}
+
+fn remove_task(task: &Task) {
+    EXECUTOR.with(|executor| {
+        if let Some(executor) = executor.borrow().as_ref() {
+            executor.tasks.borrow_mut().remove(&task.id);
+        }
+    });
+
+    task.finish();
+}
+
+/// Start the executor, this needs to be called on the main thread.
+///
+/// This is called when the plugin gets loaded, closures can only be queued
+/// using `on_main` once the executor is running. Returns an error if the
+/// wakeup pipe of the executor can't be set up.
+#[doc(hidden)]
+pub fn start(weechat: &Weechat) -> Result<(), StartError> {
+    EXECUTOR.with(|executor| {
+        let mut executor = executor.borrow_mut();
+
+        if executor.is_none() {
+            *executor = Some(Executor::new(weechat)?);
+        }
+
+        Ok(())
+    })
+}
+
+/// Run the remaining closures, drop all the tasks, remove the executor and
+/// the main thread channels.
+///
+/// This is called when the plugin gets unloaded.
+#[doc(hidden)]
+pub fn shutdown() {
+    lock(&SHARED).take();
+
+    let shared = EXECUTOR
+        .with(|executor| executor.borrow().as_ref().map(|e| e.shared.clone()));
+
+    // No closures can be queued after the executor is closed, the ones that
+    // are still queued run before the tasks are dropped.
+    if let Some(shared) = shared {
+        shared.close();
+        shared.run_jobs();
+    }
+
+    let executor = EXECUTOR.with(|executor| executor.borrow_mut().take());
+
+    if let Some(executor) = executor {
+        let tasks: Vec<Rc<Task>> = executor
+            .tasks
+            .borrow_mut()
+            .drain()
+            .map(|(_, t)| t)
+            .collect();
+
+        for task in tasks {
+            task.future.borrow_mut().take();
+            task.finish();
+        }
+    }
+    crate::channel::shutdown();
+}
+
+/// The result of a closure that runs on a background thread, shared between
+/// the thread and the task that waits for the result.
+struct BlockingState<T> {
+    result: Option<thread::Result<T>>,
+    waker: Option<Waker>,
+}
+
+/// A future that resolves once the background thread finished.
+struct BlockingResult<T> {
+    state: Arc<Mutex<BlockingState<T>>>,
+}
+
+impl<T> Future for BlockingResult<T> {
+    type Output = thread::Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = lock(&self.state);
+
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// A handle to a spawned task.
+///
+/// Dropping the handle detaches the task, it keeps on running. The handle is
+/// a future which resolves once the task finishes.
+pub struct JoinHandle {
+    task: Rc<Task>,
+}
+
+impl JoinHandle {
+    /// Has the task finished, either by running to completion, by panicking
+    /// or by being cancelled.
+    pub fn is_finished(&self) -> bool {
+        self.task.finished.get()
+    }
+
+    /// Cancel the task, the future of the task is dropped.
+    pub fn cancel(self) {
+        self.task.future.borrow_mut().take();
+        remove_task(&self.task);
+    }
+}
+
+impl Future for JoinHandle {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.task.finished.get() {
+            Poll::Ready(())
+        } else {
+            *self.task.join_waker.borrow_mut() = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl Weechat {
+    /// Spawn a new task on the Weechat main loop.
+    ///
+    /// The future is polled on the main thread, it may hold and use Weechat
+    /// objects. It can be woken up from any thread. Panics inside the task
+    /// are caught and printed on the core buffer, the task is then removed.
+    /// All tasks are dropped when the plugin gets unloaded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the executor isn't running and can't be started.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let handle = weechat.spawn(async move {
+    ///     let message = receiver.recv().await;
+    ///     buffer.print(&message);
+    /// });
+    /// ```
+    pub fn spawn(
+        &self,
+        future: impl Future<Output = ()> + 'static,
+    ) -> JoinHandle {
+        let task = EXECUTOR.with(|executor| {
+            let mut executor = executor.borrow_mut();
+
+            if executor.is_none() {
+                let started = Executor::new(self)
+                    .unwrap_or_else(|e| panic!("Can't spawn the task: {}", e));
+                *executor = Some(started);
+            }
+
+            executor.as_ref().unwrap().spawn(Box::pin(future))
+        });
+
+        JoinHandle { task }
+    }
+
+    /// Run a closure on a background thread and hand its result to a
+    /// closure on the main thread.
+    ///
+    /// The `work` closure runs on its own thread, it must not use Weechat
+    /// objects. Once it returns, the `done` closure runs from the Weechat
+    /// main loop with its result, so it may use Weechat objects, e.g. print
+    /// the result on a buffer.
+    ///
+    /// If `work` panics, the panic is printed on the core buffer and `done`
+    /// doesn't run. If the plugin gets unloaded before `work` returns, the
+    /// result is dropped on the background thread and `done` doesn't run.
+    /// Note that the code of the plugin is unloaded as well, long running
+    /// closures should be stopped when the plugin is dropped.
+    ///
+    /// This returns a handle to the task that waits for the result,
+    /// cancelling it prevents `done` from running.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// weechat.spawn_blocking(
+    ///     move || std::fs::read_to_string(path),
+    ///     move |_, contents| match contents {
+    ///         Ok(contents) => buffer.print(&contents),
+    ///         Err(e) => buffer.print_error(&e.to_string()),
+    ///     },
+    /// );
+    /// ```
+    #[track_caller]
+    pub fn spawn_blocking<T: Send + 'static>(
+        &self,
+        work: impl FnOnce() -> T + Send + 'static,
+        done: impl FnOnce(&Weechat, T) + 'static,
+    ) -> JoinHandle {
+        let state = Arc::new(Mutex::new(BlockingState {
+            result: None,
+            waker: None,
+        }));
+
+        let thread_state = state.clone();
+
+        let spawned = thread::Builder::new()
+            .name("weechat-blocking".to_owned())
+            .spawn(move || {
+                let result = panic::catch_unwind(AssertUnwindSafe(work));
+
+                let waker = {
+                    let mut state = lock(&thread_state);
+                    state.result = Some(result);
+                    state.waker.take()
+                };
+
+                // Waking up the task does nothing once the executor was
+                // shut down.
+                if let Some(waker) = waker {
+                    waker.wake();
+                }
+            });
+
+        if let Err(e) = spawned {
+            lock(&state).result = Some(Err(Box::new(format!(
+                "can't spawn a background thread: {}",
+                e
+            ))));
+        }
+
+        let weechat_ptr = self.ptr;
+
+        self.spawn(async move {
+            let result = BlockingResult { state }.await;
+            let weechat = Weechat::from_ptr(weechat_ptr);
+
+            match result {
+                Ok(result) => done(&weechat, result),
+                Err(payload) => weechat.print_error(&format!(
+                    "Panic in a background task: {}",
+                    panic_message(&*payload)
+                )),
+            }
+        })
+    }
+
+    /// Run a closure on the main thread.
+    ///
+    /// This can be called from any thread, the closure is queued and runs
+    /// from the Weechat main loop with a reference to the `Weechat` object.
+    /// Closures run in the order they were queued, the closures that are
+    /// still queued when the plugin gets unloaded run before the plugin is
+    /// unloaded.
+    ///
+    /// The closure is dropped without running if the plugin isn't loaded.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// std::thread::spawn(move || {
+    ///     let response = fetch_messages();
+    ///
+    ///     Weechat::on_main(move |weechat| {
+    ///         weechat.print(&response);
+    ///     });
+    /// });
+    /// ```
+    pub fn on_main(f: impl FnOnce(&Weechat) + Send + 'static) {
+        let shared = lock(&SHARED).clone();
+
+        if let Some(shared) = shared {
+            // The closure is dropped if the executor was shut down.
+            let _ = shared.queue(Box::new(f));
+        }
+    }
+
+    /// Run a closure on the main thread and wait for its result.
+    ///
+    /// This can be called from any thread, the closure is queued and runs
+    /// from the Weechat main loop with a reference to the `Weechat` object.
+    /// If this is called on the main thread the closure runs right away.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the plugin isn't loaded or gets unloaded before the closure
+    /// ran, or if the closure panics.
+    pub fn on_main_blocking<F, R>(f: F) -> R
+    where
+        F: FnOnce(&Weechat) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let shared = lock(&SHARED)
+            .clone()
+            .expect("The Weechat plugin isn't loaded");
+
+        if thread::current().id() == shared.main_thread {
+            return f(&Weechat::from_ptr(shared.weechat_ptr));
+        }
+
+        let (sender, receiver) = mpsc::channel();
+
+        let job: Job = Box::new(move |weechat| {
+            let _ = sender.send(f(weechat));
+        });
+
+        if shared.queue(job).is_err() {
+            panic!("The Weechat plugin was unloaded");
+        }
+
+        // Drop our reference so the shared state can be freed on unload.
+        drop(shared);
+
+        receiver
+            .recv()
+            .expect("The closure panicked or the plugin was unloaded")
+    }
+}
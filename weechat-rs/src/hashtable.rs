@@ -1,12 +1,36 @@
 //! Hashtables allow storing key value pairs.
 
-use crate::{LossyCString, Weechat};
-use std::ffi::CString;
+use crate::{Buffer, LossyCString, Weechat};
+use libc::{c_char, c_int, c_void, time_t};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::marker::PhantomData;
 use weechat_sys::{t_hashtable, t_weechat_plugin};
 
+/// A Weechat hashtable.
+///
+/// Hashtables created with `Weechat::new_hashtable()` are freed when they are
+/// dropped, hashtables borrowed from Weechat are left alone.
 pub struct Hashtable {
     weechat_ptr: *mut t_weechat_plugin,
     pub(crate) ptr: *mut t_hashtable,
+    owned: bool,
+}
+
+impl Drop for Hashtable {
+    fn drop(&mut self) {
+        if !self.owned {
+            return;
+        }
+
+        let hashtable_free = Weechat::from_ptr(self.weechat_ptr)
+            .get()
+            .hashtable_free
+            .unwrap();
+
+        unsafe { hashtable_free(self.ptr) }
+    }
 }
 
 pub enum HashtableItemType {
@@ -17,6 +41,26 @@ pub enum HashtableItemType {
     Time,
 }
 
+/// Error returned when a value is stored in a hashtable that stores a
+/// different value type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidValueType {
+    /// The value type of the hashtable, e.g. `"string"`.
+    pub value_type: String,
+}
+
+impl std::fmt::Display for InvalidValueType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the hashtable stores {} values, not pointers",
+            self.value_type
+        )
+    }
+}
+
+impl std::error::Error for InvalidValueType {}
+
 impl ToString for HashtableItemType {
     fn to_string(&self) -> String {
         use HashtableItemType::*;
@@ -33,6 +77,7 @@ impl ToString for HashtableItemType {
 
 impl Weechat {
     /// Create a new hashtable with the given key and value types.
+    #[track_caller]
     pub fn new_hashtable(
         &self,
         size: u16,
@@ -61,13 +106,31 @@ impl Weechat {
             Some(Hashtable {
                 weechat_ptr: self.ptr,
                 ptr: hashtable,
+                owned: true,
             })
         }
     }
 }
 
 impl Hashtable {
+    /// Wrap a raw hashtable pointer.
+    ///
+    /// Owned hashtables are freed on drop, hashtables that are owned by
+    /// Weechat, e.g. ones that were passed to a callback, must not be owned.
+    pub(crate) fn from_ptrs(
+        weechat_ptr: *mut t_weechat_plugin,
+        ptr: *mut t_hashtable,
+        owned: bool,
+    ) -> Hashtable {
+        Hashtable {
+            weechat_ptr,
+            ptr,
+            owned,
+        }
+    }
+
     /// Add or update an item in the hashtable.
+    #[track_caller]
     pub fn set(&self, key: &str, value: &str) {
         let weechat_hashtable_set = Weechat::from_ptr(self.weechat_ptr)
             .get()
@@ -85,4 +148,560 @@ impl Hashtable {
             );
         }
     }
+
+    /// Add or update an item in a hashtable that stores pointer or buffer
+    /// values.
+    ///
+    /// Returns an error if the hashtable stores a different value type.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must stay valid for as long as Weechat or other plugins
+    /// might dereference it, e.g. while the hashtable is in use.
+    #[track_caller]
+    pub unsafe fn set_pointer(
+        &self,
+        key: &str,
+        ptr: *mut c_void,
+    ) -> Result<(), InvalidValueType> {
+        let value_type = self.value_type();
+
+        match value_type.as_ref() {
+            "pointer" | "buffer" => (),
+            _ => return Err(InvalidValueType { value_type }),
+        }
+
+        let hashtable_set = Weechat::from_ptr(self.weechat_ptr)
+            .get()
+            .hashtable_set
+            .unwrap();
+
+        let key = LossyCString::new(key);
+
+        hashtable_set(self.ptr, key.as_ptr() as *const _, ptr);
+
+        Ok(())
+    }
+
+    /// Add or update a buffer in a hashtable that stores pointer or buffer
+    /// values.
+    ///
+    /// Returns an error if the hashtable stores a different value type.
+    #[track_caller]
+    pub fn set_buffer(
+        &self,
+        key: &str,
+        buffer: &Buffer,
+    ) -> Result<(), InvalidValueType> {
+        unsafe { self.set_pointer(key, buffer.ptr as *mut c_void) }
+    }
+
+    /// Remove an item from the hashtable.
+    ///
+    /// Nothing happens if the key isn't found in the hashtable.
+    #[track_caller]
+    pub fn remove(&self, key: &str) {
+        let hashtable_remove = Weechat::from_ptr(self.weechat_ptr)
+            .get()
+            .hashtable_remove
+            .unwrap();
+
+        let key = LossyCString::new(key);
+
+        unsafe { hashtable_remove(self.ptr, key.as_ptr() as *const _) }
+    }
+
+    /// Remove all the items from the hashtable.
+    #[track_caller]
+    pub fn remove_all(&self) {
+        let hashtable_remove_all = Weechat::from_ptr(self.weechat_ptr)
+            .get()
+            .hashtable_remove_all
+            .unwrap();
+
+        unsafe { hashtable_remove_all(self.ptr) }
+    }
+
+    /// Check if the hashtable contains an item with the given key.
+    #[track_caller]
+    pub fn has_key(&self, key: &str) -> bool {
+        let hashtable_has_key = Weechat::from_ptr(self.weechat_ptr)
+            .get()
+            .hashtable_has_key
+            .unwrap();
+
+        let key = LossyCString::new(key);
+
+        unsafe { hashtable_has_key(self.ptr, key.as_ptr() as *const _) != 0 }
+    }
+
+    /// Get the number of items in the hashtable.
+    #[track_caller]
+    pub fn len(&self) -> i32 {
+        let hashtable_get_integer = Weechat::from_ptr(self.weechat_ptr)
+            .get()
+            .hashtable_get_integer
+            .unwrap();
+
+        let property = cstr!("items_count");
+
+        unsafe { hashtable_get_integer(self.ptr, property.as_ptr()) }
+    }
+
+    /// Check if the hashtable contains no items.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the keys of all the items in the hashtable.
+    pub fn keys(&self) -> Vec<String> {
+        self.iter().map(|(key, _)| key).collect()
+    }
+
+    /// Iterate over the items of the hashtable.
+    ///
+    /// Values that aren't strings are converted to strings by Weechat, the
+    /// same way as values returned by `get()`.
+    #[track_caller]
+    pub fn iter(&self) -> impl Iterator<Item = (String, String)> {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        hashtable_to_map(&weechat, self.ptr).into_iter()
+    }
+
+    /// Get the type of the values of the hashtable, e.g. `"string"`.
+    #[track_caller]
+    fn value_type(&self) -> String {
+        let hashtable_get_string = Weechat::from_ptr(self.weechat_ptr)
+            .get()
+            .hashtable_get_string
+            .unwrap();
+
+        let property = cstr!("type_values");
+
+        unsafe {
+            let value_type = hashtable_get_string(self.ptr, property.as_ptr());
+            if value_type.is_null() {
+                String::new()
+            } else {
+                CStr::from_ptr(value_type).to_string_lossy().into_owned()
+            }
+        }
+    }
+
+    #[track_caller]
+    fn get_raw(&self, key: &str) -> *mut c_void {
+        let hashtable_get = Weechat::from_ptr(self.weechat_ptr)
+            .get()
+            .hashtable_get
+            .unwrap();
+
+        let key = LossyCString::new(key);
+
+        unsafe { hashtable_get(self.ptr, key.as_ptr() as *const _) }
+    }
+
+    /// Get the value of an item in the hashtable.
+    ///
+    /// Integer and time values are formatted as numbers, pointer and buffer
+    /// values are formatted as a hexadecimal address.
+    ///
+    /// Returns `None` if the key isn't found in the hashtable.
+    #[track_caller]
+    pub fn get(&self, key: &str) -> Option<Cow<'_, str>> {
+        let value = self.get_raw(key);
+
+        if value.is_null() {
+            return None;
+        }
+
+        let value = unsafe {
+            match self.value_type().as_ref() {
+                "string" => {
+                    CStr::from_ptr(value as *const c_char).to_string_lossy()
+                }
+                "integer" => Cow::from((*(value as *const c_int)).to_string()),
+                "time" => Cow::from((*(value as *const time_t)).to_string()),
+                _ => Cow::from(format!("{:p}", value)),
+            }
+        };
+
+        Some(value)
+    }
+
+    /// Get the value of an item in a hashtable that stores pointer or buffer
+    /// values.
+    ///
+    /// Returns `None` if the key isn't found or if the hashtable stores a
+    /// different value type.
+    #[track_caller]
+    pub fn get_pointer(&self, key: &str) -> Option<*mut c_void> {
+        match self.value_type().as_ref() {
+            "pointer" | "buffer" => (),
+            _ => return None,
+        }
+
+        let value = self.get_raw(key);
+
+        if value.is_null() {
+            None
+        } else {
+            Some(value)
+        }
+    }
+}
+
+/// Copy the items of a hashtable into a `HashMap`, keys and values that
+/// aren't strings are converted to strings.
+#[track_caller]
+pub(crate) fn hashtable_to_map(
+    weechat: &Weechat,
+    hashtable: *mut t_hashtable,
+) -> HashMap<String, String> {
+    unsafe extern "C" fn c_map_cb(
+        data: *mut c_void,
+        _hashtable: *mut t_hashtable,
+        key: *const c_char,
+        value: *const c_char,
+    ) {
+        let map = &mut *(data as *mut HashMap<String, String>);
+
+        if key.is_null() {
+            return;
+        }
+
+        let key = CStr::from_ptr(key).to_string_lossy().into_owned();
+        let value = if value.is_null() {
+            String::new()
+        } else {
+            CStr::from_ptr(value).to_string_lossy().into_owned()
+        };
+
+        map.insert(key, value);
+    }
+
+    let hashtable_map_string = weechat.get().hashtable_map_string.unwrap();
+    let mut map = HashMap::new();
+
+    unsafe {
+        hashtable_map_string(
+            hashtable,
+            Some(c_map_cb),
+            &mut map as *mut _ as *mut c_void,
+        );
+    }
+
+    map
+}
+
+/// A Rust type that can be stored as a key or a value of a hashtable.
+pub trait HashtableValue: Sized {
+    /// The Weechat item type that is used to store the Rust type.
+    fn item_type() -> HashtableItemType;
+
+    /// Run a function with a pointer to the value, in the layout Weechat
+    /// expects for the item type.
+    fn with_ptr<R>(&self, f: impl FnOnce(*const c_void) -> R) -> R;
+
+    /// Read a value out of a pointer returned by Weechat.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be non-null and point to an item of `item_type()`.
+    unsafe fn from_ptr(ptr: *const c_void) -> Self;
+}
+
+impl HashtableValue for String {
+    fn item_type() -> HashtableItemType {
+        HashtableItemType::String
+    }
+
+    fn with_ptr<R>(&self, f: impl FnOnce(*const c_void) -> R) -> R {
+        let value = LossyCString::new(self.as_str());
+        f(value.as_ptr() as *const c_void)
+    }
+
+    unsafe fn from_ptr(ptr: *const c_void) -> Self {
+        CStr::from_ptr(ptr as *const c_char)
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+impl HashtableValue for i32 {
+    fn item_type() -> HashtableItemType {
+        HashtableItemType::Integer
+    }
+
+    fn with_ptr<R>(&self, f: impl FnOnce(*const c_void) -> R) -> R {
+        let value = *self as c_int;
+        f(&value as *const c_int as *const c_void)
+    }
+
+    unsafe fn from_ptr(ptr: *const c_void) -> Self {
+        *(ptr as *const c_int)
+    }
+}
+
+/// 64 bit integers are stored as Weechat time values, e.g. unix timestamps.
+impl HashtableValue for i64 {
+    fn item_type() -> HashtableItemType {
+        HashtableItemType::Time
+    }
+
+    fn with_ptr<R>(&self, f: impl FnOnce(*const c_void) -> R) -> R {
+        let value = *self as time_t;
+        f(&value as *const time_t as *const c_void)
+    }
+
+    // The size of time_t depends on the platform.
+    #[allow(clippy::unnecessary_cast)]
+    unsafe fn from_ptr(ptr: *const c_void) -> Self {
+        *(ptr as *const time_t) as i64
+    }
+}
+
+impl HashtableValue for *mut c_void {
+    fn item_type() -> HashtableItemType {
+        HashtableItemType::Pointer
+    }
+
+    fn with_ptr<R>(&self, f: impl FnOnce(*const c_void) -> R) -> R {
+        f(*self as *const c_void)
+    }
+
+    unsafe fn from_ptr(ptr: *const c_void) -> Self {
+        ptr as *mut c_void
+    }
+}
+
+/// A hashtable with typed keys and values.
+///
+/// The key and value types that are passed to Weechat are derived from the
+/// `K` and `V` types.
+pub struct TypedHashtable<K, V> {
+    inner: Hashtable,
+    _types: PhantomData<(K, V)>,
+}
+
+impl Weechat {
+    /// Create a new hashtable with typed keys and values.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let counts = weechat.new_typed_hashtable::<String, i32>(16).unwrap();
+    /// counts.set(&"messages".to_owned(), &3);
+    /// assert_eq!(counts.get(&"messages".to_owned()), Some(3));
+    /// ```
+    #[track_caller]
+    pub fn new_typed_hashtable<K: HashtableValue, V: HashtableValue>(
+        &self,
+        size: u16,
+    ) -> Option<TypedHashtable<K, V>> {
+        let inner = self.new_hashtable(size, K::item_type(), V::item_type())?;
+
+        Some(TypedHashtable {
+            inner,
+            _types: PhantomData,
+        })
+    }
+}
+
+impl<K: HashtableValue, V: HashtableValue> TypedHashtable<K, V> {
+    fn weechat(&self) -> Weechat {
+        Weechat::from_ptr(self.inner.weechat_ptr)
+    }
+
+    /// Add or update an item in the hashtable.
+    #[track_caller]
+    pub fn set(&self, key: &K, value: &V) {
+        let hashtable_set = self.weechat().get().hashtable_set.unwrap();
+
+        key.with_ptr(|key| {
+            value.with_ptr(|value| unsafe {
+                hashtable_set(self.inner.ptr, key, value);
+            })
+        });
+    }
+
+    /// Get the value of an item in the hashtable.
+    ///
+    /// Returns `None` if the key isn't found in the hashtable.
+    #[track_caller]
+    pub fn get(&self, key: &K) -> Option<V> {
+        let hashtable_get = self.weechat().get().hashtable_get.unwrap();
+
+        let value = key.with_ptr(|key| unsafe {
+            hashtable_get(self.inner.ptr, key) as *const c_void
+        });
+
+        if value.is_null() {
+            None
+        } else {
+            Some(unsafe { V::from_ptr(value) })
+        }
+    }
+
+    /// Remove an item from the hashtable.
+    ///
+    /// Nothing happens if the key isn't found in the hashtable.
+    #[track_caller]
+    pub fn remove(&self, key: &K) {
+        let hashtable_remove = self.weechat().get().hashtable_remove.unwrap();
+
+        key.with_ptr(|key| unsafe { hashtable_remove(self.inner.ptr, key) });
+    }
+
+    /// Remove all the items from the hashtable.
+    #[track_caller]
+    pub fn remove_all(&self) {
+        self.inner.remove_all()
+    }
+
+    /// Check if the hashtable contains an item with the given key.
+    #[track_caller]
+    pub fn has_key(&self, key: &K) -> bool {
+        let hashtable_has_key = self.weechat().get().hashtable_has_key.unwrap();
+
+        key.with_ptr(|key| unsafe {
+            hashtable_has_key(self.inner.ptr, key) != 0
+        })
+    }
+
+    /// Get the number of items in the hashtable.
+    pub fn len(&self) -> i32 {
+        self.inner.len()
+    }
+
+    /// Check if the hashtable contains no items.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Get the untyped hashtable, e.g. to pass it to an API that expects a
+    /// `Hashtable`.
+    pub fn as_untyped(&self) -> &Hashtable {
+        &self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockWeechat;
+
+    fn new_hashtable(
+        weechat: &Weechat,
+        value_type: HashtableItemType,
+    ) -> Hashtable {
+        weechat
+            .new_hashtable(8, HashtableItemType::String, value_type)
+            .unwrap()
+    }
+
+    #[test]
+    fn string_values_round_trip() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let hashtable = new_hashtable(&weechat, HashtableItemType::String);
+
+        assert_eq!(hashtable.get("nick"), None);
+
+        hashtable.set("nick", "Emma");
+        hashtable.set("nick", "Anna");
+        hashtable.set("channel", "#rust");
+
+        assert_eq!(hashtable.get("nick").as_deref(), Some("Anna"));
+        assert_eq!(hashtable.get("channel").as_deref(), Some("#rust"));
+        assert_eq!(hashtable.get_pointer("nick"), None);
+        assert_eq!(hashtable.len(), 2);
+
+        hashtable.remove("nick");
+
+        assert_eq!(hashtable.get("nick"), None);
+        assert!(!hashtable.has_key("nick"));
+        assert!(hashtable.has_key("channel"));
+    }
+
+    #[test]
+    fn integer_and_time_values_are_formatted() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+
+        let integers = weechat.new_typed_hashtable::<String, i32>(8).unwrap();
+        integers.set(&"count".to_owned(), &-3);
+
+        let times = weechat.new_typed_hashtable::<String, i64>(8).unwrap();
+        times.set(&"date".to_owned(), &1_600_000_000);
+
+        assert_eq!(integers.get(&"count".to_owned()), Some(-3));
+        assert_eq!(integers.as_untyped().get("count").as_deref(), Some("-3"));
+        assert_eq!(times.get(&"date".to_owned()), Some(1_600_000_000));
+        assert_eq!(
+            times.as_untyped().get("date").as_deref(),
+            Some("1600000000")
+        );
+        assert_eq!(integers.as_untyped().get("missing"), None);
+        assert_eq!(times.as_untyped().get_pointer("date"), None);
+    }
+
+    #[test]
+    fn pointer_and_buffer_values_round_trip() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let pointers = new_hashtable(&weechat, HashtableItemType::Pointer);
+        let buffers = new_hashtable(&weechat, HashtableItemType::Buffer);
+        let strings = new_hashtable(&weechat, HashtableItemType::String);
+
+        let buffer =
+            weechat.buffer_new::<(), ()>("room", None, None, None, None);
+        let pointer = 0xbeef0 as *mut c_void;
+
+        unsafe { pointers.set_pointer("data", pointer) }.unwrap();
+        buffers.set_buffer("buffer", &buffer).unwrap();
+
+        assert_eq!(pointers.get_pointer("data"), Some(pointer));
+        assert_eq!(pointers.get("data").as_deref(), Some("0xbeef0"));
+        assert_eq!(
+            buffers.get_pointer("buffer"),
+            Some(buffer.ptr as *mut c_void)
+        );
+        assert_eq!(pointers.get_pointer("missing"), None);
+
+        assert_eq!(
+            strings.set_buffer("buffer", &buffer),
+            Err(InvalidValueType {
+                value_type: "string".to_owned()
+            })
+        );
+        assert_eq!(strings.get("buffer"), None);
+    }
+
+    #[test]
+    fn integer_keys_round_trip() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let names = weechat.new_typed_hashtable::<i32, String>(8).unwrap();
+
+        names.set(&1, &"core".to_owned());
+        names.set(&2, &"rust".to_owned());
+        names.set(&1, &"weechat".to_owned());
+
+        assert_eq!(names.get(&1).as_deref(), Some("weechat"));
+        assert_eq!(names.get(&3), None);
+        assert_eq!(names.len(), 2);
+
+        let mut items: Vec<_> = names.as_untyped().iter().collect();
+        items.sort();
+        assert_eq!(
+            items,
+            [
+                ("1".to_owned(), "weechat".to_owned()),
+                ("2".to_owned(), "rust".to_owned()),
+            ]
+        );
+
+        drop(names);
+        assert_eq!(mock.hashtables(), (1, 1));
+    }
 }
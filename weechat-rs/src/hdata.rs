@@ -1,10 +1,13 @@
 //! A safe and high level API to access HData tables
 
-use crate::{Buffer, LossyCString, Weechat};
+use crate::{Buffer, LossyCString, Nick, Weechat};
 use chrono::{DateTime, NaiveDateTime, Utc};
 use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::convert::TryInto;
 use std::ffi::{c_void, CStr};
+use std::ptr;
 use weechat_sys::{t_hdata, t_weechat_plugin};
 
 /// The HData object represents a table of variables associated with an object.
@@ -18,23 +21,434 @@ pub struct HData {
 }
 
 impl HData {
+    /// Check if a pointer points to an element of one of the lists of this
+    /// hdata, e.g. to check if a cached buffer pointer is still valid.
+    ///
+    /// Only the lists that Weechat allows to be checked are searched.
+    #[track_caller]
+    pub fn check_pointer(&self, pointer: &HDataPointer) -> bool {
+        self.check_raw_pointer(pointer.ptr)
+    }
+
+    #[track_caller]
+    fn check_raw_pointer(&self, ptr: *mut c_void) -> bool {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let hdata_check_pointer = weechat.get().hdata_check_pointer.unwrap();
+
+        unsafe { hdata_check_pointer(self.ptr, ptr::null_mut(), ptr) != 0 }
+    }
+
+    /// Check the pointer of an object of this hdata before it's accessed.
+    ///
+    /// Weechat only checks the lists that are flagged to be checked if no
+    /// list is given, most hdata like nicks or lines have no such lists. The
+    /// pointer is then checked against every list of the hdata, a pointer of
+    /// an hdata without lists can't be checked and is assumed to be valid.
+    #[cfg(feature = "check-pointers")]
+    #[track_caller]
+    fn check_object_pointer(&self, ptr: *mut c_void) -> bool {
+        if self.check_raw_pointer(ptr) {
+            return true;
+        }
+
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let hdata_get_list = weechat.get().hdata_get_list.unwrap();
+        let hdata_check_pointer = weechat.get().hdata_check_pointer.unwrap();
+
+        let lists = self.lists();
+
+        lists.is_empty()
+            || lists.iter().any(|name| {
+                let name = LossyCString::new(name);
+
+                unsafe {
+                    let list = hdata_get_list(self.ptr, name.as_ptr());
+                    !list.is_null()
+                        && hdata_check_pointer(self.ptr, list, ptr) != 0
+                }
+            })
+    }
+
     /// Retrieve the value of a variable in a hdata.
+    ///
+    /// If the `check-pointers` feature is enabled, `None` is returned if the
+    /// object of the hdata isn't valid anymore.
+    #[track_caller]
     pub fn get_var<T: HDataType>(&self, name: &str) -> Option<T> {
         let weechat = Weechat::from_ptr(self.weechat_ptr);
 
+        #[cfg(feature = "check-pointers")]
+        {
+            if !self.check_object_pointer(self.object) {
+                return None;
+            }
+        }
+
         HDataType::hdata_value(self, name)
     }
 
+    /// Retrieve the value of a variable in a hdata.
+    ///
+    /// Unlike `get_var()` this tells apart variables that don't exist,
+    /// variables of a different type and variables that don't hold a value,
+    /// e.g. a null string. If the `check-pointers` feature is enabled and
+    /// the object of the hdata isn't valid anymore `HDataError::NullValue`
+    /// is returned.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// match hdata.try_get_var::<i32>("number") {
+    ///     Ok(number) => weechat.print(&format!("Buffer number {}", number)),
+    ///     Err(e) => weechat.print(&format!("Can't get the number: {}", e)),
+    /// }
+    /// ```
+    #[track_caller]
+    pub fn try_get_var<T: HDataType>(
+        &self,
+        name: &str,
+    ) -> Result<T, HDataError> {
+        let actual =
+            self.var_type(name)
+                .ok_or_else(|| HDataError::UnknownVariable {
+                    name: name.to_owned(),
+                })?;
+
+        if let Some(expected) = T::hdata_var_type() {
+            if expected != actual {
+                return Err(HDataError::TypeMismatch {
+                    name: name.to_owned(),
+                    expected,
+                    actual,
+                });
+            }
+        }
+
+        self.get_var(name).ok_or_else(|| HDataError::NullValue {
+            name: name.to_owned(),
+        })
+    }
+
     /// Update the value of a variable in a hdata.
+    #[track_caller]
     pub fn update_var<T: HDataType>(&self, name: &str, value: T) -> usize {
+        HDataType::hdata_set_value(self, name, value)
+    }
+
+    /// Get a pointer to a list of this hdata, e.g. `"gui_buffers"` for the
+    /// buffer hdata.
+    ///
+    /// Returns `None` if the list isn't found or is empty.
+    #[track_caller]
+    pub fn get_list(&self, name: &str) -> Option<HDataPointer> {
         let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let hdata_get_list = weechat.get().hdata_get_list.unwrap();
 
-        HDataType::hdata_set_value(self, name, value)
+        let name = LossyCString::new(name);
+
+        let ptr = unsafe { hdata_get_list(self.ptr, name.as_ptr()) };
+
+        if ptr.is_null() {
+            None
+        } else {
+            Some(HDataPointer {
+                ptr,
+                weechat: self.weechat_ptr,
+            })
+        }
+    }
+
+    /// Iterate over the elements of a list of this hdata, starting with the
+    /// first element of the list.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let hdata = buffer.get_hdata("buffer").unwrap();
+    ///
+    /// for pointer in hdata.iter("gui_buffers") {
+    ///     let buffer_hdata = pointer.get_hdata("buffer").unwrap();
+    ///     let name: String = buffer_hdata.get_var("name").unwrap();
+    ///     weechat.print(&name);
+    /// }
+    /// ```
+    #[track_caller]
+    pub fn iter(&self, list_name: &str) -> HDataIterator<'_> {
+        HDataIterator {
+            hdata: self,
+            current: self.get_list(list_name),
+            step: 1,
+        }
+    }
+
+    /// Iterate over the elements of a list of this hdata in reverse order.
+    ///
+    /// The name should be the name of a list that points to the last element,
+    /// e.g. `"last_gui_buffer"` for the buffer hdata.
+    #[track_caller]
+    pub fn iter_rev(&self, last_list_name: &str) -> HDataIterator<'_> {
+        HDataIterator {
+            hdata: self,
+            current: self.get_list(last_list_name),
+            step: -1,
+        }
+    }
+
+    /// Search for an element in a list of this hdata using an expression.
+    ///
+    /// The expression is evaluated for every element, starting with `start`,
+    /// until it evaluates to true. The current element is available in the
+    /// expression under the name of the hdata, e.g. `${buffer.full_name}`
+    /// for the buffer hdata. See the `/eval` command of Weechat for the
+    /// expression syntax.
+    ///
+    /// Returns `None` if no element matches the expression.
+    ///
+    /// * `start` - The element the search starts with.
+    /// * `expr` - The expression that should be evaluated.
+    /// * `move_count` - The number of elements to move after each element
+    ///     that didn't match, negative numbers move backwards.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let hdata = buffer.get_hdata("buffer").unwrap();
+    /// let start = hdata.get_list("gui_buffers").unwrap();
+    ///
+    /// let found = hdata.search(
+    ///     &start,
+    ///     "${buffer.full_name} == irc.libera.#weechat",
+    ///     1,
+    /// );
+    /// ```
+    #[track_caller]
+    pub fn search(
+        &self,
+        start: &HDataPointer,
+        expr: &str,
+        move_count: i32,
+    ) -> Option<HDataPointer> {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let hdata_search = weechat.get().hdata_search.unwrap();
+
+        let expr = LossyCString::new(expr);
+
+        let ptr = unsafe {
+            hdata_search(self.ptr, start.ptr, expr.as_ptr(), move_count)
+        };
+
+        if ptr.is_null() {
+            None
+        } else {
+            Some(HDataPointer {
+                ptr,
+                weechat: self.weechat_ptr,
+            })
+        }
+    }
+
+    #[track_caller]
+    fn get_string_property(&self, property: &CStr) -> Option<Cow<'_, str>> {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let hdata_get_string = weechat.get().hdata_get_string.unwrap();
+
+        unsafe {
+            let ret = hdata_get_string(self.ptr, property.as_ptr());
+            if ret.is_null() {
+                None
+            } else {
+                Some(CStr::from_ptr(ret).to_string_lossy())
+            }
+        }
+    }
+
+    #[track_caller]
+    fn get_string_list(&self, property: &CStr) -> Vec<String> {
+        match self.get_string_property(property) {
+            Some(keys) => keys
+                .split(',')
+                .filter(|key| !key.is_empty())
+                .map(str::to_owned)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Get the names of all the variables of this hdata.
+    #[track_caller]
+    pub fn keys(&self) -> Vec<String> {
+        self.get_string_list(cstr!("var_keys"))
+    }
+
+    /// Get the names of all the lists of this hdata.
+    #[track_caller]
+    pub fn lists(&self) -> Vec<String> {
+        self.get_string_list(cstr!("list_keys"))
+    }
+
+    /// Get the type of a variable of this hdata.
+    ///
+    /// Returns `None` if the variable doesn't exist.
+    #[track_caller]
+    pub fn var_type(&self, name: &str) -> Option<HDataVarType> {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let hdata_get_var_type = weechat.get().hdata_get_var_type.unwrap();
+
+        let name = LossyCString::new(strip_array_index(name));
+
+        let var_type = unsafe { hdata_get_var_type(self.ptr, name.as_ptr()) };
+
+        HDataVarType::from_int(var_type)
+    }
+
+    /// Compare a variable of two objects of this hdata.
+    ///
+    /// Both pointers must point to objects of this hdata, comparing objects
+    /// of different hdata types gives meaningless results.
+    ///
+    /// * `first` - The first object.
+    /// * `second` - The second object.
+    /// * `name` - The name of the variable that should be compared, e.g.
+    ///     `"full_name"` for buffers.
+    /// * `case_sensitive` - Should strings be compared case sensitively.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut buffers: Vec<HDataPointer> = hdata.iter("gui_buffers").collect();
+    /// buffers.sort_by(|a, b| hdata.compare(a, b, "full_name", false));
+    /// ```
+    #[track_caller]
+    pub fn compare(
+        &self,
+        first: &HDataPointer,
+        second: &HDataPointer,
+        name: &str,
+        case_sensitive: bool,
+    ) -> Ordering {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let hdata_compare = weechat.get().hdata_compare.unwrap();
+
+        let name = LossyCString::new(name);
+
+        let ret = unsafe {
+            hdata_compare(
+                self.ptr,
+                first.ptr,
+                second.ptr,
+                name.as_ptr(),
+                case_sensitive as i32,
+            )
+        };
+
+        ret.cmp(&0)
+    }
+
+    /// Update multiple variables of the hdata object at once.
+    ///
+    /// Returns the number of variables that were updated. Variables that
+    /// can't be updated are skipped by Weechat, `can_update()` can be used to
+    /// check a variable beforehand.
+    #[track_caller]
+    pub fn update(&self, changes: &HashMap<String, HDataValue>) -> usize {
+        let items: Vec<(&str, String)> = changes
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.to_string()))
+            .collect();
+
+        self.update_raw(&items)
+    }
+
+    /// Check if a variable of this hdata can be updated.
+    #[track_caller]
+    pub fn can_update(&self, name: &str) -> bool {
+        self.update_raw(&[("__update_allowed", name.to_owned())]) == 1
+    }
+
+    /// Delete the hdata object.
+    ///
+    /// Returns `false` if the hdata doesn't allow deleting objects.
+    #[track_caller]
+    pub fn delete(&self) -> bool {
+        if self.update_raw(&[("__delete_allowed", String::new())]) != 1 {
+            return false;
+        }
+
+        self.update_raw(&[("__delete", String::new())]) == 1
+    }
+
+    /// Update variables from their string representation.
+    ///
+    /// Weechat parses the values according to the types of the variables,
+    /// the hashtable is freed once the update is done.
+    #[track_caller]
+    fn update_raw(&self, items: &[(&str, String)]) -> usize {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let hdata_update = weechat.get().hdata_update.unwrap();
+
+        let hashtable = match weechat.new_hashtable(
+            items.len().max(1).try_into().unwrap_or(u16::MAX),
+            crate::HashtableItemType::String,
+            crate::HashtableItemType::String,
+        ) {
+            Some(h) => h,
+            None => return 0,
+        };
+
+        for (name, value) in items {
+            hashtable.set(name, value);
+        }
+
+        unsafe { hdata_update(self.ptr, self.object, hashtable.ptr) as usize }
+    }
+
+    /// Get the number of elements of an array variable.
+    ///
+    /// Returns `None` if the variable isn't an array. Arrays that are
+    /// terminated by a null pointer are counted by Weechat.
+    #[track_caller]
+    pub fn array_len(&self, name: &str) -> Option<usize> {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let get_array_size = weechat.get().hdata_get_var_array_size.unwrap();
+
+        let name = LossyCString::new(name);
+
+        let size =
+            unsafe { get_array_size(self.ptr, self.object, name.as_ptr()) };
+
+        size.try_into().ok()
+    }
+
+    /// Retrieve the value of an element of an array variable.
+    ///
+    /// Returns `None` if the variable isn't an array or if the index is out
+    /// of range.
+    #[track_caller]
+    pub fn get_var_at<T: HDataType>(
+        &self,
+        name: &str,
+        index: usize,
+    ) -> Option<T> {
+        if index >= self.array_len(name)? {
+            return None;
+        }
+
+        self.get_var(&format!("{}|{}", index, name))
+    }
+
+    /// Retrieve all the elements of an array of strings, e.g. the tags of a
+    /// line.
+    #[track_caller]
+    pub fn get_string_array(&self, name: &str) -> Option<Vec<String>> {
+        let len = self.array_len(name)?;
+
+        (0..len).map(|index| self.get_var_at(name, index)).collect()
     }
 
     /// Retrieve a variable as a string.
     ///
     /// If the data is not compatible bad things will happen.
+    #[track_caller]
     pub unsafe fn get_string_unchecked(
         &self,
         name: &str,
@@ -57,6 +471,7 @@ impl HData {
     /// Retrieve a variable as a integer.
     ///
     /// If the data is not compatible bad things will happen.
+    #[track_caller]
     pub unsafe fn get_i32_unchecked(&self, name: &str) -> i32 {
         let weechat = Weechat::from_ptr(self.weechat_ptr);
         let hdata_integer = weechat.get().hdata_integer.unwrap();
@@ -69,6 +484,7 @@ impl HData {
     /// Retrieve a variable as a long.
     ///
     /// If the data is not compatible bad things will happen.
+    #[track_caller]
     pub unsafe fn get_i64_unchecked(&self, name: &str) -> i64 {
         let weechat = Weechat::from_ptr(self.weechat_ptr);
         let hdata_long = weechat.get().hdata_long.unwrap();
@@ -79,6 +495,194 @@ impl HData {
     }
 }
 
+impl HasHData for Nick {
+    #[track_caller]
+    fn get_hdata(&self, name: &str) -> Option<HData> {
+        if self.buffer_closed() {
+            return None;
+        }
+
+        let hdata_get =
+            Weechat::from_ptr(self.weechat_ptr).get().hdata_get.unwrap();
+
+        let name = LossyCString::new(name);
+
+        unsafe {
+            let hdata = hdata_get(self.weechat_ptr, name.as_ptr());
+            if hdata.is_null() {
+                None
+            } else {
+                Some(HData {
+                    weechat_ptr: self.weechat_ptr,
+                    object: self.ptr as *mut _,
+                    ptr: hdata,
+                })
+            }
+        }
+    }
+}
+
+/// An iterator over the elements of a hdata list.
+pub struct HDataIterator<'a> {
+    hdata: &'a HData,
+    current: Option<HDataPointer>,
+    step: i32,
+}
+
+impl<'a> Iterator for HDataIterator<'a> {
+    type Item = HDataPointer;
+
+    #[track_caller]
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.current.take()?;
+        self.current = current.advance(self.hdata, self.step);
+
+        Some(current)
+    }
+}
+
+/// Strip the `N|` index prefix that is used to access array elements from a
+/// variable name.
+fn strip_array_index(name: &str) -> &str {
+    match name.find('|') {
+        Some(index) if name[..index].chars().all(|c| c.is_ascii_digit()) => {
+            &name[index + 1..]
+        }
+        _ => name,
+    }
+}
+
+/// A trait for Rust types that can be read out of a hdata object, usually
+/// implemented using `#[derive(FromHData)]`.
+pub trait FromHData: Sized {
+    /// Read the type out of a hdata object.
+    ///
+    /// Returns `None` if a required variable is missing or has an unexpected
+    /// type.
+    fn from_hdata(hdata: &HData) -> Option<Self>;
+}
+
+/// The type of a hdata variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HDataVarType {
+    /// A single character.
+    Char,
+    /// An integer.
+    Integer,
+    /// A long integer.
+    Long,
+    /// A string, shared strings are strings as well.
+    String,
+    /// A pointer to another object.
+    Pointer,
+    /// A time value.
+    Time,
+    /// A hashtable.
+    Hashtable,
+    /// Any other type.
+    Other,
+}
+
+impl HDataVarType {
+    fn from_int(var_type: i32) -> Option<HDataVarType> {
+        if var_type < 0 {
+            return None;
+        }
+
+        let var_type = match var_type as u32 {
+            weechat_sys::WEECHAT_HDATA_CHAR => HDataVarType::Char,
+            weechat_sys::WEECHAT_HDATA_INTEGER => HDataVarType::Integer,
+            weechat_sys::WEECHAT_HDATA_LONG => HDataVarType::Long,
+            weechat_sys::WEECHAT_HDATA_STRING
+            | weechat_sys::WEECHAT_HDATA_SHARED_STRING => HDataVarType::String,
+            weechat_sys::WEECHAT_HDATA_POINTER => HDataVarType::Pointer,
+            weechat_sys::WEECHAT_HDATA_TIME => HDataVarType::Time,
+            weechat_sys::WEECHAT_HDATA_HASHTABLE => HDataVarType::Hashtable,
+            _ => HDataVarType::Other,
+        };
+
+        Some(var_type)
+    }
+}
+
+/// Error returned by `HData::try_get_var()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HDataError {
+    /// The hdata doesn't have a variable with the given name.
+    UnknownVariable {
+        /// The name of the variable.
+        name: String,
+    },
+    /// The variable has a different type than the requested one.
+    TypeMismatch {
+        /// The name of the variable.
+        name: String,
+        /// The type that was requested.
+        expected: HDataVarType,
+        /// The type of the variable.
+        actual: HDataVarType,
+    },
+    /// The variable exists but doesn't hold a value, e.g. a null string.
+    NullValue {
+        /// The name of the variable.
+        name: String,
+    },
+}
+
+impl std::fmt::Display for HDataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HDataError::UnknownVariable { name } => {
+                write!(f, "the hdata variable {} doesn't exist", name)
+            }
+            HDataError::TypeMismatch {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "the hdata variable {} has the type {:?}, expected {:?}",
+                name, actual, expected
+            ),
+            HDataError::NullValue { name } => {
+                write!(f, "the hdata variable {} has no value", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for HDataError {}
+
+/// A new value for a hdata variable, used with `HData::update()`.
+#[derive(Debug, Clone)]
+pub enum HDataValue {
+    /// A value for a string variable.
+    String(String),
+    /// A value for a char variable.
+    Char(char),
+    /// A value for an integer variable.
+    Integer(i32),
+    /// A value for a long variable.
+    Long(i64),
+    /// A value for a time variable.
+    Time(DateTime<Utc>),
+    /// A value for a pointer variable.
+    Pointer(HDataPointer),
+}
+
+impl std::fmt::Display for HDataValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HDataValue::String(value) => write!(f, "{}", value),
+            HDataValue::Char(value) => write!(f, "{}", value),
+            HDataValue::Integer(value) => write!(f, "{}", value),
+            HDataValue::Long(value) => write!(f, "{}", value),
+            HDataValue::Time(value) => write!(f, "{}", value.timestamp()),
+            HDataValue::Pointer(value) => write!(f, "{:p}", value.ptr),
+        }
+    }
+}
+
 /// A trait for types that have hdata.
 pub trait HasHData {
     /// Retrieve a hdata table tied to this object.
@@ -86,7 +690,12 @@ pub trait HasHData {
 }
 
 impl HasHData for Buffer {
+    #[track_caller]
     fn get_hdata(&self, name: &str) -> Option<HData> {
+        if self.is_closed() {
+            return None;
+        }
+
         let hdata_get =
             Weechat::from_ptr(self.weechat).get().hdata_get.unwrap();
 
@@ -112,22 +721,37 @@ pub trait HDataType: Sized {
     /// Retrieve the value of a hdata variable by name.
     fn hdata_value(hdata: &HData, name: &str) -> Option<Self>;
 
+    /// The hdata variable type the value is read from.
+    ///
+    /// Used by `HData::try_get_var()` to report type mismatches, if `None`
+    /// the type isn't checked beforehand.
+    fn hdata_var_type() -> Option<HDataVarType> {
+        None
+    }
+
     /// Set the value of a hdata variable by name.
-    // TODO: Figure out ownership issues
     fn hdata_set_value(hdata: &HData, name: &str, value: Self) -> usize;
 }
 
 impl HDataType for Cow<'_, str> {
+    fn hdata_var_type() -> Option<HDataVarType> {
+        Some(HDataVarType::String)
+    }
+
+    #[track_caller]
     fn hdata_value(hdata: &HData, name: &str) -> Option<Self> {
         let weechat = Weechat::from_ptr(hdata.weechat_ptr);
         let hdata_string = weechat.get().hdata_string.unwrap();
         let hdata_get_var_type = weechat.get().hdata_get_var_type.unwrap();
 
+        let var_name = LossyCString::new(strip_array_index(name));
         let name = LossyCString::new(name);
 
         unsafe {
-            if hdata_get_var_type(hdata.ptr, name.as_ptr())
-                != weechat_sys::WEECHAT_HDATA_STRING as i32
+            let var_type = hdata_get_var_type(hdata.ptr, var_name.as_ptr());
+
+            if var_type != weechat_sys::WEECHAT_HDATA_STRING as i32
+                && var_type != weechat_sys::WEECHAT_HDATA_SHARED_STRING as i32
             {
                 return None;
             }
@@ -141,44 +765,44 @@ impl HDataType for Cow<'_, str> {
         }
     }
 
+    #[track_caller]
     fn hdata_set_value(hdata: &HData, name: &str, value: Self) -> usize {
-        let weechat = Weechat::from_ptr(hdata.weechat_ptr);
-        let hdata_update = weechat.get().hdata_update.unwrap();
-
-        let hashtable = weechat
-            .new_hashtable(
-                1,
-                crate::HashtableItemType::String,
-                crate::HashtableItemType::String,
-            )
-            .unwrap();
-
-        hashtable.set(name, &value);
-
-        unsafe { hdata_update(hdata.ptr, hdata.object, hashtable.ptr) as usize }
+        hdata.update_raw(&[(name, value.into_owned())])
     }
 }
 
 impl HDataType for String {
+    fn hdata_var_type() -> Option<HDataVarType> {
+        Some(HDataVarType::String)
+    }
+
+    #[track_caller]
     fn hdata_value(hdata: &HData, name: &str) -> Option<Self> {
         HDataType::hdata_value(hdata, name).map(Cow::into_owned)
     }
 
+    #[track_caller]
     fn hdata_set_value(hdata: &HData, name: &str, value: Self) -> usize {
         HDataType::hdata_set_value(hdata, name, Cow::from(value))
     }
 }
 
 impl HDataType for char {
+    fn hdata_var_type() -> Option<HDataVarType> {
+        Some(HDataVarType::Char)
+    }
+
+    #[track_caller]
     fn hdata_value(hdata: &HData, name: &str) -> Option<Self> {
         let weechat = Weechat::from_ptr(hdata.weechat_ptr);
         let hdata_char = weechat.get().hdata_char.unwrap();
         let hdata_get_var_type = weechat.get().hdata_get_var_type.unwrap();
 
+        let var_name = LossyCString::new(strip_array_index(name));
         let name = LossyCString::new(name);
 
         unsafe {
-            if hdata_get_var_type(hdata.ptr, name.as_ptr())
+            if hdata_get_var_type(hdata.ptr, var_name.as_ptr())
                 != weechat_sys::WEECHAT_HDATA_CHAR as i32
             {
                 return None;
@@ -189,34 +813,28 @@ impl HDataType for char {
         }
     }
 
+    #[track_caller]
     fn hdata_set_value(hdata: &HData, name: &str, value: Self) -> usize {
-        let weechat = Weechat::from_ptr(hdata.weechat_ptr);
-        let hdata_update = weechat.get().hdata_update.unwrap();
-
-        let hashtable = weechat
-            .new_hashtable(
-                1,
-                crate::HashtableItemType::String,
-                crate::HashtableItemType::String,
-            )
-            .unwrap();
-
-        hashtable.set(name, &value.to_string());
-
-        unsafe { hdata_update(hdata.ptr, hdata.object, hashtable.ptr) as usize }
+        hdata.update_raw(&[(name, value.to_string())])
     }
 }
 
 impl HDataType for i64 {
+    fn hdata_var_type() -> Option<HDataVarType> {
+        Some(HDataVarType::Long)
+    }
+
+    #[track_caller]
     fn hdata_value(hdata: &HData, name: &str) -> Option<Self> {
         let weechat = Weechat::from_ptr(hdata.weechat_ptr);
         let hdata_long = weechat.get().hdata_long.unwrap();
         let hdata_get_var_type = weechat.get().hdata_get_var_type.unwrap();
 
+        let var_name = LossyCString::new(strip_array_index(name));
         let name = LossyCString::new(name);
 
         unsafe {
-            if hdata_get_var_type(hdata.ptr, name.as_ptr())
+            if hdata_get_var_type(hdata.ptr, var_name.as_ptr())
                 != weechat_sys::WEECHAT_HDATA_LONG as i32
             {
                 return None;
@@ -226,34 +844,28 @@ impl HDataType for i64 {
         }
     }
 
+    #[track_caller]
     fn hdata_set_value(hdata: &HData, name: &str, value: Self) -> usize {
-        let weechat = Weechat::from_ptr(hdata.weechat_ptr);
-        let hdata_update = weechat.get().hdata_update.unwrap();
-
-        let hashtable = weechat
-            .new_hashtable(
-                1,
-                crate::HashtableItemType::String,
-                crate::HashtableItemType::Integer,
-            )
-            .unwrap();
-
-        hashtable.set(name, &value.to_string());
-
-        unsafe { hdata_update(hdata.ptr, hdata.object, hashtable.ptr) as usize }
+        hdata.update_raw(&[(name, value.to_string())])
     }
 }
 
 impl HDataType for i32 {
+    fn hdata_var_type() -> Option<HDataVarType> {
+        Some(HDataVarType::Integer)
+    }
+
+    #[track_caller]
     fn hdata_value(hdata: &HData, name: &str) -> Option<Self> {
         let weechat = Weechat::from_ptr(hdata.weechat_ptr);
         let hdata_integer = weechat.get().hdata_integer.unwrap();
         let hdata_get_var_type = weechat.get().hdata_get_var_type.unwrap();
 
+        let var_name = LossyCString::new(strip_array_index(name));
         let name = LossyCString::new(name);
 
         unsafe {
-            if hdata_get_var_type(hdata.ptr, name.as_ptr())
+            if hdata_get_var_type(hdata.ptr, var_name.as_ptr())
                 != weechat_sys::WEECHAT_HDATA_INTEGER as i32
             {
                 return None;
@@ -263,34 +875,28 @@ impl HDataType for i32 {
         }
     }
 
+    #[track_caller]
     fn hdata_set_value(hdata: &HData, name: &str, value: Self) -> usize {
-        let weechat = Weechat::from_ptr(hdata.weechat_ptr);
-        let hdata_update = weechat.get().hdata_update.unwrap();
-
-        let hashtable = weechat
-            .new_hashtable(
-                1,
-                crate::HashtableItemType::String,
-                crate::HashtableItemType::Integer,
-            )
-            .unwrap();
-
-        hashtable.set(name, &value.to_string());
-
-        unsafe { hdata_update(hdata.ptr, hdata.object, hashtable.ptr) as usize }
+        hdata.update_raw(&[(name, value.to_string())])
     }
 }
 
 impl HDataType for DateTime<Utc> {
+    fn hdata_var_type() -> Option<HDataVarType> {
+        Some(HDataVarType::Time)
+    }
+
+    #[track_caller]
     fn hdata_value(hdata: &HData, name: &str) -> Option<Self> {
         let weechat = Weechat::from_ptr(hdata.weechat_ptr);
         let hdata_time = weechat.get().hdata_time.unwrap();
         let hdata_get_var_type = weechat.get().hdata_get_var_type.unwrap();
 
+        let var_name = LossyCString::new(strip_array_index(name));
         let name = LossyCString::new(name);
 
         unsafe {
-            if hdata_get_var_type(hdata.ptr, name.as_ptr())
+            if hdata_get_var_type(hdata.ptr, var_name.as_ptr())
                 != weechat_sys::WEECHAT_HDATA_TIME as i32
             {
                 return None;
@@ -303,36 +909,36 @@ impl HDataType for DateTime<Utc> {
         }
     }
 
+    #[track_caller]
     fn hdata_set_value(hdata: &HData, name: &str, value: Self) -> usize {
-        let weechat = Weechat::from_ptr(hdata.weechat_ptr);
-        let hdata_update = weechat.get().hdata_update.unwrap();
-
-        let hashtable = weechat
-            .new_hashtable(
-                1,
-                crate::HashtableItemType::String,
-                crate::HashtableItemType::Integer,
-            )
-            .unwrap();
-
-        hashtable.set(name, &value.timestamp().to_string());
-
-        unsafe { hdata_update(hdata.ptr, hdata.object, hashtable.ptr) as usize }
+        hdata.update_raw(&[(name, value.timestamp().to_string())])
     }
 }
 
 /// An opaque wrapper for a pointer stored in hdata
+#[derive(Debug, Clone)]
 pub struct HDataPointer {
-    ptr: *mut c_void,
+    pub(crate) ptr: *mut c_void,
     weechat: *mut t_weechat_plugin,
 }
 
 impl HDataPointer {
     /// Moves a pointer to a new location in a list
+    ///
+    /// If the `check-pointers` feature is enabled, `None` is returned if the
+    /// pointer isn't valid anymore.
+    #[track_caller]
     pub fn advance(&self, hdata: &HData, count: i32) -> Option<HDataPointer> {
         let weechat = Weechat::from_ptr(hdata.weechat_ptr);
         let hdata_move = weechat.get().hdata_move.unwrap();
 
+        #[cfg(feature = "check-pointers")]
+        {
+            if !hdata.check_object_pointer(self.ptr) {
+                return None;
+            }
+        }
+
         unsafe {
             let new_ptr = hdata_move(hdata.ptr, self.ptr, count);
 
@@ -346,18 +952,105 @@ impl HDataPointer {
             }
         }
     }
+
+    /// Convert the pointer into a concrete Weechat type, e.g. a `Buffer`.
+    ///
+    /// The pointer is checked with Weechat before the conversion, `None` is
+    /// returned if it doesn't point to a valid object of the requested type.
+    #[track_caller]
+    pub fn downcast<T: FromHDataPointer>(&self) -> Option<T> {
+        let weechat = Weechat::from_ptr(self.weechat);
+        let hdata_get = weechat.get().hdata_get.unwrap();
+        let hdata_get_list = weechat.get().hdata_get_list.unwrap();
+        let hdata_check_pointer = weechat.get().hdata_check_pointer.unwrap();
+
+        if self.ptr.is_null() {
+            return None;
+        }
+
+        let hdata_name = LossyCString::new(T::hdata_name());
+
+        unsafe {
+            let hdata = hdata_get(self.weechat, hdata_name.as_ptr());
+            if hdata.is_null() {
+                return None;
+            }
+
+            let list = match T::list_name() {
+                Some(name) => {
+                    let name = LossyCString::new(name);
+                    hdata_get_list(hdata, name.as_ptr())
+                }
+                None => ptr::null_mut(),
+            };
+
+            if hdata_check_pointer(hdata, list, self.ptr) == 0 {
+                return None;
+            }
+        }
+
+        Some(T::from_hdata_pointer(self.weechat, self.ptr))
+    }
+
+    /// Convert the pointer into a buffer.
+    ///
+    /// Returns `None` if the pointer doesn't point to a valid buffer.
+    #[track_caller]
+    pub fn try_into_buffer(&self) -> Option<Buffer> {
+        self.downcast()
+    }
+}
+
+/// A trait for Weechat types that can be created from a hdata pointer.
+pub trait FromHDataPointer: Sized {
+    /// The name of the hdata of the type, e.g. `"buffer"`.
+    fn hdata_name() -> &'static str;
+
+    /// The name of the list the pointer is checked against, e.g.
+    /// `"gui_buffers"`. If `None` the pointer is checked against all the lists
+    /// of the hdata that allow pointer checks.
+    fn list_name() -> Option<&'static str>;
+
+    /// Create the type from a pointer that was checked to be valid.
+    fn from_hdata_pointer(
+        weechat_ptr: *mut t_weechat_plugin,
+        ptr: *mut c_void,
+    ) -> Self;
+}
+
+impl FromHDataPointer for Buffer {
+    fn hdata_name() -> &'static str {
+        "buffer"
+    }
+
+    fn list_name() -> Option<&'static str> {
+        Some("gui_buffers")
+    }
+
+    fn from_hdata_pointer(
+        weechat_ptr: *mut t_weechat_plugin,
+        ptr: *mut c_void,
+    ) -> Self {
+        Buffer::from_ptr(weechat_ptr, ptr as *mut _)
+    }
 }
 
 impl HDataType for HDataPointer {
+    fn hdata_var_type() -> Option<HDataVarType> {
+        Some(HDataVarType::Pointer)
+    }
+
+    #[track_caller]
     fn hdata_value(hdata: &HData, name: &str) -> Option<Self> {
         let weechat = Weechat::from_ptr(hdata.weechat_ptr);
         let hdata_pointer = weechat.get().hdata_pointer.unwrap();
         let hdata_get_var_type = weechat.get().hdata_get_var_type.unwrap();
 
+        let var_name = LossyCString::new(strip_array_index(name));
         let name = LossyCString::new(name);
 
         unsafe {
-            if hdata_get_var_type(hdata.ptr, name.as_ptr())
+            if hdata_get_var_type(hdata.ptr, var_name.as_ptr())
                 != weechat_sys::WEECHAT_HDATA_POINTER as i32
             {
                 return None;
@@ -370,25 +1063,14 @@ impl HDataType for HDataPointer {
         }
     }
 
+    #[track_caller]
     fn hdata_set_value(hdata: &HData, name: &str, value: Self) -> usize {
-        let weechat = Weechat::from_ptr(hdata.weechat_ptr);
-        let hdata_update = weechat.get().hdata_update.unwrap();
-
-        let hashtable = weechat
-            .new_hashtable(
-                1,
-                crate::HashtableItemType::String,
-                crate::HashtableItemType::Integer,
-            )
-            .unwrap();
-
-        hashtable.set(name, &(value.ptr as usize).to_string());
-
-        unsafe { hdata_update(hdata.ptr, hdata.object, hashtable.ptr) as usize }
+        hdata.update_raw(&[(name, format!("{:p}", value.ptr))])
     }
 }
 
 impl HasHData for HDataPointer {
+    #[track_caller]
     fn get_hdata(&self, name: &str) -> Option<HData> {
         let hdata_get =
             Weechat::from_ptr(self.weechat).get().hdata_get.unwrap();
@@ -409,3 +1091,103 @@ impl HasHData for HDataPointer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockWeechat;
+    use crate::NickSettings;
+
+    #[test]
+    fn updates_free_their_hashtable() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+
+        let buffer =
+            weechat.buffer_new::<(), ()>("room", None, None, None, None);
+        let hdata = buffer.get_hdata("buffer").unwrap();
+        let time = DateTime::<Utc>::from_timestamp(1_600_000_000, 0).unwrap();
+
+        for i in 0..1000 {
+            assert_eq!(hdata.update_var("title", format!("topic {}", i)), 1);
+            assert_eq!(hdata.update_var("number", i), 1);
+            assert_eq!(hdata.update_var("lines_hidden", i64::from(i)), 1);
+            assert_eq!(hdata.update_var("last_read_line", time), 1);
+        }
+
+        assert!(hdata.can_update("title"));
+        assert!(!hdata.delete());
+
+        // Every update creates one hashtable and frees it again.
+        let (created, freed) = mock.hashtables();
+        assert_eq!(created, 4002);
+        assert_eq!(freed, created);
+
+        let updates = mock.hdata_updates();
+        assert_eq!(updates.len(), 4000);
+        assert_eq!(
+            updates[updates.len() - 4..],
+            [
+                ("title".to_owned(), "topic 999".to_owned()),
+                ("number".to_owned(), "999".to_owned()),
+                ("lines_hidden".to_owned(), "999".to_owned()),
+                ("last_read_line".to_owned(), "1600000000".to_owned()),
+            ]
+        );
+        assert_eq!(mock.invalid_accesses(), 0);
+    }
+
+    #[test]
+    fn pointers_are_updated_as_hexadecimal_addresses() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+
+        let buffer =
+            weechat.buffer_new::<(), ()>("room", None, None, None, None);
+        let hdata = buffer.get_hdata("buffer").unwrap();
+        let pointer = HDataPointer {
+            ptr: 0xbeef0 as *mut c_void,
+            weechat: weechat.ptr,
+        };
+
+        assert_eq!(hdata.update_var("input_callback_data", pointer), 1);
+        assert_eq!(
+            mock.hdata_updates(),
+            [("input_callback_data".to_owned(), "0xbeef0".to_owned())]
+        );
+    }
+
+    #[test]
+    fn objects_of_hdata_without_lists_can_be_read() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+
+        let buffer =
+            weechat.buffer_new::<(), ()>("room", None, None, None, None);
+        let nick = buffer.add_nick(NickSettings::new("Emma"), None).unwrap();
+
+        let hdata = nick.get_hdata("nick").unwrap();
+        assert!(hdata.lists().is_empty());
+        assert_eq!(hdata.get_var::<String>("name").unwrap(), "Emma");
+
+        let hdata = buffer.get_hdata("buffer").unwrap();
+        assert_eq!(hdata.get_var::<String>("name").unwrap(), "room");
+        assert_eq!(mock.invalid_accesses(), 0);
+    }
+
+    #[cfg(feature = "check-pointers")]
+    #[test]
+    fn objects_that_are_gone_are_not_read() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+
+        let buffer =
+            weechat.buffer_new::<(), ()>("room", None, None, None, None);
+        let hdata = buffer.get_hdata("buffer").unwrap();
+
+        mock.close_buffer(buffer.ptr);
+
+        assert!(hdata.get_var::<String>("name").is_none());
+        assert_eq!(mock.invalid_accesses(), 0);
+    }
+}
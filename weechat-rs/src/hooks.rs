@@ -3,17 +3,23 @@
 //! listen to events on a file descriptor, add completions to weechat, etc.
 //! This module contains hook creation methods for the `Weechat` object.
 
-use libc::{c_char, c_int};
+use libc::{c_char, c_int, c_long, c_short};
 use std::borrow::Cow;
-use std::ffi::CStr;
+use std::cell::Cell;
+use std::convert::TryFrom;
+use std::ffi::{CStr, CString};
+use std::ops::{BitOr, BitOrAssign};
 use std::os::raw::c_void;
 use std::os::unix::io::AsRawFd;
 use std::ptr;
 use std::time::Duration;
 
-use weechat_sys::{t_gui_buffer, t_hook, t_weechat_plugin, WEECHAT_RC_OK};
+use weechat_sys::{t_gui_buffer, t_hook, t_infolist, t_weechat_plugin};
 
-use crate::{ArgsWeechat, Buffer, LossyCString, ReturnCode, Weechat};
+use crate::{
+    callback_return_code, catch_panic, ArgsWeechat, Buffer, InfolistBuilder,
+    IntoReturnCode, LossyCString, ReturnCode, Weechat,
+};
 
 /// Weechat Hook type. The hook is unhooked automatically when the object is
 /// dropped.
@@ -32,13 +38,13 @@ impl Drop for Hook {
 
 /// Hook for a weechat command, the command is removed when the object is
 /// dropped.
-pub struct CommandHook<T> {
+pub struct CommandHook<T, R = ()> {
     _hook: Hook,
-    _hook_data: Box<CommandHookData<T>>,
+    _hook_data: Box<CommandHookData<T, R>>,
 }
 
-struct CommandHookData<T> {
-    callback: fn(&T, Buffer, ArgsWeechat),
+struct CommandHookData<T, R> {
+    callback: fn(&T, Buffer, ArgsWeechat) -> R,
     callback_data: T,
     weechat_ptr: *mut t_weechat_plugin,
 }
@@ -70,38 +76,192 @@ impl FdHookMode {
     }
 }
 
+/// The events a hooked file descriptor is ready for.
+///
+/// Weechat doesn't tell which event woke up the hook, the file descriptor is
+/// polled right before the callback runs. Flags can be combined using the
+/// `|` operator.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FdReadiness(c_short);
+
+impl FdReadiness {
+    /// The file descriptor isn't ready for any event.
+    pub const NONE: FdReadiness = FdReadiness(0);
+    /// The file descriptor can be read from, or the other end hung up and a
+    /// read returns the end of file.
+    pub const READ: FdReadiness = FdReadiness(libc::POLLIN | libc::POLLHUP);
+    /// The file descriptor can be written to.
+    pub const WRITE: FdReadiness = FdReadiness(libc::POLLOUT);
+    /// An error or an exceptional condition happened on the file descriptor.
+    pub const EXCEPTION: FdReadiness =
+        FdReadiness(libc::POLLPRI | libc::POLLERR | libc::POLLNVAL);
+
+    /// Check if any of the given flags are set.
+    pub fn contains(&self, other: FdReadiness) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// Check if the file descriptor can be read from.
+    pub fn is_readable(&self) -> bool {
+        self.contains(FdReadiness::READ)
+    }
+
+    /// Check if the file descriptor can be written to.
+    pub fn is_writable(&self) -> bool {
+        self.contains(FdReadiness::WRITE)
+    }
+
+    /// Check if an error or an exceptional condition happened.
+    pub fn is_exception(&self) -> bool {
+        self.contains(FdReadiness::EXCEPTION)
+    }
+
+    fn poll(fd: c_int, mode: &FdHookMode) -> FdReadiness {
+        let (read, write) = mode.as_tuple();
+
+        let mut events = libc::POLLPRI;
+
+        if read == 1 {
+            events |= libc::POLLIN;
+        }
+
+        if write == 1 {
+            events |= libc::POLLOUT;
+        }
+
+        let mut pollfd = libc::pollfd {
+            fd,
+            events,
+            revents: 0,
+        };
+
+        if unsafe { libc::poll(&mut pollfd, 1, 0) } <= 0 {
+            return FdReadiness::NONE;
+        }
+
+        FdReadiness(pollfd.revents)
+    }
+}
+
+impl BitOr for FdReadiness {
+    type Output = FdReadiness;
+
+    fn bitor(self, rhs: FdReadiness) -> FdReadiness {
+        FdReadiness(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for FdReadiness {
+    fn bitor_assign(&mut self, rhs: FdReadiness) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// What should happen with a fd hook after its callback ran.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FdHookAction {
+    /// Keep watching the file descriptor.
+    Continue,
+    /// Stop watching the file descriptor, e.g. after the end of file was
+    /// reached.
+    Unhook,
+}
+
 /// Hook for a file descriptor, the hook is removed when the object is dropped.
+///
+/// The callback can remove the hook by returning `FdHookAction::Unhook`, the
+/// file descriptor object and the callback data are kept until the hook
+/// object is dropped.
 pub struct FdHook<T, F> {
-    _hook: Hook,
-    _hook_data: Box<FdHookData<T, F>>,
+    hook_data: Box<FdHookData<T, F>>,
+}
+
+type FdCallback<T, F> = fn(&T, &mut F, FdReadiness) -> FdHookAction;
+type FdWeechatCallback<T, F> =
+    fn(&T, &Weechat, &mut F, FdReadiness) -> FdHookAction;
+
+/// The callback of a fd hook, callbacks of the deprecated `hook_fd()` don't
+/// receive the Weechat object.
+enum FdHookCallback<T, F> {
+    Legacy(FdCallback<T, F>),
+    WithWeechat(FdWeechatCallback<T, F>),
 }
 
 struct FdHookData<T, F> {
-    callback: fn(&T, fd_object: &mut F),
+    callback: FdHookCallback<T, F>,
     callback_data: T,
     fd_object: F,
+    mode: FdHookMode,
+    hook_ptr: Cell<*mut t_hook>,
+    weechat_ptr: *mut t_weechat_plugin,
+}
+
+impl<T, F> FdHookData<T, F> {
+    #[track_caller]
+    fn unhook(&self) {
+        let hook_ptr = self.hook_ptr.replace(ptr::null_mut());
+
+        if !hook_ptr.is_null() {
+            let weechat = Weechat::from_ptr(self.weechat_ptr);
+            let unhook = weechat.get().unhook.unwrap();
+            unsafe { unhook(hook_ptr) };
+        }
+    }
+}
+
+impl<T, F> Drop for FdHook<T, F> {
+    fn drop(&mut self) {
+        self.hook_data.unhook();
+    }
+}
+
+impl<T, F> FdHook<T, F> {
+    /// Is the file descriptor still watched, this is `false` once the
+    /// callback returned `FdHookAction::Unhook`.
+    #[track_caller]
+    pub fn is_hooked(&self) -> bool {
+        !self.hook_data.hook_ptr.get().is_null()
+    }
+
+    /// Get a reference to the hooked file descriptor object.
+    pub fn fd_object(&self) -> &F {
+        &self.hook_data.fd_object
+    }
 }
 
 /// Hook for a weechat command, the hook is removed when the object is dropped.
-pub struct CommandRunHook<T> {
+pub struct CommandRunHook<T, R = ReturnCode> {
     _hook: Hook,
-    _hook_data: Box<CommandRunHookData<T>>,
+    _hook_data: Box<CommandRunHookData<T, R>>,
 }
 
-struct CommandRunHookData<T> {
-    callback: fn(&T, Buffer, Cow<str>) -> ReturnCode,
+struct CommandRunHookData<T, R> {
+    callback: fn(&T, Buffer, Cow<str>) -> R,
     callback_data: T,
     weechat_ptr: *mut t_weechat_plugin,
 }
 
 /// Hook for a signal, the hook is removed when the object is dropped.
-pub struct SignalHook<T> {
+pub struct SignalHook<T, R = ReturnCode> {
+    _hook: Hook,
+    _hook_data: Box<SignalHookData<T, R>>,
+}
+
+struct SignalHookData<T, R> {
+    callback: fn(&T, &Weechat, SignalHookValue) -> R,
+    callback_data: T,
+    weechat_ptr: *mut t_weechat_plugin,
+}
+
+/// Hook for changes of config options, the hook is removed when the object is
+/// dropped.
+pub struct ConfigHook<T> {
     _hook: Hook,
-    _hook_data: Box<SignalHookData<T>>,
+    _hook_data: Box<ConfigHookData<T>>,
 }
 
-struct SignalHookData<T> {
-    callback: fn(&T, &Weechat, SignalHookValue) -> ReturnCode,
+struct ConfigHookData<T> {
+    callback: fn(&T, &Weechat, Cow<str>, Cow<str>),
     callback_data: T,
     weechat_ptr: *mut t_weechat_plugin,
 }
@@ -144,6 +304,41 @@ impl SignalHookValue {
     }
 }
 
+/// Hook for a modifier, the hook is removed when the object is dropped.
+pub struct ModifierHook<T> {
+    _hook: Hook,
+    _hook_data: Box<ModifierHookData<T>>,
+}
+
+type ModifierCallback<T> =
+    fn(&T, &Weechat, Cow<str>, Cow<str>, Cow<str>) -> Option<String>;
+
+struct ModifierHookData<T> {
+    callback: ModifierCallback<T>,
+    callback_data: T,
+    weechat_ptr: *mut t_weechat_plugin,
+}
+
+/// Hook for an infolist, the infolist is removed when the object is dropped.
+pub struct InfolistHook<T> {
+    _hook: Hook,
+    _hook_data: Box<InfolistHookData<T>>,
+}
+
+type InfolistCallback<T> = fn(
+    &T,
+    &Weechat,
+    Cow<str>,
+    Option<*mut c_void>,
+    Cow<str>,
+) -> Option<InfolistBuilder>;
+
+struct InfolistHookData<T> {
+    callback: InfolistCallback<T>,
+    callback_data: T,
+    weechat_ptr: *mut t_weechat_plugin,
+}
+
 /// A hook for a timer, the hook will be removed when the object is dropped.
 pub struct TimerHook<T> {
     _hook: Hook,
@@ -156,6 +351,102 @@ struct TimerHookData<T> {
     weechat_ptr: *mut t_weechat_plugin,
 }
 
+/// Errors that can happen while creating a hook.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HookError {
+    /// The interval of a timer was zero.
+    ZeroInterval,
+    /// The interval of a timer was shorter than a millisecond, the smallest
+    /// interval Weechat supports.
+    SubMillisecondInterval(Duration),
+    /// The hook priority was larger than [`MAX_HOOK_PRIORITY`].
+    InvalidPriority(u32),
+    /// The hooked name already contained a priority separator (`|`).
+    InvalidName(String),
+    /// A command pattern contained the pattern separator (`;`).
+    InvalidPattern(String),
+    /// No commands were given to hook.
+    NoCommands,
+    /// The name of the command was empty.
+    EmptyName,
+    /// An argument contained a null byte, the name of the argument is given.
+    NulByte(&'static str),
+    /// A command with the given name already exists.
+    AlreadyExists(String),
+    /// Weechat refused to create the hook.
+    CreationFailed,
+}
+
+impl std::fmt::Display for HookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HookError::ZeroInterval => {
+                write!(f, "the timer interval can't be zero")
+            }
+            HookError::SubMillisecondInterval(interval) => write!(
+                f,
+                "the timer interval {:?} is shorter than a millisecond",
+                interval
+            ),
+            HookError::InvalidPriority(priority) => write!(
+                f,
+                "the hook priority {} is larger than the maximum of {}",
+                priority, MAX_HOOK_PRIORITY
+            ),
+            HookError::InvalidName(name) => write!(
+                f,
+                "the hooked name {:?} can't contain the priority separator",
+                name
+            ),
+            HookError::InvalidPattern(pattern) => write!(
+                f,
+                "the command pattern {:?} can't contain the pattern separator",
+                pattern
+            ),
+            HookError::NoCommands => {
+                write!(f, "at least one command needs to be hooked")
+            }
+            HookError::EmptyName => {
+                write!(f, "the command name can't be empty")
+            }
+            HookError::NulByte(argument) => {
+                write!(f, "the {} can't contain a null byte", argument)
+            }
+            HookError::AlreadyExists(name) => {
+                write!(f, "the command {:?} already exists", name)
+            }
+            HookError::CreationFailed => {
+                write!(f, "Weechat couldn't create the hook")
+            }
+        }
+    }
+}
+
+/// The largest priority a hook can have.
+pub const MAX_HOOK_PRIORITY: u32 = i32::MAX as u32;
+
+/// Prefix the hooked name with the priority, e.g. `5000|my_signal`.
+pub(crate) fn hook_name_with_priority(
+    name: &str,
+    priority: Option<u32>,
+) -> Result<String, HookError> {
+    if name.contains('|') {
+        return Err(HookError::InvalidName(name.to_owned()));
+    }
+
+    match priority {
+        Some(p) if p > MAX_HOOK_PRIORITY => Err(HookError::InvalidPriority(p)),
+        Some(p) => Ok(format!("{}|{}", p, name)),
+        None => Ok(name.to_owned()),
+    }
+}
+
+impl std::error::Error for HookError {}
+
+fn c_string(argument: &'static str, value: &str) -> Result<CString, HookError> {
+    CString::new(value).map_err(|_| HookError::NulByte(argument))
+}
+
 #[derive(Default)]
 /// Description for a weechat command that should will be hooked.
 /// The fields of this struct accept the same string formats that are described
@@ -169,23 +460,33 @@ pub struct CommandDescription<'a> {
     pub args: &'a str,
     /// Description for the command arguments (displayed with `/help command`)
     pub args_description: &'a str,
-    /// Completion template for the command.
+    /// Completion template for the command, see
+    /// [`CompletionTemplate`](crate::CompletionTemplate) for a builder.
     pub completion: &'a str,
 }
 
 impl Weechat {
     /// Create a new weechat command. Returns the hook of the command. The
     /// command is unhooked if the hook is dropped.
-    pub fn hook_command<T>(
+    ///
+    /// The callback can return a `Result`, if an error is returned it will be
+    /// printed to the core buffer and the command fails.
+    ///
+    /// Returns an error if the name is empty, if any of the fields of the
+    /// description contains a null byte or if a command with the same name
+    /// was already created by this or another plugin.
+    #[track_caller]
+    pub fn hook_command<T, R>(
         &self,
         command_info: CommandDescription,
-        callback: fn(data: &T, buffer: Buffer, args: ArgsWeechat),
+        callback: fn(data: &T, buffer: Buffer, args: ArgsWeechat) -> R,
         callback_data: Option<T>,
-    ) -> CommandHook<T>
+    ) -> Result<CommandHook<T, R>, HookError>
     where
         T: Default,
+        R: IntoReturnCode,
     {
-        unsafe extern "C" fn c_hook_cb<T>(
+        unsafe extern "C" fn c_hook_cb<T, R: IntoReturnCode>(
             pointer: *const c_void,
             _data: *mut c_void,
             buffer: *mut t_gui_buffer,
@@ -193,23 +494,37 @@ impl Weechat {
             argv: *mut *mut c_char,
             _argv_eol: *mut *mut c_char,
         ) -> c_int {
-            let hook_data: &mut CommandHookData<T> =
-                { &mut *(pointer as *mut CommandHookData<T>) };
-            let buffer = Buffer::from_ptr(hook_data.weechat_ptr, buffer);
+            let hook_data: &mut CommandHookData<T, R> =
+                { &mut *(pointer as *mut CommandHookData<T, R>) };
+            let weechat_ptr = hook_data.weechat_ptr;
+            let buffer = Buffer::from_ptr(weechat_ptr, buffer);
             let callback = hook_data.callback;
             let callback_data = &hook_data.callback_data;
             let args = ArgsWeechat::new(argc, argv);
 
-            callback(callback_data, buffer, args);
+            callback_return_code(weechat_ptr, |_| {
+                callback(callback_data, buffer, args)
+            })
+        }
 
-            WEECHAT_RC_OK
+        if command_info.name.is_empty() {
+            return Err(HookError::EmptyName);
         }
 
-        let name = LossyCString::new(command_info.name);
-        let description = LossyCString::new(command_info.description);
-        let args = LossyCString::new(command_info.args);
-        let args_description = LossyCString::new(command_info.args_description);
-        let completion = LossyCString::new(command_info.completion);
+        let name = c_string("command name", command_info.name)?;
+        let description =
+            c_string("command description", command_info.description)?;
+        let args = c_string("command arguments", command_info.args)?;
+        let args_description = c_string(
+            "command arguments description",
+            command_info.args_description,
+        )?;
+        let completion =
+            c_string("command completion", command_info.completion)?;
+
+        if self.command_exists(command_info.name) {
+            return Err(HookError::AlreadyExists(command_info.name.to_owned()));
+        }
 
         let data = Box::new(CommandHookData {
             callback,
@@ -228,42 +543,170 @@ impl Weechat {
                 args.as_ptr(),
                 args_description.as_ptr(),
                 completion.as_ptr(),
-                Some(c_hook_cb::<T>),
+                Some(c_hook_cb::<T, R>),
                 data_ref as *const _ as *const c_void,
                 ptr::null_mut(),
             )
         };
         let hook_data = unsafe { Box::from_raw(data_ref) };
+
+        if hook_ptr.is_null() {
+            return Err(HookError::CreationFailed);
+        }
+
         let hook = Hook {
             ptr: hook_ptr,
             weechat_ptr: self.ptr,
         };
 
-        CommandHook::<T> {
+        Ok(CommandHook::<T, R> {
             _hook: hook,
             _hook_data: hook_data,
+        })
+    }
+
+    /// Check if a command with the given name was created by any plugin.
+    #[track_caller]
+    fn command_exists(&self, name: &str) -> bool {
+        let arguments = format!("command,{}", name);
+
+        let mut infolist =
+            match self.infolist_get_with("hook", None, Some(&arguments)) {
+                Some(infolist) => infolist,
+                None => return false,
+            };
+
+        // The name is matched as a mask, the wildcards it might contain need
+        // an exact comparison.
+        while let Some(item) = infolist.next_item() {
+            if item.string("command").as_deref() == Some(name) {
+                return true;
+            }
         }
+
+        false
     }
 
     /// Hook an object that can be turned into a raw file descriptor.
-    /// Returns the hook object.
+    /// Returns the hook object, or an error if Weechat refused to watch the
+    /// file descriptor.
     /// * `fd_object` - An object for wich the file descriptor will be watched
     ///     and the callback called when read or write operations can happen
     ///     on it.
     /// * `mode` - Configure the hook to watch for writes, reads or both on the
     ///     file descriptor.
     /// * `callback` - A function that will be called if a watched event on the
-    ///     file descriptor happends.
+    ///     file descriptor happends. It receives the events the file
+    ///     descriptor is ready for and returns if the hook should stay, the
+    ///     hook is removed after the callback returned `FdHookAction::Unhook`.
     /// * `callback_data` - Data that will be passed to the callback every time
     ///     the callback runs. This data will be freed when the hook is
     ///     unhooked.
+    #[deprecated(
+        note = "Use hook_fd_with_weechat() instead, its callback receives the Weechat object."
+    )]
+    #[track_caller]
     pub fn hook_fd<T, F>(
         &self,
         fd_object: F,
         mode: FdHookMode,
-        callback: fn(data: &T, fd_object: &mut F),
+        callback: fn(
+            data: &T,
+            fd_object: &mut F,
+            readiness: FdReadiness,
+        ) -> FdHookAction,
+        callback_data: Option<T>,
+    ) -> Result<FdHook<T, F>, HookError>
+    where
+        T: Default,
+        F: AsRawFd,
+    {
+        self.new_fd_hook(
+            fd_object,
+            mode,
+            FdHookCallback::Legacy(callback),
+            callback_data,
+        )
+    }
+
+    /// Hook an object that can be turned into a raw file descriptor.
+    /// Returns the hook object, or an error if Weechat refused to watch the
+    /// file descriptor.
+    /// * `fd_object` - An object for wich the file descriptor will be watched
+    ///     and the callback called when read or write operations can happen
+    ///     on it.
+    /// * `mode` - Configure the hook to watch for writes, reads or both on the
+    ///     file descriptor.
+    /// * `callback` - A function that will be called if a watched event on the
+    ///     file descriptor happends. It receives the Weechat object and the
+    ///     events the file descriptor is ready for and returns if the hook
+    ///     should stay, the hook is removed after the callback returned
+    ///     `FdHookAction::Unhook`.
+    /// * `callback_data` - Data that will be passed to the callback every time
+    ///     the callback runs. This data will be freed when the hook is
+    ///     unhooked.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// fn read_cb(
+    ///     _: &(),
+    ///     weechat: &Weechat,
+    ///     socket: &mut UnixStream,
+    ///     readiness: FdReadiness,
+    /// ) -> FdHookAction {
+    ///     if readiness.is_exception() {
+    ///         weechat.print("The socket was closed");
+    ///         return FdHookAction::Unhook;
+    ///     }
+    ///
+    ///     let mut line = String::new();
+    ///     let _ = socket.read_to_string(&mut line);
+    ///     weechat.print(&line);
+    ///
+    ///     FdHookAction::Continue
+    /// }
+    ///
+    /// let hook = weechat.hook_fd_with_weechat(
+    ///     socket,
+    ///     FdHookMode::Read,
+    ///     read_cb,
+    ///     None,
+    /// )?;
+    /// ```
+    #[track_caller]
+    pub fn hook_fd_with_weechat<T, F>(
+        &self,
+        fd_object: F,
+        mode: FdHookMode,
+        callback: fn(
+            data: &T,
+            weechat: &Weechat,
+            fd_object: &mut F,
+            readiness: FdReadiness,
+        ) -> FdHookAction,
+        callback_data: Option<T>,
+    ) -> Result<FdHook<T, F>, HookError>
+    where
+        T: Default,
+        F: AsRawFd,
+    {
+        self.new_fd_hook(
+            fd_object,
+            mode,
+            FdHookCallback::WithWeechat(callback),
+            callback_data,
+        )
+    }
+
+    #[track_caller]
+    fn new_fd_hook<T, F>(
+        &self,
+        fd_object: F,
+        mode: FdHookMode,
+        callback: FdHookCallback<T, F>,
         callback_data: Option<T>,
-    ) -> FdHook<T, F>
+    ) -> Result<FdHook<T, F>, HookError>
     where
         T: Default,
         F: AsRawFd,
@@ -271,17 +714,35 @@ impl Weechat {
         unsafe extern "C" fn c_hook_cb<T, F>(
             pointer: *const c_void,
             _data: *mut c_void,
-            _fd: i32,
+            fd: i32,
         ) -> c_int {
             let hook_data: &mut FdHookData<T, F> =
                 { &mut *(pointer as *mut FdHookData<T, F>) };
-            let callback = hook_data.callback;
+            let weechat_ptr = hook_data.weechat_ptr;
+            let callback = &hook_data.callback;
             let callback_data = &hook_data.callback_data;
             let fd_object = &mut hook_data.fd_object;
+            let readiness = FdReadiness::poll(fd, &hook_data.mode);
+
+            let action = catch_panic(weechat_ptr, || match callback {
+                FdHookCallback::Legacy(callback) => {
+                    callback(callback_data, fd_object, readiness)
+                }
+                FdHookCallback::WithWeechat(callback) => callback(
+                    callback_data,
+                    &Weechat::from_ptr(weechat_ptr),
+                    fd_object,
+                    readiness,
+                ),
+            });
 
-            callback(callback_data, fd_object);
+            // Weechat allows a hook to be removed while its callback runs, the
+            // hook data stays alive until the hook object is dropped.
+            if action == Some(FdHookAction::Unhook) {
+                hook_data.unhook();
+            }
 
-            WEECHAT_RC_OK
+            ReturnCode::from(action.is_some()).into_raw()
         }
 
         let fd = fd_object.as_raw_fd();
@@ -290,11 +751,14 @@ impl Weechat {
             callback,
             callback_data: callback_data.unwrap_or_default(),
             fd_object,
+            mode,
+            hook_ptr: Cell::new(ptr::null_mut()),
+            weechat_ptr: self.ptr,
         });
 
         let data_ref = Box::leak(data);
         let hook_fd = self.get().hook_fd.unwrap();
-        let (read, write) = mode.as_tuple();
+        let (read, write) = data_ref.mode.as_tuple();
 
         let hook_ptr = unsafe {
             hook_fd(
@@ -309,20 +773,27 @@ impl Weechat {
             )
         };
         let hook_data = unsafe { Box::from_raw(data_ref) };
-        let hook = Hook {
-            ptr: hook_ptr,
-            weechat_ptr: self.ptr,
-        };
 
-        FdHook::<T, F> {
-            _hook: hook,
-            _hook_data: hook_data,
+        if hook_ptr.is_null() {
+            return Err(HookError::CreationFailed);
         }
+
+        hook_data.hook_ptr.set(hook_ptr);
+
+        Ok(FdHook::<T, F> { hook_data })
     }
 
     /// Create a timer that will repeatedly fire.
     ///
-    /// * `interval` - The delay between calls in milliseconds.
+    /// The interval isn't validated, intervals shorter than a millisecond are
+    /// truncated to zero.
+    ///
+    /// # Panics
+    ///
+    /// Panics if Weechat refuses to create the timer, e.g. because the
+    /// interval is zero.
+    ///
+    /// * `interval` - The delay between calls.
     /// * `align_second` - The alignment on a second. For example, if current time is 09:00, if
     ///     interval = 60000 (60 seconds), and align_second = 60, then timer is called each minute when
     ///     second is 0.
@@ -331,6 +802,10 @@ impl Weechat {
     ///     will be -1 if the timer has no end.
     /// * `callback_data` - Data that will be passed to the callback every time
     ///     the callback runs. This data will be freed when the hook is unhooked.
+    #[deprecated(
+        note = "Use try_hook_timer() instead, which validates the interval."
+    )]
+    #[track_caller]
     pub fn hook_timer<T>(
         &self,
         interval: Duration,
@@ -339,6 +814,69 @@ impl Weechat {
         callback: fn(data: &T, weechat: &Weechat, remaining: i32),
         callback_data: Option<T>,
     ) -> TimerHook<T>
+    where
+        T: Default,
+    {
+        self.new_timer_hook(
+            interval,
+            align_second,
+            max_calls,
+            callback,
+            callback_data,
+        )
+        .expect("Weechat couldn't create the timer")
+    }
+
+    /// Create a timer that will repeatedly fire.
+    ///
+    /// Returns an error if the interval is zero or shorter than a
+    /// millisecond, intervals that don't fit into a C `long` are saturated.
+    ///
+    /// * `interval` - The delay between calls.
+    /// * `align_second` - The alignment on a second. For example, if current time is 09:00, if
+    ///     interval = 60000 (60 seconds), and align_second = 60, then timer is called each minute when
+    ///     second is 0.
+    /// * `max_calls` - The number of calls to timer (if 0, then timer has no end)
+    /// * `callback` - A function that will be called when the timer fires, the `remaining` argument
+    ///     will be -1 if the timer has no end.
+    /// * `callback_data` - Data that will be passed to the callback every time
+    ///     the callback runs. This data will be freed when the hook is unhooked.
+    #[track_caller]
+    pub fn try_hook_timer<T>(
+        &self,
+        interval: Duration,
+        align_second: i32,
+        max_calls: i32,
+        callback: fn(data: &T, weechat: &Weechat, remaining: i32),
+        callback_data: Option<T>,
+    ) -> Result<TimerHook<T>, HookError>
+    where
+        T: Default,
+    {
+        if interval == Duration::from_secs(0) {
+            return Err(HookError::ZeroInterval);
+        } else if interval < Duration::from_millis(1) {
+            return Err(HookError::SubMillisecondInterval(interval));
+        }
+
+        self.new_timer_hook(
+            interval,
+            align_second,
+            max_calls,
+            callback,
+            callback_data,
+        )
+    }
+
+    #[track_caller]
+    fn new_timer_hook<T>(
+        &self,
+        interval: Duration,
+        align_second: i32,
+        max_calls: i32,
+        callback: fn(data: &T, weechat: &Weechat, remaining: i32),
+        callback_data: Option<T>,
+    ) -> Result<TimerHook<T>, HookError>
     where
         T: Default,
     {
@@ -351,14 +889,11 @@ impl Weechat {
                 { &mut *(pointer as *mut TimerHookData<T>) };
             let callback = &hook_data.callback;
             let callback_data = &hook_data.callback_data;
+            let weechat_ptr = hook_data.weechat_ptr;
 
-            callback(
-                callback_data,
-                &Weechat::from_ptr(hook_data.weechat_ptr),
-                remaining,
-            );
-
-            WEECHAT_RC_OK
+            callback_return_code(weechat_ptr, |weechat| {
+                callback(callback_data, weechat, remaining)
+            })
         }
 
         let data = Box::new(TimerHookData::<T> {
@@ -370,10 +905,13 @@ impl Weechat {
         let data_ref = Box::leak(data);
         let hook_timer = self.get().hook_timer.unwrap();
 
+        let interval =
+            c_long::try_from(interval.as_millis()).unwrap_or(c_long::MAX);
+
         let hook_ptr = unsafe {
             hook_timer(
                 self.ptr,
-                interval.as_millis() as i64,
+                interval,
                 align_second,
                 max_calls,
                 Some(c_hook_cb::<T>),
@@ -382,49 +920,77 @@ impl Weechat {
             )
         };
         let hook_data = unsafe { Box::from_raw(data_ref) };
+
+        if hook_ptr.is_null() {
+            return Err(HookError::CreationFailed);
+        }
+
         let hook = Hook {
             ptr: hook_ptr,
             weechat_ptr: self.ptr,
         };
 
-        TimerHook {
+        Ok(TimerHook {
             _hook: hook,
             _hook_data: hook_data,
-        }
+        })
     }
 
     /// Hook a command when Weechat runs it.
     ///
-    /// * `command` - The command to hook (wildcard `*` is allowed).
-    /// * `callback` - A function that will be called when the command is run.
+    /// * `commands` - The commands to hook, e.g. `&["/input return",
+    ///     "/input complete*"]` (wildcard `*` is allowed). At least one
+    ///     command must be given and the patterns must not contain a `;`.
+    /// * `priority` - The priority of the hook, hooks with a higher priority
+    ///     are called first. Weechat uses a priority of 1000 if none is given.
+    /// * `callback` - A function that will be called with the buffer and the
+    ///     full command when the command is run. Returning `ReturnCode::OkEat`
+    ///     stops the command from being executed, if it returns an error the
+    ///     error is printed to the core buffer and the command fails.
     /// * `callback_data` - Data that will be passed to the callback every time
     ///     the callback runs. This data will be freed when the hook is unhooked.
-    pub fn hook_command_run<T>(
+    #[track_caller]
+    pub fn hook_command_run<T, R>(
         &self,
-        command: &str,
-        callback: fn(data: &T, buffer: Buffer, command: Cow<str>) -> ReturnCode,
+        commands: &[&str],
+        priority: Option<u32>,
+        callback: fn(data: &T, buffer: Buffer, command: Cow<str>) -> R,
         callback_data: Option<T>,
-    ) -> CommandRunHook<T>
+    ) -> Result<CommandRunHook<T, R>, HookError>
     where
         T: Default,
+        R: IntoReturnCode,
     {
-        unsafe extern "C" fn c_hook_cb<T>(
+        unsafe extern "C" fn c_hook_cb<T, R: IntoReturnCode>(
             pointer: *const c_void,
             _data: *mut c_void,
             buffer: *mut t_gui_buffer,
             command: *const std::os::raw::c_char,
         ) -> c_int {
-            let hook_data: &mut CommandRunHookData<T> =
-                { &mut *(pointer as *mut CommandRunHookData<T>) };
+            let hook_data: &mut CommandRunHookData<T, R> =
+                { &mut *(pointer as *mut CommandRunHookData<T, R>) };
             let callback = hook_data.callback;
             let callback_data = &hook_data.callback_data;
+            let weechat_ptr = hook_data.weechat_ptr;
 
-            let buffer = Buffer::from_ptr(hook_data.weechat_ptr, buffer);
+            let buffer = Buffer::from_ptr(weechat_ptr, buffer);
             let command = CStr::from_ptr(command).to_string_lossy();
 
-            callback(callback_data, buffer, command) as isize as i32
+            callback_return_code(weechat_ptr, |_| {
+                callback(callback_data, buffer, command)
+            })
+        }
+
+        if commands.is_empty() {
+            return Err(HookError::NoCommands);
         }
 
+        if let Some(pattern) = commands.iter().find(|c| c.contains(';')) {
+            return Err(HookError::InvalidPattern(pattern.to_string()));
+        }
+
+        let command = hook_name_with_priority(&commands.join(";"), priority)?;
+
         let data = Box::new(CommandRunHookData {
             callback,
             callback_data: callback_data.unwrap_or_default(),
@@ -432,59 +998,72 @@ impl Weechat {
         });
 
         let data_ref = Box::leak(data);
-        let hook_timer = self.get().hook_command_run.unwrap();
+        let hook_command_run = self.get().hook_command_run.unwrap();
 
         let command = LossyCString::new(command);
 
         let hook_ptr = unsafe {
-            hook_timer(
+            hook_command_run(
                 self.ptr,
                 command.as_ptr(),
-                Some(c_hook_cb::<T>),
+                Some(c_hook_cb::<T, R>),
                 data_ref as *const _ as *const c_void,
                 ptr::null_mut(),
             )
         };
         let hook_data = unsafe { Box::from_raw(data_ref) };
+
+        if hook_ptr.is_null() {
+            return Err(HookError::CreationFailed);
+        }
+
         let hook = Hook {
             ptr: hook_ptr,
             weechat_ptr: self.ptr,
         };
 
-        CommandRunHook::<T> {
+        Ok(CommandRunHook::<T, R> {
             _hook: hook,
             _hook_data: hook_data,
-        }
+        })
     }
 
     /// Hook a signal.
     ///
     /// * `signal` - The signal to hook (wildcard `*` is allowed).
-    /// * `callback` - A function that will be called when the signal is received.
+    /// * `priority` - The priority of the hook, hooks with a higher priority
+    ///     are called first. Weechat uses a priority of 1000 if none is given.
+    /// * `callback` - A function that will be called when the signal is
+    ///     received. Returning `ReturnCode::OkEat` stops the signal from being
+    ///     sent to other hooks, if it returns an error the error is printed to
+    ///     the core buffer.
     /// * `callback_data` - Data that will be passed to the callback every time
     ///     the callback runs. This data will be freed when the hook is unhooked.
-    pub fn hook_signal<T>(
+    #[track_caller]
+    pub fn hook_signal<T, R>(
         &self,
         signal: &str,
+        priority: Option<u32>,
         callback: fn(
             data: &T,
             weechat: &Weechat,
             signal_value: SignalHookValue,
-        ) -> ReturnCode,
+        ) -> R,
         callback_data: Option<T>,
-    ) -> SignalHook<T>
+    ) -> Result<SignalHook<T, R>, HookError>
     where
         T: Default,
+        R: IntoReturnCode,
     {
-        unsafe extern "C" fn c_hook_cb<T>(
+        unsafe extern "C" fn c_hook_cb<T, R: IntoReturnCode>(
             pointer: *const c_void,
             _data: *mut c_void,
             _signal: *const c_char,
             data_type: *const c_char,
             signal_data: *mut c_void,
         ) -> c_int {
-            let hook_data: &mut SignalHookData<T> =
-                { &mut *(pointer as *mut SignalHookData<T>) };
+            let hook_data: &mut SignalHookData<T, R> =
+                { &mut *(pointer as *mut SignalHookData<T, R>) };
             let callback = hook_data.callback;
             let callback_data = &hook_data.callback_data;
 
@@ -494,16 +1073,18 @@ impl Weechat {
             if let Some(value) =
                 SignalHookValue::from_raw_with_type(data_type, signal_data)
             {
-                callback(
-                    callback_data,
-                    &Weechat::from_ptr(hook_data.weechat_ptr),
-                    value,
-                ) as i32
+                let weechat_ptr = hook_data.weechat_ptr;
+
+                callback_return_code(weechat_ptr, |weechat| {
+                    callback(callback_data, weechat, value)
+                })
             } else {
-                WEECHAT_RC_OK
+                ReturnCode::Ok.into_raw()
             }
         }
 
+        let signal = hook_name_with_priority(signal, priority)?;
+
         let data = Box::new(SignalHookData {
             callback,
             callback_data: callback_data.unwrap_or_default(),
@@ -519,20 +1100,750 @@ impl Weechat {
             hook_signal(
                 self.ptr,
                 signal.as_ptr(),
-                Some(c_hook_cb::<T>),
+                Some(c_hook_cb::<T, R>),
                 data_ref as *const _ as *const c_void,
                 ptr::null_mut(),
             )
         };
         let hook_data = unsafe { Box::from_raw(data_ref) };
+
+        if hook_ptr.is_null() {
+            return Err(HookError::CreationFailed);
+        }
+
         let hook = Hook {
             ptr: hook_ptr,
             weechat_ptr: self.ptr,
         };
 
-        SignalHook::<T> {
+        Ok(SignalHook::<T, R> {
             _hook: hook,
             _hook_data: hook_data,
-        }
+        })
+    }
+
+    /// Hook the changes of config options.
+    ///
+    /// * `option` - The full name of the option, e.g.
+    ///     `"plugins.var.python.myscript.enabled"` (wildcard `*` is allowed).
+    /// * `callback` - A function that will be called with the full name and
+    ///     the new value of the option when the option changes.
+    /// * `callback_data` - Data that will be passed to the callback every time
+    ///     the callback runs. This data will be freed when the hook is unhooked.
+    #[track_caller]
+    pub fn hook_config<T>(
+        &self,
+        option: &str,
+        callback: fn(
+            data: &T,
+            weechat: &Weechat,
+            option: Cow<str>,
+            value: Cow<str>,
+        ),
+        callback_data: Option<T>,
+    ) -> Result<ConfigHook<T>, HookError>
+    where
+        T: Default,
+    {
+        unsafe extern "C" fn c_hook_cb<T>(
+            pointer: *const c_void,
+            _data: *mut c_void,
+            option: *const c_char,
+            value: *const c_char,
+        ) -> c_int {
+            let hook_data: &mut ConfigHookData<T> =
+                { &mut *(pointer as *mut ConfigHookData<T>) };
+            let callback = hook_data.callback;
+            let callback_data = &hook_data.callback_data;
+            let weechat_ptr = hook_data.weechat_ptr;
+
+            let option = CStr::from_ptr(option).to_string_lossy();
+            // The value is null if the option was removed.
+            let value = if value.is_null() {
+                Cow::Borrowed("")
+            } else {
+                CStr::from_ptr(value).to_string_lossy()
+            };
+
+            callback_return_code(weechat_ptr, |weechat| {
+                callback(callback_data, weechat, option, value)
+            })
+        }
+
+        let option = c_string("option name", option)?;
+
+        let data = Box::new(ConfigHookData {
+            callback,
+            callback_data: callback_data.unwrap_or_default(),
+            weechat_ptr: self.ptr,
+        });
+
+        let data_ref = Box::leak(data);
+        let hook_config = self.get().hook_config.unwrap();
+
+        let hook_ptr = unsafe {
+            hook_config(
+                self.ptr,
+                option.as_ptr(),
+                Some(c_hook_cb::<T>),
+                data_ref as *const _ as *const c_void,
+                ptr::null_mut(),
+            )
+        };
+        let hook_data = unsafe { Box::from_raw(data_ref) };
+
+        if hook_ptr.is_null() {
+            return Err(HookError::CreationFailed);
+        }
+
+        let hook = Hook {
+            ptr: hook_ptr,
+            weechat_ptr: self.ptr,
+        };
+
+        Ok(ConfigHook::<T> {
+            _hook: hook,
+            _hook_data: hook_data,
+        })
+    }
+
+    /// Send a signal to the hooks of the signal.
+    ///
+    /// Returns `ReturnCode::OkEat` if one of the hooks ate the signal, hooks
+    /// with a lower priority didn't receive the signal in that case.
+    ///
+    /// * `signal` - The signal that should be sent.
+    /// * `value` - The data that will be passed to the hooks.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let value = SignalHookValue::String("popup closed".to_owned());
+    ///
+    /// if weechat.signal_send("myplugin_popup", value) == ReturnCode::OkEat {
+    ///     weechat.print("The signal was eaten");
+    /// }
+    /// ```
+    #[track_caller]
+    pub fn signal_send(
+        &self,
+        signal: &str,
+        value: SignalHookValue,
+    ) -> ReturnCode {
+        let hook_signal_send = self.get().hook_signal_send.unwrap();
+
+        let signal = LossyCString::new(signal);
+
+        let ret = match value {
+            SignalHookValue::String(string) => {
+                let string = LossyCString::from_string(string);
+                unsafe {
+                    hook_signal_send(
+                        signal.as_ptr(),
+                        cstr!("string").as_ptr(),
+                        string.as_ptr() as *mut c_void,
+                    )
+                }
+            }
+            SignalHookValue::Integer(mut integer) => unsafe {
+                hook_signal_send(
+                    signal.as_ptr(),
+                    cstr!("integer").as_ptr(),
+                    &mut integer as *mut c_int as *mut c_void,
+                )
+            },
+            SignalHookValue::Pointer(pointer) => unsafe {
+                hook_signal_send(
+                    signal.as_ptr(),
+                    cstr!("pointer").as_ptr(),
+                    pointer,
+                )
+            },
+        };
+
+        ReturnCode::from_raw(ret)
+    }
+
+    /// Hook a modifier.
+    ///
+    /// * `modifier` - The name of the modifier to hook, e.g.
+    ///     `irc_in_privmsg`.
+    /// * `priority` - The priority of the hook, hooks with a higher priority
+    ///     are called first. Weechat uses a priority of 1000 if none is given.
+    /// * `callback` - A function that will be called when the modifier is
+    ///     used, it receives the modifier name, the modifier data and the
+    ///     string that should be modified. If the callback returns `None` the
+    ///     string is left unchanged, if it returns an empty string the string
+    ///     is dropped.
+    /// * `callback_data` - Data that will be passed to the callback every time
+    ///     the callback runs. This data will be freed when the hook is unhooked.
+    #[allow(clippy::type_complexity)]
+    #[track_caller]
+    pub fn hook_modifier<T>(
+        &self,
+        modifier: &str,
+        priority: Option<u32>,
+        callback: fn(
+            data: &T,
+            weechat: &Weechat,
+            modifier: Cow<str>,
+            modifier_data: Cow<str>,
+            string: Cow<str>,
+        ) -> Option<String>,
+        callback_data: Option<T>,
+    ) -> Result<ModifierHook<T>, HookError>
+    where
+        T: Default,
+    {
+        unsafe extern "C" fn c_hook_cb<T>(
+            pointer: *const c_void,
+            _data: *mut c_void,
+            modifier: *const c_char,
+            modifier_data: *const c_char,
+            string: *const c_char,
+        ) -> *mut c_char {
+            let hook_data: &mut ModifierHookData<T> =
+                { &mut *(pointer as *mut ModifierHookData<T>) };
+            let callback = hook_data.callback;
+            let callback_data = &hook_data.callback_data;
+
+            let modifier = CStr::from_ptr(modifier).to_string_lossy();
+            let modifier_data = if modifier_data.is_null() {
+                Cow::from("")
+            } else {
+                CStr::from_ptr(modifier_data).to_string_lossy()
+            };
+            let string = if string.is_null() {
+                Cow::from("")
+            } else {
+                CStr::from_ptr(string).to_string_lossy()
+            };
+
+            let weechat_ptr = hook_data.weechat_ptr;
+
+            let ret = catch_panic(weechat_ptr, || {
+                callback(
+                    callback_data,
+                    &Weechat::from_ptr(weechat_ptr),
+                    modifier,
+                    modifier_data,
+                    string,
+                )
+            })
+            .flatten();
+
+            match ret {
+                // Weechat wants a malloc'ed string that it will free.
+                Some(s) => libc::strdup(LossyCString::new(s).as_ptr()),
+                None => ptr::null_mut(),
+            }
+        }
+
+        let modifier = hook_name_with_priority(modifier, priority)?;
+
+        let data = Box::new(ModifierHookData {
+            callback,
+            callback_data: callback_data.unwrap_or_default(),
+            weechat_ptr: self.ptr,
+        });
+
+        let data_ref = Box::leak(data);
+        let hook_modifier = self.get().hook_modifier.unwrap();
+
+        let modifier = LossyCString::new(modifier);
+
+        let hook_ptr = unsafe {
+            hook_modifier(
+                self.ptr,
+                modifier.as_ptr(),
+                Some(c_hook_cb::<T>),
+                data_ref as *const _ as *const c_void,
+                ptr::null_mut(),
+            )
+        };
+        let hook_data = unsafe { Box::from_raw(data_ref) };
+
+        if hook_ptr.is_null() {
+            return Err(HookError::CreationFailed);
+        }
+
+        let hook = Hook {
+            ptr: hook_ptr,
+            weechat_ptr: self.ptr,
+        };
+
+        Ok(ModifierHook::<T> {
+            _hook: hook,
+            _hook_data: hook_data,
+        })
+    }
+
+    /// Hook an infolist, this allows other plugins and scripts to fetch the
+    /// infolist using `infolist_get()`.
+    ///
+    /// * `name` - The name of the infolist.
+    /// * `description` - The description of the infolist.
+    /// * `pointer_description` - The description of the pointer that can be
+    ///     passed when the infolist is fetched.
+    /// * `args_description` - The description of the arguments that can be
+    ///     passed when the infolist is fetched.
+    /// * `callback` - A function that will be called when the infolist is
+    ///     fetched, it receives the name of the infolist, the pointer and
+    ///     the arguments. The returned infolist is handed over to the caller
+    ///     of `infolist_get()`.
+    /// * `callback_data` - Data that will be passed to the callback every time
+    ///     the callback runs. This data will be freed when the hook is unhooked.
+    ///
+    /// Returns an error if Weechat refused to create the hook.
+    #[allow(clippy::type_complexity)]
+    #[track_caller]
+    pub fn hook_infolist<T>(
+        &self,
+        name: &str,
+        description: &str,
+        pointer_description: &str,
+        args_description: &str,
+        callback: fn(
+            data: &T,
+            weechat: &Weechat,
+            name: Cow<str>,
+            pointer: Option<*mut c_void>,
+            arguments: Cow<str>,
+        ) -> Option<InfolistBuilder>,
+        callback_data: Option<T>,
+    ) -> Result<InfolistHook<T>, HookError>
+    where
+        T: Default,
+    {
+        unsafe extern "C" fn c_hook_cb<T>(
+            pointer: *const c_void,
+            _data: *mut c_void,
+            infolist_name: *const c_char,
+            obj_pointer: *mut c_void,
+            arguments: *const c_char,
+        ) -> *mut t_infolist {
+            let hook_data: &mut InfolistHookData<T> =
+                { &mut *(pointer as *mut InfolistHookData<T>) };
+            let callback = hook_data.callback;
+            let callback_data = &hook_data.callback_data;
+
+            let infolist_name = CStr::from_ptr(infolist_name).to_string_lossy();
+            let obj_pointer = if obj_pointer.is_null() {
+                None
+            } else {
+                Some(obj_pointer)
+            };
+            let arguments = if arguments.is_null() {
+                Cow::from("")
+            } else {
+                CStr::from_ptr(arguments).to_string_lossy()
+            };
+
+            let weechat_ptr = hook_data.weechat_ptr;
+
+            let ret = catch_panic(weechat_ptr, || {
+                callback(
+                    callback_data,
+                    &Weechat::from_ptr(weechat_ptr),
+                    infolist_name,
+                    obj_pointer,
+                    arguments,
+                )
+            })
+            .flatten();
+
+            match ret {
+                // Weechat frees the infolist once the caller is done with it.
+                Some(builder) => builder.into_raw(),
+                None => ptr::null_mut(),
+            }
+        }
+
+        let data = Box::new(InfolistHookData {
+            callback,
+            callback_data: callback_data.unwrap_or_default(),
+            weechat_ptr: self.ptr,
+        });
+
+        let data_ref = Box::leak(data);
+        let hook_infolist = self.get().hook_infolist.unwrap();
+
+        let name = LossyCString::new(name);
+        let description = LossyCString::new(description);
+        let pointer_description = LossyCString::new(pointer_description);
+        let args_description = LossyCString::new(args_description);
+
+        let hook_ptr = unsafe {
+            hook_infolist(
+                self.ptr,
+                name.as_ptr(),
+                description.as_ptr(),
+                pointer_description.as_ptr(),
+                args_description.as_ptr(),
+                Some(c_hook_cb::<T>),
+                data_ref as *const _ as *const c_void,
+                ptr::null_mut(),
+            )
+        };
+        let hook_data = unsafe { Box::from_raw(data_ref) };
+
+        if hook_ptr.is_null() {
+            return Err(HookError::CreationFailed);
+        }
+
+        let hook = Hook {
+            ptr: hook_ptr,
+            weechat_ptr: self.ptr,
+        };
+
+        Ok(InfolistHook::<T> {
+            _hook: hook,
+            _hook_data: hook_data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::mock::MockWeechat;
+
+    type Calls = Rc<RefCell<Vec<&'static str>>>;
+
+    fn eating_cb(calls: &Calls, _: &Weechat, _: SignalHookValue) -> ReturnCode {
+        calls.borrow_mut().push("eating");
+        ReturnCode::OkEat
+    }
+
+    fn observing_cb(calls: &Calls, _: &Weechat, _: SignalHookValue) {
+        calls.borrow_mut().push("observing");
+    }
+
+    #[test]
+    fn eaten_signals_are_not_propagated() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let calls = Calls::default();
+
+        let _observing = weechat
+            .hook_signal(
+                "mock_signal",
+                Some(1000),
+                observing_cb,
+                Some(calls.clone()),
+            )
+            .unwrap();
+        let _eating = weechat
+            .hook_signal(
+                "mock_signal",
+                Some(2000),
+                eating_cb,
+                Some(calls.clone()),
+            )
+            .unwrap();
+
+        let value = SignalHookValue::String("popup".to_owned());
+        let ret = weechat.signal_send("mock_signal", value);
+
+        assert_eq!(ret, ReturnCode::OkEat);
+        assert_eq!(*calls.borrow(), ["eating"]);
+    }
+
+    #[test]
+    fn signals_are_propagated_to_all_hooks() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let calls = Calls::default();
+
+        let _first = weechat
+            .hook_signal("mock_signal", None, observing_cb, Some(calls.clone()))
+            .unwrap();
+        let _second = weechat
+            .hook_signal("mock_signal", None, observing_cb, Some(calls.clone()))
+            .unwrap();
+
+        let ret =
+            weechat.signal_send("mock_signal", SignalHookValue::Integer(1));
+
+        assert_eq!(ret, ReturnCode::Ok);
+        assert_eq!(*calls.borrow(), ["observing", "observing"]);
+    }
+
+    #[test]
+    fn dropped_signal_hooks_are_not_called() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let calls = Calls::default();
+
+        let eating = weechat
+            .hook_signal(
+                "mock_signal",
+                Some(2000),
+                eating_cb,
+                Some(calls.clone()),
+            )
+            .unwrap();
+        let _observing = weechat
+            .hook_signal("mock_signal", None, observing_cb, Some(calls.clone()))
+            .unwrap();
+
+        drop(eating);
+        weechat.signal_send("mock_signal", SignalHookValue::Integer(1));
+
+        assert_eq!(*calls.borrow(), ["observing"]);
+    }
+
+    type Reads = Rc<RefCell<Vec<(FdReadiness, Vec<u8>)>>>;
+
+    fn read_cb(
+        reads: &Reads,
+        _: &Weechat,
+        socket: &mut UnixStream,
+        readiness: FdReadiness,
+    ) -> FdHookAction {
+        let mut buf = [0; 64];
+        let length = socket.read(&mut buf).unwrap();
+        reads.borrow_mut().push((readiness, buf[..length].to_vec()));
+
+        if length == 0 {
+            FdHookAction::Unhook
+        } else {
+            FdHookAction::Continue
+        }
+    }
+
+    #[test]
+    fn fd_hook_unhooks_itself_at_the_end_of_file() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let reads = Reads::default();
+
+        let (mut writer, reader) = UnixStream::pair().unwrap();
+        let fd = reader.as_raw_fd();
+
+        let hook = weechat
+            .hook_fd_with_weechat(
+                reader,
+                FdHookMode::Read,
+                read_cb,
+                Some(reads.clone()),
+            )
+            .unwrap();
+
+        writer.write_all(b"hello").unwrap();
+        mock.run_until_idle();
+
+        assert!(hook.is_hooked());
+        assert_eq!(reads.borrow().len(), 1);
+        assert!(reads.borrow()[0].0.is_readable());
+        assert_eq!(reads.borrow()[0].1, b"hello");
+
+        drop(writer);
+
+        // The hook is ready forever after the end of file, it needs to be
+        // removed inside the callback to not run it in a loop.
+        mock.run_until_idle();
+
+        assert!(!hook.is_hooked());
+        assert!(!mock.hooked_fds().contains(&fd));
+        assert_eq!(reads.borrow().len(), 2);
+        assert!(reads.borrow()[1].0.is_readable());
+        assert!(reads.borrow()[1].1.is_empty());
+
+        // The file descriptor object is kept until the hook is dropped.
+        assert_eq!(hook.fd_object().as_raw_fd(), fd);
+        drop(hook);
+        assert_eq!(mock.run_fd_hooks(), 0);
+    }
+
+    fn write_cb(
+        reads: &Reads,
+        _: &Weechat,
+        _: &mut UnixStream,
+        readiness: FdReadiness,
+    ) -> FdHookAction {
+        reads.borrow_mut().push((readiness, Vec::new()));
+        FdHookAction::Unhook
+    }
+
+    #[test]
+    fn fd_hook_reports_the_readiness() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let reads = Reads::default();
+
+        let (mut writer, reader) = UnixStream::pair().unwrap();
+
+        let _hook = weechat
+            .hook_fd_with_weechat(
+                reader,
+                FdHookMode::ReadWrite,
+                write_cb,
+                Some(reads.clone()),
+            )
+            .unwrap();
+
+        mock.run_until_idle();
+
+        assert_eq!(reads.borrow().len(), 1);
+        let readiness = reads.borrow()[0].0;
+        assert!(readiness.is_writable());
+        assert!(!readiness.is_readable());
+        assert!(!readiness.is_exception());
+
+        writer.write_all(b"hello").unwrap();
+        mock.run_until_idle();
+
+        // The hook was removed by the first callback.
+        assert_eq!(reads.borrow().len(), 1);
+    }
+
+    fn tick_cb(ticks: &Rc<RefCell<Vec<i32>>>, _: &Weechat, remaining: i32) {
+        ticks.borrow_mut().push(remaining);
+    }
+
+    #[test]
+    fn timer_intervals_are_validated() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+
+        let hook =
+            weechat.try_hook_timer(Duration::from_secs(0), 0, 0, tick_cb, None);
+        assert_eq!(hook.err(), Some(HookError::ZeroInterval));
+
+        let interval = Duration::from_micros(500);
+        let hook = weechat.try_hook_timer(interval, 0, 0, tick_cb, None);
+        assert_eq!(
+            hook.err(),
+            Some(HookError::SubMillisecondInterval(interval))
+        );
+
+        assert!(mock.timer_intervals().is_empty());
+    }
+
+    fn command_run_cb(_: &(), _: Buffer, _: Cow<str>) {}
+
+    #[test]
+    fn command_run_hooks_need_a_command() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+
+        let hook = weechat.hook_command_run(&[], None, command_run_cb, None);
+        assert_eq!(hook.err(), Some(HookError::NoCommands));
+
+        let hook =
+            weechat.hook_command_run(&["/a;/b"], None, command_run_cb, None);
+        assert_eq!(
+            hook.err(),
+            Some(HookError::InvalidPattern("/a;/b".to_owned()))
+        );
+    }
+
+    #[test]
+    fn long_timer_intervals_are_saturated() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+
+        let _hook = weechat
+            .try_hook_timer(Duration::from_secs(u64::MAX), 0, 0, tick_cb, None)
+            .unwrap();
+        let _other = weechat
+            .try_hook_timer(Duration::from_millis(1500), 0, 0, tick_cb, None)
+            .unwrap();
+
+        assert_eq!(mock.timer_intervals(), [c_long::MAX, 1500]);
+    }
+
+    #[test]
+    fn timer_callbacks_receive_the_remaining_calls() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let ticks = Rc::new(RefCell::new(Vec::new()));
+
+        let hook = weechat
+            .try_hook_timer(
+                Duration::from_secs(1),
+                0,
+                2,
+                tick_cb,
+                Some(ticks.clone()),
+            )
+            .unwrap();
+
+        while mock.run_timers() > 0 {}
+
+        assert_eq!(*ticks.borrow(), [1, 0]);
+        drop(hook);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn deprecated_timer_hook_still_works() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let ticks = Rc::new(RefCell::new(Vec::new()));
+
+        let hook = weechat.hook_timer(
+            Duration::from_millis(10),
+            0,
+            0,
+            tick_cb,
+            Some(ticks.clone()),
+        );
+
+        mock.run_timers();
+        mock.run_timers();
+        assert_eq!(*ticks.borrow(), [-1, -1]);
+
+        drop(hook);
+        assert_eq!(mock.run_timers(), 0);
+    }
+
+    fn print_cb(_: &(), weechat: &Weechat, remaining: i32) {
+        weechat.print(&format!("tick, {} remaining", remaining));
+    }
+
+    #[test]
+    fn timer_callbacks_can_print() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+
+        let _hook = weechat
+            .try_hook_timer(Duration::from_secs(1), 0, 2, print_cb, None)
+            .unwrap();
+
+        while mock.run_timers() > 0 {}
+
+        assert_eq!(mock.messages(), ["tick, 1 remaining", "tick, 0 remaining"]);
+    }
+
+    fn print_fd_cb(
+        _: &(),
+        weechat: &Weechat,
+        _: &mut UnixStream,
+        _: FdReadiness,
+    ) -> FdHookAction {
+        weechat.print("writable");
+        FdHookAction::Unhook
+    }
+
+    #[test]
+    fn fd_callbacks_can_print() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+
+        let (socket, _other) = UnixStream::pair().unwrap();
+        let _hook = weechat
+            .hook_fd_with_weechat(socket, FdHookMode::Write, print_fd_cb, None)
+            .unwrap();
+
+        mock.run_until_idle();
+
+        assert_eq!(mock.messages(), ["writable"]);
     }
 }
@@ -1,20 +1,56 @@
 //! Weechat Infolist module.
 
+use chrono::{DateTime, TimeZone, Utc};
+use libc::time_t;
 use std::ffi::CStr;
-use std::os::raw::c_void;
+use std::marker::PhantomData;
+use std::mem;
+use std::os::raw::{c_int, c_void};
 use std::ptr;
 
-use weechat_sys::{t_gui_buffer, t_infolist, t_weechat_plugin};
+use weechat_sys::{
+    t_gui_buffer, t_infolist, t_infolist_item, t_weechat_plugin,
+};
 
+use crate::hdata::HDataPointer;
 use crate::{Buffer, LossyCString, Weechat};
 use std::borrow::Cow;
 
 /// Weechat Infolist type.
+///
+/// The infolist is freed when it's dropped. Infolists contain a snapshot of
+/// Weechat objects that can change once control returns to Weechat, so they
+/// shouldn't be kept around and they can't be sent to other threads:
+///
+/// ```compile_fail
+/// fn assert_send<T: Send>() {}
+/// assert_send::<weechat::Infolist>();
+/// ```
 pub struct Infolist {
     pub(crate) ptr: *mut t_infolist,
     pub(crate) weechat_ptr: *mut t_weechat_plugin,
 }
 
+/// An item of an infolist.
+///
+/// The item borrows the infolist mutably since all the items share the cursor
+/// of the infolist, only one item can be alive at a time.
+/// Items can't outlive their infolist:
+///
+/// ```compile_fail
+/// fn first_name(weechat: &weechat::Weechat) -> Option<String> {
+///     let item = {
+///         let mut infolist = weechat.infolist_get("buffer", "")?;
+///         infolist.next_item()?
+///     };
+///
+///     item.get_string("name").map(|name| name.into_owned())
+/// }
+/// ```
+pub struct InfolistItem<'a> {
+    infolist: &'a Infolist,
+}
+
 impl Drop for Infolist {
     fn drop(&mut self) {
         let weechat = Weechat::from_ptr(self.weechat_ptr);
@@ -23,28 +59,316 @@ impl Drop for Infolist {
     }
 }
 
+/// A builder for a new infolist, e.g. an infolist that is returned from an
+/// infolist hook.
+///
+/// The infolist is freed when the builder is dropped, unless it was turned
+/// into an `Infolist` or handed over to Weechat.
+pub struct InfolistBuilder {
+    ptr: *mut t_infolist,
+    weechat_ptr: *mut t_weechat_plugin,
+}
+
+/// A new item of an infolist that is being built.
+pub struct InfolistItemBuilder<'a> {
+    ptr: *mut t_infolist_item,
+    weechat_ptr: *mut t_weechat_plugin,
+    _builder: PhantomData<&'a mut InfolistBuilder>,
+}
+
+impl Drop for InfolistBuilder {
+    fn drop(&mut self) {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let free = weechat.get().infolist_free.unwrap();
+        unsafe { free(self.ptr) }
+    }
+}
+
+impl InfolistBuilder {
+    /// Add a new item to the infolist.
+    ///
+    /// Returns `None` if Weechat couldn't allocate the item.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut builder = weechat.infolist_new().unwrap();
+    ///
+    /// for (number, account) in accounts.iter().enumerate() {
+    ///     builder
+    ///         .new_item()
+    ///         .unwrap()
+    ///         .add_integer("number", number as i32)
+    ///         .add_string("name", &account.name);
+    /// }
+    /// ```
+    #[track_caller]
+    pub fn new_item(&mut self) -> Option<InfolistItemBuilder<'_>> {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let infolist_new_item = weechat.get().infolist_new_item.unwrap();
+
+        let ptr = unsafe { infolist_new_item(self.ptr) };
+
+        if ptr.is_null() {
+            None
+        } else {
+            Some(InfolistItemBuilder {
+                ptr,
+                weechat_ptr: self.weechat_ptr,
+                _builder: PhantomData,
+            })
+        }
+    }
+
+    /// Finish building the infolist, the returned infolist can be read like
+    /// an infolist that was fetched from Weechat.
+    pub fn build(self) -> Infolist {
+        let infolist = Infolist {
+            ptr: self.ptr,
+            weechat_ptr: self.weechat_ptr,
+        };
+        mem::forget(self);
+
+        infolist
+    }
+
+    /// Give up the ownership of the infolist, Weechat frees infolists that
+    /// are returned from infolist hooks.
+    pub(crate) fn into_raw(self) -> *mut t_infolist {
+        let ptr = self.ptr;
+        mem::forget(self);
+
+        ptr
+    }
+}
+
+impl<'a> InfolistItemBuilder<'a> {
+    /// Add a string variable to the item.
+    #[track_caller]
+    pub fn add_string(&mut self, name: &str, value: &str) -> &mut Self {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let new_var_string = weechat.get().infolist_new_var_string.unwrap();
+
+        let name = LossyCString::new(name);
+        let value = LossyCString::new(value);
+
+        unsafe { new_var_string(self.ptr, name.as_ptr(), value.as_ptr()) };
+
+        self
+    }
+
+    /// Add an integer variable to the item.
+    #[track_caller]
+    pub fn add_integer(&mut self, name: &str, value: i32) -> &mut Self {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let new_var_integer = weechat.get().infolist_new_var_integer.unwrap();
+
+        let name = LossyCString::new(name);
+
+        unsafe { new_var_integer(self.ptr, name.as_ptr(), value) };
+
+        self
+    }
+
+    /// Add a time variable to the item.
+    #[track_caller]
+    pub fn add_time(&mut self, name: &str, value: DateTime<Utc>) -> &mut Self {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let new_var_time = weechat.get().infolist_new_var_time.unwrap();
+
+        let name = LossyCString::new(name);
+
+        unsafe {
+            new_var_time(self.ptr, name.as_ptr(), value.timestamp() as time_t)
+        };
+
+        self
+    }
+
+    /// Add a buffer variable to the item, the bytes are copied into the
+    /// infolist.
+    #[track_caller]
+    pub fn add_buffer(&mut self, name: &str, value: &[u8]) -> &mut Self {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let new_var_buffer = weechat.get().infolist_new_var_buffer.unwrap();
+
+        let name = LossyCString::new(name);
+
+        unsafe {
+            new_var_buffer(
+                self.ptr,
+                name.as_ptr(),
+                value.as_ptr() as *mut c_void,
+                value.len() as c_int,
+            )
+        };
+
+        self
+    }
+
+    /// Add a pointer variable to the item.
+    ///
+    /// # Safety
+    ///
+    /// The pointer is handed out to the readers of the infolist, it must be
+    /// valid for as long as the infolist is in use.
+    #[track_caller]
+    pub unsafe fn add_pointer(
+        &mut self,
+        name: &str,
+        value: *mut c_void,
+    ) -> &mut Self {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let new_var_pointer = weechat.get().infolist_new_var_pointer.unwrap();
+
+        let name = LossyCString::new(name);
+
+        new_var_pointer(self.ptr, name.as_ptr(), value);
+
+        self
+    }
+}
+
+/// The type of a field of an infolist item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InfolistFieldType {
+    /// An integer field.
+    Integer,
+    /// A string field.
+    String,
+    /// A pointer field.
+    Pointer,
+    /// A buffer field, buffer fields contain raw bytes.
+    Buffer,
+    /// A time field.
+    Time,
+    /// A field with an unknown type character.
+    Other(char),
+}
+
+impl InfolistFieldType {
+    fn from_char(field_type: char) -> InfolistFieldType {
+        match field_type {
+            'i' => InfolistFieldType::Integer,
+            's' => InfolistFieldType::String,
+            'p' => InfolistFieldType::Pointer,
+            'b' => InfolistFieldType::Buffer,
+            't' => InfolistFieldType::Time,
+            c => InfolistFieldType::Other(c),
+        }
+    }
+}
+
+/// The value of a field of an infolist item.
+#[derive(Debug, Clone)]
+pub enum InfolistValue {
+    /// The value of an integer field.
+    Integer(i32),
+    /// The value of a string field.
+    String(String),
+    /// The value of a pointer field.
+    Pointer(*mut c_void),
+    /// The content of a buffer field.
+    Buffer(Vec<u8>),
+    /// The value of a time field.
+    Time(DateTime<Utc>),
+}
+
+/// An object that an infolist can be scoped to.
+pub enum InfolistPointer<'a> {
+    /// A buffer, e.g. to get the nicklist of a single buffer.
+    Buffer(&'a Buffer),
+    /// An object that was found using hdata.
+    HData(&'a HDataPointer),
+}
+
+impl<'a> InfolistPointer<'a> {
+    fn as_ptr(&self) -> *mut c_void {
+        match self {
+            InfolistPointer::Buffer(buffer) => buffer.ptr as *mut c_void,
+            InfolistPointer::HData(pointer) => pointer.ptr,
+        }
+    }
+}
+
+impl<'a> From<&'a Buffer> for InfolistPointer<'a> {
+    fn from(buffer: &'a Buffer) -> Self {
+        InfolistPointer::Buffer(buffer)
+    }
+}
+
+impl<'a> From<&'a HDataPointer> for InfolistPointer<'a> {
+    fn from(pointer: &'a HDataPointer) -> Self {
+        InfolistPointer::HData(pointer)
+    }
+}
+
 impl Weechat {
+    /// Create a new empty infolist.
+    #[track_caller]
+    pub fn infolist_new(&self) -> Option<InfolistBuilder> {
+        let infolist_new = self.get().infolist_new.unwrap();
+
+        let ptr = unsafe { infolist_new(self.ptr) };
+
+        if ptr.is_null() {
+            None
+        } else {
+            Some(InfolistBuilder {
+                ptr,
+                weechat_ptr: self.ptr,
+            })
+        }
+    }
+
     /// Get an infolist.
     /// * `name` - The name of the infolist.
     /// * `arguments` - Optional arguments for the infolist. See the weechat
     ///     C API documentation for valid values.
     /// Returns an Infolist object that behaves like a cursor that can be moved
     /// back and forth to access individual Infolist items.
+    #[track_caller]
     pub fn infolist_get(
         &self,
         name: &str,
         arguments: &str,
+    ) -> Option<Infolist> {
+        self.infolist_get_with(name, None, Some(arguments))
+    }
+
+    /// Get an infolist that is scoped to a single object.
+    /// * `name` - The name of the infolist.
+    /// * `pointer` - The object the infolist should be scoped to, e.g. the
+    ///     buffer for the `nicklist` infolist.
+    /// * `arguments` - Optional arguments for the infolist, e.g. the hook
+    ///     type for the `hook` infolist. See the weechat C API documentation
+    ///     for valid values.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let nicklist =
+    ///     weechat.infolist_get_with("nicklist", Some((&buffer).into()), None);
+    /// let hooks = weechat.infolist_get_with("hook", None, Some("command"));
+    /// ```
+    #[track_caller]
+    pub fn infolist_get_with(
+        &self,
+        name: &str,
+        pointer: Option<InfolistPointer>,
+        arguments: Option<&str>,
     ) -> Option<Infolist> {
         let name = LossyCString::new(name);
-        let arguments = LossyCString::new(arguments);
+        let arguments = arguments.map(LossyCString::new);
+        let pointer = pointer.map_or(ptr::null_mut(), |p| p.as_ptr());
 
         let infolist_get = self.get().infolist_get.unwrap();
         let ptr = unsafe {
             infolist_get(
                 self.ptr,
                 name.as_ptr(),
-                ptr::null_mut(),
-                arguments.as_ptr(),
+                pointer,
+                arguments.as_ref().map_or(ptr::null(), |a| a.as_ptr()),
             )
         };
 
@@ -60,7 +384,54 @@ impl Weechat {
 }
 
 impl Infolist {
+    /// Move the cursor to the next item and return it.
+    ///
+    /// Returns `None` once the end of the infolist is reached, calling it
+    /// again afterwards starts again from the first item.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut infolist = weechat.infolist_get("buffer", "").unwrap();
+    ///
+    /// while let Some(item) = infolist.next_item() {
+    ///     if let Some(name) = item.get_string("full_name") {
+    ///         weechat.print(&name);
+    ///     }
+    /// }
+    /// ```
+    pub fn next_item(&mut self) -> Option<InfolistItem<'_>> {
+        if self.next() {
+            Some(InfolistItem { infolist: self })
+        } else {
+            None
+        }
+    }
+
+    /// Move the cursor to the previous item and return it.
+    ///
+    /// Returns `None` once the start of the infolist is reached, calling it
+    /// again afterwards starts again from the last item.
+    #[track_caller]
+    pub fn prev_item(&mut self) -> Option<InfolistItem<'_>> {
+        if self.prev() {
+            Some(InfolistItem { infolist: self })
+        } else {
+            None
+        }
+    }
+
+    /// Reset the cursor, the next call to `next_item()` returns the first
+    /// item and the next call to `prev_item()` returns the last item.
+    #[track_caller]
+    pub fn reset(&mut self) {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let infolist_reset = weechat.get().infolist_reset_item_cursor.unwrap();
+        unsafe { infolist_reset(self.ptr) }
+    }
+
     /// Move the "cursor" to the next item in an infolist.
+    #[track_caller]
     pub fn next(&self) -> bool {
         let weechat = Weechat::from_ptr(self.weechat_ptr);
         let infolist_next = weechat.get().infolist_next.unwrap();
@@ -69,6 +440,7 @@ impl Infolist {
     }
 
     /// Move the "cursor" to the previous item in an infolist.
+    #[track_caller]
     pub fn prev(&self) -> bool {
         let weechat = Weechat::from_ptr(self.weechat_ptr);
         let infolist_prev = weechat.get().infolist_prev.unwrap();
@@ -82,6 +454,7 @@ impl Infolist {
     /// The types are: "i" (integer), "s" (string), "p" (pointer), "b" (buffer),
     /// "t" (time).
     /// Example: "i:my_integer,s:my_string"
+    #[track_caller]
     pub fn fields(&self) -> Option<Cow<str>> {
         let weechat = Weechat::from_ptr(self.weechat_ptr);
         let infolist_fields = weechat.get().infolist_fields.unwrap();
@@ -95,6 +468,7 @@ impl Infolist {
         }
     }
 
+    #[track_caller]
     fn get_pointer(&self, name: &str) -> *mut c_void {
         let weechat = Weechat::from_ptr(self.weechat_ptr);
         let infolist_pointer = weechat.get().infolist_pointer.unwrap();
@@ -106,6 +480,7 @@ impl Infolist {
 
     /// Get the buffer of the current infolist item.
     /// If the infolist item doesn't have a buffer None is returned.
+    #[track_caller]
     pub fn get_buffer(&self) -> Option<Buffer> {
         let ptr = self.get_pointer("buffer");
 
@@ -118,6 +493,7 @@ impl Infolist {
 
     /// Get the value of a string variable in the current infolist item.
     /// * `name` - The variable name of the infolist item.
+    #[track_caller]
     pub fn get_string(&self, name: &str) -> Option<Cow<str>> {
         let weechat = Weechat::from_ptr(self.weechat_ptr);
         let infolist_string = weechat.get().infolist_string.unwrap();
@@ -134,3 +510,259 @@ impl Infolist {
         }
     }
 }
+
+impl<'a> InfolistItem<'a> {
+    /// Get the names and types of the fields of the item.
+    #[track_caller]
+    pub fn fields(&self) -> Vec<(String, InfolistFieldType)> {
+        let fields = match self.infolist.fields() {
+            Some(fields) => fields,
+            None => return Vec::new(),
+        };
+
+        fields
+            .split(',')
+            .filter_map(|field| {
+                let mut parts = field.splitn(2, ':');
+                let field_type = parts.next()?.chars().next()?;
+                let name = parts.next()?;
+
+                Some((
+                    name.to_owned(),
+                    InfolistFieldType::from_char(field_type),
+                ))
+            })
+            .collect()
+    }
+
+    /// Get the value of a field of the item, whatever its type is.
+    ///
+    /// Returns `None` if the item doesn't have a field with the given name or
+    /// if the field has an unknown type.
+    #[track_caller]
+    pub fn get_any(&self, name: &str) -> Option<InfolistValue> {
+        let (_, field_type) =
+            self.fields().into_iter().find(|(field, _)| field == name)?;
+
+        match field_type {
+            InfolistFieldType::Integer => {
+                self.integer(name).map(InfolistValue::Integer)
+            }
+            InfolistFieldType::String => self
+                .string(name)
+                .map(|s| InfolistValue::String(s.into_owned())),
+            InfolistFieldType::Pointer => {
+                self.pointer(name).map(InfolistValue::Pointer)
+            }
+            InfolistFieldType::Buffer => {
+                self.buffer(name).map(InfolistValue::Buffer)
+            }
+            InfolistFieldType::Time => self.time(name).map(InfolistValue::Time),
+            InfolistFieldType::Other(_) => None,
+        }
+    }
+
+    /// Get the buffer of the item.
+    /// If the item doesn't have a buffer None is returned.
+    #[track_caller]
+    pub fn get_buffer(&self) -> Option<Buffer> {
+        self.infolist.get_buffer()
+    }
+
+    /// Get the value of a string variable of the item.
+    /// * `name` - The variable name of the infolist item.
+    #[track_caller]
+    pub fn get_string(&self, name: &str) -> Option<Cow<'_, str>> {
+        self.infolist.get_string(name)
+    }
+
+    /// Check if the item has a field with the given name and type.
+    #[track_caller]
+    fn has_field(&self, name: &str, field_type: InfolistFieldType) -> bool {
+        self.fields().iter().any(|(field, current_type)| {
+            field == name && *current_type == field_type
+        })
+    }
+
+    /// Get the value of a string field of the item.
+    ///
+    /// Returns `None` if the item doesn't have a string field with the given
+    /// name.
+    #[track_caller]
+    pub fn string(&self, name: &str) -> Option<Cow<'_, str>> {
+        if !self.has_field(name, InfolistFieldType::String) {
+            return None;
+        }
+
+        self.infolist.get_string(name)
+    }
+
+    /// Get the value of an integer field of the item.
+    ///
+    /// Returns `None` if the item doesn't have an integer field with the
+    /// given name.
+    #[track_caller]
+    pub fn integer(&self, name: &str) -> Option<i32> {
+        if !self.has_field(name, InfolistFieldType::Integer) {
+            return None;
+        }
+
+        let weechat = Weechat::from_ptr(self.infolist.weechat_ptr);
+        let infolist_integer = weechat.get().infolist_integer.unwrap();
+
+        let name = LossyCString::new(name);
+
+        Some(unsafe { infolist_integer(self.infolist.ptr, name.as_ptr()) })
+    }
+
+    /// Get the value of a time field of the item.
+    ///
+    /// Returns `None` if the item doesn't have a time field with the given
+    /// name.
+    // The size of time_t depends on the platform.
+    #[allow(clippy::unnecessary_cast)]
+    #[track_caller]
+    pub fn time(&self, name: &str) -> Option<DateTime<Utc>> {
+        if !self.has_field(name, InfolistFieldType::Time) {
+            return None;
+        }
+
+        let weechat = Weechat::from_ptr(self.infolist.weechat_ptr);
+        let infolist_time = weechat.get().infolist_time.unwrap();
+
+        let name = LossyCString::new(name);
+
+        let time = unsafe { infolist_time(self.infolist.ptr, name.as_ptr()) };
+
+        Utc.timestamp_opt(time as i64, 0).single()
+    }
+
+    /// Get the value of a pointer field of the item.
+    ///
+    /// Returns `None` if the item doesn't have a pointer field with the given
+    /// name or if the pointer is null.
+    #[track_caller]
+    pub fn pointer(&self, name: &str) -> Option<*mut c_void> {
+        if !self.has_field(name, InfolistFieldType::Pointer) {
+            return None;
+        }
+
+        let ptr = self.infolist.get_pointer(name);
+
+        if ptr.is_null() {
+            None
+        } else {
+            Some(ptr)
+        }
+    }
+
+    /// Get the content of a buffer field of the item, buffer fields contain
+    /// raw bytes.
+    ///
+    /// Returns `None` if the item doesn't have a buffer field with the given
+    /// name.
+    #[track_caller]
+    pub fn buffer(&self, name: &str) -> Option<Vec<u8>> {
+        if !self.has_field(name, InfolistFieldType::Buffer) {
+            return None;
+        }
+
+        let weechat = Weechat::from_ptr(self.infolist.weechat_ptr);
+        let infolist_buffer = weechat.get().infolist_buffer.unwrap();
+
+        let name = LossyCString::new(name);
+        let mut size: c_int = 0;
+
+        unsafe {
+            let ptr =
+                infolist_buffer(self.infolist.ptr, name.as_ptr(), &mut size);
+
+            if ptr.is_null() || size < 0 {
+                return None;
+            }
+
+            let bytes =
+                std::slice::from_raw_parts(ptr as *const u8, size as usize);
+
+            Some(bytes.to_vec())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockWeechat;
+
+    #[test]
+    fn infolists_are_freed_once() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+
+        weechat.buffer_new::<(), ()>("room", None, None, None, None);
+        weechat.buffer_new::<(), ()>("other", None, None, None, None);
+
+        for _ in 0..100 {
+            let mut infolist = weechat.infolist_get("buffer", "").unwrap();
+            let mut names = Vec::new();
+
+            while let Some(item) = infolist.next_item() {
+                names.push(item.string("name").unwrap().into_owned());
+            }
+
+            assert_eq!(names, ["room", "other"]);
+
+            let item = infolist.prev_item().unwrap();
+            assert_eq!(item.integer("number"), Some(2));
+        }
+
+        // Built infolists, turned into an `Infolist` or not, are freed too.
+        for i in 0..100 {
+            let mut builder = weechat.infolist_new().unwrap();
+            builder.new_item().unwrap().add_integer("count", i);
+
+            if i % 2 == 0 {
+                let mut infolist = builder.build();
+                let item = infolist.next_item().unwrap();
+                assert!(matches!(
+                    item.get_any("count"),
+                    Some(InfolistValue::Integer(count)) if count == i
+                ));
+            }
+        }
+
+        assert!(weechat.infolist_get("missing", "").is_none());
+        assert_eq!(mock.infolists(), (200, 200));
+        assert_eq!(mock.invalid_accesses(), 0);
+    }
+
+    #[test]
+    fn built_infolists_keep_their_fields() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let time = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+
+        let mut builder = weechat.infolist_new().unwrap();
+        builder
+            .new_item()
+            .unwrap()
+            .add_string("name", "room")
+            .add_time("created", time)
+            .add_buffer("data", b"\x00\x01");
+        let mut infolist = builder.build();
+        let item = infolist.next_item().unwrap();
+
+        assert_eq!(
+            item.fields(),
+            [
+                ("name".to_owned(), InfolistFieldType::String),
+                ("created".to_owned(), InfolistFieldType::Time),
+                ("data".to_owned(), InfolistFieldType::Buffer),
+            ]
+        );
+        assert_eq!(item.time("created"), Some(time));
+        assert_eq!(item.buffer("data"), Some(vec![0, 1]));
+        // The types of the fields are checked.
+        assert_eq!(item.integer("name"), None);
+    }
+}
@@ -0,0 +1,85 @@
+//! Key binding module.
+//! Allows plugins to bind and unbind keys in the different Weechat key
+//! contexts.
+
+use std::collections::HashMap;
+
+use crate::{HashtableItemType, LossyCString, Weechat};
+
+/// The context in which a key binding is active.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyContext {
+    /// The default context, used for keys pressed in the input bar.
+    Default,
+    /// The search context, used while searching text in a buffer.
+    Search,
+    /// The cursor context, used while the cursor moves freely on the screen.
+    Cursor,
+    /// The mouse context, used for mouse events.
+    Mouse,
+}
+
+impl KeyContext {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            KeyContext::Default => "default",
+            KeyContext::Search => "search",
+            KeyContext::Cursor => "cursor",
+            KeyContext::Mouse => "mouse",
+        }
+    }
+}
+
+impl Weechat {
+    /// Add new key bindings.
+    ///
+    /// Returns the number of keys that were added.
+    ///
+    /// * `context` - The context in which the keys will be bound.
+    /// * `bindings` - A map of keys to commands, e.g. `"meta-g"` to
+    ///     `"/myplugin go"`. The special key `"__quiet"` can be used to
+    ///     prevent Weechat from displaying the added keys.
+    #[track_caller]
+    pub fn key_bind(
+        &self,
+        context: KeyContext,
+        bindings: &HashMap<String, String>,
+    ) -> i32 {
+        let key_bind = self.get().key_bind.unwrap();
+
+        let hashtable = match self.new_hashtable(
+            32,
+            HashtableItemType::String,
+            HashtableItemType::String,
+        ) {
+            Some(h) => h,
+            None => return 0,
+        };
+
+        for (key, command) in bindings {
+            hashtable.set(key, command);
+        }
+
+        let context = LossyCString::new(context.as_str());
+
+        unsafe { key_bind(context.as_ptr(), hashtable.ptr) }
+    }
+
+    /// Remove a key binding.
+    ///
+    /// Returns the number of keys that were removed.
+    ///
+    /// * `context` - The context of the key binding.
+    /// * `key` - The key to remove, the special value `"all"` removes all the
+    ///     keys of the context and `"area:"` prefixed values remove all keys
+    ///     for a mouse area.
+    #[track_caller]
+    pub fn key_unbind(&self, context: KeyContext, key: &str) -> i32 {
+        let key_unbind = self.get().key_unbind.unwrap();
+
+        let context = LossyCString::new(context.as_str());
+        let key = LossyCString::new(key);
+
+        unsafe { key_unbind(context.as_ptr(), key.as_ptr()) }
+    }
+}
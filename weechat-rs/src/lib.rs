@@ -1,85 +1,523 @@
 #![warn(missing_docs)]
 
+/// Create a `&'static CStr` out of a string literal.
+///
+/// This is used for the fixed property and variable names that are passed
+/// to Weechat, so they don't need to be allocated on every call. The literal
+/// is checked for null bytes at compile time.
+macro_rules! cstr {
+    ($string:literal) => {{
+        const CSTR: &::std::ffi::CStr =
+            match ::std::ffi::CStr::from_bytes_with_nul(
+                concat!($string, "\0").as_bytes(),
+            ) {
+                Ok(cstr) => cstr,
+                Err(_) => panic!("the string contains a null byte"),
+            };
+        CSTR
+    }};
+}
+
 pub mod bar;
 pub mod buffer;
+pub mod channel;
+pub mod color;
 pub mod completion;
 pub mod config;
 pub mod config_options;
+pub mod eval;
+pub mod executor;
 pub mod hashtable;
 pub mod hdata;
 pub mod hooks;
 pub mod infolist;
+pub mod keys;
+#[cfg(feature = "weechat-logger")]
+pub mod logger;
+#[cfg(test)]
+mod mock;
+pub mod option_settings;
 pub mod plugin;
+pub mod strings;
+#[cfg(feature = "tracing-support")]
+pub mod subscriber;
+pub mod upgrade;
 pub mod weechat;
+pub mod window;
 
-pub use weechat_macro::weechat_plugin;
+pub use weechat_macro::{weechat_plugin, FromHData};
 
-pub use plugin::{WeechatPlugin, WeechatResult};
-pub use weechat::{ArgsWeechat, OptionChanged, Weechat};
+pub use plugin::{PluginError, WeechatPlugin, WeechatResult};
+pub use weechat::{
+    ArgsWeechat, OptionChangeSummary, OptionChanged, OptionUnset, Prefix,
+    UnsupportedVersion, Weechat, WeechatVersion,
+};
 
-pub use buffer::{Buffer, Nick, NickArgs};
+pub use bar::{
+    Bar, BarError, BarFilling, BarItem, BarItemError, BarItems, BarPosition,
+    BarSettings, BarType,
+};
+#[allow(deprecated)]
+pub use buffer::NickArgs;
+pub use buffer::{Buffer, Nick, NickSettings};
+pub use channel::{ChannelCapacity, ChannelError, MainSender, SendError};
+pub use color::{BasicColor, Color, ColorAttributes, Styled};
+pub use window::Window;
 
-pub use config::{Config, ConfigSection, ConfigSectionInfo};
+pub use config::{
+    Config, ConfigFile, ConfigReadError, ConfigSection, ConfigSectionInfo,
+    ConfigWriteError, SectionReadCallback, SectionWriteCallback,
+};
 pub use config_options::{
-    BooleanOption, ColorOption, ConfigOption, IntegerOption, StringOption,
+    AnyOption, BaseConfigOption, BooleanOption, CheckValueCallback,
+    ColorOption, ConfigOption, ConfigOptionRef, EnumOption, IntegerOption,
+    InvalidOptionValue, OptionCallback, OptionChanges, StringOption,
 };
 
 pub use hooks::{
-    CommandDescription, CommandHook, CommandRunHook, FdHook, FdHookMode,
-    SignalHook, SignalHookValue, TimerHook,
+    CommandDescription, CommandHook, CommandRunHook, ConfigHook, FdHook,
+    FdHookAction, FdHookMode, FdReadiness, HookError, InfolistHook,
+    ModifierHook, SignalHook, SignalHookValue, TimerHook, MAX_HOOK_PRIORITY,
 };
 
-pub use completion::{Completion, CompletionHook, CompletionPosition};
-pub use hashtable::{Hashtable, HashtableItemType};
-pub use hdata::HasHData;
-pub use infolist::Infolist;
+pub use completion::{
+    Completion, CompletionDirection, CompletionHook, CompletionItem,
+    CompletionPosition, CompletionTemplate, CompletionTemplateError,
+    StandaloneCompletion,
+};
+pub use eval::EvalPointer;
+pub use executor::JoinHandle;
+pub use hashtable::{
+    Hashtable, HashtableItemType, HashtableValue, InvalidValueType,
+    TypedHashtable,
+};
+pub use hdata::{FromHData, HasHData};
+pub use infolist::{
+    Infolist, InfolistBuilder, InfolistFieldType, InfolistItem,
+    InfolistItemBuilder, InfolistPointer, InfolistValue,
+};
+pub use keys::KeyContext;
+#[cfg(feature = "weechat-logger")]
+pub use logger::WeechatLogger;
+pub use option_settings::{
+    BooleanOptionSettings, ColorOptionSettings, EnumOptionSettings,
+    IntegerOptionSettings, OptionSettingsError, StringOptionSettings,
+};
+pub use strings::SplitFlags;
+#[cfg(feature = "tracing-support")]
+pub use subscriber::WeechatSubscriber;
+pub use upgrade::UpgradeFile;
 
+use libc::c_int;
+use std::any::Any;
 use std::ffi::CString;
+use std::fmt::Display;
+use std::panic::{self, AssertUnwindSafe};
 
 /// Status values for weechat callbacks
+///
+/// Callbacks of hooks that Weechat runs before it handles an event, e.g.
+/// signals or `hook_command_run()`, can return `ReturnCode::OkEat` to stop
+/// the event from being handled further.
+///
+/// # Example
+///
+/// ```ignore
+/// fn input_return_cb(_: &(), buffer: Buffer, _: Cow<str>) -> ReturnCode {
+///     // Eat the enter key on our own buffer, other buffers are untouched.
+///     ReturnCode::eat(buffer.get_full_name() == "myplugin.main")
+/// }
+///
+/// let hook = weechat.hook_command_run(
+///     &["/input return"],
+///     None,
+///     input_return_cb,
+///     None,
+/// )?;
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ReturnCode {
     Ok = weechat_sys::WEECHAT_RC_OK as isize,
     OkEat = weechat_sys::WEECHAT_RC_OK_EAT as isize,
     Error = weechat_sys::WEECHAT_RC_ERROR as isize,
 }
 
+impl ReturnCode {
+    /// Get `ReturnCode::OkEat` if the event should be eaten, otherwise
+    /// `ReturnCode::Ok`.
+    pub fn eat(eat: bool) -> ReturnCode {
+        if eat {
+            ReturnCode::OkEat
+        } else {
+            ReturnCode::Ok
+        }
+    }
+
+    pub(crate) fn from_raw(code: c_int) -> ReturnCode {
+        match code {
+            weechat_sys::WEECHAT_RC_OK => ReturnCode::Ok,
+            weechat_sys::WEECHAT_RC_OK_EAT => ReturnCode::OkEat,
+            _ => ReturnCode::Error,
+        }
+    }
+
+    pub(crate) fn into_raw(self) -> c_int {
+        match self {
+            ReturnCode::Ok => weechat_sys::WEECHAT_RC_OK,
+            ReturnCode::OkEat => weechat_sys::WEECHAT_RC_OK_EAT,
+            ReturnCode::Error => weechat_sys::WEECHAT_RC_ERROR,
+        }
+    }
+}
+
+impl<E> From<Result<(), E>> for ReturnCode {
+    fn from(result: Result<(), E>) -> ReturnCode {
+        match result {
+            Ok(()) => ReturnCode::Ok,
+            Err(_) => ReturnCode::Error,
+        }
+    }
+}
+
+impl From<bool> for ReturnCode {
+    fn from(success: bool) -> ReturnCode {
+        if success {
+            ReturnCode::Ok
+        } else {
+            ReturnCode::Error
+        }
+    }
+}
+
+/// A trait for values that callbacks can return, the value is converted into
+/// a `ReturnCode` that is passed back to Weechat.
+///
+/// Errors are printed to the core buffer using the error prefix and result in
+/// a `ReturnCode::Error`.
+pub trait IntoReturnCode {
+    /// Convert the value into a `ReturnCode`.
+    fn into_return_code(self, weechat: &Weechat) -> ReturnCode;
+}
+
+impl IntoReturnCode for () {
+    fn into_return_code(self, _: &Weechat) -> ReturnCode {
+        ReturnCode::Ok
+    }
+}
+
+impl IntoReturnCode for ReturnCode {
+    fn into_return_code(self, _: &Weechat) -> ReturnCode {
+        self
+    }
+}
+
+impl IntoReturnCode for bool {
+    fn into_return_code(self, _: &Weechat) -> ReturnCode {
+        self.into()
+    }
+}
+
+impl<E: Display> IntoReturnCode for Result<(), E> {
+    fn into_return_code(self, weechat: &Weechat) -> ReturnCode {
+        self.map(|_| ReturnCode::Ok).into_return_code(weechat)
+    }
+}
+
+impl<E: Display> IntoReturnCode for Result<ReturnCode, E> {
+    fn into_return_code(self, weechat: &Weechat) -> ReturnCode {
+        match self {
+            Ok(code) => code,
+            Err(e) => {
+                weechat.print_error(&e.to_string());
+                ReturnCode::Error
+            }
+        }
+    }
+}
+
+/// Run a callback and translate its return value into the return code that
+/// is passed back to Weechat.
+///
+/// A panic in the callback results in `WEECHAT_RC_ERROR`.
+pub(crate) fn callback_return_code<R: IntoReturnCode>(
+    weechat_ptr: *mut weechat_sys::t_weechat_plugin,
+    callback: impl FnOnce(&Weechat) -> R,
+) -> c_int {
+    catch_panic(weechat_ptr, || {
+        let weechat = Weechat::from_ptr(weechat_ptr);
+        callback(&weechat).into_return_code(&weechat)
+    })
+    .unwrap_or(ReturnCode::Error)
+    .into_raw()
+}
+
+/// Run a callback, catching any panic so it doesn't unwind across the FFI
+/// boundary.
+///
+/// If the callback panics the panic message is printed to the core buffer and
+/// `None` is returned.
+#[doc(hidden)]
+pub fn catch_panic<R>(
+    weechat_ptr: *mut weechat_sys::t_weechat_plugin,
+    callback: impl FnOnce() -> R,
+) -> Option<R> {
+    match panic::catch_unwind(AssertUnwindSafe(callback)) {
+        Ok(ret) => Some(ret),
+        Err(payload) => {
+            let message = panic_message(&*payload);
+
+            let weechat = Weechat::from_ptr(weechat_ptr);
+            weechat.print_error(&format!("Panic in a callback: {}", message));
+
+            None
+        }
+    }
+}
+
+/// Get the message of a panic payload.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(m) = payload.downcast_ref::<&str>() {
+        m
+    } else if let Some(m) = payload.downcast_ref::<String>() {
+        m.as_str()
+    } else {
+        "unknown panic"
+    }
+}
+
 pub(crate) struct LossyCString;
 
 impl LossyCString {
     pub(crate) fn new<T: AsRef<str>>(t: T) -> CString {
         match CString::new(t.as_ref()) {
             Ok(cstr) => cstr,
-            Err(_) => CString::new(t.as_ref().replace('\0', ""))
-                .expect("string has no nulls"),
+            Err(e) => {
+                // Reuse the allocation of the failed conversion.
+                let mut bytes = e.into_vec();
+                bytes.retain(|b| *b != 0);
+                CString::new(bytes).expect("string has no nulls")
+            }
+        }
+    }
+
+    /// Convert an owned string, the allocation of the string is reused for
+    /// the C string.
+    pub(crate) fn from_string(string: String) -> CString {
+        match CString::new(string.into_bytes()) {
+            Ok(cstr) => cstr,
+            Err(e) => {
+                let mut bytes = e.into_vec();
+                bytes.retain(|b| *b != 0);
+                CString::new(bytes).expect("string has no nulls")
+            }
         }
     }
 }
 
+/// Display a formatted message on the core weechat buffer.
+///
+/// Takes a `Weechat` object followed by the same arguments as the `format!`
+/// macro.
+///
+/// # Example
+///
+/// ```ignore
+/// weechat_print!(weechat, "Loaded {} servers, 100% done", servers.len());
+/// ```
+#[macro_export]
+macro_rules! weechat_print {
+    ($weechat:expr, $($arg:tt)*) => {
+        $weechat.print(&::std::format!($($arg)*))
+    };
+}
+
 /// A sealed type, allowing thread-unsafe weechat types to be safely
 /// passed between threads.
 ///
-/// Sealed items can be created with the `sealed` function on some types,
+/// Sealed items can be created with the `seal` function on some types,
 /// and then unsealed with the `unseal` function and a reference to the
 /// `Weechat` object.
 ///
 /// If the sealed object has been sent to a background thread, then to obtain
 /// a weechat object you must use the `on_main` or `on_main_blocking` functions
 /// to run code on the main thread with a reference to the `Weechat` object.
-pub struct Sealed<T>(T);
+///
+/// Sealed objects can be sent to other threads but they can't be shared
+/// between threads:
+///
+/// ```compile_fail
+/// fn assert_sync<T: Sync>() {}
+/// assert_sync::<weechat::Sealed<weechat::Buffer>>();
+/// ```
+///
+/// Only the types of this crate can be sealed, arbitrary objects can't:
+///
+/// ```compile_fail
+/// let sealed = weechat::Sealed::new(
+///     std::rc::Rc::new(()),
+///     String::new(),
+///     |_, _, _| true,
+/// );
+/// ```
+pub struct Sealed<T: 'static> {
+    inner: T,
+    token: String,
+    check: fn(&T, &Weechat, &str) -> bool,
+}
 
-unsafe impl<T> Send for Sealed<T> {}
-unsafe impl<T> Sync for Sealed<T> {}
+// Sealed objects can only be created by this crate, for types that are only
+// pointers into Weechat which are checked again before they are unsealed.
+unsafe impl<T: 'static> Send for Sealed<T> {}
+
+impl<T: 'static> Sealed<T> {
+    /// Seal an object.
+    ///
+    /// * `inner` - The object that should be sealed.
+    /// * `token` - A token that identifies the object, e.g. its name.
+    /// * `check` - A function that checks if the object is still valid and
+    ///     still matches the token when it is unsealed.
+    pub(crate) fn new(
+        inner: T,
+        token: String,
+        check: fn(&T, &Weechat, &str) -> bool,
+    ) -> Self {
+        Sealed {
+            inner,
+            token,
+            check,
+        }
+    }
 
-impl<T> Sealed<T> {
     /// Unseal an object, returning the sealed object.
     ///
+    /// Returns `None` if the object isn't valid anymore, e.g. a sealed
+    /// buffer was closed in the meantime.
+    ///
     /// This requires a `Weechat` object, and because it is !Send
     /// you must use the `on_main` function to safely obtain a Weechat
-    /// object.
-    ///
-    /// The Weechat reference is not used and serves only as a token
-    /// to ensure the function is called on the main thread.
-    pub fn unseal(self, _: &weechat::Weechat) -> T {
-        self.0
+    /// object. The Weechat reference ensures that the function is called on
+    /// the main thread.
+    pub fn unseal(self, weechat: &Weechat) -> Option<T> {
+        if (self.check)(&self.inner, weechat, &self.token) {
+            Some(self.inner)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use weechat_sys::{WEECHAT_RC_ERROR, WEECHAT_RC_OK, WEECHAT_RC_OK_EAT};
+
+    use super::*;
+    use crate::mock::MockWeechat;
+
+    #[test]
+    fn lossy_c_string_removes_null_bytes() {
+        assert_eq!(LossyCString::new("a\0b").as_bytes(), b"ab");
+        assert_eq!(
+            LossyCString::from_string("a\0b\0".to_owned()).as_bytes(),
+            b"ab"
+        );
+        assert_eq!(
+            LossyCString::from_string("grüße".to_owned())
+                .to_str()
+                .unwrap(),
+            "grüße"
+        );
+    }
+
+    #[test]
+    fn return_code_from_result() {
+        assert_eq!(ReturnCode::from(Ok::<(), &str>(())), ReturnCode::Ok);
+        assert_eq!(ReturnCode::from(Err::<(), _>("failed")), ReturnCode::Error);
+        assert_eq!(ReturnCode::from(Err::<(), _>(())), ReturnCode::Error);
+    }
+
+    #[test]
+    fn return_code_from_bool() {
+        assert_eq!(ReturnCode::from(true), ReturnCode::Ok);
+        assert_eq!(ReturnCode::from(false), ReturnCode::Error);
+        assert_eq!(ReturnCode::eat(true), ReturnCode::OkEat);
+        assert_eq!(ReturnCode::eat(false), ReturnCode::Ok);
+    }
+
+    #[test]
+    fn return_code_raw_round_trip() {
+        for code in &[ReturnCode::Ok, ReturnCode::OkEat, ReturnCode::Error] {
+            assert_eq!(ReturnCode::from_raw(code.into_raw()), *code);
+        }
+
+        assert_eq!(ReturnCode::Ok.into_raw(), WEECHAT_RC_OK);
+        assert_eq!(ReturnCode::OkEat.into_raw(), WEECHAT_RC_OK_EAT);
+        assert_eq!(ReturnCode::Error.into_raw(), WEECHAT_RC_ERROR);
+    }
+
+    #[test]
+    fn callback_errors_are_printed() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+
+        let ret = callback_return_code(weechat.ptr, |_| Err::<(), _>("failed"));
+        assert_eq!(ret, WEECHAT_RC_ERROR);
+
+        let ret = callback_return_code(weechat.ptr, |_| {
+            Ok::<_, &str>(ReturnCode::OkEat)
+        });
+        assert_eq!(ret, WEECHAT_RC_OK_EAT);
+
+        assert_eq!(mock.messages(), ["failed"]);
+    }
+
+    #[test]
+    fn callback_panics_are_errors() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+
+        let ret = callback_return_code(weechat.ptr, |_| -> () {
+            panic!("oh no");
+        });
+
+        assert_eq!(ret, WEECHAT_RC_ERROR);
+        assert_eq!(mock.messages(), ["Panic in a callback: oh no"]);
+    }
+
+    #[test]
+    fn sealed_buffer_can_be_sent_to_other_threads() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let buffer =
+            weechat.buffer_new::<(), ()>("room", None, None, None, None);
+
+        let sealed = buffer.seal();
+        let sealed = thread::spawn(move || sealed).join().unwrap();
+
+        let unsealed = sealed.unseal(&weechat).unwrap();
+        assert!(unsealed == buffer);
+    }
+
+    #[test]
+    fn stale_sealed_buffer_is_rejected() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+
+        let buffer =
+            weechat.buffer_new::<(), ()>("room", None, None, None, None);
+        let sealed = buffer.seal();
+        let reused = buffer.seal();
+
+        mock.close_buffer(buffer.ptr);
+        assert!(sealed.unseal(&weechat).is_none());
+
+        // The memory of the closed buffer is reused for a new buffer, the
+        // sealed buffer still doesn't match.
+        let other =
+            weechat.buffer_new::<(), ()>("other", None, None, None, None);
+        assert!(other.ptr == buffer.ptr);
+        assert!(reused.unseal(&weechat).is_none());
+        assert_eq!(mock.invalid_accesses(), 0);
     }
 }
@@ -0,0 +1,202 @@
+//! Logging module.
+//! Provides a backend for the `log` crate that writes records to the Weechat
+//! log file and the core buffer.
+//!
+//! Records of `tracing` can be routed to the logger as well by enabling the
+//! `log` feature of `tracing`.
+
+use std::fmt;
+use std::thread::{self, ThreadId};
+
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+use weechat_sys::t_weechat_plugin;
+
+use crate::channel::{ChannelCapacity, ChannelError, MainSender};
+use crate::Weechat;
+
+/// A record that is written out on the main thread.
+struct QueuedRecord {
+    level: Level,
+    target: String,
+    message: String,
+}
+
+/// A `log` backend for Weechat.
+///
+/// Error and warning records are printed on the core buffer with the error
+/// prefix, all other records are written to the Weechat log file.
+///
+/// The Weechat API may only be used on the main thread, records that are
+/// logged on other threads are sent to the main thread using a main thread
+/// channel.
+///
+/// # Example
+///
+/// ```ignore
+/// WeechatLogger::init(&weechat, LevelFilter::Debug)?;
+///
+/// log::warn!("The server is not responding");
+/// ```
+pub struct WeechatLogger {
+    weechat_ptr: *mut t_weechat_plugin,
+    main_thread: ThreadId,
+    sender: MainSender<QueuedRecord>,
+}
+
+// The Weechat pointer is only ever dereferenced on the main thread, records
+// logged on other threads only touch the channel.
+unsafe impl Send for WeechatLogger {}
+unsafe impl Sync for WeechatLogger {}
+
+/// Errors that can happen while the logger is installed.
+#[derive(Debug)]
+pub enum InitError {
+    /// A global logger was already installed.
+    SetLogger(SetLoggerError),
+    /// The channel for records of other threads couldn't be created.
+    Channel(ChannelError),
+}
+
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InitError::SetLogger(e) => e.fmt(f),
+            InitError::Channel(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for InitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            InitError::SetLogger(e) => Some(e),
+            InitError::Channel(e) => Some(e),
+        }
+    }
+}
+
+impl From<SetLoggerError> for InitError {
+    fn from(error: SetLoggerError) -> Self {
+        InitError::SetLogger(error)
+    }
+}
+
+impl From<ChannelError> for InitError {
+    fn from(error: ChannelError) -> Self {
+        InitError::Channel(error)
+    }
+}
+
+impl WeechatLogger {
+    /// Install the Weechat logger as the global logger.
+    ///
+    /// This needs to be called on the main thread, e.g. in the `init`
+    /// function of the plugin.
+    ///
+    /// An error is returned if a global logger was already installed or if
+    /// the channel for records of other threads can't be created.
+    ///
+    /// * `weechat` - The Weechat object of the plugin.
+    /// * `level` - The maximum level of records that will be logged.
+    #[track_caller]
+    pub fn init(
+        weechat: &Weechat,
+        level: LevelFilter,
+    ) -> Result<(), InitError> {
+        let sender = weechat.main_channel(
+            ChannelCapacity::Unbounded,
+            |weechat, record: QueuedRecord| {
+                write(weechat, record.level, &record.target, &record.message)
+            },
+        )?;
+
+        let logger = WeechatLogger {
+            weechat_ptr: weechat.ptr,
+            main_thread: thread::current().id(),
+            sender,
+        };
+
+        log::set_boxed_logger(Box::new(logger))?;
+        log::set_max_level(level);
+
+        Ok(())
+    }
+
+    fn is_main_thread(&self) -> bool {
+        thread::current().id() == self.main_thread
+    }
+}
+
+#[track_caller]
+fn write(weechat: &Weechat, level: Level, target: &str, message: &str) {
+    match level {
+        Level::Error | Level::Warn => {
+            weechat.print_error(&format!("{}: {}", target, message))
+        }
+        _ => weechat.log(&format!("{} {}: {}", level, target, message)),
+    }
+}
+
+impl Log for WeechatLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        let message = record.args().to_string();
+
+        if self.is_main_thread() {
+            let weechat = Weechat::from_ptr(self.weechat_ptr);
+            write(&weechat, record.level(), record.target(), &message);
+        } else {
+            let record = QueuedRecord {
+                level: record.level(),
+                target: record.target().to_owned(),
+                message,
+            };
+
+            // The channel is closed once the plugin is unloaded, records that
+            // are logged after that are dropped.
+            let _ = self.sender.send(record);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockWeechat;
+
+    // A global logger can only be installed once per process, this is the
+    // only test that installs it.
+    #[test]
+    fn records_are_written_up_to_the_level() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+
+        WeechatLogger::init(&weechat, LevelFilter::Info).unwrap();
+
+        log::info!(target: "myplugin", "Connected");
+        log::debug!(target: "myplugin", "Hidden");
+        log::warn!(target: "myplugin", "Slow server");
+
+        assert_eq!(mock.logs(), ["INFO myplugin: Connected"]);
+        assert_eq!(mock.messages(), ["myplugin: Slow server"]);
+
+        thread::spawn(|| log::info!(target: "myplugin", "From a thread"))
+            .join()
+            .unwrap();
+
+        assert_eq!(mock.logs().len(), 1);
+        mock.run_fd_hooks();
+        assert_eq!(mock.logs()[1], "INFO myplugin: From a thread");
+
+        assert!(matches!(
+            WeechatLogger::init(&weechat, LevelFilter::Debug),
+            Err(InitError::SetLogger(_))
+        ));
+        assert_eq!(mock.invalid_accesses(), 0);
+    }
+}
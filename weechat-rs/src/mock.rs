@@ -0,0 +1,1372 @@
+//! A fake Weechat for the unit tests.
+//!
+//! The mock fills a plugin struct with stub functions that keep their state
+//! in thread local storage, so the wrappers of this crate can be tested
+//! without running Weechat. Only the parts of the API that the tests use are
+//! implemented, the other function pointers stay unset.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::ptr;
+use std::sync::{Mutex, MutexGuard};
+
+use libc::{c_char, c_int, c_long, c_void};
+use weechat_sys::{
+    t_gui_bar_item, t_gui_buffer, t_gui_nick, t_gui_nick_group, t_gui_window,
+    t_hashtable, t_hdata, t_hook, t_weechat_plugin, time_t,
+};
+
+use crate::Weechat;
+
+mod config;
+mod hashtable;
+mod infolist;
+mod strings;
+
+/// Tests that use the mock run one after another, some parts of the crate,
+/// e.g. the executor, have global state.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// A line that was printed.
+#[derive(Debug, Clone)]
+pub(crate) struct Line {
+    pub(crate) buffer: *mut t_gui_buffer,
+    pub(crate) message: String,
+}
+
+type FdCallback =
+    unsafe extern "C" fn(*const c_void, *mut c_void, c_int) -> c_int;
+
+#[derive(Clone, Copy)]
+struct FdHook {
+    hook: *mut t_hook,
+    fd: c_int,
+    read: bool,
+    write: bool,
+    callback: FdCallback,
+    pointer: *const c_void,
+}
+
+type TimerCallback =
+    unsafe extern "C" fn(*const c_void, *mut c_void, c_int) -> c_int;
+
+#[derive(Clone, Copy)]
+struct TimerHook {
+    hook: *mut t_hook,
+    interval: c_long,
+    remaining_calls: c_int,
+    callback: TimerCallback,
+    pointer: *const c_void,
+}
+
+type SignalCallback = unsafe extern "C" fn(
+    *const c_void,
+    *mut c_void,
+    *const c_char,
+    *const c_char,
+    *mut c_void,
+) -> c_int;
+
+type ConfigCallback = unsafe extern "C" fn(
+    *const c_void,
+    *mut c_void,
+    *const c_char,
+    *const c_char,
+) -> c_int;
+
+#[derive(Clone)]
+struct ConfigHook {
+    hook: *mut t_hook,
+    option: String,
+    callback: ConfigCallback,
+    pointer: *const c_void,
+}
+
+#[derive(Clone)]
+struct SignalHook {
+    hook: *mut t_hook,
+    signal: String,
+    priority: i32,
+    callback: SignalCallback,
+    pointer: *const c_void,
+}
+
+type BarItemCallback = unsafe extern "C" fn(
+    *const c_void,
+    *mut c_void,
+    *mut t_gui_bar_item,
+    *mut t_gui_window,
+    *mut t_gui_buffer,
+    *mut t_hashtable,
+) -> *mut c_char;
+
+#[derive(Clone)]
+struct MockBarItem {
+    ptr: *mut t_gui_bar_item,
+    name: String,
+    callback: BarItemCallback,
+    pointer: *const c_void,
+    content: Option<String>,
+}
+
+type CloseCallback = unsafe extern "C" fn(
+    *const c_void,
+    *mut c_void,
+    *mut t_gui_buffer,
+) -> c_int;
+
+struct MockNick {
+    ptr: *mut t_gui_nick,
+    group: *mut t_gui_nick_group,
+    name: CString,
+    color: CString,
+    prefix: CString,
+    prefix_color: CString,
+}
+
+struct MockGroup {
+    ptr: *mut t_gui_nick_group,
+    parent: *mut t_gui_nick_group,
+    name: CString,
+}
+
+struct MockBuffer {
+    ptr: *mut t_gui_buffer,
+    name: CString,
+    full_name: CString,
+    close_callback: Option<CloseCallback>,
+    close_pointer: *const c_void,
+    nicks: Vec<MockNick>,
+    groups: Vec<MockGroup>,
+}
+
+#[derive(Default)]
+struct State {
+    lines: Vec<Line>,
+    logs: Vec<String>,
+    next_pointer: usize,
+    fd_hooks: Vec<FdHook>,
+    signal_hooks: Vec<SignalHook>,
+    config_hooks: Vec<ConfigHook>,
+    timer_hooks: Vec<TimerHook>,
+    buffers: Vec<MockBuffer>,
+    bar_items: Vec<MockBarItem>,
+    freed_buffers: Vec<*mut t_gui_buffer>,
+    configs: Vec<config::MockConfig>,
+    sections: Vec<config::MockSection>,
+    options: Vec<config::MockOption>,
+    plugin_options: Vec<(String, CString)>,
+    hashtables: Vec<hashtable::MockHashtable>,
+    created_hashtables: usize,
+    freed_hashtables: usize,
+    hdata_updates: Vec<(String, String)>,
+    infolists: Vec<infolist::MockInfolist>,
+    created_infolists: usize,
+    freed_infolists: usize,
+    splits: Vec<strings::Split>,
+    version: Option<u32>,
+    commands: Vec<String>,
+    secured_data: Vec<(String, String)>,
+    invalid_accesses: usize,
+}
+
+impl State {
+    /// Create a new fake pointer, the pointers are only compared, never
+    /// dereferenced.
+    fn new_pointer<T>(&mut self) -> *mut T {
+        self.next_pointer += 1;
+        (self.next_pointer * 8) as *mut T
+    }
+
+    fn buffer(&mut self, ptr: *mut t_gui_buffer) -> Option<&mut MockBuffer> {
+        let buffer = self.buffers.iter_mut().find(|b| b.ptr == ptr);
+
+        if buffer.is_none() {
+            self.invalid_accesses += 1;
+        }
+
+        buffer
+    }
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State::default());
+}
+
+fn with_state<R>(f: impl FnOnce(&mut State) -> R) -> R {
+    STATE.with(|state| f(&mut state.borrow_mut()))
+}
+
+unsafe fn to_string(string: *const c_char) -> String {
+    if string.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(string).to_string_lossy().into_owned()
+    }
+}
+
+// The variadic print functions are only ever called with a "%s" format
+// string and a single argument by this crate, the stubs take the argument as
+// a fixed parameter.
+/// The signature of the `printf_date_tags` stub, the variadic part of the
+/// real function only ever holds the message.
+type PrintfStub = unsafe extern "C" fn(
+    *mut t_gui_buffer,
+    time_t,
+    *const c_char,
+    *const c_char,
+    *const c_char,
+);
+type PrintfDateTags = unsafe extern "C" fn(
+    *mut t_gui_buffer,
+    time_t,
+    *const c_char,
+    *const c_char,
+    ...
+);
+
+unsafe extern "C" fn printf_date_tags(
+    buffer: *mut t_gui_buffer,
+    _date: time_t,
+    _tags: *const c_char,
+    _format: *const c_char,
+    message: *const c_char,
+) {
+    let line = Line {
+        buffer,
+        message: to_string(message),
+    };
+
+    with_state(|state| {
+        if buffer.is_null() || state.buffer(buffer).is_some() {
+            state.lines.push(line);
+        }
+    });
+}
+
+/// The signature of the `log_printf` stub.
+type LogPrintfStub = unsafe extern "C" fn(*const c_char, *const c_char);
+type LogPrintf = unsafe extern "C" fn(*const c_char, ...);
+
+unsafe extern "C" fn log_printf(
+    _format: *const c_char,
+    message: *const c_char,
+) {
+    with_state(|state| state.logs.push(to_string(message)));
+}
+
+// Colors are left out of the printed lines.
+unsafe extern "C" fn color(_color_name: *const c_char) -> *const c_char {
+    cstr!("").as_ptr()
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C" fn buffer_new(
+    _plugin: *mut t_weechat_plugin,
+    name: *const c_char,
+    _input_callback: Option<
+        unsafe extern "C" fn(
+            *const c_void,
+            *mut c_void,
+            *mut t_gui_buffer,
+            *const c_char,
+        ) -> c_int,
+    >,
+    _input_pointer: *const c_void,
+    _input_data: *mut c_void,
+    close_callback: Option<CloseCallback>,
+    close_pointer: *const c_void,
+    _close_data: *mut c_void,
+) -> *mut t_gui_buffer {
+    let name = CStr::from_ptr(name).to_owned();
+
+    with_state(|state| {
+        if state.buffers.iter().any(|b| b.name == name) {
+            return ptr::null_mut();
+        }
+
+        // Weechat might reuse the memory of a closed buffer.
+        let ptr = match state.freed_buffers.pop() {
+            Some(ptr) => ptr,
+            None => state.new_pointer(),
+        };
+
+        let full_name = format!("mock.{}", name.to_string_lossy());
+
+        state.buffers.push(MockBuffer {
+            ptr,
+            name,
+            full_name: CString::new(full_name).unwrap(),
+            close_callback,
+            close_pointer,
+            nicks: Vec::new(),
+            groups: Vec::new(),
+        });
+
+        ptr
+    })
+}
+
+unsafe extern "C" fn buffer_search(
+    _plugin: *const c_char,
+    name: *const c_char,
+) -> *mut t_gui_buffer {
+    let name = to_string(name);
+
+    with_state(|state| {
+        state
+            .buffers
+            .iter()
+            .find(|b| b.name.to_string_lossy() == name)
+            .map(|b| b.ptr)
+            .unwrap_or(ptr::null_mut())
+    })
+}
+
+unsafe extern "C" fn buffer_close(buffer: *mut t_gui_buffer) {
+    let callback = with_state(|state| {
+        state
+            .buffer(buffer)
+            .map(|b| (b.close_callback, b.close_pointer))
+    });
+
+    if let Some((callback, pointer)) = callback {
+        if let Some(callback) = callback {
+            callback(pointer, ptr::null_mut(), buffer);
+        }
+
+        with_state(|state| {
+            state.buffers.retain(|b| b.ptr != buffer);
+            state.freed_buffers.push(buffer);
+        });
+    }
+}
+
+unsafe extern "C" fn buffer_get_string(
+    buffer: *mut t_gui_buffer,
+    property: *const c_char,
+) -> *const c_char {
+    let property = to_string(property);
+
+    with_state(|state| match state.buffer(buffer) {
+        Some(buffer) => match property.as_str() {
+            "name" => buffer.name.as_ptr(),
+            "full_name" => buffer.full_name.as_ptr(),
+            "plugin" => cstr!("mock").as_ptr(),
+            _ => ptr::null(),
+        },
+        None => ptr::null(),
+    })
+}
+
+unsafe extern "C" fn buffer_set(
+    buffer: *mut t_gui_buffer,
+    property: *const c_char,
+    value: *const c_char,
+) {
+    let property = to_string(property);
+    let value = CStr::from_ptr(value).to_owned();
+
+    with_state(|state| {
+        if let Some(buffer) = state.buffer(buffer) {
+            if property == "name" {
+                buffer.name = value;
+            }
+        }
+    });
+}
+
+unsafe extern "C" fn nicklist_add_group(
+    buffer: *mut t_gui_buffer,
+    parent: *mut t_gui_nick_group,
+    name: *const c_char,
+    _color: *const c_char,
+    _visible: c_int,
+) -> *mut t_gui_nick_group {
+    let name = CStr::from_ptr(name).to_owned();
+
+    with_state(|state| {
+        let ptr = state.new_pointer();
+
+        match state.buffer(buffer) {
+            Some(buffer) => {
+                buffer.groups.push(MockGroup { ptr, parent, name });
+                ptr
+            }
+            None => ptr::null_mut(),
+        }
+    })
+}
+
+unsafe extern "C" fn nicklist_search_group(
+    buffer: *mut t_gui_buffer,
+    _from_group: *mut t_gui_nick_group,
+    name: *const c_char,
+) -> *mut t_gui_nick_group {
+    let name = CStr::from_ptr(name);
+
+    with_state(|state| {
+        state
+            .buffer(buffer)
+            .and_then(|b| b.groups.iter().find(|g| g.name.as_c_str() == name))
+            .map(|g| g.ptr)
+            .unwrap_or(ptr::null_mut())
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C" fn nicklist_add_nick(
+    buffer: *mut t_gui_buffer,
+    group: *mut t_gui_nick_group,
+    name: *const c_char,
+    color: *const c_char,
+    prefix: *const c_char,
+    prefix_color: *const c_char,
+    _visible: c_int,
+) -> *mut t_gui_nick {
+    let nick = |ptr| MockNick {
+        ptr,
+        group,
+        name: CStr::from_ptr(name).to_owned(),
+        color: CStr::from_ptr(color).to_owned(),
+        prefix: CStr::from_ptr(prefix).to_owned(),
+        prefix_color: CStr::from_ptr(prefix_color).to_owned(),
+    };
+
+    with_state(|state| {
+        let ptr = state.new_pointer();
+
+        match state.buffer(buffer) {
+            Some(buffer)
+                if !buffer
+                    .nicks
+                    .iter()
+                    .any(|n| n.name.as_c_str() == CStr::from_ptr(name)) =>
+            {
+                buffer.nicks.push(nick(ptr));
+                ptr
+            }
+            _ => ptr::null_mut(),
+        }
+    })
+}
+
+unsafe extern "C" fn nicklist_search_nick(
+    buffer: *mut t_gui_buffer,
+    _from_group: *mut t_gui_nick_group,
+    name: *const c_char,
+) -> *mut t_gui_nick {
+    let name = CStr::from_ptr(name);
+
+    with_state(|state| {
+        state
+            .buffer(buffer)
+            .and_then(|b| b.nicks.iter().find(|n| n.name.as_c_str() == name))
+            .map(|n| n.ptr)
+            .unwrap_or(ptr::null_mut())
+    })
+}
+
+unsafe extern "C" fn nicklist_remove_nick(
+    buffer: *mut t_gui_buffer,
+    nick: *mut t_gui_nick,
+) {
+    with_state(|state| {
+        if let Some(buffer) = state.buffer(buffer) {
+            buffer.nicks.retain(|n| n.ptr != nick);
+        }
+    });
+}
+
+unsafe extern "C" fn nicklist_remove_group(
+    buffer: *mut t_gui_buffer,
+    group: *mut t_gui_nick_group,
+) {
+    with_state(|state| {
+        if let Some(buffer) = state.buffer(buffer) {
+            buffer.nicks.retain(|n| n.group != group);
+            buffer
+                .groups
+                .retain(|g| g.ptr != group && g.parent != group);
+        }
+    });
+}
+
+// Only the nicks are returned, the nicks are returned in the order they
+// were added.
+unsafe extern "C" fn nicklist_get_next_item(
+    buffer: *mut t_gui_buffer,
+    group: *mut *mut t_gui_nick_group,
+    nick: *mut *mut t_gui_nick,
+) {
+    let next = with_state(|state| {
+        let buffer = state.buffer(buffer)?;
+
+        let position = if (*nick).is_null() {
+            0
+        } else {
+            buffer.nicks.iter().position(|n| n.ptr == *nick)? + 1
+        };
+
+        buffer.nicks.get(position).map(|n| (n.group, n.ptr))
+    });
+
+    let (next_group, next_nick) =
+        next.unwrap_or((ptr::null_mut(), ptr::null_mut()));
+    *group = next_group;
+    *nick = next_nick;
+}
+
+unsafe extern "C" fn nicklist_nick_get_string(
+    buffer: *mut t_gui_buffer,
+    nick: *mut t_gui_nick,
+    property: *const c_char,
+) -> *const c_char {
+    let property = to_string(property);
+
+    with_state(|state| {
+        let nick = match state.buffer(buffer) {
+            Some(buffer) => buffer.nicks.iter().find(|n| n.ptr == nick),
+            None => None,
+        };
+
+        match nick {
+            Some(nick) => match property.as_str() {
+                "name" => nick.name.as_ptr(),
+                "color" => nick.color.as_ptr(),
+                "prefix" => nick.prefix.as_ptr(),
+                "prefix_color" => nick.prefix_color.as_ptr(),
+                _ => ptr::null(),
+            },
+            None => {
+                state.invalid_accesses += 1;
+                ptr::null()
+            }
+        }
+    })
+}
+
+// The buffer hdata has lists that Weechat checks pointers against, the nick
+// hdata has none like in Weechat.
+const BUFFER_HDATA: *mut t_hdata = 16 as *mut t_hdata;
+const NICK_HDATA: *mut t_hdata = 17 as *mut t_hdata;
+
+unsafe extern "C" fn hdata_get(
+    _plugin: *mut t_weechat_plugin,
+    name: *const c_char,
+) -> *mut t_hdata {
+    match to_string(name).as_str() {
+        "buffer" => BUFFER_HDATA,
+        "nick" => NICK_HDATA,
+        _ => ptr::null_mut(),
+    }
+}
+
+unsafe extern "C" fn hdata_get_string(
+    hdata: *mut t_hdata,
+    property: *const c_char,
+) -> *const c_char {
+    match to_string(property).as_str() {
+        "list_keys" if hdata == BUFFER_HDATA => {
+            cstr!("gui_buffers,last_gui_buffer").as_ptr()
+        }
+        "list_keys" => cstr!("").as_ptr(),
+        _ => ptr::null(),
+    }
+}
+
+// Only the names of buffers and nicks are known.
+unsafe extern "C" fn hdata_get_var_type(
+    _hdata: *mut t_hdata,
+    name: *const c_char,
+) -> c_int {
+    match to_string(name).as_str() {
+        "name" => weechat_sys::WEECHAT_HDATA_STRING as c_int,
+        _ => -1,
+    }
+}
+
+unsafe extern "C" fn hdata_string(
+    hdata: *mut t_hdata,
+    pointer: *mut c_void,
+    name: *const c_char,
+) -> *const c_char {
+    if to_string(name) != "name" {
+        return ptr::null();
+    }
+
+    with_state(|state| {
+        let name = if hdata == BUFFER_HDATA {
+            state
+                .buffers
+                .iter()
+                .find(|b| b.ptr as *mut c_void == pointer)
+                .map(|b| &b.name)
+        } else {
+            state
+                .buffers
+                .iter()
+                .flat_map(|b| b.nicks.iter())
+                .find(|n| n.ptr as *mut c_void == pointer)
+                .map(|n| &n.name)
+        };
+
+        match name {
+            Some(name) => name.as_ptr(),
+            None => {
+                state.invalid_accesses += 1;
+                ptr::null()
+            }
+        }
+    })
+}
+
+unsafe extern "C" fn hdata_get_list(
+    _hdata: *mut t_hdata,
+    _name: *const c_char,
+) -> *mut c_void {
+    32 as *mut c_void
+}
+
+// Every variable of the mock buffer hdata can be updated, the updates are
+// recorded but don't change the buffer.
+/// The version the mock reports if a test doesn't set one.
+const DEFAULT_VERSION: u32 = 0x0308_0000;
+
+unsafe extern "C" fn info_get(
+    _plugin: *mut t_weechat_plugin,
+    info_name: *const c_char,
+    _arguments: *const c_char,
+) -> *mut c_char {
+    if to_string(info_name) != "version_number" {
+        return ptr::null_mut();
+    }
+
+    let version = with_state(|state| state.version.unwrap_or(DEFAULT_VERSION));
+    let version = CString::new(version.to_string()).unwrap();
+
+    libc::strdup(version.as_ptr())
+}
+
+unsafe extern "C" fn command(
+    _plugin: *mut t_weechat_plugin,
+    buffer: *mut t_gui_buffer,
+    command: *const c_char,
+) -> c_int {
+    with_state(|state| {
+        if !buffer.is_null() && state.buffer(buffer).is_none() {
+            return weechat_sys::WEECHAT_RC_ERROR;
+        }
+
+        state.commands.push(to_string(command));
+        weechat_sys::WEECHAT_RC_OK
+    })
+}
+
+/// Only the `commands` option is supported, the masks are matched like the
+/// masks of Weechat, e.g. `"*,-exec"` allows every command but `/exec`.
+unsafe extern "C" fn command_options(
+    plugin: *mut t_weechat_plugin,
+    buffer: *mut t_gui_buffer,
+    command_string: *const c_char,
+    options: *mut t_hashtable,
+) -> c_int {
+    let options = with_state(|state| hashtable::string_items(state, options));
+
+    let masks = match options.iter().find(|(name, _)| name == "commands") {
+        Some((_, masks)) => masks.clone(),
+        None => return command(plugin, buffer, command_string),
+    };
+
+    let input = to_string(command_string);
+    let name = input
+        .trim_start_matches('/')
+        .split(' ')
+        .next()
+        .unwrap_or_default();
+
+    let (excluded, included): (Vec<&str>, Vec<&str>) =
+        masks.split(',').partition(|mask| mask.starts_with('-'));
+
+    let allowed = included
+        .iter()
+        .any(|mask| strings::match_mask(name, mask, false))
+        && !excluded
+            .iter()
+            .any(|mask| strings::match_mask(name, &mask[1..], false));
+
+    if allowed {
+        command(plugin, buffer, command_string)
+    } else {
+        weechat_sys::WEECHAT_RC_ERROR
+    }
+}
+
+unsafe extern "C" fn hdata_update(
+    _hdata: *mut t_hdata,
+    pointer: *mut c_void,
+    hashtable: *mut t_hashtable,
+) -> c_int {
+    with_state(|state| {
+        let items = hashtable::string_items(state, hashtable);
+
+        if state.buffer(pointer as *mut t_gui_buffer).is_none() {
+            return 0;
+        }
+
+        let mut updated = 0;
+
+        for (name, value) in items {
+            match name.as_str() {
+                "__update_allowed" => return 1,
+                "__delete_allowed" | "__delete" => return 0,
+                _ => {
+                    state.hdata_updates.push((name, value));
+                    updated += 1;
+                }
+            }
+        }
+
+        updated
+    })
+}
+
+// Without a list only the flagged lists are checked, the nick hdata has none.
+unsafe extern "C" fn hdata_check_pointer(
+    hdata: *mut t_hdata,
+    list: *mut c_void,
+    pointer: *mut c_void,
+) -> c_int {
+    if hdata != BUFFER_HDATA && list.is_null() {
+        return 0;
+    }
+
+    with_state(|state| {
+        state
+            .buffers
+            .iter()
+            .any(|b| b.ptr as *mut c_void == pointer) as c_int
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C" fn hook_fd(
+    _plugin: *mut t_weechat_plugin,
+    fd: c_int,
+    read: c_int,
+    write: c_int,
+    _exception: c_int,
+    callback: Option<FdCallback>,
+    pointer: *const c_void,
+    _data: *mut c_void,
+) -> *mut t_hook {
+    with_state(|state| {
+        let hook = state.new_pointer();
+        state.fd_hooks.push(FdHook {
+            hook,
+            fd,
+            read: read != 0,
+            write: write != 0,
+            callback: callback.unwrap(),
+            pointer,
+        });
+        hook
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C" fn hook_timer(
+    _plugin: *mut t_weechat_plugin,
+    interval: c_long,
+    _align_second: c_int,
+    max_calls: c_int,
+    callback: Option<TimerCallback>,
+    pointer: *const c_void,
+    _data: *mut c_void,
+) -> *mut t_hook {
+    with_state(|state| {
+        let hook = state.new_pointer();
+        state.timer_hooks.push(TimerHook {
+            hook,
+            interval,
+            remaining_calls: if max_calls > 0 { max_calls } else { -1 },
+            callback: callback.unwrap(),
+            pointer,
+        });
+        hook
+    })
+}
+
+unsafe extern "C" fn hook_signal(
+    _plugin: *mut t_weechat_plugin,
+    signal: *const c_char,
+    callback: Option<SignalCallback>,
+    pointer: *const c_void,
+    _data: *mut c_void,
+) -> *mut t_hook {
+    let signal = to_string(signal);
+
+    let (priority, signal) = match signal.split_once('|') {
+        Some((priority, signal)) => {
+            (priority.parse().unwrap_or(1000), signal.to_owned())
+        }
+        None => (1000, signal),
+    };
+
+    with_state(|state| {
+        let hook = state.new_pointer();
+
+        // Hooks with the same priority are called in the order they were
+        // added.
+        let position = state
+            .signal_hooks
+            .iter()
+            .position(|h| h.priority < priority)
+            .unwrap_or(state.signal_hooks.len());
+
+        state.signal_hooks.insert(
+            position,
+            SignalHook {
+                hook,
+                signal,
+                priority,
+                callback: callback.unwrap(),
+                pointer,
+            },
+        );
+
+        hook
+    })
+}
+
+unsafe extern "C" fn hook_signal_send(
+    signal: *const c_char,
+    data_type: *const c_char,
+    signal_data: *mut c_void,
+) -> c_int {
+    let name = to_string(signal);
+    let hooks = with_state(|state| state.signal_hooks.clone());
+
+    for hook in hooks.iter().filter(|h| h.signal == name || h.signal == "*") {
+        // A callback might have removed the hook.
+        if !with_state(|state| {
+            state.signal_hooks.iter().any(|h| h.hook == hook.hook)
+        }) {
+            continue;
+        }
+
+        let ret = (hook.callback)(
+            hook.pointer,
+            ptr::null_mut(),
+            signal,
+            data_type,
+            signal_data,
+        );
+
+        if ret == weechat_sys::WEECHAT_RC_OK_EAT {
+            return ret;
+        }
+    }
+
+    weechat_sys::WEECHAT_RC_OK
+}
+
+unsafe extern "C" fn hook_config(
+    _plugin: *mut t_weechat_plugin,
+    option: *const c_char,
+    callback: Option<ConfigCallback>,
+    pointer: *const c_void,
+    _data: *mut c_void,
+) -> *mut t_hook {
+    with_state(|state| {
+        let hook = state.new_pointer();
+        state.config_hooks.push(ConfigHook {
+            hook,
+            option: to_string(option),
+            callback: callback.unwrap(),
+            pointer,
+        });
+        hook
+    })
+}
+
+/// Call the config hooks whose option mask matches the given option.
+unsafe fn run_config_hooks(option: &str, value: &CStr) {
+    let hooks = with_state(|state| state.config_hooks.clone());
+    let name = CString::new(option).unwrap();
+
+    for hook in hooks
+        .iter()
+        .filter(|h| strings::match_mask(option, &h.option, false))
+    {
+        (hook.callback)(
+            hook.pointer,
+            ptr::null_mut(),
+            name.as_ptr(),
+            value.as_ptr(),
+        );
+    }
+}
+
+unsafe extern "C" fn unhook(hook: *mut t_hook) {
+    with_state(|state| {
+        state.fd_hooks.retain(|h| h.hook != hook);
+        state.signal_hooks.retain(|h| h.hook != hook);
+        state.config_hooks.retain(|h| h.hook != hook);
+        state.timer_hooks.retain(|h| h.hook != hook);
+    });
+}
+
+unsafe extern "C" fn window_get_pointer(
+    _window: *mut t_gui_window,
+    _property: *const c_char,
+) -> *mut c_void {
+    // The mock doesn't have any windows.
+    ptr::null_mut()
+}
+
+unsafe extern "C" fn bar_item_new(
+    _plugin: *mut t_weechat_plugin,
+    name: *const c_char,
+    callback: Option<BarItemCallback>,
+    pointer: *const c_void,
+    _data: *mut c_void,
+) -> *mut t_gui_bar_item {
+    let name = to_string(name);
+
+    with_state(|state| {
+        if state.bar_items.iter().any(|i| i.name == name) {
+            return ptr::null_mut();
+        }
+
+        let ptr = state.new_pointer();
+        state.bar_items.push(MockBarItem {
+            ptr,
+            name,
+            callback: callback.unwrap(),
+            pointer,
+            content: None,
+        });
+        ptr
+    })
+}
+
+/// Build the content of a bar item like Weechat does when it redraws a bar.
+unsafe fn build_bar_item(
+    name: &str,
+    buffer: *mut t_gui_buffer,
+) -> Option<String> {
+    let item = with_state(|state| {
+        state.bar_items.iter().find(|i| i.name == name).cloned()
+    })?;
+
+    let content = (item.callback)(
+        item.pointer,
+        ptr::null_mut(),
+        item.ptr,
+        ptr::null_mut(),
+        buffer,
+        ptr::null_mut(),
+    );
+
+    let string = to_string(content);
+    libc::free(content as *mut c_void);
+
+    with_state(|state| {
+        if let Some(item) =
+            state.bar_items.iter_mut().find(|i| i.ptr == item.ptr)
+        {
+            item.content = Some(string.clone());
+        }
+    });
+
+    Some(string)
+}
+
+// The mock only has root bars, the items are rebuilt right away without a
+// window and a buffer.
+unsafe extern "C" fn bar_item_update(name: *const c_char) {
+    build_bar_item(&to_string(name), ptr::null_mut());
+}
+
+unsafe extern "C" fn bar_item_remove(item: *mut t_gui_bar_item) {
+    with_state(|state| state.bar_items.retain(|i| i.ptr != item));
+}
+
+unsafe extern "C" fn prefix(_prefix: *const c_char) -> *const c_char {
+    cstr!("").as_ptr()
+}
+
+/// A fake Weechat plugin, the state of the mock is reset when it's dropped.
+pub(crate) struct MockWeechat {
+    plugin: *mut t_weechat_plugin,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl MockWeechat {
+    /// Create a new fake plugin and make the current thread the main thread.
+    pub(crate) fn new() -> MockWeechat {
+        let lock = match LOCK.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        let mut plugin: Box<t_weechat_plugin> =
+            Box::new(unsafe { std::mem::zeroed() });
+
+        plugin.name = cstr!("mock").as_ptr() as *mut c_char;
+        plugin.printf_date_tags = unsafe {
+            std::mem::transmute::<PrintfStub, Option<PrintfDateTags>>(
+                printf_date_tags,
+            )
+        };
+        plugin.log_printf = unsafe {
+            std::mem::transmute::<LogPrintfStub, Option<LogPrintf>>(log_printf)
+        };
+        plugin.color = Some(color);
+        plugin.prefix = Some(prefix);
+        plugin.info_get = Some(info_get);
+        plugin.command = Some(command);
+        plugin.command_options = Some(command_options);
+        plugin.hook_fd = Some(hook_fd);
+        plugin.hook_timer = Some(hook_timer);
+        plugin.hook_signal = Some(hook_signal);
+        plugin.hook_signal_send = Some(hook_signal_send);
+        plugin.hook_config = Some(hook_config);
+        plugin.unhook = Some(unhook);
+        plugin.buffer_new = Some(buffer_new);
+        plugin.buffer_search = Some(buffer_search);
+        plugin.buffer_close = Some(buffer_close);
+        plugin.buffer_get_string = Some(buffer_get_string);
+        plugin.buffer_set = Some(buffer_set);
+        plugin.nicklist_add_group = Some(nicklist_add_group);
+        plugin.nicklist_search_group = Some(nicklist_search_group);
+        plugin.nicklist_add_nick = Some(nicklist_add_nick);
+        plugin.nicklist_search_nick = Some(nicklist_search_nick);
+        plugin.nicklist_remove_nick = Some(nicklist_remove_nick);
+        plugin.nicklist_remove_group = Some(nicklist_remove_group);
+        plugin.nicklist_get_next_item = Some(nicklist_get_next_item);
+        plugin.nicklist_nick_get_string = Some(nicklist_nick_get_string);
+        plugin.window_get_pointer = Some(window_get_pointer);
+        plugin.bar_item_new = Some(bar_item_new);
+        plugin.bar_item_update = Some(bar_item_update);
+        plugin.bar_item_remove = Some(bar_item_remove);
+        plugin.hdata_get = Some(hdata_get);
+        plugin.hdata_get_list = Some(hdata_get_list);
+        plugin.hdata_get_string = Some(hdata_get_string);
+        plugin.hdata_get_var_type = Some(hdata_get_var_type);
+        plugin.hdata_string = Some(hdata_string);
+        plugin.hdata_check_pointer = Some(hdata_check_pointer);
+        plugin.hdata_update = Some(hdata_update);
+        plugin.infolist_new = Some(infolist::infolist_new);
+        plugin.infolist_new_item = Some(infolist::infolist_new_item);
+        plugin.infolist_new_var_integer =
+            Some(infolist::infolist_new_var_integer);
+        plugin.infolist_new_var_string =
+            Some(infolist::infolist_new_var_string);
+        plugin.infolist_new_var_pointer =
+            Some(infolist::infolist_new_var_pointer);
+        plugin.infolist_new_var_buffer =
+            Some(infolist::infolist_new_var_buffer);
+        plugin.infolist_new_var_time = Some(infolist::infolist_new_var_time);
+        plugin.infolist_get = Some(infolist::infolist_get);
+        plugin.infolist_next = Some(infolist::infolist_next);
+        plugin.infolist_prev = Some(infolist::infolist_prev);
+        plugin.infolist_reset_item_cursor =
+            Some(infolist::infolist_reset_item_cursor);
+        plugin.infolist_fields = Some(infolist::infolist_fields);
+        plugin.infolist_integer = Some(infolist::infolist_integer);
+        plugin.infolist_string = Some(infolist::infolist_string);
+        plugin.infolist_pointer = Some(infolist::infolist_pointer);
+        plugin.infolist_buffer = Some(infolist::infolist_buffer);
+        plugin.infolist_time = Some(infolist::infolist_time);
+        plugin.infolist_free = Some(infolist::infolist_free);
+        plugin.hashtable_new = Some(hashtable::hashtable_new);
+        plugin.hashtable_free = Some(hashtable::hashtable_free);
+        plugin.hashtable_set = Some(hashtable::hashtable_set);
+        plugin.hashtable_get = Some(hashtable::hashtable_get);
+        plugin.hashtable_has_key = Some(hashtable::hashtable_has_key);
+        plugin.hashtable_remove = Some(hashtable::hashtable_remove);
+        plugin.hashtable_remove_all = Some(hashtable::hashtable_remove_all);
+        plugin.hashtable_get_integer = Some(hashtable::hashtable_get_integer);
+        plugin.hashtable_get_string = Some(hashtable::hashtable_get_string);
+        plugin.hashtable_map_string = Some(hashtable::hashtable_map_string);
+        plugin.iconv_to_internal = Some(strings::iconv_to_internal);
+        plugin.iconv_from_internal = Some(strings::iconv_from_internal);
+        plugin.string_match = Some(strings::string_match);
+        plugin.string_eval_expression = Some(strings::string_eval_expression);
+        plugin.string_split = Some(strings::string_split);
+        plugin.string_free_split = Some(strings::string_free_split);
+        plugin.string_input_for_buffer = Some(strings::string_input_for_buffer);
+        plugin.config_new = Some(config::config_new);
+        plugin.config_free = Some(config::config_free);
+        plugin.config_new_section = Some(config::config_new_section);
+        plugin.config_search_section = Some(config::config_search_section);
+        plugin.config_section_free_options =
+            Some(config::config_section_free_options);
+        plugin.config_section_free = Some(config::config_section_free);
+        plugin.config_new_option = Some(config::config_new_option);
+        plugin.config_search_option = Some(config::config_search_option);
+        plugin.config_option_set = Some(config::config_option_set);
+        plugin.config_option_reset = Some(config::config_option_reset);
+        plugin.config_option_unset = Some(config::config_option_unset);
+        plugin.config_option_set_null = Some(config::config_option_set_null);
+        plugin.config_option_is_null = Some(config::config_option_is_null);
+        plugin.config_option_get_string =
+            Some(config::config_option_get_string);
+        plugin.config_option_get_pointer =
+            Some(config::config_option_get_pointer);
+        plugin.config_string = Some(config::config_string);
+        plugin.config_string_default = Some(config::config_string_default);
+        plugin.config_integer = Some(config::config_integer);
+        plugin.config_integer_default = Some(config::config_integer_default);
+        plugin.config_boolean = Some(config::config_boolean);
+        plugin.config_boolean_default = Some(config::config_boolean_default);
+        plugin.config_color = Some(config::config_color);
+        plugin.config_color_default = Some(config::config_color_default);
+        plugin.config_get_plugin = Some(config::config_get_plugin);
+        plugin.config_is_set_plugin = Some(config::config_is_set_plugin);
+        plugin.config_set_plugin = Some(config::config_set_plugin);
+        plugin.config_unset_plugin = Some(config::config_unset_plugin);
+
+        Weechat::init_main_thread();
+
+        let mock = MockWeechat {
+            plugin: Box::into_raw(plugin),
+            _lock: lock,
+        };
+
+        crate::executor::start(&mock.weechat())
+            .expect("Can't start the executor");
+
+        mock
+    }
+
+    /// Get a `Weechat` object for the fake plugin.
+    pub(crate) fn weechat(&self) -> Weechat {
+        Weechat::from_ptr(self.plugin)
+    }
+
+    /// Get the lines that were printed so far.
+    pub(crate) fn lines(&self) -> Vec<Line> {
+        with_state(|state| state.lines.clone())
+    }
+
+    /// Run the fd hooks whose file descriptor is ready, like one iteration
+    /// of the Weechat main loop. Returns the number of callbacks that ran.
+    pub(crate) fn run_fd_hooks(&self) -> usize {
+        let hooks = with_state(|state| state.fd_hooks.clone());
+        let mut ran = 0;
+
+        for hook in hooks {
+            // A callback might have removed the hook.
+            if !with_state(|state| {
+                state.fd_hooks.iter().any(|h| h.hook == hook.hook)
+            }) {
+                continue;
+            }
+
+            let mut events = 0;
+
+            if hook.read {
+                events |= libc::POLLIN;
+            }
+            if hook.write {
+                events |= libc::POLLOUT;
+            }
+
+            let mut poll_fd = libc::pollfd {
+                fd: hook.fd,
+                events,
+                revents: 0,
+            };
+
+            let ready = unsafe { libc::poll(&mut poll_fd, 1, 0) };
+
+            if ready > 0 {
+                unsafe {
+                    (hook.callback)(hook.pointer, std::ptr::null_mut(), hook.fd)
+                };
+                ran += 1;
+            }
+        }
+
+        ran
+    }
+
+    /// Run the main loop until no fd hook is ready anymore.
+    pub(crate) fn run_until_idle(&self) {
+        for _ in 0..1000 {
+            if self.run_fd_hooks() == 0 {
+                return;
+            }
+        }
+
+        panic!("The fd hooks are still ready after 1000 iterations");
+    }
+
+    /// Fire every timer once, no matter its interval. Timers that reached
+    /// their maximal number of calls are removed, like Weechat does. Returns
+    /// the number of callbacks that ran.
+    pub(crate) fn run_timers(&self) -> usize {
+        let hooks = with_state(|state| state.timer_hooks.clone());
+        let mut ran = 0;
+
+        for hook in hooks {
+            let remaining = with_state(|state| {
+                let timer = state
+                    .timer_hooks
+                    .iter_mut()
+                    .find(|h| h.hook == hook.hook)?;
+
+                if timer.remaining_calls > 0 {
+                    timer.remaining_calls -= 1;
+                }
+
+                Some(timer.remaining_calls)
+            });
+
+            // A callback might have removed the hook.
+            let remaining = match remaining {
+                Some(remaining) => remaining,
+                None => continue,
+            };
+
+            if remaining == 0 {
+                with_state(|state| {
+                    state.timer_hooks.retain(|h| h.hook != hook.hook)
+                });
+            }
+
+            unsafe {
+                (hook.callback)(hook.pointer, ptr::null_mut(), remaining)
+            };
+            ran += 1;
+        }
+
+        ran
+    }
+
+    /// Get the messages that were written to the Weechat log file.
+    #[cfg(any(feature = "tracing-support", feature = "weechat-logger"))]
+    pub(crate) fn logs(&self) -> Vec<String> {
+        with_state(|state| state.logs.clone())
+    }
+
+    /// Get the intervals, in milliseconds, of the hooked timers.
+    pub(crate) fn timer_intervals(&self) -> Vec<c_long> {
+        with_state(|state| {
+            state.timer_hooks.iter().map(|h| h.interval).collect()
+        })
+    }
+
+    /// Get the file descriptors that are watched by fd hooks.
+    pub(crate) fn hooked_fds(&self) -> Vec<c_int> {
+        with_state(|state| state.fd_hooks.iter().map(|h| h.fd).collect())
+    }
+
+    /// Build the content of a bar item for a buffer, like Weechat does for
+    /// items that are displayed in a window or for a specific buffer.
+    /// Returns `None` if the bar item doesn't exist.
+    pub(crate) fn build_bar_item(
+        &self,
+        name: &str,
+        buffer: *mut t_gui_buffer,
+    ) -> Option<String> {
+        unsafe { build_bar_item(name, buffer) }
+    }
+
+    /// Get the content of a bar item from the last time it was built.
+    pub(crate) fn bar_item_content(&self, name: &str) -> Option<String> {
+        with_state(|state| {
+            state
+                .bar_items
+                .iter()
+                .find(|i| i.name == name)
+                .and_then(|i| i.content.clone())
+        })
+    }
+
+    /// Close a buffer like the user would with `/buffer close`.
+    pub(crate) fn close_buffer(&self, buffer: *mut t_gui_buffer) {
+        unsafe { buffer_close(buffer) }
+    }
+
+    /// Get the number of config files, sections and options that weren't
+    /// freed yet.
+    pub(crate) fn config_objects(&self) -> usize {
+        with_state(|state| {
+            state.configs.len() + state.sections.len() + state.options.len()
+        })
+    }
+
+    /// Get the number of hashtables that were created and freed so far.
+    pub(crate) fn hashtables(&self) -> (usize, usize) {
+        with_state(|state| (state.created_hashtables, state.freed_hashtables))
+    }
+
+    /// Get the number of infolists that were created and freed so far.
+    pub(crate) fn infolists(&self) -> (usize, usize) {
+        with_state(|state| (state.created_infolists, state.freed_infolists))
+    }
+
+    /// Set the version number that the mock reports, e.g. `0x0205_0000` for
+    /// Weechat 2.5.0.
+    pub(crate) fn set_version(&self, number: u32) {
+        with_state(|state| state.version = Some(number));
+    }
+
+    /// Set a secured data value, like `/secure set` does.
+    pub(crate) fn set_secured_data(&self, name: &str, value: &str) {
+        with_state(|state| {
+            state.secured_data.push((name.to_owned(), value.to_owned()))
+        });
+    }
+
+    /// Get the commands that were executed so far.
+    pub(crate) fn commands(&self) -> Vec<String> {
+        with_state(|state| state.commands.clone())
+    }
+
+    /// Get the number of split strings that weren't freed yet.
+    pub(crate) fn split_strings(&self) -> usize {
+        with_state(|state| state.splits.len())
+    }
+
+    /// Get the hdata variables that were updated, with their new values.
+    pub(crate) fn hdata_updates(&self) -> Vec<(String, String)> {
+        with_state(|state| state.hdata_updates.clone())
+    }
+
+    /// Get the full names and values of the plugin options that are set.
+    pub(crate) fn plugin_options(&self) -> Vec<(String, String)> {
+        with_state(|state| {
+            state
+                .plugin_options
+                .iter()
+                .map(|(n, v)| (n.clone(), v.to_string_lossy().into_owned()))
+                .collect()
+        })
+    }
+
+    /// Get the number of times a closed buffer, a removed nick, or a freed
+    /// config object was passed to Weechat.
+    pub(crate) fn invalid_accesses(&self) -> usize {
+        with_state(|state| state.invalid_accesses)
+    }
+
+    /// Get the messages that were printed so far.
+    pub(crate) fn messages(&self) -> Vec<String> {
+        with_state(|state| {
+            state.lines.iter().map(|l| l.message.clone()).collect()
+        })
+    }
+}
+
+impl Drop for MockWeechat {
+    fn drop(&mut self) {
+        crate::executor::shutdown();
+        with_state(|state| *state = State::default());
+        drop(unsafe { Box::from_raw(self.plugin) });
+    }
+}
@@ -0,0 +1,716 @@
+//! Config files, sections and options of the mock.
+//!
+//! Freeing an object that doesn't exist anymore, or using an option after
+//! its section was freed, counts as an invalid access.
+
+use std::ffi::{CStr, CString};
+use std::ptr;
+
+use libc::{c_char, c_int, c_void};
+use weechat_sys::{
+    t_config_file, t_config_option, t_config_section, t_weechat_plugin,
+};
+
+use super::{to_string, with_state, State};
+
+const OPTION_SET_OK_CHANGED: c_int = 2;
+const OPTION_SET_OK_SAME_VALUE: c_int = 1;
+const OPTION_SET_ERROR: c_int = 0;
+const OPTION_UNSET_OK_NO_RESET: c_int = 0;
+const OPTION_UNSET_OK_RESET: c_int = 1;
+const OPTION_UNSET_OK_REMOVED: c_int = 2;
+const OPTION_UNSET_ERROR: c_int = -1;
+
+type CheckCallback = unsafe extern "C" fn(
+    *const c_void,
+    *mut c_void,
+    *mut t_config_option,
+    *const c_char,
+) -> c_int;
+
+type OptionCallback =
+    unsafe extern "C" fn(*const c_void, *mut c_void, *mut t_config_option);
+
+pub(super) struct MockConfig {
+    ptr: *mut t_config_file,
+    name: CString,
+}
+
+pub(super) struct MockSection {
+    ptr: *mut t_config_section,
+    config: *mut t_config_file,
+    name: CString,
+}
+
+#[derive(Clone)]
+pub(super) struct MockOption {
+    ptr: *mut t_config_option,
+    config: *mut t_config_file,
+    section: *mut t_config_section,
+    name: CString,
+    option_type: CString,
+    description: CString,
+    config_name: CString,
+    section_name: CString,
+    string_values: Vec<CString>,
+    min: c_int,
+    max: c_int,
+    default_value: CString,
+    value: CString,
+    check_cb: Option<(CheckCallback, *const c_void)>,
+    change_cb: Option<(OptionCallback, *const c_void)>,
+    delete_cb: Option<(OptionCallback, *const c_void)>,
+}
+
+impl MockOption {
+    fn is(&self, option_type: &str) -> bool {
+        self.option_type.to_str() == Ok(option_type)
+    }
+
+    /// Check a new value and bring it into the form Weechat stores it in.
+    fn normalize(&self, value: &str) -> Option<CString> {
+        let value = if self.is("boolean") {
+            match value {
+                "on" | "true" => "on".to_owned(),
+                "off" | "false" => "off".to_owned(),
+                "toggle" if self.value.as_bytes() == b"on" => "off".to_owned(),
+                "toggle" => "on".to_owned(),
+                _ => return None,
+            }
+        } else if self.is("integer") && !self.string_values.is_empty() {
+            self.string_values
+                .iter()
+                .find(|v| v.to_str() == Ok(value))?
+                .to_str()
+                .ok()?
+                .to_owned()
+        } else if self.is("integer") {
+            let value: c_int = value.parse().ok()?;
+
+            if value < self.min || value > self.max {
+                return None;
+            }
+
+            value.to_string()
+        } else {
+            value.to_owned()
+        };
+
+        CString::new(value).ok()
+    }
+
+    fn integer(&self, value: &CStr) -> c_int {
+        if self.is("boolean") {
+            (value.to_bytes() == b"on") as c_int
+        } else if !self.string_values.is_empty() {
+            self.string_values
+                .iter()
+                .position(|v| v.as_c_str() == value)
+                .unwrap_or(0) as c_int
+        } else {
+            value
+                .to_str()
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0)
+        }
+    }
+
+    fn string(&self, value: &CString) -> *const c_char {
+        if self.is("integer") && self.string_values.is_empty() {
+            ptr::null()
+        } else {
+            value.as_ptr()
+        }
+    }
+}
+
+impl State {
+    fn option(&mut self, ptr: *mut t_config_option) -> Option<&mut MockOption> {
+        let option = self.options.iter_mut().find(|o| o.ptr == ptr);
+
+        if option.is_none() {
+            self.invalid_accesses += 1;
+        }
+
+        option
+    }
+
+    /// Remove the options of a section, the removed options are returned so
+    /// their delete callbacks can run.
+    fn remove_options(
+        &mut self,
+        section: *mut t_config_section,
+    ) -> Vec<MockOption> {
+        let (removed, kept) =
+            self.options.drain(..).partition(|o| o.section == section);
+        self.options = kept;
+        removed
+    }
+}
+
+fn run_delete_callbacks(options: Vec<MockOption>) {
+    for option in options {
+        if let Some((callback, pointer)) = option.delete_cb {
+            unsafe { callback(pointer, ptr::null_mut(), option.ptr) };
+        }
+    }
+}
+
+pub(super) unsafe extern "C" fn config_new(
+    _plugin: *mut t_weechat_plugin,
+    name: *const c_char,
+    _callback: Option<
+        unsafe extern "C" fn(
+            *const c_void,
+            *mut c_void,
+            *mut t_config_file,
+        ) -> c_int,
+    >,
+    _pointer: *const c_void,
+    _data: *mut c_void,
+) -> *mut t_config_file {
+    let name = CStr::from_ptr(name).to_owned();
+
+    with_state(|state| {
+        if state.configs.iter().any(|c| c.name == name) {
+            return ptr::null_mut();
+        }
+
+        let ptr = state.new_pointer();
+        state.configs.push(MockConfig { ptr, name });
+        ptr
+    })
+}
+
+pub(super) unsafe extern "C" fn config_free(config: *mut t_config_file) {
+    let options = with_state(|state| {
+        if !state.configs.iter().any(|c| c.ptr == config) {
+            state.invalid_accesses += 1;
+            return Vec::new();
+        }
+
+        state.configs.retain(|c| c.ptr != config);
+
+        let sections: Vec<_> = state
+            .sections
+            .iter()
+            .filter(|s| s.config == config)
+            .map(|s| s.ptr)
+            .collect();
+        state.sections.retain(|s| s.config != config);
+
+        sections
+            .into_iter()
+            .flat_map(|s| state.remove_options(s))
+            .collect()
+    });
+
+    run_delete_callbacks(options);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(super) unsafe extern "C" fn config_new_section(
+    config: *mut t_config_file,
+    name: *const c_char,
+    _user_can_add_options: c_int,
+    _user_can_delete_options: c_int,
+    _read_callback: Option<
+        unsafe extern "C" fn(
+            *const c_void,
+            *mut c_void,
+            *mut t_config_file,
+            *mut t_config_section,
+            *const c_char,
+            *const c_char,
+        ) -> c_int,
+    >,
+    _read_pointer: *const c_void,
+    _read_data: *mut c_void,
+    _write_callback: Option<
+        unsafe extern "C" fn(
+            *const c_void,
+            *mut c_void,
+            *mut t_config_file,
+            *const c_char,
+        ) -> c_int,
+    >,
+    _write_pointer: *const c_void,
+    _write_data: *mut c_void,
+    _write_default_callback: Option<
+        unsafe extern "C" fn(
+            *const c_void,
+            *mut c_void,
+            *mut t_config_file,
+            *const c_char,
+        ) -> c_int,
+    >,
+    _write_default_pointer: *const c_void,
+    _write_default_data: *mut c_void,
+    _create_option_callback: Option<
+        unsafe extern "C" fn(
+            *const c_void,
+            *mut c_void,
+            *mut t_config_file,
+            *mut t_config_section,
+            *const c_char,
+            *const c_char,
+        ) -> c_int,
+    >,
+    _create_option_pointer: *const c_void,
+    _create_option_data: *mut c_void,
+    _delete_option_callback: Option<
+        unsafe extern "C" fn(
+            *const c_void,
+            *mut c_void,
+            *mut t_config_file,
+            *mut t_config_section,
+            *mut t_config_option,
+        ) -> c_int,
+    >,
+    _delete_option_pointer: *const c_void,
+    _delete_option_data: *mut c_void,
+) -> *mut t_config_section {
+    let name = CStr::from_ptr(name).to_owned();
+
+    with_state(|state| {
+        if !state.configs.iter().any(|c| c.ptr == config) {
+            state.invalid_accesses += 1;
+            return ptr::null_mut();
+        }
+
+        if state
+            .sections
+            .iter()
+            .any(|s| s.config == config && s.name == name)
+        {
+            return ptr::null_mut();
+        }
+
+        let ptr = state.new_pointer();
+        state.sections.push(MockSection { ptr, config, name });
+        ptr
+    })
+}
+
+pub(super) unsafe extern "C" fn config_search_section(
+    config: *mut t_config_file,
+    name: *const c_char,
+) -> *mut t_config_section {
+    let name = CStr::from_ptr(name);
+
+    with_state(|state| {
+        state
+            .sections
+            .iter()
+            .find(|s| s.config == config && s.name.as_c_str() == name)
+            .map(|s| s.ptr)
+            .unwrap_or(ptr::null_mut())
+    })
+}
+
+pub(super) unsafe extern "C" fn config_section_free_options(
+    section: *mut t_config_section,
+) {
+    let options = with_state(|state| {
+        if !state.sections.iter().any(|s| s.ptr == section) {
+            state.invalid_accesses += 1;
+            return Vec::new();
+        }
+
+        state.remove_options(section)
+    });
+
+    run_delete_callbacks(options);
+}
+
+pub(super) unsafe extern "C" fn config_section_free(
+    section: *mut t_config_section,
+) {
+    let options = with_state(|state| {
+        if !state.sections.iter().any(|s| s.ptr == section) {
+            state.invalid_accesses += 1;
+            return Vec::new();
+        }
+
+        state.sections.retain(|s| s.ptr != section);
+        state.remove_options(section)
+    });
+
+    run_delete_callbacks(options);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(super) unsafe extern "C" fn config_new_option(
+    config: *mut t_config_file,
+    section: *mut t_config_section,
+    name: *const c_char,
+    option_type: *const c_char,
+    description: *const c_char,
+    string_values: *const c_char,
+    min: c_int,
+    max: c_int,
+    default_value: *const c_char,
+    value: *const c_char,
+    _null_value_allowed: c_int,
+    check_callback: Option<CheckCallback>,
+    check_pointer: *const c_void,
+    _check_data: *mut c_void,
+    change_callback: Option<OptionCallback>,
+    change_pointer: *const c_void,
+    _change_data: *mut c_void,
+    delete_callback: Option<OptionCallback>,
+    delete_pointer: *const c_void,
+    _delete_data: *mut c_void,
+) -> *mut t_config_option {
+    let name = CStr::from_ptr(name).to_owned();
+    let string_values: Vec<CString> = to_string(string_values)
+        .split('|')
+        .filter(|v| !v.is_empty())
+        .map(|v| CString::new(v).unwrap())
+        .collect();
+
+    with_state(|state| {
+        let (config_name, section_name) = match (
+            state.configs.iter().find(|c| c.ptr == config),
+            state.sections.iter().find(|s| s.ptr == section),
+        ) {
+            (Some(c), Some(s)) => (c.name.clone(), s.name.clone()),
+            _ => {
+                state.invalid_accesses += 1;
+                return ptr::null_mut();
+            }
+        };
+
+        if state
+            .options
+            .iter()
+            .any(|o| o.section == section && o.name == name)
+        {
+            return ptr::null_mut();
+        }
+
+        let mut option = MockOption {
+            ptr: ptr::null_mut(),
+            config,
+            section,
+            name,
+            option_type: CStr::from_ptr(option_type).to_owned(),
+            description: CStr::from_ptr(description).to_owned(),
+            config_name,
+            section_name,
+            string_values,
+            min,
+            max,
+            default_value: CString::default(),
+            value: CString::default(),
+            check_cb: check_callback.map(|c| (c, check_pointer)),
+            change_cb: change_callback.map(|c| (c, change_pointer)),
+            delete_cb: delete_callback.map(|c| (c, delete_pointer)),
+        };
+
+        // Weechat refuses to create options with invalid values.
+        let default_value = option.normalize(&to_string(default_value));
+        let value = option.normalize(&to_string(value));
+
+        match (default_value, value) {
+            (Some(default_value), Some(value)) => {
+                option.default_value = default_value;
+                option.value = value;
+            }
+            _ => return ptr::null_mut(),
+        }
+
+        option.ptr = state.new_pointer();
+        state.options.push(option.clone());
+        option.ptr
+    })
+}
+
+pub(super) unsafe extern "C" fn config_search_option(
+    config: *mut t_config_file,
+    section: *mut t_config_section,
+    name: *const c_char,
+) -> *mut t_config_option {
+    let name = CStr::from_ptr(name);
+
+    with_state(|state| {
+        state
+            .options
+            .iter()
+            .find(|o| {
+                o.config == config
+                    && (section.is_null() || o.section == section)
+                    && o.name.as_c_str() == name
+            })
+            .map(|o| o.ptr)
+            .unwrap_or(ptr::null_mut())
+    })
+}
+
+/// Set the value of an option, running the check and change callbacks of
+/// the option like Weechat does.
+unsafe fn set_value(
+    option: *mut t_config_option,
+    value: &str,
+    run_callback: bool,
+) -> c_int {
+    let option_copy = match with_state(|state| state.option(option).cloned()) {
+        Some(option) => option,
+        None => return OPTION_SET_ERROR,
+    };
+
+    let value = match option_copy.normalize(value) {
+        Some(value) => value,
+        None => return OPTION_SET_ERROR,
+    };
+
+    if let Some((callback, pointer)) = option_copy.check_cb {
+        if callback(pointer, ptr::null_mut(), option, value.as_ptr()) == 0 {
+            return OPTION_SET_ERROR;
+        }
+    }
+
+    if value == option_copy.value {
+        return OPTION_SET_OK_SAME_VALUE;
+    }
+
+    with_state(|state| {
+        if let Some(option) = state.option(option) {
+            option.value = value;
+        }
+    });
+
+    if run_callback {
+        if let Some((callback, pointer)) = option_copy.change_cb {
+            callback(pointer, ptr::null_mut(), option);
+        }
+    }
+
+    OPTION_SET_OK_CHANGED
+}
+
+pub(super) unsafe extern "C" fn config_option_set(
+    option: *mut t_config_option,
+    value: *const c_char,
+    run_callback: c_int,
+) -> c_int {
+    set_value(option, &to_string(value), run_callback != 0)
+}
+
+fn default_value(option: *mut t_config_option) -> Option<String> {
+    with_state(|state| {
+        state
+            .option(option)
+            .map(|o| o.default_value.to_string_lossy().into_owned())
+    })
+}
+
+pub(super) unsafe extern "C" fn config_option_reset(
+    option: *mut t_config_option,
+    run_callback: c_int,
+) -> c_int {
+    match default_value(option) {
+        Some(value) => set_value(option, &value, run_callback != 0),
+        None => OPTION_SET_ERROR,
+    }
+}
+
+// The options of the mock sections are never removed by an unset, they are
+// reset to their default value.
+pub(super) unsafe extern "C" fn config_option_unset(
+    option: *mut t_config_option,
+) -> c_int {
+    match default_value(option) {
+        Some(value) => match set_value(option, &value, true) {
+            OPTION_SET_OK_CHANGED => OPTION_UNSET_OK_RESET,
+            _ => OPTION_UNSET_OK_NO_RESET,
+        },
+        None => OPTION_UNSET_ERROR,
+    }
+}
+
+pub(super) unsafe extern "C" fn config_option_is_null(
+    option: *mut t_config_option,
+) -> c_int {
+    with_state(|state| state.option(option).map(|_| 0).unwrap_or(1))
+}
+
+pub(super) unsafe extern "C" fn config_option_get_string(
+    option: *mut t_config_option,
+    property: *const c_char,
+) -> *const c_char {
+    let property = to_string(property);
+
+    with_state(|state| match state.option(option) {
+        Some(option) => match property.as_str() {
+            "name" => option.name.as_ptr(),
+            "type" => option.option_type.as_ptr(),
+            "description" => option.description.as_ptr(),
+            "config_name" => option.config_name.as_ptr(),
+            "section_name" => option.section_name.as_ptr(),
+            _ => ptr::null(),
+        },
+        None => ptr::null(),
+    })
+}
+
+pub(super) unsafe extern "C" fn config_option_get_pointer(
+    option: *mut t_config_option,
+    property: *const c_char,
+) -> *mut c_void {
+    let property = to_string(property);
+
+    with_state(|state| match state.option(option) {
+        Some(option) => match property.as_str() {
+            "string_values" if !option.string_values.is_empty() => {
+                option.string_values.as_ptr() as *mut c_void
+            }
+            "config_file" => option.config as *mut c_void,
+            "section" => option.section as *mut c_void,
+            _ => ptr::null_mut(),
+        },
+        None => ptr::null_mut(),
+    })
+}
+
+pub(super) unsafe extern "C" fn config_string(
+    option: *mut t_config_option,
+) -> *const c_char {
+    with_state(|state| {
+        state
+            .option(option)
+            .map(|o| o.string(&o.value))
+            .unwrap_or(ptr::null())
+    })
+}
+
+pub(super) unsafe extern "C" fn config_string_default(
+    option: *mut t_config_option,
+) -> *const c_char {
+    with_state(|state| {
+        state
+            .option(option)
+            .map(|o| o.string(&o.default_value))
+            .unwrap_or(ptr::null())
+    })
+}
+
+pub(super) unsafe extern "C" fn config_integer(
+    option: *mut t_config_option,
+) -> c_int {
+    with_state(|state| {
+        state
+            .option(option)
+            .map(|o| o.integer(&o.value))
+            .unwrap_or(0)
+    })
+}
+
+pub(super) unsafe extern "C" fn config_integer_default(
+    option: *mut t_config_option,
+) -> c_int {
+    with_state(|state| {
+        state
+            .option(option)
+            .map(|o| o.integer(&o.default_value))
+            .unwrap_or(0)
+    })
+}
+
+pub(super) use config_integer as config_boolean;
+pub(super) use config_integer_default as config_boolean_default;
+pub(super) use config_string as config_color;
+pub(super) use config_string_default as config_color_default;
+
+// The options of the mock never allow null values.
+pub(super) unsafe extern "C" fn config_option_set_null(
+    option: *mut t_config_option,
+    _run_callback: c_int,
+) -> c_int {
+    with_state(|state| state.option(option).map(|_| ()));
+    OPTION_SET_ERROR
+}
+
+/// Get the full name of a plugin option, e.g. `plugins.var.mock.name`.
+unsafe fn plugin_option_name(
+    plugin: *mut t_weechat_plugin,
+    name: *const c_char,
+) -> String {
+    format!(
+        "plugins.var.{}.{}",
+        to_string((*plugin).name),
+        to_string(name)
+    )
+}
+
+pub(super) unsafe extern "C" fn config_get_plugin(
+    plugin: *mut t_weechat_plugin,
+    name: *const c_char,
+) -> *const c_char {
+    let name = plugin_option_name(plugin, name);
+
+    with_state(|state| {
+        state
+            .plugin_options
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, value)| value.as_ptr())
+            .unwrap_or(ptr::null())
+    })
+}
+
+pub(super) unsafe extern "C" fn config_is_set_plugin(
+    plugin: *mut t_weechat_plugin,
+    name: *const c_char,
+) -> c_int {
+    (!config_get_plugin(plugin, name).is_null()) as c_int
+}
+
+pub(super) unsafe extern "C" fn config_set_plugin(
+    plugin: *mut t_weechat_plugin,
+    name: *const c_char,
+    value: *const c_char,
+) -> c_int {
+    let name = plugin_option_name(plugin, name);
+    let value = CStr::from_ptr(value).to_owned();
+
+    let ret = with_state(|state| {
+        match state.plugin_options.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, old)) if *old == value => OPTION_SET_OK_SAME_VALUE,
+            Some((_, old)) => {
+                *old = value.clone();
+                OPTION_SET_OK_CHANGED
+            }
+            None => {
+                state.plugin_options.push((name.clone(), value.clone()));
+                OPTION_SET_OK_CHANGED
+            }
+        }
+    });
+
+    // Like Weechat the config hooks are only called if the value changed.
+    if ret == OPTION_SET_OK_CHANGED {
+        super::run_config_hooks(&name, &value);
+    }
+
+    ret
+}
+
+pub(super) unsafe extern "C" fn config_unset_plugin(
+    plugin: *mut t_weechat_plugin,
+    name: *const c_char,
+) -> c_int {
+    let name = plugin_option_name(plugin, name);
+
+    with_state(|state| {
+        let count = state.plugin_options.len();
+        state.plugin_options.retain(|(n, _)| *n != name);
+
+        if state.plugin_options.len() == count {
+            OPTION_UNSET_ERROR
+        } else {
+            OPTION_UNSET_OK_REMOVED
+        }
+    })
+}
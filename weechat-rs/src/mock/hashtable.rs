@@ -0,0 +1,316 @@
+//! Hashtables of the mock.
+//!
+//! The hashtables store their keys and values by type, like Weechat does,
+//! and count how many were created and freed so the tests can check for
+//! leaks.
+
+use std::ffi::{CStr, CString};
+use std::ptr;
+
+use libc::{c_char, c_int, c_void, time_t};
+use weechat_sys::{t_hashtable, t_hashtable_item};
+
+use super::{to_string, with_state, State};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ItemType {
+    Integer,
+    String,
+    Pointer,
+    Time,
+}
+
+impl ItemType {
+    fn from_name(name: &str) -> Option<ItemType> {
+        match name {
+            "integer" => Some(ItemType::Integer),
+            "string" => Some(ItemType::String),
+            "pointer" | "buffer" => Some(ItemType::Pointer),
+            "time" => Some(ItemType::Time),
+            _ => None,
+        }
+    }
+}
+
+/// A key or a value, boxed so the pointers returned by `hashtable_get()`
+/// stay valid while other items are added.
+#[derive(PartialEq)]
+enum Item {
+    Integer(Box<c_int>),
+    String(CString),
+    Pointer(*mut c_void),
+    Time(Box<time_t>),
+}
+
+impl Item {
+    unsafe fn read(item_type: ItemType, ptr: *const c_void) -> Item {
+        match item_type {
+            ItemType::Integer => {
+                Item::Integer(Box::new(*(ptr as *const c_int)))
+            }
+            ItemType::String => {
+                Item::String(CStr::from_ptr(ptr as *const c_char).to_owned())
+            }
+            ItemType::Pointer => Item::Pointer(ptr as *mut c_void),
+            ItemType::Time => Item::Time(Box::new(*(ptr as *const time_t))),
+        }
+    }
+
+    fn as_ptr(&self) -> *mut c_void {
+        match self {
+            Item::Integer(value) => &**value as *const c_int as *mut c_void,
+            Item::String(value) => value.as_ptr() as *mut c_void,
+            Item::Pointer(value) => *value,
+            Item::Time(value) => &**value as *const time_t as *mut c_void,
+        }
+    }
+
+    fn to_c_string(&self) -> CString {
+        let string = match self {
+            Item::Integer(value) => value.to_string(),
+            Item::String(value) => return value.clone(),
+            Item::Pointer(value) => format!("{:p}", *value),
+            Item::Time(value) => value.to_string(),
+        };
+
+        CString::new(string).unwrap()
+    }
+}
+
+pub(super) struct MockHashtable {
+    ptr: *mut t_hashtable,
+    key_type: ItemType,
+    value_type: ItemType,
+    type_keys: CString,
+    type_values: CString,
+    items: Vec<(Item, Item)>,
+}
+
+impl State {
+    fn hashtable(
+        &mut self,
+        ptr: *mut t_hashtable,
+    ) -> Option<&mut MockHashtable> {
+        let hashtable = self.hashtables.iter_mut().find(|h| h.ptr == ptr);
+
+        if hashtable.is_none() {
+            self.invalid_accesses += 1;
+        }
+
+        hashtable
+    }
+}
+
+pub(super) unsafe extern "C" fn hashtable_new(
+    _size: c_int,
+    type_keys: *const c_char,
+    type_values: *const c_char,
+    _callback_hash_key: Option<
+        unsafe extern "C" fn(*mut t_hashtable, *const c_void) -> u64,
+    >,
+    _callback_keycmp: Option<
+        unsafe extern "C" fn(
+            *mut t_hashtable,
+            *const c_void,
+            *const c_void,
+        ) -> c_int,
+    >,
+) -> *mut t_hashtable {
+    let type_keys = CStr::from_ptr(type_keys).to_owned();
+    let type_values = CStr::from_ptr(type_values).to_owned();
+
+    let (key_type, value_type) = match (
+        ItemType::from_name(&type_keys.to_string_lossy()),
+        ItemType::from_name(&type_values.to_string_lossy()),
+    ) {
+        (Some(key_type), Some(value_type)) => (key_type, value_type),
+        _ => return ptr::null_mut(),
+    };
+
+    with_state(|state| {
+        let ptr = state.new_pointer();
+        state.hashtables.push(MockHashtable {
+            ptr,
+            key_type,
+            value_type,
+            type_keys,
+            type_values,
+            items: Vec::new(),
+        });
+        state.created_hashtables += 1;
+        ptr
+    })
+}
+
+pub(super) unsafe extern "C" fn hashtable_free(hashtable: *mut t_hashtable) {
+    with_state(|state| {
+        if state.hashtable(hashtable).is_some() {
+            state.hashtables.retain(|h| h.ptr != hashtable);
+            state.freed_hashtables += 1;
+        }
+    })
+}
+
+pub(super) unsafe extern "C" fn hashtable_set(
+    hashtable: *mut t_hashtable,
+    key: *const c_void,
+    value: *const c_void,
+) -> *mut t_hashtable_item {
+    with_state(|state| {
+        let hashtable = match state.hashtable(hashtable) {
+            Some(hashtable) => hashtable,
+            None => return ptr::null_mut(),
+        };
+
+        let key = Item::read(hashtable.key_type, key);
+        let value = Item::read(hashtable.value_type, value);
+
+        match hashtable.items.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, old)) => *old = value,
+            None => hashtable.items.push((key, value)),
+        }
+
+        // The item is only checked for null by this crate.
+        hashtable.ptr as *mut t_hashtable_item
+    })
+}
+
+pub(super) unsafe extern "C" fn hashtable_get(
+    hashtable: *mut t_hashtable,
+    key: *const c_void,
+) -> *mut c_void {
+    with_state(|state| {
+        let hashtable = match state.hashtable(hashtable) {
+            Some(hashtable) => hashtable,
+            None => return ptr::null_mut(),
+        };
+
+        let key = Item::read(hashtable.key_type, key);
+
+        hashtable
+            .items
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, value)| value.as_ptr())
+            .unwrap_or(ptr::null_mut())
+    })
+}
+
+pub(super) unsafe extern "C" fn hashtable_has_key(
+    hashtable: *mut t_hashtable,
+    key: *const c_void,
+) -> c_int {
+    with_state(|state| {
+        let hashtable = match state.hashtable(hashtable) {
+            Some(hashtable) => hashtable,
+            None => return 0,
+        };
+
+        let key = Item::read(hashtable.key_type, key);
+
+        hashtable.items.iter().any(|(k, _)| *k == key) as c_int
+    })
+}
+
+pub(super) unsafe extern "C" fn hashtable_remove(
+    hashtable: *mut t_hashtable,
+    key: *const c_void,
+) {
+    with_state(|state| {
+        if let Some(hashtable) = state.hashtable(hashtable) {
+            let key = Item::read(hashtable.key_type, key);
+            hashtable.items.retain(|(k, _)| *k != key);
+        }
+    })
+}
+
+pub(super) unsafe extern "C" fn hashtable_remove_all(
+    hashtable: *mut t_hashtable,
+) {
+    with_state(|state| {
+        if let Some(hashtable) = state.hashtable(hashtable) {
+            hashtable.items.clear();
+        }
+    })
+}
+
+pub(super) unsafe extern "C" fn hashtable_get_integer(
+    hashtable: *mut t_hashtable,
+    property: *const c_char,
+) -> c_int {
+    let property = to_string(property);
+
+    with_state(|state| match state.hashtable(hashtable) {
+        Some(hashtable) if property == "items_count" => {
+            hashtable.items.len() as c_int
+        }
+        _ => 0,
+    })
+}
+
+pub(super) unsafe extern "C" fn hashtable_get_string(
+    hashtable: *mut t_hashtable,
+    property: *const c_char,
+) -> *const c_char {
+    let property = to_string(property);
+
+    with_state(|state| match state.hashtable(hashtable) {
+        Some(hashtable) => match property.as_str() {
+            "type_keys" => hashtable.type_keys.as_ptr(),
+            "type_values" => hashtable.type_values.as_ptr(),
+            _ => ptr::null(),
+        },
+        None => ptr::null(),
+    })
+}
+
+pub(super) unsafe extern "C" fn hashtable_map_string(
+    hashtable: *mut t_hashtable,
+    callback: Option<
+        unsafe extern "C" fn(
+            *mut c_void,
+            *mut t_hashtable,
+            *const c_char,
+            *const c_char,
+        ),
+    >,
+    data: *mut c_void,
+) {
+    // The items are converted first, the callback might use the hashtable.
+    let items: Vec<(CString, CString)> =
+        with_state(|state| match state.hashtable(hashtable) {
+            Some(hashtable) => hashtable
+                .items
+                .iter()
+                .map(|(k, v)| (k.to_c_string(), v.to_c_string()))
+                .collect(),
+            None => Vec::new(),
+        });
+
+    if let Some(callback) = callback {
+        for (key, value) in items {
+            callback(data, hashtable, key.as_ptr(), value.as_ptr());
+        }
+    }
+}
+
+/// Get the string items of a hashtable, e.g. to implement a stub that takes
+/// a hashtable.
+pub(super) fn string_items(
+    state: &mut State,
+    hashtable: *mut t_hashtable,
+) -> Vec<(String, String)> {
+    match state.hashtable(hashtable) {
+        Some(hashtable) => hashtable
+            .items
+            .iter()
+            .map(|(k, v)| {
+                (
+                    k.to_c_string().to_string_lossy().into_owned(),
+                    v.to_c_string().to_string_lossy().into_owned(),
+                )
+            })
+            .collect(),
+        None => Vec::new(),
+    }
+}
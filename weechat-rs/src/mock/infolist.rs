@@ -0,0 +1,362 @@
+//! Infolists of the mock.
+//!
+//! The `buffer` infolist holds a snapshot of the mock buffers, other
+//! infolists can be built by the plugin. The mock counts how many
+//! infolists were created and freed so the tests can check for leaks and
+//! double frees.
+
+use std::ffi::{CStr, CString};
+use std::ptr;
+
+use libc::{c_char, c_int, c_void, time_t};
+use weechat_sys::{
+    t_infolist, t_infolist_item, t_infolist_var, t_weechat_plugin,
+};
+
+use super::{to_string, with_state, State};
+
+enum Value {
+    Integer(c_int),
+    String(CString),
+    Pointer(*mut c_void),
+    Buffer(Vec<u8>),
+    Time(time_t),
+}
+
+impl Value {
+    fn type_char(&self) -> char {
+        match self {
+            Value::Integer(_) => 'i',
+            Value::String(_) => 's',
+            Value::Pointer(_) => 'p',
+            Value::Buffer(_) => 'b',
+            Value::Time(_) => 't',
+        }
+    }
+}
+
+struct Item {
+    ptr: *mut t_infolist_item,
+    vars: Vec<(String, Value)>,
+    fields: CString,
+}
+
+pub(super) struct MockInfolist {
+    ptr: *mut t_infolist,
+    items: Vec<Item>,
+    // The index of the current item, `None` before the first and after the
+    // last item.
+    cursor: Option<usize>,
+}
+
+impl MockInfolist {
+    fn current(&self) -> Option<&Item> {
+        self.items.get(self.cursor?)
+    }
+
+    fn var(&self, name: *const c_char) -> Option<&Value> {
+        let name = unsafe { to_string(name) };
+
+        self.current()?
+            .vars
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, value)| value)
+    }
+}
+
+impl State {
+    fn infolist(&mut self, ptr: *mut t_infolist) -> Option<&mut MockInfolist> {
+        let infolist = self.infolists.iter_mut().find(|i| i.ptr == ptr);
+
+        if infolist.is_none() {
+            self.invalid_accesses += 1;
+        }
+
+        infolist
+    }
+
+    fn infolist_item(
+        &mut self,
+        ptr: *mut t_infolist_item,
+    ) -> Option<&mut Item> {
+        let item = self
+            .infolists
+            .iter_mut()
+            .flat_map(|i| i.items.iter_mut())
+            .find(|i| i.ptr == ptr);
+
+        if item.is_none() {
+            self.invalid_accesses += 1;
+        }
+
+        item
+    }
+
+    fn new_infolist(&mut self) -> *mut t_infolist {
+        let ptr = self.new_pointer();
+
+        self.infolists.push(MockInfolist {
+            ptr,
+            items: Vec::new(),
+            cursor: None,
+        });
+        self.created_infolists += 1;
+
+        ptr
+    }
+}
+
+pub(super) unsafe extern "C" fn infolist_new(
+    _plugin: *mut t_weechat_plugin,
+) -> *mut t_infolist {
+    with_state(|state| state.new_infolist())
+}
+
+pub(super) unsafe extern "C" fn infolist_new_item(
+    infolist: *mut t_infolist,
+) -> *mut t_infolist_item {
+    with_state(|state| {
+        let ptr = state.new_pointer();
+
+        match state.infolist(infolist) {
+            Some(infolist) => {
+                infolist.items.push(Item {
+                    ptr,
+                    vars: Vec::new(),
+                    fields: CString::default(),
+                });
+                ptr
+            }
+            None => ptr::null_mut(),
+        }
+    })
+}
+
+fn new_var(
+    item: *mut t_infolist_item,
+    name: *const c_char,
+    value: Value,
+) -> *mut t_infolist_var {
+    let name = unsafe { to_string(name) };
+
+    with_state(|state| match state.infolist_item(item) {
+        Some(item) => {
+            item.vars.push((name, value));
+
+            let fields: Vec<String> = item
+                .vars
+                .iter()
+                .map(|(name, value)| format!("{}:{}", value.type_char(), name))
+                .collect();
+            item.fields = CString::new(fields.join(",")).unwrap();
+
+            // The variable is only checked for null by this crate.
+            item.ptr as *mut t_infolist_var
+        }
+        None => ptr::null_mut(),
+    })
+}
+
+pub(super) unsafe extern "C" fn infolist_new_var_integer(
+    item: *mut t_infolist_item,
+    name: *const c_char,
+    value: c_int,
+) -> *mut t_infolist_var {
+    new_var(item, name, Value::Integer(value))
+}
+
+pub(super) unsafe extern "C" fn infolist_new_var_string(
+    item: *mut t_infolist_item,
+    name: *const c_char,
+    value: *const c_char,
+) -> *mut t_infolist_var {
+    new_var(item, name, Value::String(CStr::from_ptr(value).to_owned()))
+}
+
+pub(super) unsafe extern "C" fn infolist_new_var_pointer(
+    item: *mut t_infolist_item,
+    name: *const c_char,
+    pointer: *mut c_void,
+) -> *mut t_infolist_var {
+    new_var(item, name, Value::Pointer(pointer))
+}
+
+pub(super) unsafe extern "C" fn infolist_new_var_buffer(
+    item: *mut t_infolist_item,
+    name: *const c_char,
+    pointer: *mut c_void,
+    size: c_int,
+) -> *mut t_infolist_var {
+    let bytes = std::slice::from_raw_parts(pointer as *const u8, size as usize);
+    new_var(item, name, Value::Buffer(bytes.to_vec()))
+}
+
+pub(super) unsafe extern "C" fn infolist_new_var_time(
+    item: *mut t_infolist_item,
+    name: *const c_char,
+    time: time_t,
+) -> *mut t_infolist_var {
+    new_var(item, name, Value::Time(time))
+}
+
+pub(super) unsafe extern "C" fn infolist_get(
+    plugin: *mut t_weechat_plugin,
+    name: *const c_char,
+    _pointer: *mut c_void,
+    _arguments: *const c_char,
+) -> *mut t_infolist {
+    if to_string(name) != "buffer" {
+        return ptr::null_mut();
+    }
+
+    let buffers: Vec<_> = with_state(|state| {
+        state
+            .buffers
+            .iter()
+            .map(|b| (b.ptr, b.name.clone(), b.full_name.clone()))
+            .collect()
+    });
+
+    let infolist = infolist_new(plugin);
+
+    for (number, (buffer, name, full_name)) in buffers.into_iter().enumerate() {
+        let item = infolist_new_item(infolist);
+        infolist_new_var_pointer(item, cstr!("pointer").as_ptr(), buffer as _);
+        infolist_new_var_integer(
+            item,
+            cstr!("number").as_ptr(),
+            number as c_int + 1,
+        );
+        infolist_new_var_string(item, cstr!("name").as_ptr(), name.as_ptr());
+        infolist_new_var_string(
+            item,
+            cstr!("full_name").as_ptr(),
+            full_name.as_ptr(),
+        );
+    }
+
+    infolist
+}
+
+pub(super) unsafe extern "C" fn infolist_next(
+    infolist: *mut t_infolist,
+) -> c_int {
+    with_state(|state| match state.infolist(infolist) {
+        Some(infolist) => {
+            let next = infolist.cursor.map_or(0, |c| c + 1);
+            infolist.cursor = Some(next).filter(|n| *n < infolist.items.len());
+            infolist.cursor.is_some() as c_int
+        }
+        None => 0,
+    })
+}
+
+pub(super) unsafe extern "C" fn infolist_prev(
+    infolist: *mut t_infolist,
+) -> c_int {
+    with_state(|state| match state.infolist(infolist) {
+        Some(infolist) => {
+            infolist.cursor = match infolist.cursor {
+                Some(cursor) => cursor.checked_sub(1),
+                None => infolist.items.len().checked_sub(1),
+            };
+            infolist.cursor.is_some() as c_int
+        }
+        None => 0,
+    })
+}
+
+pub(super) unsafe extern "C" fn infolist_reset_item_cursor(
+    infolist: *mut t_infolist,
+) {
+    with_state(|state| {
+        if let Some(infolist) = state.infolist(infolist) {
+            infolist.cursor = None;
+        }
+    })
+}
+
+pub(super) unsafe extern "C" fn infolist_fields(
+    infolist: *mut t_infolist,
+) -> *const c_char {
+    with_state(|state| {
+        state
+            .infolist(infolist)
+            .and_then(|i| i.current())
+            .map_or(ptr::null(), |item| item.fields.as_ptr())
+    })
+}
+
+pub(super) unsafe extern "C" fn infolist_integer(
+    infolist: *mut t_infolist,
+    var: *const c_char,
+) -> c_int {
+    with_state(|state| {
+        match state.infolist(infolist).and_then(|i| i.var(var)) {
+            Some(Value::Integer(value)) => *value,
+            _ => 0,
+        }
+    })
+}
+
+pub(super) unsafe extern "C" fn infolist_string(
+    infolist: *mut t_infolist,
+    var: *const c_char,
+) -> *const c_char {
+    with_state(|state| {
+        match state.infolist(infolist).and_then(|i| i.var(var)) {
+            Some(Value::String(value)) => value.as_ptr(),
+            _ => ptr::null(),
+        }
+    })
+}
+
+pub(super) unsafe extern "C" fn infolist_pointer(
+    infolist: *mut t_infolist,
+    var: *const c_char,
+) -> *mut c_void {
+    with_state(|state| {
+        match state.infolist(infolist).and_then(|i| i.var(var)) {
+            Some(Value::Pointer(value)) => *value,
+            _ => ptr::null_mut(),
+        }
+    })
+}
+
+pub(super) unsafe extern "C" fn infolist_buffer(
+    infolist: *mut t_infolist,
+    var: *const c_char,
+    size: *mut c_int,
+) -> *mut c_void {
+    with_state(|state| {
+        match state.infolist(infolist).and_then(|i| i.var(var)) {
+            Some(Value::Buffer(bytes)) => {
+                *size = bytes.len() as c_int;
+                bytes.as_ptr() as *mut c_void
+            }
+            _ => ptr::null_mut(),
+        }
+    })
+}
+
+pub(super) unsafe extern "C" fn infolist_time(
+    infolist: *mut t_infolist,
+    var: *const c_char,
+) -> time_t {
+    with_state(|state| {
+        match state.infolist(infolist).and_then(|i| i.var(var)) {
+            Some(Value::Time(value)) => *value,
+            _ => 0,
+        }
+    })
+}
+
+pub(super) unsafe extern "C" fn infolist_free(infolist: *mut t_infolist) {
+    with_state(|state| {
+        if state.infolist(infolist).is_some() {
+            state.infolists.retain(|i| i.ptr != infolist);
+            state.freed_infolists += 1;
+        }
+    })
+}
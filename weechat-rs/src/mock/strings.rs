@@ -0,0 +1,262 @@
+//! String functions of the mock.
+//!
+//! The split strings are kept until they are freed so the tests can check
+//! that every split string is freed exactly once. The charset conversions
+//! only know ISO-8859-1, other charsets are returned unchanged like Weechat
+//! does when a conversion fails. Evaluated expressions only expand extra
+//! variables and secured data.
+
+use std::ffi::{CStr, CString};
+use std::ptr;
+
+use libc::{c_char, c_int};
+use weechat_sys::{
+    t_hashtable, WEECHAT_STRING_SPLIT_COLLAPSE_SEPS,
+    WEECHAT_STRING_SPLIT_KEEP_EOL, WEECHAT_STRING_SPLIT_STRIP_LEFT,
+    WEECHAT_STRING_SPLIT_STRIP_RIGHT,
+};
+
+use super::{hashtable, to_string, with_state};
+
+pub(super) struct Split {
+    // The pointers point into the items, the last pointer is null like the
+    // pointers of a split string of Weechat.
+    pointers: Vec<*mut c_char>,
+    _items: Vec<CString>,
+}
+
+/// Match the characters of a string against the characters of a mask, a `*`
+/// in the mask matches any number of characters.
+fn matches(string: &[char], mask: &[char]) -> bool {
+    match mask.split_first() {
+        None => string.is_empty(),
+        Some(('*', rest)) => {
+            (0..=string.len()).any(|i| matches(&string[i..], rest))
+        }
+        Some((c, rest)) => {
+            string.first() == Some(c) && matches(&string[1..], rest)
+        }
+    }
+}
+
+pub(super) unsafe extern "C" fn string_match(
+    string: *const c_char,
+    mask: *const c_char,
+    case_sensitive: c_int,
+) -> c_int {
+    match_mask(&to_string(string), &to_string(mask), case_sensitive != 0)
+        as c_int
+}
+
+/// Check if a string matches a mask the way `string_match()` does.
+pub(super) fn match_mask(
+    string: &str,
+    mask: &str,
+    case_sensitive: bool,
+) -> bool {
+    // Weechat never matches an empty mask.
+    if mask.is_empty() {
+        return false;
+    }
+
+    let (string, mask) = if case_sensitive {
+        (string.to_owned(), mask.to_owned())
+    } else {
+        (string.to_lowercase(), mask.to_lowercase())
+    };
+
+    let string: Vec<char> = string.chars().collect();
+    let mask: Vec<char> = mask.chars().collect();
+
+    matches(&string, &mask)
+}
+
+pub(super) unsafe extern "C" fn string_split(
+    string: *const c_char,
+    separators: *const c_char,
+    _strip_items: *const c_char,
+    flags: c_int,
+    _num_items_max: c_int,
+    num_items: *mut c_int,
+) -> *mut *mut c_char {
+    let flag = |f: u32| flags & f as c_int != 0;
+
+    let string = to_string(string);
+    let separators: Vec<char> = to_string(separators).chars().collect();
+    let is_separator = |c: char| separators.contains(&c);
+
+    let mut string = string.as_str();
+
+    if flag(WEECHAT_STRING_SPLIT_STRIP_LEFT) {
+        string = string.trim_start_matches(is_separator);
+    }
+    if flag(WEECHAT_STRING_SPLIT_STRIP_RIGHT) {
+        string = string.trim_end_matches(is_separator);
+    }
+
+    *num_items = 0;
+
+    if string.is_empty() {
+        return ptr::null_mut();
+    }
+
+    // Collect the start and end offsets of the items.
+    let mut items = Vec::new();
+    let mut start = 0;
+
+    for (i, c) in string.char_indices() {
+        if is_separator(c) {
+            items.push((start, i));
+            start = i + c.len_utf8();
+        }
+    }
+    items.push((start, string.len()));
+
+    if flag(WEECHAT_STRING_SPLIT_COLLAPSE_SEPS) {
+        items.retain(|(start, end)| start != end);
+    }
+
+    let items: Vec<CString> = items
+        .into_iter()
+        .map(|(start, end)| {
+            let end = if flag(WEECHAT_STRING_SPLIT_KEEP_EOL) {
+                string.len()
+            } else {
+                end
+            };
+            CString::new(&string[start..end]).unwrap()
+        })
+        .collect();
+
+    let mut pointers: Vec<*mut c_char> =
+        items.iter().map(|i| i.as_ptr() as *mut c_char).collect();
+    pointers.push(ptr::null_mut());
+
+    *num_items = items.len() as c_int;
+
+    with_state(|state| {
+        let ptr = pointers.as_mut_ptr();
+        state.splits.push(Split {
+            pointers,
+            _items: items,
+        });
+        ptr
+    })
+}
+
+pub(super) unsafe extern "C" fn string_free_split(split: *mut *mut c_char) {
+    with_state(|state| {
+        let count = state.splits.len();
+        state
+            .splits
+            .retain(|s| s.pointers.as_ptr() != split as *const _);
+
+        if state.splits.len() == count {
+            state.invalid_accesses += 1;
+        }
+    })
+}
+
+/// Copy bytes into a string allocated with `malloc()`, like the strings that
+/// Weechat returns and this crate frees.
+unsafe fn strdup(bytes: Vec<u8>) -> *mut c_char {
+    let string = CString::new(bytes).unwrap();
+    libc::strdup(string.as_ptr())
+}
+
+fn is_latin1(charset: *const c_char) -> bool {
+    let charset = unsafe { to_string(charset) }.to_lowercase();
+    charset == "iso-8859-1" || charset == "latin1"
+}
+
+pub(super) unsafe extern "C" fn iconv_to_internal(
+    charset: *const c_char,
+    string: *const c_char,
+) -> *mut c_char {
+    let bytes = CStr::from_ptr(string).to_bytes();
+
+    if !is_latin1(charset) {
+        return strdup(bytes.to_vec());
+    }
+
+    let decoded: String = bytes.iter().map(|b| *b as char).collect();
+    strdup(decoded.into_bytes())
+}
+
+pub(super) unsafe extern "C" fn iconv_from_internal(
+    charset: *const c_char,
+    string: *const c_char,
+) -> *mut c_char {
+    if !is_latin1(charset) {
+        return strdup(CStr::from_ptr(string).to_bytes().to_vec());
+    }
+
+    let encoded: Vec<u8> = to_string(string)
+        .chars()
+        .map(|c| if (c as u32) < 0x100 { c as u8 } else { b'?' })
+        .collect();
+    strdup(encoded)
+}
+
+/// The input is a command if it starts with a `/` that isn't doubled, like
+/// Weechat with the default `weechat.look.command_chars`. Paths like
+/// `/tmp/file` are text.
+pub(super) unsafe extern "C" fn string_input_for_buffer(
+    string: *const c_char,
+) -> *const c_char {
+    let bytes = CStr::from_ptr(string).to_bytes();
+
+    if bytes.first() != Some(&b'/') || bytes.starts_with(b"/*") {
+        return string;
+    }
+
+    if bytes.get(1) == Some(&b'/') {
+        return string.add(1);
+    }
+
+    let first_word = bytes[1..].split(|b| *b == b' ').next().unwrap();
+
+    if first_word.contains(&b'/') {
+        string
+    } else {
+        ptr::null()
+    }
+}
+
+pub(super) unsafe extern "C" fn string_eval_expression(
+    expression: *const c_char,
+    _pointers: *mut t_hashtable,
+    extra_vars: *mut t_hashtable,
+    _options: *mut t_hashtable,
+) -> *mut c_char {
+    let mut rest = to_string(expression);
+    let mut evaluated = String::new();
+
+    let variables = with_state(|state| {
+        let mut variables = hashtable::string_items(state, extra_vars);
+        variables.extend(state.secured_data.iter().map(|(name, value)| {
+            (format!("sec.data.{}", name), value.clone())
+        }));
+        variables
+    });
+
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+
+        let name = &rest[start + 2..end];
+        let value = variables
+            .iter()
+            .find(|(n, _)| n == name)
+            .map_or("", |(_, value)| value.as_str());
+
+        evaluated.push_str(&rest[..start]);
+        evaluated.push_str(value);
+        rest = rest[end + 1..].to_owned();
+    }
+
+    evaluated.push_str(&rest);
+    strdup(evaluated.into_bytes())
+}
@@ -0,0 +1,687 @@
+//! Builders for the settings of config options.
+//!
+//! The settings are consumed by the `ConfigSection::add_*_option()` methods,
+//! the settings are validated before the option is created.
+
+use std::borrow::Cow;
+
+use crate::config_options::{
+    BooleanOption, CheckValueCallback, ColorOption, EnumOption, IntegerOption,
+    OptionCallback, StringOption,
+};
+use crate::Weechat;
+
+/// Error that is returned if the settings of an option are invalid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptionSettingsError {
+    /// The name of the option is empty.
+    EmptyName,
+    /// The minimum of an integer option is larger than its maximum.
+    InvalidRange {
+        /// The minimum of the option.
+        min: i32,
+        /// The maximum of the option.
+        max: i32,
+    },
+    /// The default or current value of an integer option is outside of its
+    /// range.
+    ValueOutOfRange {
+        /// The value that is out of range.
+        value: i32,
+        /// The minimum of the option.
+        min: i32,
+        /// The maximum of the option.
+        max: i32,
+    },
+    /// An enum option has no values.
+    NoValues,
+    /// A value of an enum option is empty or contains a `|`.
+    InvalidValue(String),
+    /// The default or current value of an enum option isn't one of its
+    /// values.
+    UnknownValue(String),
+    /// The section already contains an option with the given name.
+    AlreadyExists(String),
+    /// Weechat refused to create the option, e.g. because the value isn't
+    /// valid for the option type.
+    CreationFailed(String),
+}
+
+impl std::fmt::Display for OptionSettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OptionSettingsError::EmptyName => {
+                write!(f, "the option name can't be empty")
+            }
+            OptionSettingsError::InvalidRange { min, max } => write!(
+                f,
+                "the option minimum {} is larger than the maximum {}",
+                min, max
+            ),
+            OptionSettingsError::ValueOutOfRange { value, min, max } => write!(
+                f,
+                "the option value {} is outside of the range {}..={}",
+                value, min, max
+            ),
+            OptionSettingsError::NoValues => {
+                write!(f, "the option needs at least one value")
+            }
+            OptionSettingsError::InvalidValue(value) => write!(
+                f,
+                "the option value {:?} is empty or contains a `|`",
+                value
+            ),
+            OptionSettingsError::UnknownValue(value) => write!(
+                f,
+                "the option value {:?} isn't one of the allowed values",
+                value
+            ),
+            OptionSettingsError::AlreadyExists(name) => {
+                write!(f, "the option {:?} already exists", name)
+            }
+            OptionSettingsError::CreationFailed(name) => {
+                write!(f, "Weechat failed to create the option {:?}", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OptionSettingsError {}
+
+fn check_name(name: &str) -> Result<(), OptionSettingsError> {
+    if name.is_empty() {
+        Err(OptionSettingsError::EmptyName)
+    } else {
+        Ok(())
+    }
+}
+
+/// Settings for a new string option.
+///
+/// # Example
+///
+/// ```ignore
+/// let settings = StringOptionSettings::new("nick")
+///     .description("The nick that will be used")
+///     .default_value("rust-bot")
+///     .check(|value| !value.is_empty());
+///
+/// let nick = section.add_string_option(settings)?;
+/// ```
+#[derive(Default)]
+pub struct StringOptionSettings {
+    pub(crate) name: String,
+    pub(crate) description: String,
+    pub(crate) default_value: String,
+    pub(crate) value: Option<String>,
+    pub(crate) null_allowed: bool,
+    pub(crate) string_values: Vec<String>,
+    pub(crate) check_cb: Option<CheckValueCallback<StringOption<'static>>>,
+    pub(crate) change_cb: Option<OptionCallback<StringOption<'static>>>,
+    pub(crate) delete_cb: Option<OptionCallback<StringOption<'static>>>,
+}
+
+impl StringOptionSettings {
+    /// Create new settings for a string option with the given name.
+    pub fn new<N: Into<String>>(name: N) -> Self {
+        StringOptionSettings {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Set the description of the option.
+    pub fn description<D: Into<String>>(mut self, description: D) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Set the default value of the option.
+    pub fn default_value<V: Into<String>>(mut self, value: V) -> Self {
+        self.default_value = value.into();
+        self
+    }
+
+    /// Set the current value of the option, defaults to the default value.
+    pub fn value<V: Into<String>>(mut self, value: V) -> Self {
+        self.value = Some(value.into());
+        self
+    }
+
+    /// Allow the option to be set to null.
+    pub fn null_allowed(mut self, null_allowed: bool) -> Self {
+        self.null_allowed = null_allowed;
+        self
+    }
+
+    /// Restrict the option to the given values.
+    ///
+    /// Other values are rejected and the allowed values are appended to the
+    /// description of the option so they show up in `/help`.
+    pub fn string_values<V: AsRef<str>>(mut self, values: &[V]) -> Self {
+        self.string_values =
+            values.iter().map(|v| v.as_ref().to_owned()).collect();
+        self
+    }
+
+    /// Set a function that checks new values of the option, values for which
+    /// the function returns `false` are rejected.
+    pub fn check(
+        mut self,
+        mut check: impl FnMut(&str) -> bool + 'static,
+    ) -> Self {
+        self.check_cb =
+            Some(Box::new(move |_, _, value: Cow<str>| check(&value)));
+        self
+    }
+
+    /// Set a function that is called when the value of the option changes.
+    pub fn on_change(
+        mut self,
+        callback: impl FnMut(&Weechat, &StringOption) + 'static,
+    ) -> Self {
+        self.change_cb = Some(Box::new(callback));
+        self
+    }
+
+    /// Set a function that is called when the option is deleted.
+    pub fn on_delete(
+        mut self,
+        callback: impl FnMut(&Weechat, &StringOption) + 'static,
+    ) -> Self {
+        self.delete_cb = Some(Box::new(callback));
+        self
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), OptionSettingsError> {
+        check_name(&self.name)?;
+
+        if self.string_values.is_empty() {
+            return Ok(());
+        }
+
+        let values =
+            std::iter::once(&self.default_value).chain(self.value.as_ref());
+
+        for value in values {
+            if !self.string_values.contains(value) {
+                return Err(OptionSettingsError::UnknownValue(value.clone()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the description of the option, including the allowed values.
+    pub(crate) fn full_description(&self) -> String {
+        if self.string_values.is_empty() {
+            return self.description.clone();
+        }
+
+        let allowed = self.string_values.join(", ");
+
+        if self.description.is_empty() {
+            format!("values: {}", allowed)
+        } else {
+            format!("{} (values: {})", self.description, allowed)
+        }
+    }
+
+    /// Take the check callback of the option, the callback rejects values
+    /// that aren't allowed.
+    pub(crate) fn take_check_cb(
+        &mut self,
+    ) -> Option<CheckValueCallback<StringOption<'static>>> {
+        if self.string_values.is_empty() {
+            return self.check_cb.take();
+        }
+
+        let values = self.string_values.clone();
+        let mut check_cb = self.check_cb.take();
+
+        Some(Box::new(move |weechat, option, value: Cow<str>| {
+            if !values.iter().any(|v| v == value.as_ref()) {
+                return false;
+            }
+
+            match check_cb.as_mut() {
+                Some(check) => check(weechat, option, value),
+                None => true,
+            }
+        }))
+    }
+}
+
+/// Settings for a new boolean option.
+#[derive(Default)]
+pub struct BooleanOptionSettings {
+    pub(crate) name: String,
+    pub(crate) description: String,
+    pub(crate) default_value: bool,
+    pub(crate) value: Option<bool>,
+    pub(crate) null_allowed: bool,
+    pub(crate) change_cb: Option<OptionCallback<BooleanOption<'static>>>,
+    pub(crate) delete_cb: Option<OptionCallback<BooleanOption<'static>>>,
+}
+
+impl BooleanOptionSettings {
+    /// Create new settings for a boolean option with the given name.
+    pub fn new<N: Into<String>>(name: N) -> Self {
+        BooleanOptionSettings {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Set the description of the option.
+    pub fn description<D: Into<String>>(mut self, description: D) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Set the default value of the option.
+    pub fn default_value(mut self, value: bool) -> Self {
+        self.default_value = value;
+        self
+    }
+
+    /// Set the current value of the option, defaults to the default value.
+    pub fn value(mut self, value: bool) -> Self {
+        self.value = Some(value);
+        self
+    }
+
+    /// Allow the option to be set to null.
+    pub fn null_allowed(mut self, null_allowed: bool) -> Self {
+        self.null_allowed = null_allowed;
+        self
+    }
+
+    /// Set a function that is called when the value of the option changes.
+    pub fn on_change(
+        mut self,
+        callback: impl FnMut(&Weechat, &BooleanOption) + 'static,
+    ) -> Self {
+        self.change_cb = Some(Box::new(callback));
+        self
+    }
+
+    /// Set a function that is called when the option is deleted.
+    pub fn on_delete(
+        mut self,
+        callback: impl FnMut(&Weechat, &BooleanOption) + 'static,
+    ) -> Self {
+        self.delete_cb = Some(Box::new(callback));
+        self
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), OptionSettingsError> {
+        check_name(&self.name)
+    }
+}
+
+/// Settings for a new integer option.
+///
+/// # Example
+///
+/// ```ignore
+/// let settings = IntegerOptionSettings::new("port")
+///     .description("The port of the server")
+///     .min(1)
+///     .max(65535)
+///     .default_value(6697);
+///
+/// let port = section.add_integer_option(settings)?;
+/// ```
+pub struct IntegerOptionSettings {
+    pub(crate) name: String,
+    pub(crate) description: String,
+    pub(crate) min: i32,
+    pub(crate) max: i32,
+    pub(crate) default_value: i32,
+    pub(crate) value: Option<i32>,
+    pub(crate) null_allowed: bool,
+    pub(crate) string_values: Vec<String>,
+    pub(crate) check_cb: Option<CheckValueCallback<IntegerOption<'static>>>,
+    pub(crate) change_cb: Option<OptionCallback<IntegerOption<'static>>>,
+    pub(crate) delete_cb: Option<OptionCallback<IntegerOption<'static>>>,
+}
+
+impl IntegerOptionSettings {
+    /// Create new settings for an integer option with the given name.
+    ///
+    /// The option accepts all `i32` values unless a minimum or maximum is
+    /// set.
+    pub fn new<N: Into<String>>(name: N) -> Self {
+        IntegerOptionSettings {
+            name: name.into(),
+            description: String::new(),
+            min: i32::MIN,
+            max: i32::MAX,
+            default_value: 0,
+            value: None,
+            null_allowed: false,
+            string_values: Vec::new(),
+            check_cb: None,
+            change_cb: None,
+            delete_cb: None,
+        }
+    }
+
+    /// Set the description of the option.
+    pub fn description<D: Into<String>>(mut self, description: D) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Set the minimal value of the option.
+    pub fn min(mut self, min: i32) -> Self {
+        self.min = min;
+        self
+    }
+
+    /// Set the maximal value of the option.
+    pub fn max(mut self, max: i32) -> Self {
+        self.max = max;
+        self
+    }
+
+    /// Set symbolic string values for the option.
+    ///
+    /// The value of the option is then an index into the string values and
+    /// users can set the option using the strings, e.g. `/set
+    /// myplugin.look.sort_order activity`. The minimum and maximum are
+    /// ignored for options with string values.
+    pub fn string_values<V: AsRef<str>>(mut self, values: &[V]) -> Self {
+        self.string_values =
+            values.iter().map(|v| v.as_ref().to_owned()).collect();
+        self
+    }
+
+    /// Set the default value of the option.
+    pub fn default_value(mut self, value: i32) -> Self {
+        self.default_value = value;
+        self
+    }
+
+    /// Set the current value of the option, defaults to the default value.
+    pub fn value(mut self, value: i32) -> Self {
+        self.value = Some(value);
+        self
+    }
+
+    /// Allow the option to be set to null.
+    pub fn null_allowed(mut self, null_allowed: bool) -> Self {
+        self.null_allowed = null_allowed;
+        self
+    }
+
+    /// Set a function that checks new values of the option, values for which
+    /// the function returns `false` are rejected.
+    ///
+    /// The function receives the value as it was given to Weechat, e.g.
+    /// `"++1"` is a valid value for an integer option.
+    pub fn check(
+        mut self,
+        mut check: impl FnMut(&str) -> bool + 'static,
+    ) -> Self {
+        self.check_cb =
+            Some(Box::new(move |_, _, value: Cow<str>| check(&value)));
+        self
+    }
+
+    /// Set a function that is called when the value of the option changes.
+    pub fn on_change(
+        mut self,
+        callback: impl FnMut(&Weechat, &IntegerOption) + 'static,
+    ) -> Self {
+        self.change_cb = Some(Box::new(callback));
+        self
+    }
+
+    /// Set a function that is called when the option is deleted.
+    pub fn on_delete(
+        mut self,
+        callback: impl FnMut(&Weechat, &IntegerOption) + 'static,
+    ) -> Self {
+        self.delete_cb = Some(Box::new(callback));
+        self
+    }
+
+    /// The range of the option, options with string values range over the
+    /// indices of the values.
+    pub(crate) fn range(&self) -> (i32, i32) {
+        if self.string_values.is_empty() {
+            (self.min, self.max)
+        } else {
+            (0, self.string_values.len() as i32 - 1)
+        }
+    }
+
+    /// Format a value of the option the way Weechat expects it.
+    pub(crate) fn format_value(&self, value: i32) -> String {
+        if self.string_values.is_empty() {
+            value.to_string()
+        } else {
+            self.string_values[value as usize].clone()
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), OptionSettingsError> {
+        check_name(&self.name)?;
+
+        if let Some(value) = self
+            .string_values
+            .iter()
+            .find(|v| v.is_empty() || v.contains('|'))
+        {
+            return Err(OptionSettingsError::InvalidValue(value.clone()));
+        }
+
+        let (min, max) = self.range();
+
+        if min > max {
+            return Err(OptionSettingsError::InvalidRange { min, max });
+        }
+
+        let values = std::iter::once(self.default_value).chain(self.value);
+
+        for value in values {
+            if value < min || value > max {
+                return Err(OptionSettingsError::ValueOutOfRange {
+                    value,
+                    min,
+                    max,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Settings for a new color option.
+#[derive(Default)]
+pub struct ColorOptionSettings {
+    pub(crate) name: String,
+    pub(crate) description: String,
+    pub(crate) default_value: String,
+    pub(crate) value: Option<String>,
+    pub(crate) null_allowed: bool,
+    pub(crate) check_cb: Option<CheckValueCallback<ColorOption<'static>>>,
+    pub(crate) change_cb: Option<OptionCallback<ColorOption<'static>>>,
+    pub(crate) delete_cb: Option<OptionCallback<ColorOption<'static>>>,
+}
+
+impl ColorOptionSettings {
+    /// Create new settings for a color option with the given name.
+    pub fn new<N: Into<String>>(name: N) -> Self {
+        ColorOptionSettings {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Set the description of the option.
+    pub fn description<D: Into<String>>(mut self, description: D) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Set the default value of the option, e.g. `"lightblue"`.
+    pub fn default_value<V: Into<String>>(mut self, value: V) -> Self {
+        self.default_value = value.into();
+        self
+    }
+
+    /// Set the current value of the option, defaults to the default value.
+    pub fn value<V: Into<String>>(mut self, value: V) -> Self {
+        self.value = Some(value.into());
+        self
+    }
+
+    /// Allow the option to be set to null.
+    pub fn null_allowed(mut self, null_allowed: bool) -> Self {
+        self.null_allowed = null_allowed;
+        self
+    }
+
+    /// Set a function that checks new values of the option, values for which
+    /// the function returns `false` are rejected.
+    pub fn check(
+        mut self,
+        mut check: impl FnMut(&str) -> bool + 'static,
+    ) -> Self {
+        self.check_cb =
+            Some(Box::new(move |_, _, value: Cow<str>| check(&value)));
+        self
+    }
+
+    /// Set a function that is called when the value of the option changes.
+    pub fn on_change(
+        mut self,
+        callback: impl FnMut(&Weechat, &ColorOption) + 'static,
+    ) -> Self {
+        self.change_cb = Some(Box::new(callback));
+        self
+    }
+
+    /// Set a function that is called when the option is deleted.
+    pub fn on_delete(
+        mut self,
+        callback: impl FnMut(&Weechat, &ColorOption) + 'static,
+    ) -> Self {
+        self.delete_cb = Some(Box::new(callback));
+        self
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), OptionSettingsError> {
+        check_name(&self.name)
+    }
+}
+
+/// Settings for a new enum option.
+///
+/// # Example
+///
+/// ```ignore
+/// let settings = EnumOptionSettings::new("mode")
+///     .values(&["off", "local", "remote"])
+///     .default_value("local");
+///
+/// let mode = section.add_enum_option(settings)?;
+/// ```
+#[derive(Default)]
+pub struct EnumOptionSettings {
+    pub(crate) name: String,
+    pub(crate) description: String,
+    pub(crate) values: Vec<String>,
+    pub(crate) default_value: String,
+    pub(crate) value: Option<String>,
+    pub(crate) null_allowed: bool,
+    pub(crate) change_cb: Option<OptionCallback<EnumOption<'static>>>,
+    pub(crate) delete_cb: Option<OptionCallback<EnumOption<'static>>>,
+}
+
+impl EnumOptionSettings {
+    /// Create new settings for an enum option with the given name.
+    pub fn new<N: Into<String>>(name: N) -> Self {
+        EnumOptionSettings {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Set the description of the option.
+    pub fn description<D: Into<String>>(mut self, description: D) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Set the values the option can take.
+    pub fn values<V: AsRef<str>>(mut self, values: &[V]) -> Self {
+        self.values = values.iter().map(|v| v.as_ref().to_owned()).collect();
+        self
+    }
+
+    /// Set the default value of the option, must be one of the values.
+    pub fn default_value<V: Into<String>>(mut self, value: V) -> Self {
+        self.default_value = value.into();
+        self
+    }
+
+    /// Set the current value of the option, defaults to the default value.
+    pub fn value<V: Into<String>>(mut self, value: V) -> Self {
+        self.value = Some(value.into());
+        self
+    }
+
+    /// Allow the option to be set to null.
+    pub fn null_allowed(mut self, null_allowed: bool) -> Self {
+        self.null_allowed = null_allowed;
+        self
+    }
+
+    /// Set a function that is called when the value of the option changes.
+    pub fn on_change(
+        mut self,
+        callback: impl FnMut(&Weechat, &EnumOption) + 'static,
+    ) -> Self {
+        self.change_cb = Some(Box::new(callback));
+        self
+    }
+
+    /// Set a function that is called when the option is deleted.
+    pub fn on_delete(
+        mut self,
+        callback: impl FnMut(&Weechat, &EnumOption) + 'static,
+    ) -> Self {
+        self.delete_cb = Some(Box::new(callback));
+        self
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), OptionSettingsError> {
+        check_name(&self.name)?;
+
+        if self.values.is_empty() {
+            return Err(OptionSettingsError::NoValues);
+        }
+
+        if let Some(value) =
+            self.values.iter().find(|v| v.is_empty() || v.contains('|'))
+        {
+            return Err(OptionSettingsError::InvalidValue(value.clone()));
+        }
+
+        let values =
+            std::iter::once(&self.default_value).chain(self.value.as_ref());
+
+        for value in values {
+            if !self.values.contains(value) {
+                return Err(OptionSettingsError::UnknownValue(value.clone()));
+            }
+        }
+
+        Ok(())
+    }
+}
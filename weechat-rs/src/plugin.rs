@@ -1,9 +1,96 @@
 use crate::{ArgsWeechat, Weechat};
-use libc::c_int;
+use std::error::Error;
+use std::fmt;
 
 pub trait WeechatPlugin: Sized {
     fn init(weechat: Weechat, args: ArgsWeechat) -> WeechatResult<Self>;
 }
 
-pub struct Error(c_int);
-pub type WeechatResult<T> = Result<T, Error>;
+/// Error that is returned if a plugin couldn't be initialized.
+///
+/// The error is printed on the core buffer when the `init` method of the
+/// plugin returns it. Most errors can be converted into a plugin error using
+/// the `?` operator.
+///
+/// # Example
+///
+/// ```ignore
+/// fn init(weechat: Weechat, _args: ArgsWeechat) -> WeechatResult<Self> {
+///     let config = weechat.config_new("myplugin", None, None)
+///         .ok_or_else(|| PluginError::new("can't create the config"))?;
+///     let servers = std::fs::read_to_string("servers.txt")?;
+///     // ...
+/// }
+/// ```
+#[derive(Debug)]
+pub struct PluginError {
+    message: String,
+    source: Option<Box<dyn Error + 'static>>,
+}
+
+impl PluginError {
+    /// Create a new plugin error with the given message.
+    pub fn new<M: Into<String>>(message: M) -> Self {
+        PluginError {
+            message: message.into(),
+            source: None,
+        }
+    }
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.source, self.message.is_empty()) {
+            (Some(source), _) => write!(f, "{}", source),
+            (None, true) => write!(f, "the plugin couldn't be initialized"),
+            (None, false) => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl Error for PluginError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.as_deref()
+    }
+}
+
+impl From<()> for PluginError {
+    fn from(_: ()) -> Self {
+        PluginError::new("")
+    }
+}
+
+impl From<String> for PluginError {
+    fn from(message: String) -> Self {
+        PluginError::new(message)
+    }
+}
+
+impl From<&str> for PluginError {
+    fn from(message: &str) -> Self {
+        PluginError::new(message)
+    }
+}
+
+impl From<std::io::Error> for PluginError {
+    fn from(error: std::io::Error) -> Self {
+        PluginError::from(Box::new(error) as Box<dyn Error>)
+    }
+}
+
+impl From<Box<dyn Error>> for PluginError {
+    fn from(error: Box<dyn Error>) -> Self {
+        PluginError {
+            message: String::new(),
+            source: Some(error),
+        }
+    }
+}
+
+impl From<Box<dyn Error + Send + Sync>> for PluginError {
+    fn from(error: Box<dyn Error + Send + Sync>) -> Self {
+        PluginError::from(error as Box<dyn Error>)
+    }
+}
+
+pub type WeechatResult<T> = Result<T, PluginError>;
@@ -0,0 +1,427 @@
+//! String utility module.
+//! Allows plugins to use the string matching, highlight detection,
+//! splitting, charset conversion and formatting functions of Weechat.
+
+use std::ffi::{CStr, CString};
+use std::fmt::Write;
+use std::ops::{BitOr, BitOrAssign};
+use std::ptr;
+
+use chrono::{DateTime, Local, Utc};
+use libc::{c_int, c_ulonglong};
+use weechat_sys::{
+    WEECHAT_STRING_SPLIT_COLLAPSE_SEPS, WEECHAT_STRING_SPLIT_KEEP_EOL,
+    WEECHAT_STRING_SPLIT_STRIP_LEFT, WEECHAT_STRING_SPLIT_STRIP_RIGHT,
+};
+
+use crate::{LossyCString, Weechat};
+
+/// The default of the `weechat.look.time_format` option.
+const DEFAULT_TIME_FORMAT: &str = "%a, %d %b %Y %T";
+
+/// Options controlling how a string is split.
+///
+/// Flags can be combined using the `|` operator.
+///
+/// # Example
+///
+/// ```ignore
+/// let flags = SplitFlags::STRIP_LEFT
+///     | SplitFlags::STRIP_RIGHT
+///     | SplitFlags::COLLAPSE_SEPARATORS;
+/// let words = weechat.split("  hello   world ", " ", flags);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SplitFlags(c_int);
+
+impl SplitFlags {
+    /// No options, every separator splits the string.
+    pub const NONE: SplitFlags = SplitFlags(0);
+    /// Strip separators at the beginning of the string.
+    pub const STRIP_LEFT: SplitFlags =
+        SplitFlags(WEECHAT_STRING_SPLIT_STRIP_LEFT as c_int);
+    /// Strip separators at the end of the string.
+    pub const STRIP_RIGHT: SplitFlags =
+        SplitFlags(WEECHAT_STRING_SPLIT_STRIP_RIGHT as c_int);
+    /// Treat multiple consecutive separators as a single one.
+    pub const COLLAPSE_SEPARATORS: SplitFlags =
+        SplitFlags(WEECHAT_STRING_SPLIT_COLLAPSE_SEPS as c_int);
+    /// Every item contains the rest of the string up to its end.
+    pub const KEEP_EOL: SplitFlags =
+        SplitFlags(WEECHAT_STRING_SPLIT_KEEP_EOL as c_int);
+
+    /// Check if all the given flags are set.
+    pub fn contains(&self, other: SplitFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for SplitFlags {
+    type Output = SplitFlags;
+
+    fn bitor(self, rhs: SplitFlags) -> SplitFlags {
+        SplitFlags(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for SplitFlags {
+    fn bitor_assign(&mut self, rhs: SplitFlags) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl Weechat {
+    /// Check if a string matches a mask.
+    ///
+    /// * `string` - The string to check.
+    /// * `mask` - The mask, it can contain wildcards (`*`) that match any
+    ///     number of characters, e.g. `"irc.*"`.
+    /// * `case_sensitive` - Should the comparison be case sensitive.
+    #[track_caller]
+    pub fn string_match(
+        &self,
+        string: &str,
+        mask: &str,
+        case_sensitive: bool,
+    ) -> bool {
+        let string_match = self.get().string_match.unwrap();
+
+        let string = LossyCString::new(string);
+        let mask = LossyCString::new(mask);
+
+        unsafe {
+            string_match(
+                string.as_ptr(),
+                mask.as_ptr(),
+                case_sensitive as c_int,
+            ) == 1
+        }
+    }
+
+    /// Check if a string contains one or more highlights.
+    ///
+    /// * `string` - The string to check.
+    /// * `highlight_words` - A comma separated list of highlight words, e.g.
+    ///     `"alice,bob"`.
+    #[track_caller]
+    pub fn has_highlight(&self, string: &str, highlight_words: &str) -> bool {
+        let string_has_highlight = self.get().string_has_highlight.unwrap();
+
+        let string = LossyCString::new(string);
+        let highlight_words = LossyCString::new(highlight_words);
+
+        unsafe {
+            string_has_highlight(string.as_ptr(), highlight_words.as_ptr()) == 1
+        }
+    }
+
+    /// Check if a string contains one or more highlights using a regular
+    /// expression.
+    ///
+    /// A highlight only matches if it is surrounded by delimiters, e.g. spaces
+    /// or punctuation.
+    ///
+    /// * `string` - The string to check.
+    /// * `regex` - A POSIX extended regular expression.
+    #[track_caller]
+    pub fn has_highlight_regex(&self, string: &str, regex: &str) -> bool {
+        let string_has_highlight_regex =
+            self.get().string_has_highlight_regex.unwrap();
+
+        let string = LossyCString::new(string);
+        let regex = LossyCString::new(regex);
+
+        unsafe {
+            string_has_highlight_regex(string.as_ptr(), regex.as_ptr()) == 1
+        }
+    }
+
+    /// Split a string into items.
+    ///
+    /// Returns an empty vector if the string is empty.
+    ///
+    /// * `string` - The string to split.
+    /// * `separators` - The characters that separate the items.
+    /// * `flags` - Options controlling how the string is split.
+    #[track_caller]
+    pub fn split(
+        &self,
+        string: &str,
+        separators: &str,
+        flags: SplitFlags,
+    ) -> Vec<String> {
+        let string_split = self.get().string_split.unwrap();
+        let string_free_split = self.get().string_free_split.unwrap();
+
+        let string = LossyCString::new(string);
+        let separators = LossyCString::new(separators);
+        let mut num_items: c_int = 0;
+
+        unsafe {
+            let items = string_split(
+                string.as_ptr(),
+                separators.as_ptr(),
+                ptr::null(),
+                flags.0,
+                0,
+                &mut num_items,
+            );
+
+            if items.is_null() {
+                return Vec::new();
+            }
+
+            let result = (0..num_items as isize)
+                .map(|i| {
+                    CStr::from_ptr(*items.offset(i))
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .collect();
+
+            string_free_split(items);
+
+            result
+        }
+    }
+
+    /// Convert a string from a charset into the internal UTF-8 encoding of
+    /// Weechat.
+    ///
+    /// Invalid sequences are converted the same way Weechat converts them,
+    /// null bytes are removed.
+    ///
+    /// * `charset` - The charset of the bytes, e.g. `"iso-8859-1"`.
+    /// * `bytes` - The bytes that should be decoded.
+    #[track_caller]
+    pub fn decode(&self, charset: &str, bytes: &[u8]) -> String {
+        let iconv_to_internal = self.get().iconv_to_internal.unwrap();
+
+        let charset = LossyCString::new(charset);
+        let bytes: Vec<u8> =
+            bytes.iter().copied().filter(|b| *b != 0).collect();
+        let string = CString::new(bytes).expect("bytes have no nulls");
+
+        unsafe {
+            let result = iconv_to_internal(charset.as_ptr(), string.as_ptr());
+
+            if result.is_null() {
+                string.to_string_lossy().into_owned()
+            } else {
+                let decoded = CStr::from_ptr(result).to_string_lossy().into();
+                libc::free(result as *mut libc::c_void);
+                decoded
+            }
+        }
+    }
+
+    /// Convert a string from the internal UTF-8 encoding of Weechat into a
+    /// charset.
+    ///
+    /// * `charset` - The charset the string should be encoded in, e.g.
+    ///     `"iso-8859-1"`.
+    /// * `string` - The string that should be encoded.
+    #[track_caller]
+    pub fn encode(&self, charset: &str, string: &str) -> Vec<u8> {
+        let iconv_from_internal = self.get().iconv_from_internal.unwrap();
+
+        let charset = LossyCString::new(charset);
+        let string = LossyCString::new(string);
+
+        unsafe {
+            let result = iconv_from_internal(charset.as_ptr(), string.as_ptr());
+
+            if result.is_null() {
+                string.into_bytes()
+            } else {
+                let encoded = CStr::from_ptr(result).to_bytes().to_vec();
+                libc::free(result as *mut libc::c_void);
+                encoded
+            }
+        }
+    }
+
+    /// Format a size in bytes into a human readable string, e.g. `"12.3 MB"`.
+    #[track_caller]
+    pub fn format_size(&self, bytes: u64) -> String {
+        let string_format_size = self.get().string_format_size.unwrap();
+
+        unsafe {
+            let result = string_format_size(bytes as c_ulonglong);
+
+            if result.is_null() {
+                format!("{} bytes", bytes)
+            } else {
+                let size = CStr::from_ptr(result).to_string_lossy().into();
+                libc::free(result as *mut libc::c_void);
+                size
+            }
+        }
+    }
+
+    /// Format a date using the local timezone.
+    ///
+    /// * `time` - The date that should be formatted.
+    /// * `format` - A `strftime` format string, if `None` the format of the
+    ///     `weechat.look.time_format` option is used. Invalid formats fall
+    ///     back to the default format of that option.
+    #[track_caller]
+    pub fn format_date(
+        &self,
+        time: DateTime<Utc>,
+        format: Option<&str>,
+    ) -> String {
+        let time = time.with_timezone(&Local);
+
+        let configured = match format {
+            Some(format) => Some(format.to_owned()),
+            None => self
+                .config_get("weechat.look.time_format")
+                .and_then(|option| option.string().map(|f| f.into_owned())),
+        };
+
+        let format = configured
+            .filter(|format| !format.is_empty())
+            .unwrap_or_else(|| DEFAULT_TIME_FORMAT.to_owned());
+
+        // Formatting fails instead of panicking if the format is invalid.
+        let mut date = String::new();
+
+        if write!(date, "{}", time.format(&format)).is_err() {
+            date.clear();
+            let _ = write!(date, "{}", time.format(DEFAULT_TIME_FORMAT));
+        }
+
+        date
+    }
+
+    /// Check if the input of a buffer is text that should be sent to the
+    /// buffer or a command.
+    ///
+    /// Returns `None` if the input is a command, otherwise the text that
+    /// should be sent, e.g. `"/text"` for the escaped input `"//text"`. The
+    /// command characters of the `weechat.look.command_chars` option are
+    /// taken into account.
+    ///
+    /// * `input` - The input of the buffer.
+    #[track_caller]
+    pub fn input_for_buffer<'a>(&self, input: &'a str) -> Option<&'a str> {
+        let string_input_for_buffer =
+            self.get().string_input_for_buffer.unwrap();
+
+        // Only the start of the input matters, cut it at the first null byte
+        // so the offsets in the C string match the offsets in the input.
+        let end = input.find('\0').unwrap_or(input.len());
+        let c_input =
+            CString::new(&input[..end]).expect("the input has no nulls");
+
+        let result = unsafe { string_input_for_buffer(c_input.as_ptr()) };
+
+        if result.is_null() {
+            None
+        } else {
+            let offset = result as usize - c_input.as_ptr() as usize;
+            input.get(offset..)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockWeechat;
+
+    #[test]
+    fn strings_are_matched_against_masks() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+
+        assert!(weechat.string_match("irc.libera.#rust", "irc.*", true));
+        assert!(weechat.string_match("irc.libera.#rust", "*#rust", true));
+        assert!(!weechat.string_match("irc.libera.#rust", "IRC.*", true));
+        assert!(weechat.string_match("irc.libera.#rust", "IRC.*", false));
+
+        assert!(weechat.string_match("", "*", true));
+        assert!(weechat.string_match("anything", "*", true));
+        assert!(!weechat.string_match("", "", true));
+        assert!(!weechat.string_match("", "a", true));
+
+        assert!(weechat.string_match("Grüße", "gr*ße", false));
+        assert!(weechat.string_match("日本語", "日*", true));
+        assert!(!weechat.string_match("日本語", "*日", true));
+    }
+
+    #[test]
+    fn strings_are_split() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let flags = SplitFlags::STRIP_LEFT
+            | SplitFlags::STRIP_RIGHT
+            | SplitFlags::COLLAPSE_SEPARATORS;
+
+        assert_eq!(
+            weechat.split("  hello   world ", " ", flags),
+            vec!["hello", "world"]
+        );
+        assert_eq!(
+            weechat.split("a,,b", ",", SplitFlags::NONE),
+            vec!["a", "", "b"]
+        );
+        assert_eq!(
+            weechat.split("one two three", " ", SplitFlags::KEEP_EOL),
+            vec!["one two three", "two three", "three"]
+        );
+
+        assert!(weechat.split("", " ", flags).is_empty());
+        assert!(weechat.split("   ", " ", flags).is_empty());
+
+        assert_eq!(
+            weechat.split("größe·日本語·ok", "·", SplitFlags::NONE),
+            vec!["größe", "日本語", "ok"]
+        );
+
+        assert_eq!(mock.split_strings(), 0);
+        assert_eq!(mock.invalid_accesses(), 0);
+    }
+
+    #[test]
+    fn latin1_round_trips() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let latin1 = [b'c', b'a', b'f', 0xE9, b' ', 0xFC, b'b', b'e', b'r'];
+
+        assert_eq!(weechat.decode("iso-8859-1", &latin1), "café über");
+        assert_eq!(weechat.encode("iso-8859-1", "café über"), latin1);
+        assert_eq!(
+            weechat
+                .encode("iso-8859-1", &weechat.decode("iso-8859-1", &latin1)),
+            latin1
+        );
+
+        // Null bytes are removed before the string is decoded.
+        assert_eq!(weechat.decode("iso-8859-1", b"caf\0\xE9"), "café");
+        // Characters that don't exist in the charset are replaced.
+        assert_eq!(weechat.encode("iso-8859-1", "日本"), b"??");
+    }
+
+    #[test]
+    fn commands_are_told_apart_from_text() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+
+        assert_eq!(weechat.input_for_buffer("/join #rust"), None);
+        assert_eq!(weechat.input_for_buffer("/"), None);
+        assert_eq!(weechat.input_for_buffer("//text"), Some("/text"));
+        assert_eq!(weechat.input_for_buffer("hello"), Some("hello"));
+        assert_eq!(weechat.input_for_buffer(""), Some(""));
+        assert_eq!(weechat.input_for_buffer("/tmp/file"), Some("/tmp/file"));
+        assert_eq!(weechat.input_for_buffer("héllo /me"), Some("héllo /me"));
+
+        // The text is a slice of the input, including the part after a null
+        // byte that Weechat never sees.
+        let input = "//a\0b";
+        let text = weechat.input_for_buffer(input).unwrap();
+        assert_eq!(text, "/a\0b");
+        assert_eq!(text.as_ptr(), input[1..].as_ptr());
+    }
+}
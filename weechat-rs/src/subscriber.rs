@@ -0,0 +1,693 @@
+//! Tracing module.
+//! Provides a `tracing` subscriber that writes events to a debug buffer of
+//! the plugin and to the Weechat log file.
+//!
+//! The verbosity can be changed at runtime using the
+//! `plugins.var.<plugin>.tracing_filter` option. The option contains a comma
+//! separated list of directives, e.g. `"warn,myplugin::net=trace"`. A
+//! directive without a target sets the default level, otherwise the
+//! directive with the longest matching target decides the level of an
+//! event. Events up to the info level are written if the option isn't set.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let _filter_hook = weechat::subscriber::init(&weechat)?;
+//!
+//! tracing::info!(server = "example.org", "Connected");
+//! ```
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::fmt::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::thread::{self, ThreadId};
+
+use tracing_core::dispatcher::{self, Dispatch, SetGlobalDefaultError};
+use tracing_core::field::{Field, Visit};
+use tracing_core::span::{Attributes, Id, Record};
+use tracing_core::subscriber::{Interest, Subscriber};
+use tracing_core::{Event, Level, LevelFilter, Metadata};
+use weechat_sys::t_weechat_plugin;
+
+use crate::channel::{ChannelCapacity, ChannelError, MainSender};
+use crate::hooks::{ConfigHook, HookError};
+use crate::{Buffer, Weechat};
+
+/// The name of the plugin option that contains the filter.
+const FILTER_OPTION: &str = "tracing_filter";
+
+/// The name of the buffer that events are printed on.
+const BUFFER_NAME: &str = "debug";
+
+thread_local! {
+    /// The spans that were entered on this thread, the innermost span is
+    /// the last one.
+    static SPAN_STACK: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// The verbosity of events per target.
+#[derive(Debug, Clone)]
+struct Filter {
+    default: LevelFilter,
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter {
+            default: LevelFilter::INFO,
+            targets: Vec::new(),
+        }
+    }
+}
+
+impl Filter {
+    /// Parse the directives of a filter, invalid directives are ignored.
+    fn parse(filter: &str) -> Filter {
+        let mut parsed = Filter::default();
+
+        let directives =
+            filter.split(',').map(str::trim).filter(|d| !d.is_empty());
+
+        for directive in directives {
+            match directive.split_once('=') {
+                Some((target, level)) => {
+                    if let Ok(level) = level.trim().parse() {
+                        parsed.targets.push((target.trim().to_owned(), level));
+                    }
+                }
+                None => {
+                    if let Ok(level) = directive.parse() {
+                        parsed.default = level;
+                    }
+                }
+            }
+        }
+
+        // Longer targets are more specific, they need to be checked first.
+        parsed
+            .targets
+            .sort_by_key(|(target, _)| Reverse(target.len()));
+
+        parsed
+    }
+
+    /// Get the most verbose level that is enabled for the given target.
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.targets
+            .iter()
+            .find(|(prefix, _)| target_matches(target, prefix))
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        *metadata.level() <= self.level_for(metadata.target())
+    }
+}
+
+/// A target matches a directive if it's the target of the directive or a
+/// module below it.
+fn target_matches(target: &str, prefix: &str) -> bool {
+    match target.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with("::"),
+        None => false,
+    }
+}
+
+type SharedFilter = Arc<RwLock<Filter>>;
+
+/// Collects the message and the other fields of an event or a span.
+#[derive(Default)]
+struct Fields {
+    message: String,
+    fields: String,
+}
+
+impl Fields {
+    fn separate(&mut self) {
+        if !self.fields.is_empty() {
+            self.fields.push(' ');
+        }
+    }
+}
+
+impl Visit for Fields {
+    #[track_caller]
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            self.separate();
+            let _ = write!(self.fields, "{}={}", field.name(), value);
+        }
+    }
+
+    #[track_caller]
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            self.separate();
+            let _ = write!(self.fields, "{}={:?}", field.name(), value);
+        }
+    }
+}
+
+struct SpanData {
+    name: &'static str,
+    fields: String,
+    references: usize,
+}
+
+/// An event that is written out on the main thread.
+struct QueuedEvent {
+    level: Level,
+    target: String,
+    message: String,
+}
+
+#[track_caller]
+fn plugin_name(weechat: &Weechat) -> String {
+    unsafe {
+        CStr::from_ptr(weechat.get().name)
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+/// Get the debug buffer of the plugin, the buffer is created if it doesn't
+/// exist yet or if it was closed.
+#[track_caller]
+fn debug_buffer(weechat: &Weechat) -> Buffer {
+    let plugin_name = plugin_name(weechat);
+
+    match weechat.buffer_search(&plugin_name, BUFFER_NAME) {
+        Some(buffer) => buffer,
+        None => {
+            let buffer = weechat.buffer_new::<(), ()>(
+                BUFFER_NAME,
+                None,
+                None,
+                None,
+                None,
+            );
+            buffer.set_title(&format!("{} debug", plugin_name));
+            buffer
+        }
+    }
+}
+
+#[track_caller]
+fn write_event(weechat: &Weechat, event: &QueuedEvent) {
+    if event.level == Level::TRACE || event.level == Level::DEBUG {
+        weechat.log(&format!(
+            "{} {}: {}",
+            event.level, event.target, event.message
+        ));
+        return;
+    }
+
+    let color = if event.level == Level::ERROR {
+        "red"
+    } else if event.level == Level::WARN {
+        "yellow"
+    } else {
+        "green"
+    };
+
+    debug_buffer(weechat).print(&format!(
+        "{}{}{}\t{}: {}",
+        weechat.color(color),
+        event.level,
+        weechat.color("reset"),
+        event.target,
+        event.message
+    ));
+}
+
+/// A `tracing` subscriber for Weechat.
+///
+/// Trace and debug events are written to the Weechat log file, all other
+/// events are printed on the debug buffer of the plugin. The buffer is
+/// created once the first event is printed and again if it was closed.
+///
+/// The Weechat API may only be used on the main thread, events that are
+/// emitted on other threads are sent to the main thread using a main thread
+/// channel.
+///
+/// The subscriber is installed using [`init`].
+pub struct WeechatSubscriber {
+    weechat_ptr: *mut t_weechat_plugin,
+    main_thread: ThreadId,
+    filter: SharedFilter,
+    spans: Mutex<HashMap<u64, SpanData>>,
+    next_id: AtomicU64,
+    sender: MainSender<QueuedEvent>,
+}
+
+// The Weechat pointer is only ever dereferenced on the main thread, events
+// emitted on other threads only touch the channel.
+unsafe impl Send for WeechatSubscriber {}
+unsafe impl Sync for WeechatSubscriber {}
+
+/// Errors that can happen while the subscriber is installed.
+#[derive(Debug)]
+pub enum InitError {
+    /// A global default subscriber was already installed.
+    SetGlobalDefault(SetGlobalDefaultError),
+    /// The filter option couldn't be hooked.
+    Hook(HookError),
+    /// The channel for events of other threads couldn't be created.
+    Channel(ChannelError),
+}
+
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InitError::SetGlobalDefault(e) => e.fmt(f),
+            InitError::Hook(e) => {
+                write!(f, "can't hook the tracing filter option: {}", e)
+            }
+            InitError::Channel(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for InitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            InitError::SetGlobalDefault(e) => Some(e),
+            InitError::Hook(e) => Some(e),
+            InitError::Channel(e) => Some(e),
+        }
+    }
+}
+
+impl From<SetGlobalDefaultError> for InitError {
+    fn from(error: SetGlobalDefaultError) -> Self {
+        InitError::SetGlobalDefault(error)
+    }
+}
+
+impl From<HookError> for InitError {
+    fn from(error: HookError) -> Self {
+        InitError::Hook(error)
+    }
+}
+
+impl From<ChannelError> for InitError {
+    fn from(error: ChannelError) -> Self {
+        InitError::Channel(error)
+    }
+}
+
+/// A hook that updates the filter of the subscriber when the
+/// `plugins.var.<plugin>.tracing_filter` option changes.
+pub struct FilterHook {
+    _hook: ConfigHook<SharedFilter>,
+}
+
+/// Install the Weechat subscriber as the global default subscriber.
+///
+/// This needs to be called on the main thread, e.g. in the `init` function of
+/// the plugin.
+///
+/// Returns a hook that re-reads the filter when the filter option changes,
+/// changes are only picked up as long as the hook is kept around. An error is
+/// returned if a global default subscriber was already installed or if the
+/// filter option can't be hooked, the subscriber isn't installed in that
+/// case.
+///
+/// * `weechat` - The Weechat object of the plugin.
+#[track_caller]
+pub fn init(weechat: &Weechat) -> Result<FilterHook, InitError> {
+    // Everything that can fail is set up before the subscriber is installed,
+    // a subscriber can't be uninstalled again.
+    let (subscriber, hook) = WeechatSubscriber::new(weechat)?;
+
+    dispatcher::set_global_default(Dispatch::new(subscriber))?;
+
+    Ok(hook)
+}
+
+impl WeechatSubscriber {
+    #[track_caller]
+    fn new(weechat: &Weechat) -> Result<(Self, FilterHook), InitError> {
+        fn filter_cb(
+            filter: &SharedFilter,
+            _weechat: &Weechat,
+            _option: Cow<str>,
+            value: Cow<str>,
+        ) {
+            let parsed = Filter::parse(&value);
+
+            match filter.write() {
+                Ok(mut filter) => *filter = parsed,
+                Err(poisoned) => *poisoned.into_inner() = parsed,
+            }
+        }
+
+        let filter = Filter::parse(
+            &weechat.plugin_option(FILTER_OPTION).unwrap_or_default(),
+        );
+        let filter = Arc::new(RwLock::new(filter));
+
+        let option =
+            format!("plugins.var.{}.{}", plugin_name(weechat), FILTER_OPTION);
+        let hook =
+            weechat.hook_config(&option, filter_cb, Some(filter.clone()))?;
+
+        let sender = weechat.main_channel(
+            ChannelCapacity::Unbounded,
+            |weechat, event: QueuedEvent| write_event(weechat, &event),
+        )?;
+
+        let subscriber = WeechatSubscriber {
+            weechat_ptr: weechat.ptr,
+            main_thread: thread::current().id(),
+            filter,
+            spans: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            sender,
+        };
+
+        Ok((subscriber, FilterHook { _hook: hook }))
+    }
+
+    fn is_main_thread(&self) -> bool {
+        thread::current().id() == self.main_thread
+    }
+
+    /// Get the names and fields of the spans that were entered on the
+    /// current thread.
+    fn span_context(&self) -> String {
+        let spans = lock(&self.spans);
+
+        SPAN_STACK.with(|stack| {
+            let mut context = String::new();
+
+            for span in stack.borrow().iter().filter_map(|id| spans.get(id)) {
+                context.push_str(span.name);
+
+                if !span.fields.is_empty() {
+                    let _ = write!(context, "{{{}}}", span.fields);
+                }
+
+                context.push_str(": ");
+            }
+
+            context
+        })
+    }
+}
+
+impl Subscriber for WeechatSubscriber {
+    fn register_callsite(&self, _: &'static Metadata<'static>) -> Interest {
+        // The filter can change at runtime, so the interest can't be cached.
+        Interest::sometimes()
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        match self.filter.read() {
+            Ok(filter) => filter.enabled(metadata),
+            Err(poisoned) => poisoned.into_inner().enabled(metadata),
+        }
+    }
+
+    #[track_caller]
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut fields = Fields::default();
+        span.record(&mut fields);
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        lock(&self.spans).insert(
+            id,
+            SpanData {
+                name: span.metadata().name(),
+                fields: fields.fields,
+                references: 1,
+            },
+        );
+
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        // Record the values before locking, formatting them might emit
+        // events as well.
+        let mut fields = Fields::default();
+        values.record(&mut fields);
+
+        if let Some(span) = lock(&self.spans).get_mut(&span.into_u64()) {
+            if !span.fields.is_empty() && !fields.fields.is_empty() {
+                span.fields.push(' ');
+            }
+
+            span.fields.push_str(&fields.fields);
+        }
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    #[track_caller]
+    fn event(&self, event: &Event<'_>) {
+        let metadata = event.metadata();
+
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+
+        let mut message = self.span_context();
+        message.push_str(&fields.message);
+
+        if !fields.fields.is_empty() {
+            if !fields.message.is_empty() {
+                message.push(' ');
+            }
+
+            message.push_str(&fields.fields);
+        }
+
+        let event = QueuedEvent {
+            level: *metadata.level(),
+            target: metadata.target().to_owned(),
+            message,
+        };
+
+        if self.is_main_thread() {
+            write_event(&Weechat::from_ptr(self.weechat_ptr), &event);
+        } else {
+            // The channel is closed once the plugin is unloaded, events that
+            // are emitted after that are dropped.
+            let _ = self.sender.send(event);
+        }
+    }
+
+    fn enter(&self, span: &Id) {
+        SPAN_STACK.with(|stack| stack.borrow_mut().push(span.into_u64()));
+    }
+
+    fn exit(&self, span: &Id) {
+        let id = span.into_u64();
+
+        SPAN_STACK.with(|stack| {
+            let mut stack = stack.borrow_mut();
+
+            if let Some(position) = stack.iter().rposition(|s| *s == id) {
+                stack.remove(position);
+            }
+        });
+    }
+
+    fn clone_span(&self, span: &Id) -> Id {
+        if let Some(span) = lock(&self.spans).get_mut(&span.into_u64()) {
+            span.references += 1;
+        }
+
+        span.clone()
+    }
+
+    fn try_close(&self, span: Id) -> bool {
+        let id = span.into_u64();
+        let mut spans = lock(&self.spans);
+
+        let closed = match spans.get_mut(&id) {
+            Some(span) => {
+                span.references -= 1;
+                span.references == 0
+            }
+            None => false,
+        };
+
+        if closed {
+            spans.remove(&id);
+        }
+
+        closed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockWeechat;
+
+    /// Create a subscriber that the tests set as the default of a thread, a
+    /// global default can only be installed once per process.
+    fn new_dispatch(weechat: &Weechat) -> (Dispatch, FilterHook) {
+        let (subscriber, hook) = WeechatSubscriber::new(weechat).unwrap();
+        (Dispatch::new(subscriber), hook)
+    }
+
+    fn debug_lines(mock: &MockWeechat, weechat: &Weechat) -> Vec<String> {
+        let buffer = match weechat.buffer_search("mock", BUFFER_NAME) {
+            Some(buffer) => buffer,
+            None => return Vec::new(),
+        };
+
+        mock.lines()
+            .into_iter()
+            .filter(|l| l.buffer == buffer.ptr)
+            .map(|l| l.message)
+            .collect()
+    }
+
+    #[test]
+    fn events_are_printed_on_the_debug_buffer() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let (dispatch, _hook) = new_dispatch(&weechat);
+
+        dispatcher::with_default(&dispatch, || {
+            tracing::info!(target: "myplugin", "Connected");
+            tracing::warn!(target: "myplugin::net", "Slow server");
+            tracing::debug!(target: "myplugin", "Hidden");
+        });
+
+        assert_eq!(
+            debug_lines(&mock, &weechat),
+            [
+                "INFO\tmyplugin: Connected",
+                "WARN\tmyplugin::net: Slow server"
+            ]
+        );
+        assert!(mock.logs().is_empty());
+    }
+
+    #[test]
+    fn filter_changes_take_effect() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let (dispatch, _hook) = new_dispatch(&weechat);
+
+        weechat.set_plugin_option(FILTER_OPTION, "warn,myplugin=debug");
+
+        dispatcher::with_default(&dispatch, || {
+            tracing::debug!(target: "myplugin", "Joined");
+            tracing::info!(target: "other", "Hidden");
+        });
+
+        assert_eq!(mock.logs(), ["DEBUG myplugin: Joined"]);
+        assert!(debug_lines(&mock, &weechat).is_empty());
+    }
+
+    #[test]
+    fn events_of_other_threads_are_sent_to_the_main_thread() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        let (dispatch, _hook) = new_dispatch(&weechat);
+
+        thread::spawn(move || {
+            dispatcher::with_default(&dispatch, || {
+                tracing::info!(target: "myplugin", "From a thread");
+            });
+        })
+        .join()
+        .unwrap();
+
+        assert!(debug_lines(&mock, &weechat).is_empty());
+
+        mock.run_fd_hooks();
+
+        assert_eq!(
+            debug_lines(&mock, &weechat),
+            ["INFO\tmyplugin: From a thread"]
+        );
+        assert_eq!(mock.invalid_accesses(), 0);
+    }
+
+    #[test]
+    fn targets_match_themselves_and_their_modules() {
+        assert!(target_matches("myplugin", "myplugin"));
+        assert!(target_matches("myplugin::net", "myplugin"));
+        assert!(target_matches("myplugin::net::tls", "myplugin::net"));
+
+        assert!(!target_matches("myplugin_extra", "myplugin"));
+        assert!(!target_matches("myplugin", "myplugin::net"));
+        assert!(!target_matches("other::myplugin", "myplugin"));
+    }
+
+    #[test]
+    fn empty_filter_enables_info() {
+        let filter = Filter::parse("");
+
+        assert_eq!(filter.level_for("myplugin"), LevelFilter::INFO);
+        assert!(filter.targets.is_empty());
+    }
+
+    #[test]
+    fn filter_directives_are_parsed() {
+        let filter =
+            Filter::parse(" warn , myplugin=debug,myplugin::net=trace");
+
+        assert_eq!(filter.default, LevelFilter::WARN);
+        assert_eq!(filter.level_for("other"), LevelFilter::WARN);
+        assert_eq!(filter.level_for("myplugin"), LevelFilter::DEBUG);
+        assert_eq!(filter.level_for("myplugin::buffer"), LevelFilter::DEBUG);
+        assert_eq!(filter.level_for("myplugin::net"), LevelFilter::TRACE);
+        assert_eq!(filter.level_for("myplugin::net::tls"), LevelFilter::TRACE);
+    }
+
+    #[test]
+    fn longest_target_wins_independent_of_order() {
+        let filter = Filter::parse("myplugin::net=off,myplugin=trace");
+
+        assert_eq!(filter.level_for("myplugin::net"), LevelFilter::OFF);
+        assert_eq!(filter.level_for("myplugin"), LevelFilter::TRACE);
+    }
+
+    #[test]
+    fn invalid_directives_are_ignored() {
+        let filter = Filter::parse("loud,myplugin=verbose,,net=error");
+
+        assert_eq!(filter.default, LevelFilter::INFO);
+        assert_eq!(filter.level_for("myplugin"), LevelFilter::INFO);
+        assert_eq!(filter.level_for("net"), LevelFilter::ERROR);
+    }
+
+    #[test]
+    fn levels_are_compared_against_the_filter() {
+        let filter = Filter::parse("info,myplugin=off");
+
+        let level = filter.level_for("other");
+        assert!(Level::ERROR <= level);
+        assert!(Level::INFO <= level);
+        assert!(!(Level::DEBUG <= level));
+
+        let level = filter.level_for("myplugin");
+        assert!(!(Level::ERROR <= level));
+    }
+}
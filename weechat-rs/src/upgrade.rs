@@ -0,0 +1,243 @@
+//! Upgrade file module.
+//! Allows plugins to keep their state across a `/upgrade` of Weechat.
+//!
+//! Before Weechat upgrades itself the `upgrade` signal is sent, plugins
+//! should then save their state as infolists into an upgrade file. After the
+//! upgrade the plugin is loaded again, `Weechat::is_upgrading()` returns
+//! `true` while the plugin is initialized and the state can be restored by
+//! reading the upgrade file.
+//!
+//! # Example
+//!
+//! A plugin that keeps a counter across upgrades:
+//!
+//! ```ignore
+//! use std::cell::Cell;
+//! use std::rc::Rc;
+//! use weechat::{
+//!     weechat_plugin, ArgsWeechat, ReturnCode, SignalHook, SignalHookValue,
+//!     Weechat, WeechatPlugin, WeechatResult,
+//! };
+//!
+//! const COUNTER_ID: i32 = 1;
+//!
+//! struct Counter {
+//!     _upgrade_hook: SignalHook<Rc<Cell<i32>>>,
+//! }
+//!
+//! impl Counter {
+//!     fn upgrade_cb(
+//!         counter: &Rc<Cell<i32>>,
+//!         weechat: &Weechat,
+//!         _value: SignalHookValue,
+//!     ) -> ReturnCode {
+//!         let mut builder = match weechat.infolist_new() {
+//!             Some(builder) => builder,
+//!             None => return ReturnCode::Error,
+//!         };
+//!
+//!         if let Some(mut item) = builder.new_item() {
+//!             item.add_integer("counter", counter.get());
+//!         }
+//!
+//!         let infolist = builder.build();
+//!
+//!         match weechat.upgrade_file("counter") {
+//!             Some(file) if file.write_object(COUNTER_ID, &infolist) => {
+//!                 ReturnCode::Ok
+//!             }
+//!             _ => ReturnCode::Error,
+//!         }
+//!     }
+//! }
+//!
+//! impl WeechatPlugin for Counter {
+//!     fn init(weechat: Weechat, _args: ArgsWeechat) -> WeechatResult<Self> {
+//!         let counter = Rc::new(Cell::new(0));
+//!
+//!         if weechat.is_upgrading() {
+//!             weechat.upgrade_read("counter", |_, object_id, infolist| {
+//!                 if object_id != COUNTER_ID {
+//!                     return;
+//!                 }
+//!
+//!                 if let Some(item) = infolist.next_item() {
+//!                     counter.set(item.integer("counter").unwrap_or(0));
+//!                 }
+//!             });
+//!         }
+//!
+//!         counter.set(counter.get() + 1);
+//!         weechat.print(&format!("Loaded {} times", counter.get()));
+//!
+//!         let hook = weechat
+//!             .hook_signal("upgrade", None, Counter::upgrade_cb, Some(counter))
+//!             .expect("Can't hook the upgrade signal");
+//!
+//!         Ok(Counter {
+//!             _upgrade_hook: hook,
+//!         })
+//!     }
+//! }
+//!
+//! weechat_plugin!(
+//!     Counter,
+//!     name: "counter",
+//!     author: "Author",
+//!     description: "Counts how often it was loaded",
+//!     version: "0.1",
+//!     license: "MIT"
+//! );
+//! ```
+
+use std::mem::ManuallyDrop;
+use std::os::raw::c_void;
+use std::ptr;
+
+use libc::c_int;
+use weechat_sys::{t_infolist, t_upgrade_file, t_weechat_plugin};
+
+use crate::{Infolist, LossyCString, ReturnCode, Weechat};
+
+/// An upgrade file that is open for writing, the file is closed when the
+/// object is dropped.
+pub struct UpgradeFile {
+    ptr: *mut t_upgrade_file,
+    weechat_ptr: *mut t_weechat_plugin,
+}
+
+impl Drop for UpgradeFile {
+    fn drop(&mut self) {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let upgrade_close = weechat.get().upgrade_close.unwrap();
+
+        unsafe { upgrade_close(self.ptr) };
+    }
+}
+
+impl UpgradeFile {
+    /// Write an object into the upgrade file.
+    ///
+    /// Returns `true` if the object was written, `false` otherwise.
+    ///
+    /// * `object_id` - An id for the object, the id is passed to the read
+    ///     callback so different kinds of objects can be told apart.
+    /// * `infolist` - The infolist containing the state of the object.
+    #[track_caller]
+    pub fn write_object(&self, object_id: i32, infolist: &Infolist) -> bool {
+        let weechat = Weechat::from_ptr(self.weechat_ptr);
+        let upgrade_write_object = weechat.get().upgrade_write_object.unwrap();
+
+        unsafe { upgrade_write_object(self.ptr, object_id, infolist.ptr) == 1 }
+    }
+}
+
+impl Weechat {
+    /// Is Weechat being upgraded, this is the case while plugins are loaded
+    /// again after a `/upgrade`.
+    #[track_caller]
+    pub fn is_upgrading(&self) -> bool {
+        self.info_get("weechat_upgrading", "").as_deref() == Some("1")
+    }
+
+    /// Create an upgrade file that will be written.
+    ///
+    /// Returns `None` if the file couldn't be created.
+    ///
+    /// * `name` - The name of the upgrade file, the file will be stored in
+    ///     the Weechat data directory.
+    #[track_caller]
+    pub fn upgrade_file(&self, name: &str) -> Option<UpgradeFile> {
+        let upgrade_new = self.get().upgrade_new.unwrap();
+
+        let name = LossyCString::new(name);
+
+        let ptr = unsafe {
+            upgrade_new(name.as_ptr(), None, ptr::null(), ptr::null_mut())
+        };
+
+        if ptr.is_null() {
+            None
+        } else {
+            Some(UpgradeFile {
+                ptr,
+                weechat_ptr: self.ptr,
+            })
+        }
+    }
+
+    /// Read an upgrade file.
+    ///
+    /// The callback is called for every object that was written into the
+    /// upgrade file, it receives the id of the object and the infolist of
+    /// the object. Returns `true` if the file was read, `false` otherwise.
+    ///
+    /// * `name` - The name of the upgrade file.
+    /// * `callback` - The function that will be called for every object.
+    #[track_caller]
+    pub fn upgrade_read(
+        &self,
+        name: &str,
+        mut callback: impl FnMut(&Weechat, i32, &mut Infolist),
+    ) -> bool {
+        struct ReadData<'a> {
+            callback: &'a mut dyn FnMut(&Weechat, i32, &mut Infolist),
+            weechat_ptr: *mut t_weechat_plugin,
+        }
+
+        unsafe extern "C" fn c_read_cb(
+            pointer: *const c_void,
+            _data: *mut c_void,
+            _upgrade_file: *mut t_upgrade_file,
+            object_id: c_int,
+            infolist: *mut t_infolist,
+        ) -> c_int {
+            let data: &mut ReadData = { &mut *(pointer as *mut ReadData) };
+            let weechat = Weechat::from_ptr(data.weechat_ptr);
+
+            // The infolist is owned by Weechat, it's freed after the callback
+            // returns.
+            let mut infolist = ManuallyDrop::new(Infolist {
+                ptr: infolist,
+                weechat_ptr: data.weechat_ptr,
+            });
+
+            let callback = &mut data.callback;
+
+            crate::catch_panic(data.weechat_ptr, || {
+                callback(&weechat, object_id, &mut infolist)
+            });
+
+            ReturnCode::Ok.into_raw()
+        }
+
+        let upgrade_new = self.get().upgrade_new.unwrap();
+        let upgrade_read = self.get().upgrade_read.unwrap();
+        let upgrade_close = self.get().upgrade_close.unwrap();
+
+        let mut data = ReadData {
+            callback: &mut callback,
+            weechat_ptr: self.ptr,
+        };
+
+        let name = LossyCString::new(name);
+
+        unsafe {
+            let file = upgrade_new(
+                name.as_ptr(),
+                Some(c_read_cb),
+                &mut data as *mut ReadData as *const c_void,
+                ptr::null_mut(),
+            );
+
+            if file.is_null() {
+                return false;
+            }
+
+            let ret = upgrade_read(file);
+            upgrade_close(file);
+
+            ret == 1
+        }
+    }
+}
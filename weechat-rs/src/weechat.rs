@@ -2,43 +2,115 @@
 
 use weechat_sys::t_weechat_plugin;
 
-use crate::{ConfigOption, LossyCString, StringOption};
+use crate::hashtable::Hashtable;
+use crate::{
+    Buffer, ConfigOption, HashtableItemType, LossyCString, ReturnCode,
+    StringOption,
+};
 use libc::{c_char, c_int};
 use std::borrow::Cow;
-use std::ffi::CStr;
+#[cfg(all(feature = "thread-checks", debug_assertions))]
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::ffi::{CStr, OsString};
+use std::io;
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+#[cfg(all(feature = "thread-checks", debug_assertions))]
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::{ptr, vec};
 
-/// An iterator over the arguments of a command, yielding a String value for
-/// each argument.
+/// Set once the plugin was initialized, from then on the Weechat API may
+/// only be used on the main thread.
+#[cfg(all(feature = "thread-checks", debug_assertions))]
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(all(feature = "thread-checks", debug_assertions))]
+thread_local! {
+    /// Set on the thread the plugin was initialized on.
+    static IS_MAIN_THREAD: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Panic if the Weechat API is used outside of the main thread.
+///
+/// The panic location points to the code that called the Weechat API.
+#[cfg(all(feature = "thread-checks", debug_assertions))]
+#[track_caller]
+fn assert_main_thread() {
+    if INITIALIZED.load(Ordering::Relaxed) && !IS_MAIN_THREAD.with(Cell::get) {
+        panic!(
+            "The Weechat API was used outside of the main thread at {}",
+            std::panic::Location::caller()
+        );
+    }
+}
+
+/// An iterator over the arguments that were passed to the plugin, yielding
+/// a String value for each argument.
+///
+/// The arguments are the plugin arguments of the Weechat command line, e.g.
+/// `--no-connect`, `--upgrade`, or the arguments of a `/plugin load`
+/// command. Unlike the arguments of a program they don't start with the name
+/// of an executable, argument parsers like `clap` expect one so it needs to
+/// be prepended.
+///
+/// # Example
+///
+/// ```ignore
+/// fn init(weechat: Weechat, args: ArgsWeechat) -> WeechatResult<Self> {
+///     let matches = App::new("myplugin")
+///         .arg(Arg::with_name("no-connect").long("no-connect"))
+///         .get_matches_from_safe(std::iter::once("myplugin".into()).chain(args.args_os()))
+///         .map_err(|_| ())?;
+///     // ...
+/// }
+/// ```
 pub struct ArgsWeechat {
-    iter: vec::IntoIter<String>,
+    iter: vec::IntoIter<OsString>,
 }
 
 impl ArgsWeechat {
     /// Create an ArgsWeechat object from the underlying weechat C types.
-    /// Expects the strings in argv to be valid utf8, if not invalid UTF-8
-    /// sequences are replaced with the replacement character.
+    /// Invalid UTF-8 sequences in the arguments are replaced with the
+    /// replacement character when the arguments are yielded as strings.
     pub fn new(argc: c_int, argv: *mut *mut c_char) -> ArgsWeechat {
-        let argc = argc as isize;
-        let args: Vec<String> = (0..argc)
+        let args: Vec<OsString> = (0..argc as isize)
             .map(|i| {
                 let cstr = unsafe {
                     CStr::from_ptr(*argv.offset(i) as *const libc::c_char)
                 };
 
-                String::from_utf8_lossy(&cstr.to_bytes().to_vec()).to_string()
+                OsString::from_vec(cstr.to_bytes().to_vec())
             })
             .collect();
+
         ArgsWeechat {
-            iter: args.clone().into_iter(),
+            iter: args.into_iter(),
         }
     }
+
+    /// Convert the remaining arguments into an iterator that yields the
+    /// arguments unchanged, without replacing invalid UTF-8 sequences.
+    pub fn args_os(self) -> vec::IntoIter<OsString> {
+        self.iter
+    }
+
+    /// Get the remaining arguments as a vector of strings.
+    pub fn to_vec(&self) -> Vec<String> {
+        self.iter
+            .as_slice()
+            .iter()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
 }
 
 impl Iterator for ArgsWeechat {
     type Item = String;
     fn next(&mut self) -> Option<String> {
-        self.iter.next()
+        self.iter
+            .next()
+            .map(|arg| arg.to_string_lossy().into_owned())
     }
     fn size_hint(&self) -> (usize, Option<usize>) {
         self.iter.size_hint()
@@ -53,11 +125,76 @@ impl ExactSizeIterator for ArgsWeechat {
 
 impl DoubleEndedIterator for ArgsWeechat {
     fn next_back(&mut self) -> Option<String> {
-        self.iter.next_back()
+        self.iter
+            .next_back()
+            .map(|arg| arg.to_string_lossy().into_owned())
+    }
+}
+
+/// A Weechat version.
+///
+/// Versions are ordered, which allows checking for features that were added
+/// in a specific version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct WeechatVersion {
+    /// The major version number.
+    pub major: u32,
+    /// The minor version number.
+    pub minor: u32,
+    /// The patch version number.
+    pub patch: u32,
+}
+
+impl WeechatVersion {
+    /// Create a new version.
+    pub fn new(major: u32, minor: u32, patch: u32) -> Self {
+        WeechatVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Create a version from its numeric form, e.g. `0x03020100` for
+    /// version 3.2.1.
+    pub fn from_number(number: u32) -> Self {
+        WeechatVersion::new(
+            number >> 24,
+            (number >> 16) & 0xff,
+            (number >> 8) & 0xff,
+        )
     }
 }
 
+impl std::fmt::Display for WeechatVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Error returned when a feature requires a newer version of Weechat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnsupportedVersion {
+    /// The version of Weechat that is required.
+    pub required: WeechatVersion,
+    /// The version of Weechat that is running.
+    pub running: WeechatVersion,
+}
+
+impl std::fmt::Display for UnsupportedVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Weechat {} is required, but Weechat {} is running",
+            self.required, self.running
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedVersion {}
+
 /// Status for updating options
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OptionChanged {
     /// The option was successfully changed.
     Changed = weechat_sys::WEECHAT_CONFIG_OPTION_SET_OK_CHANGED as isize,
@@ -80,6 +217,170 @@ impl OptionChanged {
             _ => unreachable!(),
         }
     }
+
+    /// Did the value of the option change.
+    pub fn changed(&self) -> bool {
+        *self == OptionChanged::Changed
+    }
+
+    /// Did setting the option fail, either because the option wasn't found
+    /// or because the value was refused.
+    pub fn is_error(&self) -> bool {
+        matches!(self, OptionChanged::NotFound | OptionChanged::Error)
+    }
+}
+
+impl std::fmt::Display for OptionChanged {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match self {
+            OptionChanged::Changed => "changed",
+            OptionChanged::Unchanged => "unchanged",
+            OptionChanged::NotFound => "not found",
+            OptionChanged::Error => "invalid value",
+        };
+
+        f.write_str(status)
+    }
+}
+
+/// A summary of setting multiple options, e.g. when settings are imported.
+///
+/// The summary can be collected from an iterator over option names and the
+/// results of setting them.
+///
+/// # Example
+///
+/// ```ignore
+/// let summary: OptionChangeSummary = settings
+///     .iter()
+///     .map(|(name, value)| {
+///         (name.clone(), weechat.set_plugin_option(name, value))
+///     })
+///     .collect();
+///
+/// weechat.print(&summary.to_string());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OptionChangeSummary {
+    /// The number of options that changed.
+    pub changed: usize,
+    /// The number of options that already had the value.
+    pub unchanged: usize,
+    /// The names of the options that couldn't be set and why.
+    pub failures: Vec<(String, OptionChanged)>,
+}
+
+impl OptionChangeSummary {
+    /// Did setting any of the options fail.
+    pub fn has_failures(&self) -> bool {
+        !self.failures.is_empty()
+    }
+
+    /// The number of options the summary covers.
+    pub fn total(&self) -> usize {
+        self.changed + self.unchanged + self.failures.len()
+    }
+}
+
+impl Extend<(String, OptionChanged)> for OptionChangeSummary {
+    fn extend<I: IntoIterator<Item = (String, OptionChanged)>>(
+        &mut self,
+        iter: I,
+    ) {
+        for (name, result) in iter {
+            match result {
+                OptionChanged::Changed => self.changed += 1,
+                OptionChanged::Unchanged => self.unchanged += 1,
+                OptionChanged::NotFound | OptionChanged::Error => {
+                    self.failures.push((name, result))
+                }
+            }
+        }
+    }
+}
+
+impl std::iter::FromIterator<(String, OptionChanged)> for OptionChangeSummary {
+    fn from_iter<I: IntoIterator<Item = (String, OptionChanged)>>(
+        iter: I,
+    ) -> Self {
+        let mut summary = OptionChangeSummary::default();
+        summary.extend(iter);
+        summary
+    }
+}
+
+impl std::fmt::Display for OptionChangeSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} changed, {} unchanged, {} failed",
+            self.changed,
+            self.unchanged,
+            self.failures.len()
+        )?;
+
+        for (i, (name, result)) in self.failures.iter().enumerate() {
+            let separator = if i == 0 { ": " } else { ", " };
+            write!(f, "{}{} ({})", separator, name, result)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The standard line prefixes of Weechat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prefix<'a> {
+    /// The prefix for error messages.
+    Error,
+    /// The prefix for network messages.
+    Network,
+    /// The prefix for actions, e.g. `/me` messages.
+    Action,
+    /// The prefix for messages about users joining a channel.
+    Join,
+    /// The prefix for messages about users leaving a channel.
+    Quit,
+    /// Any other prefix name, Weechat returns an empty prefix for unknown
+    /// names.
+    Custom(&'a str),
+}
+
+impl<'a> Prefix<'a> {
+    fn as_str(&self) -> &'a str {
+        match self {
+            Prefix::Error => "error",
+            Prefix::Network => "network",
+            Prefix::Action => "action",
+            Prefix::Join => "join",
+            Prefix::Quit => "quit",
+            Prefix::Custom(name) => name,
+        }
+    }
+}
+
+/// Status for unsetting options
+pub enum OptionUnset {
+    /// The option wasn't reset, it already had its default value.
+    NoReset = weechat_sys::WEECHAT_CONFIG_OPTION_UNSET_OK_NO_RESET as isize,
+    /// The option was reset to its default value.
+    Reset = weechat_sys::WEECHAT_CONFIG_OPTION_UNSET_OK_RESET as isize,
+    /// The option was removed.
+    Removed = weechat_sys::WEECHAT_CONFIG_OPTION_UNSET_OK_REMOVED as isize,
+    /// An error occurred unsetting the option.
+    Error = weechat_sys::WEECHAT_CONFIG_OPTION_UNSET_ERROR as isize,
+}
+
+impl OptionUnset {
+    pub(crate) fn from_int(v: i32) -> OptionUnset {
+        use OptionUnset::*;
+        match v {
+            weechat_sys::WEECHAT_CONFIG_OPTION_UNSET_OK_NO_RESET => NoReset,
+            weechat_sys::WEECHAT_CONFIG_OPTION_UNSET_OK_RESET => Reset,
+            weechat_sys::WEECHAT_CONFIG_OPTION_UNSET_OK_REMOVED => Removed,
+            _ => Error,
+        }
+    }
 }
 
 /// Main Weechat struct that encapsulates common weechat API functions.
@@ -102,16 +403,61 @@ impl Weechat {
         self.ptr
     }
 
+    /// Remember the current thread as the main thread of Weechat.
+    ///
+    /// This is called when the plugin gets initialized. With the
+    /// `thread-checks` feature, debug builds panic if the Weechat API is
+    /// used on any other thread.
+    #[doc(hidden)]
+    pub fn init_main_thread() {
+        #[cfg(all(feature = "thread-checks", debug_assertions))]
+        {
+            IS_MAIN_THREAD.with(|main| main.set(true));
+            INITIALIZED.store(true, Ordering::Relaxed);
+        }
+    }
+
     #[inline]
+    #[track_caller]
     pub(crate) fn get(&self) -> &t_weechat_plugin {
+        #[cfg(all(feature = "thread-checks", debug_assertions))]
+        assert_main_thread();
+
         unsafe { &*self.ptr }
     }
 
+    /// Check if a pointer is part of a list of a Weechat hdata, e.g. to
+    /// check if a buffer still exists.
+    #[track_caller]
+    pub(crate) fn hdata_contains(
+        &self,
+        hdata_name: &CStr,
+        list_name: &CStr,
+        pointer: *mut libc::c_void,
+    ) -> bool {
+        let hdata_get = self.get().hdata_get.unwrap();
+        let hdata_get_list = self.get().hdata_get_list.unwrap();
+        let hdata_check_pointer = self.get().hdata_check_pointer.unwrap();
+
+        unsafe {
+            let hdata = hdata_get(self.ptr, hdata_name.as_ptr());
+
+            if hdata.is_null() {
+                return false;
+            }
+
+            let list = hdata_get_list(hdata, list_name.as_ptr());
+
+            hdata_check_pointer(hdata, list, pointer) != 0
+        }
+    }
+
     /// Write a message in WeeChat log file (weechat.log).
+    #[track_caller]
     pub fn log(&self, msg: &str) {
         let log_printf = self.get().log_printf.unwrap();
 
-        let fmt = LossyCString::new("%s");
+        let fmt = cstr!("%s");
         let msg = LossyCString::new(msg);
 
         unsafe {
@@ -120,10 +466,11 @@ impl Weechat {
     }
 
     /// Display a message on the core weechat buffer.
+    #[track_caller]
     pub fn print(&self, msg: &str) {
         let printf_date_tags = self.get().printf_date_tags.unwrap();
 
-        let fmt = LossyCString::new("%s");
+        let fmt = cstr!("%s");
         let msg = LossyCString::new(msg);
 
         unsafe {
@@ -137,8 +484,35 @@ impl Weechat {
         }
     }
 
+    /// Display a message on the core weechat buffer with attached date and
+    /// tags.
+    ///
+    /// * `date` - The date of the message as a unix timestamp, 0 means the
+    ///     current time.
+    /// * `tags` - A comma separated list of tags for the message.
+    /// * `msg` - The message that will be displayed.
+    #[track_caller]
+    pub fn print_date_tags(&self, date: i64, tags: &str, msg: &str) {
+        let printf_date_tags = self.get().printf_date_tags.unwrap();
+
+        let fmt = cstr!("%s");
+        let tags = LossyCString::new(tags);
+        let msg = LossyCString::new(msg);
+
+        unsafe {
+            printf_date_tags(
+                ptr::null_mut(),
+                date,
+                tags.as_ptr(),
+                fmt.as_ptr(),
+                msg.as_ptr(),
+            );
+        }
+    }
+
     /// Return a string color code for display.
     /// * `color_name` - name the color
+    #[track_caller]
     pub fn color(&self, color_name: &str) -> Cow<str> {
         let weechat_color = self.get().color.unwrap();
 
@@ -149,6 +523,103 @@ impl Weechat {
         }
     }
 
+    /// Remove Weechat color codes from a string.
+    ///
+    /// Useful for getting the plain text of a colored string, e.g. to log it
+    /// or to measure its length.
+    #[track_caller]
+    pub fn remove_color(&self, string: &str) -> String {
+        self.remove_color_with(string, None)
+    }
+
+    /// Remove Weechat color codes from a string, replacing every color code
+    /// with the given character.
+    ///
+    /// * `string` - The string that should be stripped of colors.
+    /// * `replacement` - The character that will replace every color code, if
+    ///     `None` the color codes are removed.
+    #[track_caller]
+    pub fn remove_color_with(
+        &self,
+        string: &str,
+        replacement: Option<char>,
+    ) -> String {
+        let string_remove_color = self.get().string_remove_color.unwrap();
+
+        let c_string = LossyCString::new(string);
+        let replacement =
+            replacement.map(|c| LossyCString::from_string(c.to_string()));
+        let replacement_ptr =
+            replacement.as_ref().map_or(ptr::null(), |r| r.as_ptr());
+
+        unsafe {
+            let result =
+                string_remove_color(c_string.as_ptr(), replacement_ptr);
+
+            if result.is_null() {
+                string.to_owned()
+            } else {
+                let plain = CStr::from_ptr(result).to_string_lossy().into();
+                libc::free(result as *mut libc::c_void);
+                plain
+            }
+        }
+    }
+
+    /// Run the modifier callbacks for a modifier, returns the string
+    /// unchanged if no modifier returned a new string.
+    #[track_caller]
+    fn modifier_exec(
+        &self,
+        modifier: &str,
+        modifier_data: &str,
+        string: &str,
+    ) -> String {
+        let hook_modifier_exec = self.get().hook_modifier_exec.unwrap();
+
+        let modifier = LossyCString::new(modifier);
+        let modifier_data = LossyCString::new(modifier_data);
+        let c_string = LossyCString::new(string);
+
+        unsafe {
+            let result = hook_modifier_exec(
+                self.ptr,
+                modifier.as_ptr(),
+                modifier_data.as_ptr(),
+                c_string.as_ptr(),
+            );
+
+            if result.is_null() {
+                string.to_owned()
+            } else {
+                let modified = CStr::from_ptr(result).to_string_lossy().into();
+                libc::free(result as *mut libc::c_void);
+                modified
+            }
+        }
+    }
+
+    /// Convert ANSI color codes in a string into Weechat color codes.
+    ///
+    /// Useful for displaying text from external programs or services that
+    /// uses ANSI colors, strings without ANSI color codes are returned
+    /// unchanged.
+    #[track_caller]
+    pub fn convert_ansi_to_weechat(&self, string: &str) -> String {
+        // Keep the colors instead of removing them.
+        self.modifier_exec("color_decode_ansi", "1", string)
+    }
+
+    /// Convert Weechat color codes in a string into ANSI color codes.
+    ///
+    /// Useful for displaying text outside of Weechat, e.g. in a terminal or a
+    /// web frontend, strings without Weechat color codes are returned
+    /// unchanged.
+    #[track_caller]
+    pub fn encode_ansi(&self, string: &str) -> String {
+        self.modifier_exec("color_encode_ansi", "", string)
+    }
+
     /// Retrieve a prefix value
     ///
     /// Valid prefixes are:
@@ -159,17 +630,113 @@ impl Weechat {
     /// * quit
     ///
     /// An empty string will be returned if the prefix is not found
+    #[deprecated(note = "Use prefix() instead.")]
+    #[track_caller]
     pub fn get_prefix(&self, prefix: &str) -> Cow<str> {
+        self.prefix(Prefix::Custom(prefix))
+    }
+
+    /// Retrieve one of the standard line prefixes.
+    ///
+    /// The prefix contains the color of the prefix and the tab that separates
+    /// the prefix from the message.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let message = format!("{}Connection lost", weechat.prefix(Prefix::Network));
+    /// weechat.print(&message);
+    /// ```
+    #[track_caller]
+    pub fn prefix(&self, prefix: Prefix) -> Cow<'_, str> {
         let prefix_fn = self.get().prefix.unwrap();
 
-        let prefix = LossyCString::new(prefix);
+        let prefix = LossyCString::new(prefix.as_str());
 
         unsafe { CStr::from_ptr(prefix_fn(prefix.as_ptr())).to_string_lossy() }
     }
 
+    /// Display an error message on the core weechat buffer, the message is
+    /// prefixed with the error prefix.
+    #[track_caller]
+    pub fn print_error(&self, msg: &str) {
+        self.print(&format!("{}{}", self.prefix(Prefix::Error), msg));
+    }
+
+    /// Execute a command with options.
+    ///
+    /// Returns an error if options are given but the running Weechat version
+    /// doesn't support them, without options the command is always executed.
+    ///
+    /// * `buffer` - The buffer the command is executed on, the current buffer
+    ///     is used if `None` is given.
+    /// * `command` - The command to execute, e.g. `"/join #rust"`.
+    /// * `options` - Options for the execution, e.g. `"commands"` set to
+    ///     `"*,-exec"` to refuse the `/exec` command, or `"delay"` set to the
+    ///     number of milliseconds the execution is delayed. See the weechat C
+    ///     API documentation for valid values.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut options = HashMap::new();
+    /// options.insert("commands".to_owned(), "*,-exec".to_owned());
+    ///
+    /// // The command is refused and an error is returned.
+    /// let code = weechat.command_with_options(None, "/exec ls", &options)?;
+    /// ```
+    #[track_caller]
+    pub fn command_with_options(
+        &self,
+        buffer: Option<&Buffer>,
+        command: &str,
+        options: &HashMap<String, String>,
+    ) -> Result<ReturnCode, UnsupportedVersion> {
+        if matches!(buffer, Some(buffer) if buffer.is_closed()) {
+            return Ok(ReturnCode::Error);
+        }
+
+        let buffer_ptr = buffer.map_or(ptr::null_mut(), |b| b.ptr);
+        let command = LossyCString::new(command);
+
+        let ret = if options.is_empty() {
+            let command_fn = self.get().command.unwrap();
+            unsafe { command_fn(self.ptr, buffer_ptr, command.as_ptr()) }
+        } else {
+            self.require_version(2, 5, 0)?;
+
+            let command_options = self.get().command_options.unwrap();
+
+            let hashtable = match self.new_hashtable(
+                32,
+                HashtableItemType::String,
+                HashtableItemType::String,
+            ) {
+                Some(hashtable) => hashtable,
+                None => return Ok(ReturnCode::Error),
+            };
+
+            for (key, value) in options {
+                hashtable.set(key, value);
+            }
+
+            unsafe {
+                command_options(
+                    self.ptr,
+                    buffer_ptr,
+                    command.as_ptr(),
+                    hashtable.ptr,
+                )
+            }
+        };
+
+        Ok(ReturnCode::from_raw(ret))
+    }
+
     /// Get some info from Weechat or a plugin.
     /// * `info_name` - name the info
     /// * `arguments` - arguments for the info
+    #[track_caller]
     pub fn info_get(
         &self,
         info_name: &str,
@@ -186,13 +753,211 @@ impl Weechat {
             if info.is_null() {
                 None
             } else {
-                Some(CStr::from_ptr(info).to_string_lossy())
+                let value = CStr::from_ptr(info).to_string_lossy().into_owned();
+                libc::free(info as *mut libc::c_void);
+                Some(Cow::Owned(value))
             }
         }
     }
 
+    /// Get some info from Weechat or a plugin in the form of a hashtable.
+    /// * `info_name` - name the info
+    /// * `arguments` - arguments for the info
+    #[track_caller]
+    pub fn info_get_hashtable(
+        &self,
+        info_name: &str,
+        arguments: &HashMap<String, String>,
+    ) -> Option<HashMap<String, String>> {
+        let info_get_hashtable = self.get().info_get_hashtable.unwrap();
+
+        let hashtable = self.new_hashtable(
+            32,
+            HashtableItemType::String,
+            HashtableItemType::String,
+        )?;
+
+        for (key, value) in arguments {
+            hashtable.set(key, value);
+        }
+
+        let info_name = LossyCString::new(info_name);
+
+        let result = unsafe {
+            info_get_hashtable(self.ptr, info_name.as_ptr(), hashtable.ptr)
+        };
+
+        if result.is_null() {
+            None
+        } else {
+            Some(
+                Hashtable::from_ptrs(self.ptr, result, true)
+                    .iter()
+                    .collect(),
+            )
+        }
+    }
+
+    /// Get the version of Weechat.
+    #[track_caller]
+    pub fn version(&self) -> WeechatVersion {
+        let number = self
+            .info_get("version_number", "")
+            .and_then(|n| n.parse::<u32>().ok())
+            .unwrap_or_default();
+
+        WeechatVersion::from_number(number)
+    }
+
+    /// Check if the version of Weechat is at least the given version.
+    #[track_caller]
+    pub fn version_at_least(&self, major: u32, minor: u32, patch: u32) -> bool {
+        self.version() >= WeechatVersion::new(major, minor, patch)
+    }
+
+    /// Require a minimal version of Weechat.
+    ///
+    /// Returns an error if the running version of Weechat is older than the
+    /// required version.
+    #[track_caller]
+    pub fn require_version(
+        &self,
+        major: u32,
+        minor: u32,
+        patch: u32,
+    ) -> Result<(), UnsupportedVersion> {
+        let required = WeechatVersion::new(major, minor, patch);
+        let running = self.version();
+
+        if running >= required {
+            Ok(())
+        } else {
+            Err(UnsupportedVersion { required, running })
+        }
+    }
+
+    #[track_caller]
+    fn directory_info(&self, info_name: &str) -> Option<PathBuf> {
+        self.info_get(info_name, "")
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| PathBuf::from(dir.into_owned()))
+    }
+
+    /// Get the Weechat home directory.
+    ///
+    /// Since Weechat 3.2 this is the data directory.
+    #[track_caller]
+    pub fn home_dir(&self) -> PathBuf {
+        self.directory_info("weechat_dir").unwrap_or_default()
+    }
+
+    /// Get a directory of the XDG split of the home directory, falls back to
+    /// the home directory on versions that don't have the directory.
+    #[track_caller]
+    fn split_directory(
+        &self,
+        info_name: &str,
+        since: (u32, u32, u32),
+    ) -> PathBuf {
+        let (major, minor, patch) = since;
+
+        if self.version_at_least(major, minor, patch) {
+            self.directory_info(info_name)
+                .unwrap_or_else(|| self.home_dir())
+        } else {
+            self.home_dir()
+        }
+    }
+
+    /// Get the Weechat config directory.
+    ///
+    /// Falls back to the home directory on Weechat versions that don't split
+    /// the home directory.
+    #[track_caller]
+    pub fn config_dir(&self) -> PathBuf {
+        self.split_directory("weechat_config_dir", (3, 2, 0))
+    }
+
+    /// Get the Weechat data directory.
+    ///
+    /// Falls back to the home directory on Weechat versions that don't split
+    /// the home directory.
+    #[track_caller]
+    pub fn data_dir(&self) -> PathBuf {
+        self.split_directory("weechat_data_dir", (3, 2, 0))
+    }
+
+    /// Get the Weechat cache directory.
+    ///
+    /// Falls back to the home directory on Weechat versions that don't split
+    /// the home directory.
+    #[track_caller]
+    pub fn cache_dir(&self) -> PathBuf {
+        self.split_directory("weechat_cache_dir", (3, 2, 0))
+    }
+
+    /// Get the Weechat runtime directory.
+    ///
+    /// Falls back to the home directory on Weechat versions that don't have a
+    /// runtime directory.
+    #[track_caller]
+    pub fn runtime_dir(&self) -> PathBuf {
+        self.split_directory("weechat_runtime_dir", (3, 8, 0))
+    }
+
+    /// Create a directory in the Weechat home directory.
+    ///
+    /// * `path` - The directory to create, relative to the home directory.
+    ///     Since Weechat 3.2 the path may start with a directory variable,
+    ///     e.g. `"${weechat_cache_dir}/myplugin"`.
+    /// * `mode` - The permissions of the directory, e.g. `0o755`.
+    #[track_caller]
+    pub fn mkdir_home(&self, path: &str, mode: u32) -> Result<(), io::Error> {
+        let mkdir_home = self.get().mkdir_home.unwrap();
+
+        let path = LossyCString::new(path);
+
+        if unsafe { mkdir_home(path.as_ptr(), mode as c_int) } == 1 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    /// Create a directory and all of its missing parent directories.
+    ///
+    /// * `path` - The directory to create.
+    /// * `mode` - The permissions of the created directories, e.g. `0o755`.
+    #[track_caller]
+    pub fn mkdir_parents(
+        &self,
+        path: impl AsRef<Path>,
+        mode: u32,
+    ) -> Result<(), io::Error> {
+        let mkdir_parents = self.get().mkdir_parents.unwrap();
+
+        let path = LossyCString::new(path.as_ref().to_string_lossy());
+
+        if unsafe { mkdir_parents(path.as_ptr(), mode as c_int) } == 1 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
     /// Get value of a plugin option
+    #[deprecated(note = "Use plugin_option() instead.")]
+    #[track_caller]
     pub fn get_plugin_option(&self, option: &str) -> Option<Cow<str>> {
+        self.plugin_option(option)
+    }
+
+    /// Get the value of a plugin option.
+    ///
+    /// Plugin options live in the `plugins.var.<plugin>` namespace, e.g. the
+    /// option `name` of this plugin is `plugins.var.<plugin>.name`.
+    #[track_caller]
+    pub fn plugin_option(&self, option: &str) -> Option<Cow<'_, str>> {
         let config_get_plugin = self.get().config_get_plugin.unwrap();
 
         let option_name = LossyCString::new(option);
@@ -208,7 +973,8 @@ impl Weechat {
     }
 
     /// Get value of a plugin option
-    pub fn get_string_option(&self, option: &str) -> Option<StringOption> {
+    #[track_caller]
+    pub fn get_string_option(&self, option: &str) -> Option<StringOption<'_>> {
         let config_get = self.get().config_get.unwrap();
 
         let option_name = LossyCString::new(option);
@@ -224,6 +990,7 @@ impl Weechat {
     }
 
     /// Set the value of a plugin option
+    #[track_caller]
     pub fn set_plugin_option(
         &self,
         option: &str,
@@ -245,27 +1012,229 @@ impl Weechat {
         }
     }
 
-    /// Evaluate a weechat expression and return the result
-    //
-    // TODO: Add hashtable options
-    pub fn eval_string_expression(&self, expr: &str) -> Option<Cow<str>> {
-        let string_eval_expression = self.get().string_eval_expression.unwrap();
+    /// Check if a plugin option is set.
+    #[track_caller]
+    pub fn plugin_option_is_set(&self, option: &str) -> bool {
+        let config_is_set_plugin = self.get().config_is_set_plugin.unwrap();
 
-        let expr = LossyCString::new(expr);
+        let option_name = LossyCString::new(option);
 
-        unsafe {
-            let result = string_eval_expression(
-                expr.as_ptr(),
-                ptr::null_mut(),
-                ptr::null_mut(),
-                ptr::null_mut(),
-            );
+        unsafe { config_is_set_plugin(self.ptr, option_name.as_ptr()) != 0 }
+    }
 
-            if result.is_null() {
-                None
-            } else {
-                Some(CStr::from_ptr(result).to_string_lossy())
-            }
+    /// Unset a plugin option, the option is removed from the
+    /// `plugins.var.<plugin>` namespace.
+    #[track_caller]
+    pub fn unset_plugin_option(&self, option: &str) {
+        let config_unset_plugin = self.get().config_unset_plugin.unwrap();
+
+        let option_name = LossyCString::new(option);
+
+        unsafe {
+            config_unset_plugin(self.ptr, option_name.as_ptr());
         }
     }
+
+    /// Evaluate a weechat expression and return the result.
+    ///
+    /// Use `eval_expression()` to pass pointers, extra variables or options
+    /// to the evaluation.
+    #[track_caller]
+    pub fn eval_string_expression(&self, expr: &str) -> Option<Cow<str>> {
+        self.eval_expression(
+            expr,
+            &HashMap::new(),
+            &HashMap::new(),
+            &HashMap::new(),
+        )
+        .map(Cow::Owned)
+    }
+
+    /// Get all the secured data that was set using `/secure set`.
+    ///
+    /// The returned map contains secrets, e.g. passwords or tokens, the values
+    /// should never be printed or logged.
+    #[track_caller]
+    pub fn secure_data(&self) -> HashMap<String, String> {
+        self.info_get_hashtable("secured_data", &HashMap::new())
+            .unwrap_or_default()
+    }
+
+    /// Get a single secured data value that was set using `/secure set`.
+    ///
+    /// The returned string is a secret, e.g. a password or a token, it should
+    /// never be printed or logged.
+    #[track_caller]
+    pub fn secure_get(&self, name: &str) -> Option<String> {
+        self.secure_data().remove(name)
+    }
+
+    /// Expand secured data references in a string.
+    ///
+    /// The string is evaluated, references of the form `${sec.data.name}` are
+    /// replaced with the secured data value. This is mostly useful for options
+    /// that store a reference to a secret instead of the secret itself.
+    ///
+    /// The returned string may contain secrets, it should never be printed or
+    /// logged.
+    #[track_caller]
+    pub fn expand_secure_data(&self, value: &str) -> Option<String> {
+        self.eval_expression(
+            value,
+            &HashMap::new(),
+            &HashMap::new(),
+            &HashMap::new(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::thread;
+
+    use crate::mock::MockWeechat;
+    use crate::{Buffer, OptionChanged, ReturnCode, WeechatVersion};
+
+    #[cfg(all(feature = "thread-checks", debug_assertions))]
+    #[test]
+    fn api_use_outside_of_the_main_thread_panics() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+
+        Buffer::from_ptr(weechat.as_ptr(), std::ptr::null_mut()).print("main");
+
+        // Raw pointers aren't Send, this is exactly what the check is for.
+        let plugin = weechat.as_ptr() as usize;
+
+        let result = thread::spawn(move || {
+            let buffer =
+                Buffer::from_ptr(plugin as *mut _, std::ptr::null_mut());
+            buffer.print("other");
+        })
+        .join();
+
+        let payload = result.expect_err("Using a buffer on a thread panics");
+        let message = crate::panic_message(&*payload);
+
+        assert!(
+            message.contains("outside of the main thread"),
+            "{}",
+            message
+        );
+        // The location points to the caller of the buffer method.
+        assert!(message.contains(file!()), "{}", message);
+
+        let lines = mock.lines();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].buffer.is_null());
+        assert_eq!(mock.messages(), ["main"]);
+    }
+
+    #[test]
+    fn plugin_options_round_trip() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+
+        assert_eq!(weechat.plugin_option("autojoin"), None);
+        assert!(!weechat.plugin_option_is_set("autojoin"));
+
+        assert_eq!(
+            weechat.set_plugin_option("autojoin", "#rust"),
+            OptionChanged::Changed
+        );
+        assert_eq!(
+            weechat.set_plugin_option("autojoin", "#rust"),
+            OptionChanged::Unchanged
+        );
+        assert_eq!(weechat.plugin_option("autojoin").as_deref(), Some("#rust"));
+        assert!(weechat.plugin_option_is_set("autojoin"));
+
+        // The options live in the namespace of the plugin.
+        assert_eq!(
+            mock.plugin_options(),
+            [("plugins.var.mock.autojoin".to_owned(), "#rust".to_owned())]
+        );
+
+        weechat.unset_plugin_option("autojoin");
+
+        assert_eq!(weechat.plugin_option("autojoin"), None);
+        assert!(!weechat.plugin_option_is_set("autojoin"));
+        assert!(mock.plugin_options().is_empty());
+    }
+
+    #[test]
+    fn refused_commands_are_not_executed() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+
+        let mut options = HashMap::new();
+        options.insert("commands".to_owned(), "*,-exec".to_owned());
+
+        let code = weechat.command_with_options(None, "/exec ls", &options);
+        assert_eq!(code.unwrap(), ReturnCode::Error);
+
+        let code = weechat.command_with_options(None, "/join #rust", &options);
+        assert_eq!(code.unwrap(), ReturnCode::Ok);
+
+        assert_eq!(mock.commands(), vec!["/join #rust"]);
+
+        let (created, freed) = mock.hashtables();
+        assert_eq!(created, 2);
+        assert_eq!(created, freed);
+    }
+
+    #[test]
+    fn command_options_require_a_newer_weechat() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        mock.set_version(0x0204_0000);
+
+        let mut options = HashMap::new();
+        options.insert("commands".to_owned(), "*,-exec".to_owned());
+
+        let error = weechat
+            .command_with_options(None, "/exec ls", &options)
+            .unwrap_err();
+        assert_eq!(error.required, WeechatVersion::new(2, 5, 0));
+        assert_eq!(error.running, WeechatVersion::new(2, 4, 0));
+
+        // Without options the command is executed on any version.
+        let code =
+            weechat.command_with_options(None, "/exec ls", &HashMap::new());
+        assert_eq!(code.unwrap(), ReturnCode::Ok);
+
+        assert_eq!(mock.commands(), vec!["/exec ls"]);
+    }
+
+    #[test]
+    fn expressions_are_evaluated_with_their_hashtables_freed() {
+        let mock = MockWeechat::new();
+        let weechat = mock.weechat();
+        mock.set_secured_data("token", "hunter2");
+
+        assert_eq!(
+            weechat.eval_string_expression("no variables").as_deref(),
+            Some("no variables")
+        );
+        assert_eq!(
+            weechat.expand_secure_data("${sec.data.token}").as_deref(),
+            Some("hunter2")
+        );
+
+        let mut extra_vars = HashMap::new();
+        extra_vars.insert("nick".to_owned(), "Emma".to_owned());
+
+        let evaluated = weechat.eval_expression(
+            "Hello ${nick}",
+            &HashMap::new(),
+            &extra_vars,
+            &HashMap::new(),
+        );
+        assert_eq!(evaluated.as_deref(), Some("Hello Emma"));
+
+        let (created, freed) = mock.hashtables();
+        assert_eq!(created, 9);
+        assert_eq!(created, freed);
+    }
 }
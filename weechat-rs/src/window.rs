@@ -0,0 +1,197 @@
+//! Weechat Window module.
+
+use std::ffi::c_void;
+use std::ptr;
+
+use weechat_sys::{t_gui_buffer, t_gui_window, t_weechat_plugin};
+
+use crate::{Buffer, LossyCString, Weechat};
+
+/// A Weechat window, a part of the screen that displays a buffer.
+pub struct Window {
+    pub(crate) weechat: *mut t_weechat_plugin,
+    pub(crate) ptr: *mut t_gui_window,
+}
+
+impl PartialEq for Window {
+    fn eq(&self, other: &Window) -> bool {
+        self.ptr == other.ptr
+    }
+}
+
+impl Window {
+    /// Create a high level Window object from a C plugin pointer and the
+    /// window pointer.
+    pub fn from_ptr(
+        weechat_ptr: *mut t_weechat_plugin,
+        window_ptr: *mut t_gui_window,
+    ) -> Window {
+        Window {
+            weechat: weechat_ptr,
+            ptr: window_ptr,
+        }
+    }
+
+    #[track_caller]
+    fn get_integer(&self, property: &str) -> i32 {
+        let weechat = Weechat::from_ptr(self.weechat);
+        let window_get_integer = weechat.get().window_get_integer.unwrap();
+
+        let property = LossyCString::new(property);
+
+        unsafe { window_get_integer(self.ptr, property.as_ptr()) }
+    }
+
+    /// Get the number of the window.
+    #[track_caller]
+    pub fn number(&self) -> i32 {
+        self.get_integer("number")
+    }
+
+    /// Get the width of the window in characters.
+    #[track_caller]
+    pub fn width(&self) -> i32 {
+        self.get_integer("win_width")
+    }
+
+    /// Get the height of the window in lines.
+    #[track_caller]
+    pub fn height(&self) -> i32 {
+        self.get_integer("win_height")
+    }
+
+    /// Get the width of the chat area of the window in characters.
+    #[track_caller]
+    pub fn chat_width(&self) -> i32 {
+        self.get_integer("win_chat_width")
+    }
+
+    /// Get the height of the chat area of the window in lines.
+    #[track_caller]
+    pub fn chat_height(&self) -> i32 {
+        self.get_integer("win_chat_height")
+    }
+
+    /// Is the window scrolled, e.g. the last line of the buffer isn't
+    /// displayed.
+    #[track_caller]
+    pub fn is_scrolled(&self) -> bool {
+        self.get_integer("scrolling") == 1
+    }
+
+    /// Get the buffer that is displayed in the window.
+    #[track_caller]
+    pub fn buffer(&self) -> Buffer {
+        let weechat = Weechat::from_ptr(self.weechat);
+        let window_get_pointer = weechat.get().window_get_pointer.unwrap();
+
+        let property = cstr!("buffer");
+
+        let buffer = unsafe { window_get_pointer(self.ptr, property.as_ptr()) };
+
+        Buffer::from_ptr(self.weechat, buffer as *mut t_gui_buffer)
+    }
+
+    /// Scroll the window.
+    ///
+    /// * `amount` - How far the window should be scrolled, a number of lines
+    ///     that is prefixed with `+` or `-`, or a time span with a suffix,
+    ///     e.g. `"-1d"` to scroll one day back. See the `/window scroll`
+    ///     command of Weechat for valid values.
+    #[track_caller]
+    pub fn scroll(&self, amount: &str) {
+        let weechat = Weechat::from_ptr(self.weechat);
+        let command = weechat.get().command.unwrap();
+
+        let buffer = self.buffer();
+        let scroll = LossyCString::from_string(format!(
+            "/window scroll -window {} {}",
+            self.number(),
+            amount
+        ));
+
+        unsafe {
+            command(self.weechat, buffer.ptr, scroll.as_ptr());
+        }
+    }
+}
+
+impl Buffer {
+    /// Get the window that displays the buffer.
+    ///
+    /// Returns `None` if the buffer isn't displayed in any window, if the
+    /// buffer is displayed in multiple windows the first one is returned.
+    #[track_caller]
+    pub fn window(&self) -> Option<Window> {
+        if self.is_closed() {
+            return None;
+        }
+
+        let weechat = Weechat::from_ptr(self.weechat);
+        let window_search_with_buffer =
+            weechat.get().window_search_with_buffer.unwrap();
+
+        let window = unsafe { window_search_with_buffer(self.ptr) };
+
+        if window.is_null() {
+            None
+        } else {
+            Some(Window::from_ptr(self.weechat, window))
+        }
+    }
+}
+
+impl Weechat {
+    /// Get the current window.
+    #[track_caller]
+    pub fn current_window(&self) -> Option<Window> {
+        let window_get_pointer = self.get().window_get_pointer.unwrap();
+
+        let property = cstr!("current");
+
+        let window = unsafe {
+            window_get_pointer(ptr::null_mut(), property.as_ptr())
+                as *mut t_gui_window
+        };
+
+        if window.is_null() {
+            None
+        } else {
+            Some(Window::from_ptr(self.ptr, window))
+        }
+    }
+
+    /// Get all the windows.
+    #[track_caller]
+    pub fn windows(&self) -> Vec<Window> {
+        let hdata_get = self.get().hdata_get.unwrap();
+        let hdata_get_list = self.get().hdata_get_list.unwrap();
+        let hdata_move = self.get().hdata_move.unwrap();
+
+        let hdata_name = cstr!("window");
+        let list_name = cstr!("gui_windows");
+
+        let mut windows = Vec::new();
+
+        unsafe {
+            let hdata = hdata_get(self.ptr, hdata_name.as_ptr());
+
+            if hdata.is_null() {
+                return windows;
+            }
+
+            let mut window: *mut c_void =
+                hdata_get_list(hdata, list_name.as_ptr());
+
+            while !window.is_null() {
+                windows.push(Window::from_ptr(
+                    self.ptr,
+                    window as *mut t_gui_window,
+                ));
+                window = hdata_move(hdata, window, 1);
+            }
+        }
+
+        windows
+    }
+}
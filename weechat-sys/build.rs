@@ -24,6 +24,10 @@ fn build(file: &str) -> Result<Bindings, ()> {
         "WEECHAT_HDATA_TIME",
         "WEECHAT_HDATA_HASHTABLE",
         "WEECHAT_HDATA_SHARED_STRING",
+        "WEECHAT_STRING_SPLIT_STRIP_LEFT",
+        "WEECHAT_STRING_SPLIT_STRIP_RIGHT",
+        "WEECHAT_STRING_SPLIT_COLLAPSE_SEPS",
+        "WEECHAT_STRING_SPLIT_KEEP_EOL",
     ];
     let mut builder = bindgen::Builder::default().rustfmt_bindings(true);
 
@@ -17,3 +17,16 @@ pub const WEECHAT_CONFIG_OPTION_SET_OK_CHANGED: c_int = 2;
 pub const WEECHAT_CONFIG_OPTION_SET_OK_SAME_VALUE: c_int = 1;
 pub const WEECHAT_CONFIG_OPTION_SET_ERROR: c_int = 0;
 pub const WEECHAT_CONFIG_OPTION_SET_OPTION_NOT_FOUND: c_int = -1;
+
+pub const WEECHAT_CONFIG_OPTION_UNSET_OK_NO_RESET: c_int = 0;
+pub const WEECHAT_CONFIG_OPTION_UNSET_OK_RESET: c_int = 1;
+pub const WEECHAT_CONFIG_OPTION_UNSET_OK_REMOVED: c_int = 2;
+pub const WEECHAT_CONFIG_OPTION_UNSET_ERROR: c_int = -1;
+
+pub const WEECHAT_CONFIG_READ_OK: c_int = 0;
+pub const WEECHAT_CONFIG_READ_MEMORY_ERROR: c_int = -1;
+pub const WEECHAT_CONFIG_READ_FILE_NOT_FOUND: c_int = -2;
+
+pub const WEECHAT_CONFIG_WRITE_OK: c_int = 0;
+pub const WEECHAT_CONFIG_WRITE_ERROR: c_int = -1;
+pub const WEECHAT_CONFIG_WRITE_MEMORY_ERROR: c_int = -2;